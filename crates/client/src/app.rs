@@ -1,21 +1,44 @@
-use std::{collections::HashMap, path::Path, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use crossterm::event::Event;
 use cryptolib::{
     DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, LineEnding,
     RsaPrivateKey, RsaPublicKey,
 };
+use protocol::{
+    ctos::{
+        DistributeGroupKey, GroupKey, KeyProof, RewrapKeys, RotateKey, SendMessage, WrappedKey,
+    },
+    stoc::{
+        ApiError, Challenge, Group, KeyRotation, Presence, ResponseGetMessage, ResponseGetUser,
+        Revision, Session,
+    },
+    validation::{MAX_MESSAGE_TIMER, MAX_WRAPPED_KEYS},
+};
 use rand::rngs::OsRng;
-use tokio::sync::mpsc::UnboundedReceiver;
+use reqwest::StatusCode;
+use tokio::sync::{
+    mpsc::{self, error::TryRecvError, UnboundedReceiver},
+    watch,
+};
 
 use crate::{
+    attachment::{describe_size, Attachment, MAX_FILE_SIZE},
     client_handler::MessageISTClient,
     db::{
-        structs::{Contact, StoredMessage},
+        structs::{Contact, MessageKind, StoredMessage, StoredReaction},
         Database,
     },
+    expiry::describe_timer,
     logger::LoggerRecord,
-    message_data::MessageData,
+    message_data::{conversation_tag, group_chat_id, MessageData, MessageRef},
+    notifications::NotificationEvent,
     ui::theming::{self, AppTheme},
 };
 
@@ -33,6 +56,8 @@ pub enum Pages {
     Register,
     Main,
     AddContact,
+    Profile,
+    NewGroup,
 }
 
 #[derive(Debug)]
@@ -41,12 +66,59 @@ pub enum LoginError {
     KeyGen,
 }
 
+#[derive(Debug)]
+pub struct ContactKeyChanged(pub String);
+
+impl Display for ContactKeyChanged {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The key of {} has changed and must be accepted before sending",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for ContactKeyChanged {}
+
+#[derive(Debug)]
+pub struct ContactDeleted(pub String);
+
+impl Display for ContactDeleted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} has deleted their account", self.0)
+    }
+}
+
+impl std::error::Error for ContactDeleted {}
+
+#[derive(Debug)]
+pub struct NotGroupMember;
+
+impl Display for NotGroupMember {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "You are no longer a member of this group")
+    }
+}
+
+impl std::error::Error for NotGroupMember {}
+
+const KEY_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+// well below the rate limit of user lookups
+const KEY_CHECK_SPACING: Duration = Duration::from_secs(2);
+const GROUP_KEY_ATTEMPTS: usize = 3;
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const DOWNLOADS_DIR: &str = "downloads";
+const RECENT_SYNC_LIMIT: i64 = 200;
+const HISTORY_PAGE: i64 = 50;
+
 #[derive(Debug)]
 pub struct SessionUser {
     pub id: String,
     pub name: String,
     pub public_key: RsaPublicKey,
     pub private_key: RsaPrivateKey,
+    pub retired_keys: Vec<RsaPrivateKey>,
 }
 
 impl SessionUser {
@@ -61,8 +133,22 @@ impl SessionUser {
             name,
             public_key,
             private_key,
+            retired_keys: Vec::new(),
         }
     }
+
+    pub fn private_keys(&self) -> Vec<RsaPrivateKey> {
+        let mut keys = vec![self.private_key.clone()];
+        keys.extend(self.retired_keys.iter().cloned());
+        keys
+    }
+}
+
+// what a background key check found out about a contact
+#[derive(Debug)]
+pub enum KeyCheck {
+    Fetched(ResponseGetUser),
+    Deleted(String),
 }
 
 #[derive(Debug)]
@@ -72,13 +158,27 @@ pub struct App {
     pub show_terminal: bool,
     pub contacts: Vec<Contact>,
     pub messages: HashMap<String, Vec<StoredMessage>>,
+    pub requests: HashMap<String, Vec<StoredMessage>>,
+    pub reactions: HashMap<String, Vec<StoredReaction>>,
+    pub groups: HashMap<String, Group>,
     pub frame_duration: Duration,
     pub net_client: MessageISTClient,
     pub just_registered: bool,
     pub db: Option<Arc<Database>>,
     pub theme: AppTheme,
-    pub notification_receiver: Option<UnboundedReceiver<StoredMessage>>,
+    pub notification_receiver: Option<UnboundedReceiver<NotificationEvent>>,
     pub current_user: Option<SessionUser>,
+    pub presence: HashMap<String, Presence>,
+    pub presence_hidden: bool,
+    pub sessions: Vec<Session>,
+    pub blocked: HashSet<String>,
+    pub private_keys: watch::Sender<Vec<RsaPrivateKey>>,
+    pub last_key_check: Instant,
+    pub key_checks: Option<UnboundedReceiver<KeyCheck>>,
+    pub last_expiry_check: Instant,
+    pub history_exhausted: HashSet<String>,
+    // where paging back through the whole mailbox stopped, received and sent copies
+    pub mailbox_cursor: (Option<i64>, Option<i64>),
 }
 
 impl App {
@@ -89,6 +189,9 @@ impl App {
             show_terminal: false,
             contacts: Vec::new(),
             messages: HashMap::new(),
+            requests: HashMap::new(),
+            reactions: HashMap::new(),
+            groups: HashMap::new(),
             frame_duration: Duration::ZERO,
             net_client: MessageISTClient::new(),
             just_registered: false,
@@ -96,6 +199,16 @@ impl App {
             theme: theming::CATPUCCIN_MOCHA,
             notification_receiver: None,
             current_user: None,
+            presence: HashMap::new(),
+            presence_hidden: false,
+            sessions: Vec::new(),
+            blocked: HashSet::new(),
+            private_keys: watch::channel(Vec::new()).0,
+            last_key_check: Instant::now(),
+            key_checks: None,
+            last_expiry_check: Instant::now(),
+            history_exhausted: HashSet::new(),
+            mailbox_cursor: (None, None),
         })
     }
 
@@ -129,25 +242,76 @@ impl App {
     }
 
     pub async fn login<'a>(&'a mut self, id: &str, password: &str) -> Result<(), LoginError> {
-        let user = match self.net_client.login(id, password).await {
-            Ok(v) => v,
-            Err(e) => return Err(LoginError::RequestError(e)),
-        };
+        let has_keys = Path::new(&format!("{}.priv", id)).exists();
         let Ok((prk, puk)) = self.gen_key_pair(id).await else {
             return Err(LoginError::KeyGen);
         };
+        // an existing key signs in on its own where the server allows it, the password then only
+        // unlocks the local database
+        let key_user = if has_keys {
+            match self.net_client.login_with_key(id, &prk).await {
+                Ok(v) => Some(v),
+                Err(e) => match e.downcast::<reqwest::Error>() {
+                    Ok(e) if e.status() == Some(StatusCode::FORBIDDEN) => None,
+                    Ok(e) => return Err(LoginError::RequestError(e)),
+                    Err(_) => return Err(LoginError::KeyGen),
+                },
+            }
+        } else {
+            None
+        };
+        let user = match key_user {
+            Some(user) => user,
+            None => {
+                let proof = if has_keys {
+                    match self.net_client.prove_key(id, &prk).await {
+                        Ok(v) => Some(v),
+                        Err(e) => match e.downcast::<reqwest::Error>() {
+                            Ok(e) => return Err(LoginError::RequestError(e)),
+                            Err(_) => return Err(LoginError::KeyGen),
+                        },
+                    }
+                } else {
+                    None
+                };
+                match self.net_client.login(id, password, proof).await {
+                    Ok(v) => v,
+                    Err(e) => return Err(LoginError::RequestError(e)),
+                }
+            }
+        };
         let user = SessionUser::new(user.id, user.name, puk, prk);
         self.current_user = Some(user);
+        self.publish_private_keys();
         Ok(())
     }
 
     pub async fn connect_database(&mut self, user_id: &str, password: &str) -> anyhow::Result<()> {
         let db = Database::new(&format!("sqlite://{}.db", user_id), password).await?;
         self.db = Some(Arc::new(db.clone()));
+        self.load_retired_keys(&db).await?;
         self.sync_database(&db).await?;
         Ok(())
     }
 
+    async fn load_retired_keys(&mut self, db: &Database) -> anyhow::Result<()> {
+        let mut retired_keys = Vec::new();
+        for key in db.get_retired_keys().await? {
+            retired_keys.push(RsaPrivateKey::from_pkcs8_der(&key)?);
+        }
+        if let Some(user) = &mut self.current_user {
+            user.retired_keys = retired_keys;
+        }
+        self.publish_private_keys();
+        Ok(())
+    }
+
+    fn publish_private_keys(&self) {
+        if let Some(user) = &self.current_user {
+            self.private_keys.send_replace(user.private_keys());
+        }
+    }
+
     pub async fn get_last_message_id(&self) -> i64 {
         let mut last_id = -1;
         for (_, messages) in &self.messages {
@@ -167,74 +331,57 @@ impl App {
             self.messages.insert(c.id.clone(), messages);
             self.contacts.push(c);
         }
+        for reaction in db.get_all_reactions().await? {
+            self.reactions
+                .entry(reaction.chat_id.clone())
+                .or_default()
+                .push(reaction);
+        }
         let last_sent_id = db
             .get_last_sent_message_id(&self.current_user.as_ref().unwrap().id)
             .await?;
         let last_recv_id = db
             .get_last_received_message_id(&self.current_user.as_ref().unwrap().id)
             .await?;
+        // a fresh device starts from the newest messages and pages back per conversation
+        let limit = (last_recv_id < 0 && last_sent_id < 0).then_some(RECENT_SYNC_LIMIT);
         let messages = self
             .net_client
-            .get_messages(last_recv_id, Some(last_sent_id))
+            .get_messages(last_recv_id, Some(last_sent_id), limit)
             .await?;
         log::debug!(
             "Received sync messages: inbound: {:?} outbout: {:?}",
             messages.inbound,
             messages.outbound
         );
+        let private_keys = self.current_user.as_ref().unwrap().private_keys();
         for inbound in messages.inbound {
             let id = inbound.id;
-            let secret_key = inbound.secret_key;
-            let secret_key = cryptolib::decrypt_key_with_priv_key(
-                &secret_key,
-                &self.current_user.as_ref().unwrap().private_key,
-            )?;
-            let message_data = match MessageData::decrypt(&inbound.contents, &secret_key) {
-                Ok(v) => v,
-                Err(_) => {
-                    log::warn!("Received tampered inbound message in sync!");
-                    continue;
-                }
-            };
+            let (message_data, secret_key) =
+                match MessageData::open(&inbound.contents, &inbound.secret_key, &private_keys) {
+                    Ok(v) => v,
+                    Err(_) => {
+                        log::warn!("Received tampered inbound message in sync!");
+                        continue;
+                    }
+                };
             let message = db
-                .create_message(
-                    &message_data.sender_istid,
-                    &message_data.receiver_istid,
-                    &message_data.timestamp,
-                    &message_data.content,
-                    &secret_key,
-                    message_data.receive_counter,
-                    message_data.sent_counter,
-                    id,
-                )
+                .create_message(&message_data, &secret_key, id, inbound.revision)
                 .await?;
             self.add_message(message_data.sender_istid, message).await?;
         }
         for outbound in messages.outbound {
             let id = outbound.id;
-            let secret_key = outbound.secret_key;
-            let secret_key = cryptolib::decrypt_key_with_priv_key(
-                &secret_key,
-                &self.current_user.as_ref().unwrap().private_key,
-            )?;
-            let message_data = match MessageData::decrypt(&outbound.contents, &secret_key) {
-                Ok(v) => v,
-                Err(_) => {
-                    log::warn!("Received tampered outbount message in sync!");
-                    continue;
-                }
-            };
+            let (message_data, secret_key) =
+                match MessageData::open(&outbound.contents, &outbound.secret_key, &private_keys) {
+                    Ok(v) => v,
+                    Err(_) => {
+                        log::warn!("Received tampered outbount message in sync!");
+                        continue;
+                    }
+                };
             let message = db
-                .create_message(
-                    &message_data.sender_istid,
-                    &message_data.receiver_istid,
-                    &message_data.timestamp,
-                    &message_data.content,
-                    &secret_key,
-                    message_data.receive_counter,
-                    message_data.sent_counter,
-                    id,
-                )
+                .create_message(&message_data, &secret_key, id, outbound.revision)
                 .await?;
             self.add_message(message_data.receiver_istid, message)
                 .await?;
@@ -242,6 +389,129 @@ impl App {
         Ok(())
     }
 
+    // pages one conversation back from the oldest message held locally, once per tag it had
+    pub async fn load_older_messages(&mut self, chat_id: &str) -> anyhow::Result<()> {
+        if self.groups.contains_key(chat_id) || self.history_exhausted.contains(chat_id) {
+            return Ok(());
+        }
+        let db = self.db.clone().expect("No DB");
+        let my_id = self
+            .current_user
+            .as_ref()
+            .expect("No current user")
+            .id
+            .clone();
+        let keys = db.get_conversation_keys(chat_id).await?;
+        if keys.is_empty() {
+            return self.load_older_mailbox(chat_id).await;
+        }
+        let (mut fetched, mut stored) = (0, 0);
+        for key in keys {
+            let tag = conversation_tag(&key, &my_id, chat_id);
+            let (before, out_before) = db.get_oldest_server_ids(&my_id, chat_id, &key).await?;
+            let page = self
+                .net_client
+                .get_conversation_messages(&tag, before, out_before, HISTORY_PAGE)
+                .await?;
+            fetched += page.inbound.len() + page.outbound.len();
+            stored += self.store_older_messages(chat_id, page, false).await?;
+        }
+        if fetched == 0 {
+            self.history_exhausted.insert(chat_id.to_string());
+        }
+        log::info!("Loaded {stored} older messages of {chat_id}");
+        Ok(())
+    }
+
+    // a chat with no key held locally has all its messages before the recent sync, so this
+    // pages back through every conversation until some of them turn up
+    async fn load_older_mailbox(&mut self, chat_id: &str) -> anyhow::Result<()> {
+        loop {
+            let (before, out_before) = self.mailbox_cursor;
+            let page = self
+                .net_client
+                .get_message_history(before, out_before, HISTORY_PAGE)
+                .await?;
+            if page.inbound.is_empty() && page.outbound.is_empty() {
+                self.history_exhausted.insert(chat_id.to_string());
+                return Ok(());
+            }
+            self.mailbox_cursor = (
+                page.inbound.first().map(|m| m.id).or(before),
+                page.outbound.first().map(|m| m.id).or(out_before),
+            );
+            let stored = self.store_older_messages(chat_id, page, true).await?;
+            if stored > 0 {
+                log::info!("Loaded {stored} older messages of {chat_id}");
+                return Ok(());
+            }
+        }
+    }
+
+    // stores what is not held yet, returns how many of them belong to the chat
+    async fn store_older_messages(
+        &mut self,
+        chat_id: &str,
+        page: ResponseGetMessage,
+        any_chat: bool,
+    ) -> anyhow::Result<usize> {
+        let db = self.db.clone().expect("No DB");
+        let user = self.current_user.as_ref().expect("No current user");
+        let (my_id, private_keys) = (user.id.clone(), user.private_keys());
+        let inbound = page.inbound.into_iter().map(|m| (m, true));
+        let outbound = page.outbound.into_iter().map(|m| (m, false));
+        let mut stored = 0;
+        for (message, received) in inbound.chain(outbound) {
+            let duplicate = match received {
+                true => db.has_received_message(&my_id, message.id).await?,
+                false => db.has_sent_message(&my_id, message.id).await?,
+            };
+            if duplicate {
+                continue;
+            }
+            let Ok((data, secret_key)) =
+                MessageData::open(&message.contents, &message.secret_key, &private_keys)
+            else {
+                log::warn!("Received tampered message in history of {chat_id}!");
+                continue;
+            };
+            let other = match received {
+                true => data.sender_istid.clone(),
+                false => data.receiver_istid.clone(),
+            };
+            if other != chat_id && !any_chat {
+                log::warn!("Received a message of another chat in history of {chat_id}!");
+                continue;
+            }
+            let message = db
+                .create_message(&data, &secret_key, message.id, message.revision)
+                .await?;
+            self.add_older_message(&other, message).await?;
+            if other == chat_id {
+                stored += 1;
+            }
+        }
+        Ok(stored)
+    }
+
+    // older messages may arrive after the revisions and reactions that supersede them
+    async fn add_older_message(
+        &mut self,
+        chat_id: &str,
+        message: StoredMessage,
+    ) -> anyhow::Result<()> {
+        let db = self.db.clone().expect("No DB");
+        if message.kind == MessageKind::Reaction && db.has_newer_reaction(&message).await? {
+            return Ok(());
+        }
+        let revisions = db.get_revisions_of(&message).await?;
+        self.add_message(chat_id.to_string(), message).await?;
+        for revision in revisions {
+            self.apply_revision(&revision).await?;
+        }
+        Ok(())
+    }
+
     pub async fn add_contact(
         &mut self,
         id: &str,
@@ -251,7 +521,9 @@ impl App {
         match &self.db {
             Some(db) => {
                 let contact = db.create_contact(id, name, public_key).await?;
-                self.messages.insert(contact.id.clone(), vec![]);
+                let messages = self.requests.remove(id).unwrap_or_default();
+                self.messages.insert(contact.id.clone(), messages);
+                self.subscribe_presence(vec![contact.id.clone()]).await;
                 self.contacts.push(contact);
                 Ok(())
             }
@@ -264,41 +536,227 @@ impl App {
         contact_id: String,
         message: StoredMessage,
     ) -> anyhow::Result<()> {
+        if message.revision().is_some() {
+            return self.apply_revision(&message).await;
+        }
+        if message.kind == MessageKind::Reaction {
+            return self.apply_reaction(&contact_id, &message).await;
+        }
+        let has_contact = self.contacts.iter().any(|c| c.id == contact_id);
+        let chats = match has_contact {
+            true => &mut self.messages,
+            false => &mut self.requests,
+        };
+        chats.entry(contact_id).or_insert(Vec::new()).push(message);
+        Ok(())
+    }
+
+    pub async fn add_group_message(&mut self, message: StoredMessage) -> anyhow::Result<()> {
+        if message.revision().is_some() {
+            return self.apply_revision(&message).await;
+        }
+        if message.kind == MessageKind::Reaction {
+            return self
+                .apply_reaction(&message.receiver_istid.clone(), &message)
+                .await;
+        }
         self.messages
-            .entry(contact_id.clone())
-            .or_insert(Vec::new())
+            .entry(message.receiver_istid.clone())
+            .or_default()
             .push(message);
-        let has_contact = self.contacts.iter().any(|c| c.id == contact_id);
-        if !has_contact {
-            match self.net_client.get_user(&contact_id).await {
-                Ok(contact) => {
-                    let contact = self
-                        .db
-                        .as_ref()
-                        .unwrap()
-                        .create_contact(&contact.id, &contact.name, &contact.public_key)
-                        .await?;
-                    self.contacts.push(contact);
-                }
-                Err(e) => log::error!("Failed to get contact: {e}"),
-            }
+        Ok(())
+    }
+
+    // revisions are not shown themselves, they rewrite the message they refer to
+    async fn apply_revision(&mut self, revision: &StoredMessage) -> anyhow::Result<()> {
+        let db = self.db.as_ref().expect("No DB");
+        let Some(revised) = db.apply_revision(revision).await? else {
+            log::warn!("Revised message {:?} is not stored", revision.revision());
+            return Ok(());
+        };
+        let message = self
+            .messages
+            .values_mut()
+            .chain(self.requests.values_mut())
+            .flatten()
+            .find(|m| m.id == revised.id);
+        if let Some(message) = message {
+            *message = revised;
         }
         Ok(())
     }
 
+    async fn apply_reaction(
+        &mut self,
+        chat_id: &str,
+        reaction: &StoredMessage,
+    ) -> anyhow::Result<()> {
+        let db = self.db.as_ref().expect("No DB");
+        let stored = db.set_reaction(chat_id, reaction).await?;
+        let Some(target) = reaction.reference() else {
+            return Ok(());
+        };
+        let reactions = self.reactions.entry(chat_id.to_string()).or_default();
+        reactions.retain(|r| {
+            r.sender != reaction.sender_istid
+                || r.target_sender != target.sender
+                || r.target_counter != target.counter
+        });
+        reactions.extend(stored);
+        Ok(())
+    }
+
     pub async fn send_message(&mut self, contact: &Contact, content: &str) -> anyhow::Result<()> {
+        self.deliver_message(contact, Draft::new(content)).await
+    }
+
+    pub async fn edit_last_message(&mut self, chat_id: &str, content: &str) -> anyhow::Result<()> {
+        let target = self.last_own_message(chat_id)?;
+        self.revise_message(chat_id, content, Revision::Edit(target))
+            .await
+    }
+
+    pub async fn delete_last_message(&mut self, chat_id: &str) -> anyhow::Result<()> {
+        let target = self.last_own_message(chat_id)?;
+        self.revise_message(chat_id, "", Revision::Delete(target))
+            .await
+    }
+
+    fn last_own_message(&self, chat_id: &str) -> anyhow::Result<i64> {
+        let my_id = &self.current_user.as_ref().expect("No current user").id;
+        self.messages
+            .get(chat_id)
+            .and_then(|messages| {
+                messages
+                    .iter()
+                    .filter(|m| {
+                        m.sender_istid == *my_id
+                            && m.kind == MessageKind::Message
+                            && m.server_id >= 0
+                            && !m.deleted
+                    })
+                    .max_by_key(|m| m.id)
+            })
+            .map(|m| m.server_id)
+            .ok_or_else(|| anyhow::Error::msg("No message of yours to change"))
+    }
+
+    async fn revise_message(
+        &mut self,
+        chat_id: &str,
+        content: &str,
+        revision: Revision,
+    ) -> anyhow::Result<()> {
+        let draft = Draft {
+            revision: Some(revision),
+            ..Draft::new(content)
+        };
+        self.deliver(chat_id, draft).await
+    }
+
+    pub async fn reply(
+        &mut self,
+        chat_id: &str,
+        content: &str,
+        reply_to: MessageRef,
+    ) -> anyhow::Result<()> {
+        let draft = Draft {
+            reply_to: Some(reply_to),
+            ..Draft::new(content)
+        };
+        self.deliver(chat_id, draft).await
+    }
+
+    pub async fn react(
+        &mut self,
+        chat_id: &str,
+        target: MessageRef,
+        emoji: &str,
+    ) -> anyhow::Result<()> {
+        let my_id = &self.current_user.as_ref().expect("No current user").id;
+        // reacting again with the same emoji takes the reaction back
+        let current = self.reactions.get(chat_id).and_then(|reactions| {
+            reactions.iter().find(|r| {
+                r.sender == *my_id
+                    && r.target_sender == target.sender
+                    && r.target_counter == target.counter
+            })
+        });
+        let emoji = match current {
+            Some(reaction) if reaction.emoji == emoji => "",
+            _ => emoji,
+        };
+        let draft = Draft {
+            reacts_to: Some(target),
+            ..Draft::new(emoji)
+        };
+        self.deliver(chat_id, draft).await
+    }
+
+    async fn deliver(&mut self, chat_id: &str, draft: Draft) -> anyhow::Result<()> {
+        match self.chat_contact(chat_id)? {
+            Some(contact) => self.deliver_message(&contact, draft).await,
+            None => self.deliver_group_message(chat_id, draft).await,
+        }
+    }
+
+    // the contact a chat is held with, or none for group chats
+    fn chat_contact(&self, chat_id: &str) -> anyhow::Result<Option<Contact>> {
+        if self.groups.contains_key(chat_id) {
+            return Ok(None);
+        }
+        self.contacts
+            .iter()
+            .find(|c| c.id == chat_id)
+            .cloned()
+            .map(Some)
+            .ok_or_else(|| anyhow::Error::msg("Unknown chat"))
+    }
+
+    fn compose(&self, sender: &str, chat_id: &str, draft: &Draft) -> MessageData {
+        let (sent_counter, receive_counter) = self.last_counters(chat_id);
+        let sent_counter = match draft.takes_place() {
+            true => sent_counter + 1,
+            false => sent_counter,
+        };
+        MessageData::new(
+            sender,
+            chat_id,
+            &draft.content,
+            receive_counter,
+            sent_counter,
+        )
+        .attachment(draft.attachment.clone())
+        .expires_at(self.expiry_for(chat_id, draft.timer))
+        .timer(draft.timer)
+        .reply_to(draft.reply_to.clone())
+        .reacts_to(draft.reacts_to.clone())
+    }
+
+    async fn deliver_message(&mut self, contact: &Contact, draft: Draft) -> anyhow::Result<()> {
+        if contact.deleted {
+            return Err(ContactDeleted(contact.name.clone()).into());
+        }
+        let trusted = match self.check_contact_key(&contact.id).await {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("Failed to check key of {}: {e}", contact.id);
+                contact.pending_key.is_none()
+            }
+        };
+        if !trusted {
+            return Err(ContactKeyChanged(contact.name.clone()).into());
+        }
+        let conversation_key = self.conversation_key(&contact.id).await?;
         let curr_user = self
             .current_user
             .as_ref()
             .expect("No current user when sending message");
-        let (sent_counter, receive_counter) = self.last_counters(&contact.id);
-        let message_data = MessageData::new(
-            &curr_user.id,
-            &contact.id,
-            content,
-            receive_counter,
-            sent_counter + 1,
-        );
+        let conversation = conversation_tag(&conversation_key, &curr_user.id, &contact.id);
+        let message_data = self
+            .compose(&curr_user.id, &contact.id, &draft)
+            .conversation_key(Some(conversation_key));
+        let blobs = blob_ids(&message_data);
         let secret_key = cryptolib::generate_secret_key();
         let encrypt = MessageData::encrypt(&message_data, &secret_key)?;
         let my_secret_key =
@@ -307,10 +765,13 @@ impl App {
         let recipient_secret_key = cryptolib::encrypt_key_with_pub_key(&secret_key, &contact_puk)?;
 
         log::info!("Sending message");
-        let message = self
-            .net_client
-            .send_message(&contact.id, &encrypt, &my_secret_key, &recipient_secret_key)
-            .await?;
+        let request =
+            SendMessage::new(&contact.id, &encrypt, &my_secret_key, &recipient_secret_key)
+                .attachments(&blobs)
+                .expires_at(message_data.expires_at)
+                .revision(draft.revision)
+                .conversation(Some(conversation));
+        let message = self.net_client.send_message(&request).await?;
         log::info!("Message sent {:?}", message);
         let message_data = MessageData::decrypt(&message.contents, &secret_key)?;
         log::info!("Message decrypted: {:?}", message_data);
@@ -318,22 +779,800 @@ impl App {
             .db
             .as_ref()
             .expect("No DB")
-            .create_message(
-                &message_data.sender_istid,
-                &message_data.receiver_istid,
-                &message_data.timestamp,
-                &message_data.content,
-                &secret_key,
-                message_data.receive_counter,
-                message_data.sent_counter,
-                message.id,
-            )
+            .create_message(&message_data, &secret_key, message.id, message.revision)
             .await?;
         log::info!("Message stored: {:?}", stored_message);
+        self.add_message(contact.id.clone(), stored_message).await
+    }
+
+    // the first message of a conversation picks the key, later ones keep the latest seen, a
+    // device without any of the chat's messages looks for the key in older history first
+    async fn conversation_key(&mut self, contact_id: &str) -> anyhow::Result<Vec<u8>> {
+        let db = self.db.clone().expect("No DB");
+        if db.get_conversation_key(contact_id).await?.is_none() {
+            self.load_older_messages(contact_id).await?;
+        }
+        let key = db.get_conversation_key(contact_id).await?;
+        Ok(key.unwrap_or_else(cryptolib::generate_secret_key))
+    }
+
+    pub async fn load_presence_settings(&mut self) {
+        match self.net_client.get_presence_settings().await {
+            Ok(settings) => self.presence_hidden = settings.hidden,
+            Err(e) => log::error!("Failed to get presence settings: {e}"),
+        }
+    }
+
+    pub async fn subscribe_presence(&mut self, users: Vec<String>) {
+        match self.net_client.subscribe_presence(&users).await {
+            Ok(presences) => {
+                for presence in presences {
+                    self.presence.insert(presence.id.clone(), presence);
+                }
+            }
+            Err(e) => log::error!("Failed to subscribe to presence updates: {e}"),
+        }
+    }
+
+    pub async fn set_presence_hidden(&mut self, hidden: bool) -> anyhow::Result<()> {
+        self.net_client.set_presence_hidden(hidden).await?;
+        self.presence_hidden = hidden;
+        Ok(())
+    }
+
+    pub async fn load_blocks(&mut self) {
+        match self.net_client.get_blocks().await {
+            Ok(blocks) => self.blocked = blocks.into_iter().map(|b| b.id).collect(),
+            Err(e) => log::error!("Failed to get blocked users: {e}"),
+        }
+    }
+
+    pub async fn toggle_block(&mut self, contact_id: &str) -> anyhow::Result<()> {
+        if self.blocked.contains(contact_id) {
+            self.net_client.unblock_user(contact_id).await?;
+            self.blocked.remove(contact_id);
+            log::info!("Unblocked {}", contact_id);
+        } else {
+            self.net_client.block_user(contact_id).await?;
+            self.blocked.insert(contact_id.to_string());
+            log::info!("Blocked {}", contact_id);
+        }
+        Ok(())
+    }
+
+    pub async fn load_requests(&mut self) {
+        let requests = match self.net_client.get_requests().await {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("Failed to get contact requests: {e}");
+                return;
+            }
+        };
+        let db = self.db.as_ref().expect("No DB").clone();
+        self.requests.clear();
+        for request in requests {
+            if self.contacts.iter().any(|c| c.id == request.sender) {
+                continue;
+            }
+            match db.get_messages_with(&request.sender).await {
+                Ok(messages) => {
+                    self.requests.insert(request.sender, messages);
+                }
+                Err(e) => log::error!("Failed to load request from {}: {e}", request.sender),
+            }
+        }
+    }
+
+    pub async fn accept_request(&mut self, sender_id: &str) -> anyhow::Result<()> {
+        self.net_client.accept_request(sender_id).await?;
+        let user = self.net_client.get_user(sender_id).await?;
+        self.add_contact(&user.id, &user.name, &user.public_key)
+            .await?;
+        log::info!("Accepted contact request from {}", sender_id);
+        Ok(())
+    }
+
+    pub async fn decline_request(&mut self, sender_id: &str, block: bool) -> anyhow::Result<()> {
+        if block {
+            self.net_client.block_user(sender_id).await?;
+            self.blocked.insert(sender_id.to_string());
+        }
+        self.net_client.decline_request(sender_id).await?;
+        self.db
+            .as_ref()
+            .expect("No DB")
+            .delete_messages_with(sender_id)
+            .await?;
+        self.requests.remove(sender_id);
+        self.reactions.remove(sender_id);
+        log::info!("Declined contact request from {}", sender_id);
+        Ok(())
+    }
+
+    pub async fn load_groups(&mut self) {
+        let groups = match self.net_client.get_groups().await {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("Failed to get groups: {e}");
+                return;
+            }
+        };
+        let db = self.db.as_ref().expect("No DB").clone();
+        for group in groups {
+            let chat_id = group_chat_id(group.id);
+            match db.get_messages_with(&chat_id).await {
+                Ok(messages) => {
+                    self.messages.insert(chat_id.clone(), messages);
+                }
+                Err(e) => log::error!("Failed to load messages of group {}: {e}", group.id),
+            }
+            self.groups.insert(chat_id, group);
+        }
+        if let Err(e) = self.sync_group_messages(&db).await {
+            log::error!("Failed to sync group messages: {e}");
+        }
+    }
+
+    async fn sync_group_messages(&mut self, db: &Database) -> anyhow::Result<()> {
+        let last_id = db.get_last_group_message_id().await?;
+        let private_keys = self.current_user.as_ref().unwrap().private_keys();
+        for message in self.net_client.get_group_messages(last_id).await? {
+            let (data, secret_key) = match MessageData::open_group(&message, &private_keys) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::warn!("Received invalid group message in sync: {e}");
+                    continue;
+                }
+            };
+            let stored = db
+                .create_group_message(
+                    &data,
+                    &secret_key,
+                    message.id,
+                    message.group_id,
+                    message.revision,
+                )
+                .await?;
+            self.add_group_message(stored).await?;
+        }
+        Ok(())
+    }
+
+    pub fn update_group(&mut self, group: Group) {
+        let chat_id = group_chat_id(group.id);
+        self.messages.entry(chat_id.clone()).or_default();
+        self.groups.insert(chat_id, group);
+    }
+
+    pub async fn refresh_group(&mut self, group_id: i64) -> anyhow::Result<()> {
+        let group = self.net_client.get_group(group_id).await?;
+        self.update_group(group);
+        Ok(())
+    }
+
+    pub async fn create_group(&mut self, name: &str, members: &[String]) -> anyhow::Result<()> {
+        let group = self.net_client.create_group(name, members).await?;
+        log::info!("Created group {}", group.id);
+        self.update_group(group);
+        Ok(())
+    }
+
+    pub async fn handle_group_removed(&mut self, group_id: i64) -> anyhow::Result<()> {
+        let chat_id = group_chat_id(group_id);
+        let Some(group) = self.groups.get_mut(&chat_id) else {
+            return Ok(());
+        };
+        if group.members.is_empty() {
+            return Ok(());
+        }
+        group.members.clear();
+        group.secret_key = None;
+        self.record_system_message(&chat_id, "You are no longer a member of this group")
+            .await
+    }
+
+    fn group_id(&self, chat_id: &str) -> anyhow::Result<i64> {
+        let group = self.groups.get(chat_id).ok_or(NotGroupMember)?;
+        let my_id = &self.current_user.as_ref().expect("No current user").id;
+        if !group.members.iter().any(|m| m.id == *my_id) {
+            return Err(NotGroupMember.into());
+        }
+        Ok(group.id)
+    }
+
+    pub async fn invite_to_group(&mut self, chat_id: &str, user_id: &str) -> anyhow::Result<()> {
+        let group_id = self.group_id(chat_id)?;
+        self.net_client
+            .invite_group_member(group_id, user_id)
+            .await?;
+        self.refresh_group(group_id).await
+    }
+
+    pub async fn remove_from_group(&mut self, chat_id: &str, user_id: &str) -> anyhow::Result<()> {
+        let group_id = self.group_id(chat_id)?;
+        self.net_client
+            .remove_group_member(group_id, user_id)
+            .await?;
+        match self.current_user.as_ref().is_some_and(|u| u.id == user_id) {
+            true => self.handle_group_removed(group_id).await,
+            false => self.refresh_group(group_id).await,
+        }
+    }
+
+    pub async fn set_group_admin(
+        &mut self,
+        chat_id: &str,
+        user_id: &str,
+        admin: bool,
+    ) -> anyhow::Result<()> {
+        let group_id = self.group_id(chat_id)?;
+        self.net_client
+            .set_group_admin(group_id, user_id, admin)
+            .await?;
+        self.refresh_group(group_id).await
+    }
+
+    async fn group_key(&mut self, chat_id: &str) -> anyhow::Result<(i64, i64, Vec<u8>)> {
+        let private_keys = self.current_user.as_ref().unwrap().private_keys();
+        for _ in 0..GROUP_KEY_ATTEMPTS {
+            let group_id = self.group_id(chat_id)?;
+            let group = self.groups[chat_id].clone();
+            if let Some(wrapped) = &group.secret_key {
+                for private_key in &private_keys {
+                    if let Ok(key) = cryptolib::decrypt_key_with_priv_key(wrapped, private_key) {
+                        return Ok((group_id, group.epoch, key));
+                    }
+                }
+                return Err(anyhow::Error::msg(
+                    "No private key could open the group key",
+                ));
+            }
+            // the first member to write after a membership change picks the next key
+            let key = cryptolib::generate_secret_key();
+            let mut keys = Vec::with_capacity(group.members.len());
+            for member in &group.members {
+                let public_key = self
+                    .contacts
+                    .iter()
+                    .find(|c| c.id == member.id)
+                    .map(|c| c.public_key.as_slice())
+                    .unwrap_or(&member.public_key);
+                let public_key = cryptolib::utils::public_key_from_bytes(public_key)?;
+                let wrapped = cryptolib::encrypt_key_with_pub_key(&key, &public_key)?;
+                keys.push(GroupKey::new(&member.id, &wrapped));
+            }
+            let request = DistributeGroupKey::new(group.epoch, keys);
+            match self
+                .net_client
+                .distribute_group_key(group_id, &request)
+                .await
+            {
+                Ok(()) => {
+                    log::info!("Distributed key {} of group {}", group.epoch, group_id);
+                    return Ok((group_id, group.epoch, key));
+                }
+                Err(e) if is_stale_group_key(&e) => self.refresh_group(group_id).await?,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(anyhow::Error::msg("The group key kept changing, try again"))
+    }
+
+    pub async fn send_group_message(&mut self, chat_id: &str, content: &str) -> anyhow::Result<()> {
+        self.deliver_group_message(chat_id, Draft::new(content))
+            .await
+    }
+
+    async fn deliver_group_message(&mut self, chat_id: &str, draft: Draft) -> anyhow::Result<()> {
+        let my_id = self
+            .current_user
+            .as_ref()
+            .expect("No current user")
+            .id
+            .clone();
+        for _ in 0..GROUP_KEY_ATTEMPTS {
+            let (group_id, epoch, secret_key) = self.group_key(chat_id).await?;
+            let message_data = self.compose(&my_id, chat_id, &draft);
+            let encrypted = message_data.encrypt(&secret_key)?;
+            let message = match self
+                .net_client
+                .send_group_message(
+                    group_id,
+                    epoch,
+                    &encrypted,
+                    &blob_ids(&message_data),
+                    message_data.expires_at,
+                    draft.revision,
+                )
+                .await
+            {
+                Ok(v) => v,
+                Err(e) if is_stale_group_key(&e) => {
+                    self.refresh_group(group_id).await?;
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let stored = self
+                .db
+                .as_ref()
+                .expect("No DB")
+                .create_group_message(
+                    &message_data,
+                    &secret_key,
+                    message.id,
+                    group_id,
+                    message.revision,
+                )
+                .await?;
+            return self.add_group_message(stored).await;
+        }
+        Err(anyhow::Error::msg("The group key kept changing, try again"))
+    }
+
+    pub async fn send_attachment(&mut self, chat_id: &str, path: &str) -> anyhow::Result<()> {
+        self.chat_contact(chat_id)?;
+        let path = Path::new(path);
+        // refuse oversized files before reading, encrypting and uploading them
+        let size = tokio::fs::metadata(path).await?.len();
+        if size > MAX_FILE_SIZE {
+            return Err(anyhow::Error::msg(format!(
+                "Cannot attach files larger than {}",
+                describe_size(MAX_FILE_SIZE)
+            )));
+        }
+        let data = tokio::fs::read(path).await?;
+        if data.is_empty() {
+            return Err(anyhow::Error::msg("Cannot attach an empty file"));
+        }
+        let key = cryptolib::generate_secret_key();
+        let blob = self
+            .net_client
+            .upload_blob(Attachment::encrypt(&data, &key)?)
+            .await?;
+        log::info!("Uploaded attachment {} ({} bytes)", blob.id, blob.size);
+        let attachment = Attachment::new(&blob.id, path, &data, &key);
+        let attachment_name = attachment.file_name();
+        let draft = Draft {
+            attachment: Some(attachment),
+            ..Draft::new(&attachment_name)
+        };
+        self.deliver(chat_id, draft).await
+    }
+
+    pub async fn save_attachment(
+        &mut self,
+        chat_id: &str,
+        dir: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let attachment = self
+            .messages
+            .get(chat_id)
+            .and_then(|messages| messages.iter().rev().find_map(|m| m.attachment()))
+            .ok_or_else(|| anyhow::Error::msg("There is no attachment in this chat"))?;
+        let data = self.net_client.download_blob(&attachment.blob_id).await?;
+        let plaintext = attachment.decrypt(&data)?;
+        let dir = Path::new(dir.unwrap_or(DOWNLOADS_DIR));
+        tokio::fs::create_dir_all(dir).await?;
+        let path = free_path(dir, &attachment.file_name());
+        tokio::fs::write(&path, plaintext).await?;
+        log::info!(
+            "Saved attachment {} to {}",
+            attachment.blob_id,
+            path.display()
+        );
+        self.record_system_message(chat_id, &format!("Saved to {}", path.display()))
+            .await
+    }
+
+    pub fn chat_timer(&self, chat_id: &str) -> i64 {
+        self.messages
+            .get(chat_id)
+            .and_then(|messages| {
+                messages
+                    .iter()
+                    .filter(|m| m.timer.is_some())
+                    .max_by(|a, b| a.timestamp.cmp(&b.timestamp))
+            })
+            .and_then(|m| m.timer)
+            .unwrap_or_default()
+    }
+
+    // timer changes never expire, so both sides keep agreeing on the current timer, and a
+    // peer's timer is capped to what the server accepts
+    fn expiry_for(&self, chat_id: &str, timer: Option<i64>) -> Option<i64> {
+        let seconds = self.chat_timer(chat_id).min(MAX_MESSAGE_TIMER);
+        match timer.is_none() && seconds > 0 {
+            true => Some(chrono::Utc::now().timestamp() + seconds),
+            false => None,
+        }
+    }
+
+    pub async fn set_chat_timer(&mut self, chat_id: &str, seconds: i64) -> anyhow::Result<()> {
+        let content = match seconds {
+            0 => "Disappearing messages turned off".to_string(),
+            s => format!("Disappearing messages set to {}", describe_timer(s)),
+        };
+        let draft = Draft {
+            timer: Some(seconds),
+            ..Draft::new(&content)
+        };
+        self.deliver(chat_id, draft).await
+    }
+
+    pub async fn expire_messages_if_due(&mut self) {
+        if self.db.is_none() || self.last_expiry_check.elapsed() < EXPIRY_CHECK_INTERVAL {
+            return;
+        }
+        self.last_expiry_check = Instant::now();
+        let now = chrono::Utc::now().timestamp();
+        match self.db.as_ref().unwrap().delete_expired_messages(now).await {
+            Ok(0) => (),
+            Ok(n) => log::info!("Deleted {n} expired messages"),
+            Err(e) => {
+                log::error!("Failed to delete expired messages: {e}");
+                return;
+            }
+        }
+        for chats in [&mut self.messages, &mut self.requests] {
+            for messages in chats.values_mut() {
+                messages.retain(|m| m.expires_at.is_none_or(|e| e > now));
+            }
+        }
+    }
+
+    pub fn chat_ids(&self) -> impl Iterator<Item = &String> {
         self.messages
-            .entry(contact.id.clone())
-            .or_insert(Vec::new())
-            .push(stored_message);
+            .keys()
+            .filter(|id| !self.blocked.contains(*id))
+    }
+
+    pub async fn load_sessions(&mut self) {
+        match self.net_client.get_sessions().await {
+            Ok(sessions) => self.sessions = sessions,
+            Err(e) => log::error!("Failed to get sessions: {e}"),
+        }
+    }
+
+    pub async fn revoke_other_sessions(&mut self) -> anyhow::Result<()> {
+        self.net_client.revoke_other_sessions().await?;
+        self.load_sessions().await;
+        Ok(())
+    }
+
+    pub async fn update_name(&mut self, name: &str) -> anyhow::Result<()> {
+        let user = self.net_client.update_profile(name).await?;
+        if let Some(current_user) = &mut self.current_user {
+            current_user.name = user.name;
+        }
+        Ok(())
+    }
+
+    pub async fn change_password(
+        &mut self,
+        current_password: &str,
+        new_password: &str,
+    ) -> anyhow::Result<()> {
+        let db = self
+            .db
+            .as_ref()
+            .expect("Change password without DB")
+            .clone();
+        db.change_key(new_password).await?;
+        if let Err(e) = self
+            .net_client
+            .change_password(current_password, new_password)
+            .await
+        {
+            db.change_key(current_password).await?;
+            return Err(e);
+        }
+        log::info!("Changed account password");
+        self.load_sessions().await;
+        Ok(())
+    }
+
+    pub async fn delete_account(&mut self, password: &str) -> anyhow::Result<()> {
+        let user_id = self
+            .current_user
+            .as_ref()
+            .expect("No current user when deleting account")
+            .id
+            .clone();
+        self.net_client.delete_account(password).await?;
+        log::info!("Deleted account {}", user_id);
+        if let Some(db) = self.db.take() {
+            db.close().await;
+        }
+        for extension in ["db", "priv", "pub", "priv.new", "pub.new"] {
+            let path = format!("{}.{}", user_id, extension);
+            match tokio::fs::remove_file(&path).await {
+                Ok(_) => (),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => log::error!("Failed to remove {}: {e}", path),
+            }
+        }
+        self.current_user = None;
+        Ok(())
+    }
+
+    pub async fn mark_contact_deleted(&mut self, contact_id: &str) -> anyhow::Result<()> {
+        let Some(contact) = self.contacts.iter_mut().find(|c| c.id == contact_id) else {
+            return Ok(());
+        };
+        if contact.deleted {
+            return Ok(());
+        }
+        contact.deleted = true;
+        let content = format!("{} deleted their account", contact.name);
+        let db = self.db.as_ref().expect("No DB");
+        db.mark_contact_deleted(contact_id).await?;
+        self.record_system_message(contact_id, &content).await
+    }
+
+    pub async fn update_contact_name(
+        &mut self,
+        contact_id: &str,
+        name: &str,
+    ) -> anyhow::Result<()> {
+        let Some(contact) = self.contacts.iter_mut().find(|c| c.id == contact_id) else {
+            return Ok(());
+        };
+        if contact.name == name {
+            return Ok(());
+        }
+        contact.name = name.to_string();
+        let db = self.db.as_ref().expect("No DB");
+        db.update_contact_name(contact_id, name).await?;
+        Ok(())
+    }
+
+    pub async fn rotate_key(&mut self) -> anyhow::Result<()> {
+        let db = self.db.as_ref().expect("Rotate key without DB").clone();
+        let user = self
+            .current_user
+            .as_ref()
+            .expect("No current user when rotating key");
+        let private_key = RsaPrivateKey::new(&mut OsRng, 2048)?;
+        let public_key = RsaPublicKey::from(&private_key);
+        let public_key_bytes = cryptolib::utils::public_key_to_bytes(public_key.clone())?;
+        let mut inbound = Vec::new();
+        let mut outbound = Vec::new();
+        for message in db.get_all_stored_messages().await? {
+            let secret_key = cryptolib::encrypt_key_with_pub_key(&message.secret_key, &public_key)?;
+            let wrapped = WrappedKey::new(message.server_id, &secret_key);
+            if message.receiver_istid == user.id {
+                inbound.push(wrapped);
+            } else {
+                outbound.push(wrapped);
+            }
+        }
+        let challenge = self.net_client.request_rotate_key_challenge().await?;
+        let payload = Challenge::rotation_payload(&user.id, &challenge.nonce, &public_key_bytes);
+        let signature = cryptolib::sign(&payload, &user.private_key)?;
+        let proof = KeyProof::new(&challenge.nonce, &signature);
+
+        let prk_path = format!("{}.priv", user.id);
+        let puk_path = format!("{}.pub", user.id);
+        let new_prk_path = format!("{}.priv.new", user.id);
+        let new_puk_path = format!("{}.pub.new", user.id);
+        let prk_pem = private_key.to_pkcs8_pem(LineEnding::default())?;
+        let puk_pem = public_key.to_public_key_pem(LineEnding::default())?;
+        tokio::fs::write(&new_prk_path, prk_pem).await?;
+        tokio::fs::write(&new_puk_path, puk_pem).await?;
+        let retired_id = db
+            .add_retired_key(user.private_key.to_pkcs8_der()?.as_bytes())
+            .await?;
+
+        let request = RotateKey::new(&public_key_bytes, proof);
+        if let Err(e) = self.net_client.rotate_key(&request).await {
+            db.delete_retired_key(retired_id).await?;
+            let _ = tokio::fs::remove_file(&new_prk_path).await;
+            let _ = tokio::fs::remove_file(&new_puk_path).await;
+            return Err(e);
+        }
+        tokio::fs::rename(&new_prk_path, &prk_path).await?;
+        tokio::fs::rename(&new_puk_path, &puk_path).await?;
+        log::info!("Rotated identity key of {}", user.id);
+
+        let user = self.current_user.as_mut().unwrap();
+        let retired = std::mem::replace(&mut user.private_key, private_key);
+        user.retired_keys.insert(0, retired);
+        user.public_key = public_key;
+        self.publish_private_keys();
+
+        // keys left behind by a failed page stay readable with the retired key
+        let pages = inbound
+            .chunks(MAX_WRAPPED_KEYS)
+            .map(|keys| (keys, &[][..]))
+            .chain(
+                outbound
+                    .chunks(MAX_WRAPPED_KEYS)
+                    .map(|keys| (&[][..], keys)),
+            );
+        for (inbound, outbound) in pages {
+            let request = RewrapKeys::new(&challenge.nonce, inbound.to_vec(), outbound.to_vec());
+            self.net_client.rewrap_keys(&request).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn handle_key_change(&mut self, rotation: KeyRotation) -> anyhow::Result<()> {
+        let Some(contact) = self.contacts.iter().find(|c| c.id == rotation.id).cloned() else {
+            return Ok(());
+        };
+        let verified = contact.public_key == rotation.previous_key
+            && cryptolib::utils::public_key_from_bytes(&contact.public_key).is_ok_and(|key| {
+                cryptolib::verify_signature(&rotation.signed_payload(), &rotation.signature, &key)
+            });
+        if !verified {
+            log::warn!("Received unverifiable key change for {}", contact.id);
+            self.review_contact_key(&contact.id, &rotation.public_key)
+                .await?;
+            return Ok(());
+        }
+        let db = self.db.as_ref().expect("No DB");
+        db.update_contact_key(&contact.id, &rotation.public_key)
+            .await?;
+        if let Some(c) = self.contacts.iter_mut().find(|c| c.id == contact.id) {
+            c.public_key = rotation.public_key.clone();
+            c.pending_key = None;
+        }
+        let content = format!("{} changed their key", contact.name);
+        self.record_system_message(&contact.id, &content).await
+    }
+
+    pub async fn check_contact_key(&mut self, contact_id: &str) -> anyhow::Result<bool> {
+        let check = match self.net_client.get_user(contact_id).await {
+            Ok(v) => KeyCheck::Fetched(v),
+            Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => {
+                KeyCheck::Deleted(contact_id.to_string())
+            }
+            Err(e) => return Err(e.into()),
+        };
+        self.apply_key_check(check).await
+    }
+
+    async fn apply_key_check(&mut self, check: KeyCheck) -> anyhow::Result<bool> {
+        match check {
+            KeyCheck::Fetched(user) => {
+                self.update_contact_name(&user.id, &user.name).await?;
+                self.review_contact_key(&user.id, &user.public_key).await
+            }
+            KeyCheck::Deleted(contact_id) => {
+                self.mark_contact_deleted(&contact_id).await?;
+                Ok(false)
+            }
+        }
+    }
+
+    pub async fn review_contact_key(
+        &mut self,
+        contact_id: &str,
+        public_key: &[u8],
+    ) -> anyhow::Result<bool> {
+        let Some(contact) = self.contacts.iter().find(|c| c.id == contact_id).cloned() else {
+            return Ok(true);
+        };
+        let db = self.db.as_ref().expect("No DB").clone();
+        if contact.public_key == public_key {
+            if contact.pending_key.is_some() {
+                db.set_contact_pending_key(contact_id, None).await?;
+                self.set_pending_key(contact_id, None);
+            }
+            return Ok(true);
+        }
+        if contact.pending_key.as_deref() == Some(public_key) {
+            return Ok(false);
+        }
+        log::warn!("Public key of {} has changed!", contact_id);
+        db.set_contact_pending_key(contact_id, Some(public_key))
+            .await?;
+        self.set_pending_key(contact_id, Some(public_key.to_vec()));
+        let content = format!(
+            "Security notice: the key of {} has changed, sending is blocked until you accept it",
+            contact.name
+        );
+        self.record_system_message(contact_id, &content).await?;
+        Ok(false)
+    }
+
+    // the lookups run one at a time in the background, the results are applied as they arrive
+    pub fn check_contact_keys(&mut self) {
+        if self.key_checks.is_some() {
+            return;
+        }
+        self.last_key_check = Instant::now();
+        let ids: Vec<String> = self
+            .contacts
+            .iter()
+            .filter(|c| !c.deleted)
+            .map(|c| c.id.clone())
+            .collect();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.key_checks = Some(receiver);
+        let net_client = self.net_client.clone();
+        tokio::spawn(async move {
+            for id in ids {
+                let check = match net_client.get_user(&id).await {
+                    Ok(v) => Some(KeyCheck::Fetched(v)),
+                    Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => {
+                        Some(KeyCheck::Deleted(id))
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to check key of {}: {e}", id);
+                        None
+                    }
+                };
+                if let Some(check) = check {
+                    if sender.send(check).is_err() {
+                        return;
+                    }
+                }
+                tokio::time::sleep(KEY_CHECK_SPACING).await;
+            }
+        });
+    }
+
+    pub async fn check_contact_keys_if_due(&mut self) {
+        if self.db.is_none() {
+            return;
+        }
+        if self.last_key_check.elapsed() >= KEY_CHECK_INTERVAL {
+            self.check_contact_keys();
+        }
+        let Some(receiver) = &mut self.key_checks else {
+            return;
+        };
+        match receiver.try_recv() {
+            Ok(check) => {
+                if let Err(e) = self.apply_key_check(check).await {
+                    log::warn!("Failed to apply key check: {e}");
+                }
+            }
+            Err(TryRecvError::Empty) => (),
+            Err(TryRecvError::Disconnected) => self.key_checks = None,
+        }
+    }
+
+    pub async fn accept_contact_key(&mut self, contact_id: &str) -> anyhow::Result<()> {
+        let db = self.db.as_ref().expect("No DB").clone();
+        let contact = db.accept_contact_key(contact_id).await?;
+        let content = format!("You accepted the new key of {}", contact.name);
+        if let Some(c) = self.contacts.iter_mut().find(|c| c.id == contact_id) {
+            *c = contact;
+        }
+        self.record_system_message(contact_id, &content).await
+    }
+
+    fn set_pending_key(&mut self, contact_id: &str, pending_key: Option<Vec<u8>>) {
+        if let Some(c) = self.contacts.iter_mut().find(|c| c.id == contact_id) {
+            c.pending_key = pending_key;
+        }
+    }
+
+    async fn record_system_message(
+        &mut self,
+        contact_id: &str,
+        content: &str,
+    ) -> anyhow::Result<()> {
+        let my_id = self
+            .current_user
+            .as_ref()
+            .expect("No current user")
+            .id
+            .clone();
+        let (sent_counter, receive_counter) = self.last_counters(contact_id);
+        let message = self
+            .db
+            .as_ref()
+            .expect("No DB")
+            .create_system_message(contact_id, &my_id, content, receive_counter, sent_counter)
+            .await?;
+        self.messages
+            .entry(contact_id.to_string())
+            .or_default()
+            .push(message);
         Ok(())
     }
 
@@ -354,3 +1593,58 @@ impl App {
         (last_sent_counter, last_receive_counter)
     }
 }
+
+// what the user decided about an outgoing message, counters and expiry are added on delivery
+#[derive(Default)]
+struct Draft {
+    content: String,
+    attachment: Option<Attachment>,
+    timer: Option<i64>,
+    revision: Option<Revision>,
+    reply_to: Option<MessageRef>,
+    reacts_to: Option<MessageRef>,
+}
+
+impl Draft {
+    fn new(content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    // revisions and reactions attach to an earlier message instead of continuing the conversation
+    fn takes_place(&self) -> bool {
+        self.revision.is_none() && self.reacts_to.is_none()
+    }
+}
+
+fn blob_ids(data: &MessageData) -> Vec<String> {
+    data.attachment.iter().map(|a| a.blob_id.clone()).collect()
+}
+
+fn free_path(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    if !path.exists() {
+        return path;
+    }
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| dir.join(format!("{}-{}{}", stem, n, extension)))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+fn is_stale_group_key(e: &anyhow::Error) -> bool {
+    matches!(
+        e.downcast_ref::<ApiError>(),
+        Some(ApiError::StaleGroupKey { .. })
+    )
+}
@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use protocol::validation::MAX_BLOB_SIZE;
+use serde::{Deserialize, Serialize};
+
+// the nonce and authentication tag added by the encryption
+const ENCRYPTION_OVERHEAD: u64 = 12 + 16;
+pub const MAX_FILE_SIZE: u64 = MAX_BLOB_SIZE - ENCRYPTION_OVERHEAD;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Attachment {
+    pub blob_id: String,
+    pub name: String,
+    pub size: u64,
+    pub mime: String,
+    pub hash: String,
+    pub key: Vec<u8>,
+}
+
+impl Attachment {
+    pub fn new(blob_id: &str, path: &Path, data: &[u8], key: &[u8]) -> Self {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "attachment".to_string());
+        Self {
+            blob_id: blob_id.to_string(),
+            mime: guess_mime(path).to_string(),
+            name,
+            size: data.len() as u64,
+            hash: cryptolib::hash_bytes(data),
+            key: key.to_vec(),
+        }
+    }
+
+    pub fn encrypt(data: &[u8], key: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let (mut ciphertext, mut nonce) = match cryptolib::protect(data, key) {
+            Ok(v) => v,
+            Err(_) => return Err(anyhow::Error::msg("Failed to protect attachment")),
+        };
+        ciphertext.append(&mut nonce);
+        Ok(ciphertext)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if data.len() < 12 {
+            return Err(anyhow::Error::msg("Attachment is truncated"));
+        }
+        let (ciphertext, nonce) = cryptolib::utils::separate_cipher_nonce(data);
+        let plaintext = match cryptolib::unprotect(ciphertext, &self.key, nonce) {
+            Ok(v) => v,
+            Err(_) => return Err(anyhow::Error::msg("Failed to decrypt attachment")),
+        };
+        if plaintext.len() as u64 != self.size || cryptolib::hash_bytes(&plaintext) != self.hash {
+            return Err(anyhow::Error::msg(
+                "Attachment does not match its description",
+            ));
+        }
+        Ok(plaintext)
+    }
+
+    // never trust the sender with a path, only the final component is kept
+    pub fn file_name(&self) -> String {
+        let name = Path::new(&self.name)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match name.trim_start_matches('.').is_empty() {
+            true => "attachment".to_string(),
+            false => name,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "[{}] {} ({})",
+            self.mime,
+            self.file_name(),
+            describe_size(self.size)
+        )
+    }
+}
+
+pub fn describe_size(size: u64) -> String {
+    match size {
+        s if s >= 1024 * 1024 => format!("{:.1} MiB", s as f64 / (1024.0 * 1024.0)),
+        s if s >= 1024 => format!("{:.1} KiB", s as f64 / 1024.0),
+        s => format!("{} B", s),
+    }
+}
+
+fn guess_mime(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "txt" | "md" | "log" => "text/plain",
+        "html" | "htm" => "text/html",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
@@ -1,11 +1,15 @@
+use cryptolib::RsaPrivateKey;
 use protocol::{
     ctos::*,
-    stoc::{Message, ResponseGetMessage, ResponseGetUser},
+    stoc::{
+        ApiError, Blob, BlockedUser, Challenge, ContactRequest, Group, GroupMessage, KeyRotation,
+        Message, Presence, ResponseGetMessage, ResponseGetUser, Revision, Session,
+    },
 };
 use reqwest::{Certificate, Client};
 use reqwest_websocket::{RequestBuilderExt, WebSocket};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct MessageISTClient {
     base_url: String,
     client: Client,
@@ -62,30 +66,52 @@ impl MessageISTClient {
         name: &str,
         password: &str,
         public_key: &[u8],
-    ) -> Result<(), reqwest::Error> {
+        private_key: &RsaPrivateKey,
+    ) -> anyhow::Result<()> {
+        let url = format!("{}/users/challenge", self.base_url);
+        let challenge: Challenge = self
+            .client
+            .post(url)
+            .json(&RequestChallenge::new(id))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let signature = cryptolib::sign(&challenge.signed_payload(id), private_key)?;
+        let proof = KeyProof::new(&challenge.nonce, &signature);
         let url = format!("{}/users", self.base_url);
-        let register = Register::new(id, name, password, public_key);
+        let register = Register::new(id, name, password, public_key, proof);
         log::info!(
             "Sending REGISTER request to {} with data: {:?}",
             self.base_url,
             register
         );
-        self.client
-            .post(url)
-            .json(&register)
-            .send()
-            .await?
-            .error_for_status()?;
-        Ok(())
+        let response = self.client.post(url).json(&register).send().await?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        let status = response.status();
+        match response.json::<ApiError>().await {
+            Ok(error) => Err(error.into()),
+            Err(_) => Err(anyhow::anyhow!(
+                "Registration failed with status {}",
+                status
+            )),
+        }
     }
 
     pub async fn login(
         &self,
         username: &str,
         password: &str,
+        proof: Option<KeyProof>,
     ) -> Result<ResponseGetUser, reqwest::Error> {
         let url = format!("{}/login", self.base_url);
-        let login = Login::new(username, password);
+        let mut login = Login::new(username, password, &device_label());
+        if let Some(proof) = proof {
+            login = login.proof(proof);
+        }
         let user = self
             .client
             .post(url)
@@ -98,6 +124,52 @@ impl MessageISTClient {
         Ok(user)
     }
 
+    pub async fn request_login_challenge(
+        &self,
+        username: &str,
+    ) -> Result<Challenge, reqwest::Error> {
+        let url = format!("{}/login/challenge", self.base_url);
+        let challenge = self
+            .client
+            .post(url)
+            .json(&RequestChallenge::new(username))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(challenge)
+    }
+
+    pub async fn prove_key(
+        &self,
+        username: &str,
+        private_key: &RsaPrivateKey,
+    ) -> anyhow::Result<KeyProof> {
+        let challenge = self.request_login_challenge(username).await?;
+        let signature = cryptolib::sign(&challenge.signed_payload(username), private_key)?;
+        Ok(KeyProof::new(&challenge.nonce, &signature))
+    }
+
+    pub async fn login_with_key(
+        &self,
+        username: &str,
+        private_key: &RsaPrivateKey,
+    ) -> anyhow::Result<ResponseGetUser> {
+        let proof = self.prove_key(username, private_key).await?;
+        let url = format!("{}/login/key", self.base_url);
+        let user = self
+            .client
+            .post(url)
+            .json(&KeyLogin::new(username, proof, &device_label()))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(user)
+    }
+
     pub async fn logout(&self) -> Result<(), reqwest::Error> {
         let url = format!("{}/logout", &self.base_url);
         self.client.post(url).send().await?.error_for_status()?;
@@ -117,19 +189,12 @@ impl MessageISTClient {
         Ok(user)
     }
 
-    pub async fn send_message(
-        &self,
-        recipient: &str,
-        msg: &[u8],
-        my_secret_key: &Vec<u8>,
-        recipient_secret_key: &Vec<u8>,
-    ) -> Result<Message, reqwest::Error> {
+    pub async fn send_message(&self, send_msg: &SendMessage) -> Result<Message, reqwest::Error> {
         let url = format!("{}/messages", &self.base_url);
-        let send_msg = SendMessage::new(recipient, msg, my_secret_key, recipient_secret_key);
         let response = self
             .client
             .post(url)
-            .json(&send_msg)
+            .json(send_msg)
             .send()
             .await?
             .error_for_status()?
@@ -142,11 +207,62 @@ impl MessageISTClient {
         &self,
         after: i32,
         out_after: Option<i32>,
+        limit: Option<i64>,
     ) -> Result<ResponseGetMessage, reqwest::Error> {
         let mut url = format!("{}/messages?after={}", self.base_url, after);
         if let Some(out) = out_after {
             url.push_str(&format!("&out_after={}", out));
         }
+        if let Some(limit) = limit {
+            url.push_str(&format!("&limit={}", limit));
+        }
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(response)
+    }
+
+    pub async fn get_conversation_messages(
+        &self,
+        tag: &str,
+        before: Option<i64>,
+        out_before: Option<i64>,
+        limit: i64,
+    ) -> Result<ResponseGetMessage, reqwest::Error> {
+        let url = format!(
+            "{}/conversations/{}/messages?limit={}",
+            self.base_url, tag, limit
+        );
+        self.get_history_page(url, before, out_before).await
+    }
+
+    pub async fn get_message_history(
+        &self,
+        before: Option<i64>,
+        out_before: Option<i64>,
+        limit: i64,
+    ) -> Result<ResponseGetMessage, reqwest::Error> {
+        let url = format!("{}/messages/history?limit={}", self.base_url, limit);
+        self.get_history_page(url, before, out_before).await
+    }
+
+    async fn get_history_page(
+        &self,
+        mut url: String,
+        before: Option<i64>,
+        out_before: Option<i64>,
+    ) -> Result<ResponseGetMessage, reqwest::Error> {
+        if let Some(before) = before {
+            url.push_str(&format!("&before={}", before));
+        }
+        if let Some(out_before) = out_before {
+            url.push_str(&format!("&out_before={}", out_before));
+        }
         let response = self
             .client
             .get(url)
@@ -158,6 +274,396 @@ impl MessageISTClient {
         Ok(response)
     }
 
+    pub async fn get_presence(&self, username: &str) -> Result<Presence, reqwest::Error> {
+        let url = format!("{}/users/{username}/presence", &self.base_url);
+        let presence = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(presence)
+    }
+
+    pub async fn get_presence_settings(&self) -> Result<PresenceSettings, reqwest::Error> {
+        let url = format!("{}/users/me/presence", &self.base_url);
+        let settings = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(settings)
+    }
+
+    pub async fn set_presence_hidden(&self, hidden: bool) -> Result<(), reqwest::Error> {
+        let url = format!("{}/users/me/presence", &self.base_url);
+        let settings = PresenceSettings::new(hidden);
+        self.client
+            .put(url)
+            .json(&settings)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn subscribe_presence(
+        &self,
+        users: &[String],
+    ) -> Result<Vec<Presence>, reqwest::Error> {
+        let url = format!("{}/presence/subscriptions", &self.base_url);
+        let subscribe = SubscribePresence::new(users);
+        let presences = self
+            .client
+            .post(url)
+            .json(&subscribe)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(presences)
+    }
+
+    pub async fn get_sessions(&self) -> Result<Vec<Session>, reqwest::Error> {
+        let url = format!("{}/sessions", &self.base_url);
+        let sessions = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(sessions)
+    }
+
+    pub async fn revoke_other_sessions(&self) -> Result<(), reqwest::Error> {
+        let url = format!("{}/sessions", &self.base_url);
+        self.client.delete(url).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn update_profile(&self, name: &str) -> anyhow::Result<ResponseGetUser> {
+        let url = format!("{}/users/me", self.base_url);
+        let request = UpdateProfile::new(name);
+        let response = self.client.patch(url).json(&request).send().await?;
+        if response.status().is_success() {
+            return Ok(response.json().await?);
+        }
+        let status = response.status();
+        match response.json::<ApiError>().await {
+            Ok(error) => Err(error.into()),
+            Err(_) => Err(anyhow::anyhow!(
+                "Profile update failed with status {}",
+                status
+            )),
+        }
+    }
+
+    pub async fn change_password(
+        &self,
+        current_password: &str,
+        new_password: &str,
+    ) -> anyhow::Result<()> {
+        let url = format!("{}/users/me/password", self.base_url);
+        let request = ChangePassword::new(current_password, new_password);
+        let response = self.client.post(url).json(&request).send().await?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        let status = response.status();
+        match response.json::<ApiError>().await {
+            Ok(error) => Err(error.into()),
+            Err(_) => Err(anyhow::anyhow!(
+                "Password change failed with status {}",
+                status
+            )),
+        }
+    }
+
+    pub async fn delete_account(&self, password: &str) -> anyhow::Result<()> {
+        let url = format!("{}/users/me", self.base_url);
+        let request = DeleteAccount::new(password);
+        let response = self.client.delete(url).json(&request).send().await?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        let status = response.status();
+        match response.json::<ApiError>().await {
+            Ok(error) => Err(error.into()),
+            Err(_) => Err(anyhow::anyhow!(
+                "Account deletion failed with status {}",
+                status
+            )),
+        }
+    }
+
+    pub async fn get_blocks(&self) -> Result<Vec<BlockedUser>, reqwest::Error> {
+        let url = format!("{}/blocks", self.base_url);
+        let blocks = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(blocks)
+    }
+
+    pub async fn block_user(&self, username: &str) -> Result<(), reqwest::Error> {
+        let url = format!("{}/blocks/{}", self.base_url, username);
+        self.client.put(url).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn unblock_user(&self, username: &str) -> Result<(), reqwest::Error> {
+        let url = format!("{}/blocks/{}", self.base_url, username);
+        self.client.delete(url).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn get_requests(&self) -> Result<Vec<ContactRequest>, reqwest::Error> {
+        let url = format!("{}/requests", self.base_url);
+        let requests = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(requests)
+    }
+
+    pub async fn accept_request(&self, username: &str) -> Result<(), reqwest::Error> {
+        let url = format!("{}/requests/{}/accept", self.base_url, username);
+        self.client.post(url).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn decline_request(&self, username: &str) -> Result<(), reqwest::Error> {
+        let url = format!("{}/requests/{}/decline", self.base_url, username);
+        self.client.post(url).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn create_group(
+        &self,
+        name: &str,
+        members: &[String],
+    ) -> Result<Group, reqwest::Error> {
+        let url = format!("{}/groups", self.base_url);
+        let request = CreateGroup::new(name, members);
+        let group = self
+            .client
+            .post(url)
+            .json(&request)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(group)
+    }
+
+    pub async fn get_groups(&self) -> Result<Vec<Group>, reqwest::Error> {
+        let url = format!("{}/groups", self.base_url);
+        let groups = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(groups)
+    }
+
+    pub async fn get_group(&self, group_id: i64) -> Result<Group, reqwest::Error> {
+        let url = format!("{}/groups/{}", self.base_url, group_id);
+        let group = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(group)
+    }
+
+    pub async fn invite_group_member(
+        &self,
+        group_id: i64,
+        username: &str,
+    ) -> Result<(), reqwest::Error> {
+        let url = format!("{}/groups/{}/members/{}", self.base_url, group_id, username);
+        self.client.post(url).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn remove_group_member(
+        &self,
+        group_id: i64,
+        username: &str,
+    ) -> Result<(), reqwest::Error> {
+        let url = format!("{}/groups/{}/members/{}", self.base_url, group_id, username);
+        self.client.delete(url).send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn set_group_admin(
+        &self,
+        group_id: i64,
+        username: &str,
+        admin: bool,
+    ) -> Result<(), reqwest::Error> {
+        let url = format!("{}/groups/{}/admins/{}", self.base_url, group_id, username);
+        let request = match admin {
+            true => self.client.put(url),
+            false => self.client.delete(url),
+        };
+        request.send().await?.error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn distribute_group_key(
+        &self,
+        group_id: i64,
+        request: &DistributeGroupKey,
+    ) -> anyhow::Result<()> {
+        let url = format!("{}/groups/{}/keys", self.base_url, group_id);
+        let response = self.client.put(url).json(request).send().await?;
+        if response.status().is_success() {
+            return Ok(());
+        }
+        let status = response.status();
+        match response.json::<ApiError>().await {
+            Ok(error) => Err(error.into()),
+            Err(_) => Err(anyhow::anyhow!(
+                "Group key distribution failed with status {}",
+                status
+            )),
+        }
+    }
+
+    pub async fn send_group_message(
+        &self,
+        group_id: i64,
+        epoch: i64,
+        contents: &[u8],
+        attachments: &[String],
+        expires_at: Option<i64>,
+        revision: Option<Revision>,
+    ) -> anyhow::Result<GroupMessage> {
+        let url = format!("{}/groups/{}/messages", self.base_url, group_id);
+        let request = SendGroupMessage::new(epoch, contents)
+            .attachments(attachments)
+            .expires_at(expires_at)
+            .revision(revision);
+        let response = self.client.post(url).json(&request).send().await?;
+        if response.status().is_success() {
+            return Ok(response.json().await?);
+        }
+        let status = response.status();
+        match response.json::<ApiError>().await {
+            Ok(error) => Err(error.into()),
+            Err(_) => Err(anyhow::anyhow!(
+                "Group message failed with status {}",
+                status
+            )),
+        }
+    }
+
+    pub async fn upload_blob(&self, data: Vec<u8>) -> anyhow::Result<Blob> {
+        let url = format!("{}/blobs", self.base_url);
+        let response = self.client.post(url).body(data).send().await?;
+        if response.status().is_success() {
+            return Ok(response.json().await?);
+        }
+        let status = response.status();
+        match response.json::<ApiError>().await {
+            Ok(error) => Err(error.into()),
+            Err(_) => Err(anyhow::anyhow!("Upload failed with status {}", status)),
+        }
+    }
+
+    pub async fn download_blob(&self, id: &str) -> Result<Vec<u8>, reqwest::Error> {
+        let url = format!("{}/blobs/{}", self.base_url, id);
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        Ok(response.to_vec())
+    }
+
+    pub async fn get_group_messages(
+        &self,
+        after: i64,
+    ) -> Result<Vec<GroupMessage>, reqwest::Error> {
+        let url = format!("{}/groups/messages?after={}", self.base_url, after);
+        let messages = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(messages)
+    }
+
+    pub async fn request_rotate_key_challenge(&self) -> Result<Challenge, reqwest::Error> {
+        let url = format!("{}/users/me/key/challenge", self.base_url);
+        let challenge = self
+            .client
+            .post(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(challenge)
+    }
+
+    pub async fn rotate_key(&self, request: &RotateKey) -> anyhow::Result<KeyRotation> {
+        let url = format!("{}/users/me/key", self.base_url);
+        let response = self.client.put(url).json(request).send().await?;
+        if response.status().is_success() {
+            return Ok(response.json().await?);
+        }
+        let status = response.status();
+        match response.json::<ApiError>().await {
+            Ok(error) => Err(error.into()),
+            Err(_) => Err(anyhow::anyhow!(
+                "Key rotation failed with status {}",
+                status
+            )),
+        }
+    }
+
+    pub async fn rewrap_keys(&self, request: &RewrapKeys) -> Result<(), reqwest::Error> {
+        let url = format!("{}/users/me/key/wrapped", self.base_url);
+        self.client
+            .put(url)
+            .json(request)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
     pub async fn connect_notifications_ws(&self) -> Result<WebSocket, reqwest_websocket::Error> {
         let url = format!("{}/notifications", self.base_url);
         let response = self.client.post(url).upgrade().send().await?;
@@ -165,3 +671,7 @@ impl MessageISTClient {
         Ok(websocket)
     }
 }
+
+fn device_label() -> String {
+    format!("MessagIST TUI ({})", std::env::consts::OS)
+}
@@ -1,8 +1,14 @@
 use std::str::FromStr;
 
-use sqlx::{sqlite::SqliteConnectOptions, Pool, Sqlite, SqlitePool};
+use protocol::stoc::Revision;
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Pool, Sqlite,
+};
 
-use super::structs::{Contact, StoredMessage};
+use crate::message_data::{group_chat_id, MessageData, MessageRef};
+
+use super::structs::{Contact, MessageKind, StoredMessage, StoredReaction};
 
 #[derive(Clone, Debug)]
 pub struct Database {
@@ -19,13 +25,56 @@ impl Database {
             .pragma("cipher_kdf_algorithm", "PBKDF2_HMAC_SHA512")
             .foreign_keys(true)
             .create_if_missing(true);
-        let pool = SqlitePool::connect_with(opts).await?;
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(opts)
+            .await?;
         let db = Database { pool };
         db.create_table_contact().await?;
         db.create_table_message().await?;
+        db.create_table_retired_key().await?;
+        db.create_table_reaction().await?;
         Ok(db)
     }
 
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    pub async fn change_key(&self, password: &str) -> Result<(), sqlx::Error> {
+        let rekey = format!("PRAGMA rekey = '{}'", password.replace('\'', "''"));
+        sqlx::query(&rekey).execute(&self.pool).await?;
+        let opts = (*self.pool.connect_options())
+            .clone()
+            .pragma("key", password.to_string());
+        self.pool.set_connect_options(opts);
+        Ok(())
+    }
+
+    async fn ensure_column(
+        &self,
+        table: &str,
+        column: &str,
+        definition: &str,
+    ) -> Result<(), sqlx::Error> {
+        let exists: bool = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info('{}') WHERE name = $1",
+            table
+        ))
+        .bind(column)
+        .fetch_one(&self.pool)
+        .await?;
+        if !exists {
+            sqlx::query(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}",
+                table, column, definition
+            ))
+            .execute(&self.pool)
+            .await?;
+        }
+        Ok(())
+    }
+
     async fn create_table_contact(&self) -> Result<(), sqlx::Error> {
         sqlx::query(
             "CREATE TABLE IF NOT EXISTS Contact (
@@ -36,6 +85,9 @@ impl Database {
         )
         .execute(&self.pool)
         .await?;
+        self.ensure_column("Contact", "pending_key", "BLOB").await?;
+        self.ensure_column("Contact", "deleted", "BOOLEAN NOT NULL DEFAULT 0")
+            .await?;
         Ok(())
     }
 
@@ -55,6 +107,52 @@ impl Database {
         )
         .execute(&self.pool)
         .await?;
+        self.ensure_column("Message", "kind", "TEXT NOT NULL DEFAULT 'message'")
+            .await?;
+        self.ensure_column("Message", "group_id", "INTEGER").await?;
+        self.ensure_column("Message", "attachment", "TEXT").await?;
+        self.ensure_column("Message", "expires_at", "INTEGER")
+            .await?;
+        self.ensure_column("Message", "timer", "INTEGER").await?;
+        self.ensure_column("Message", "edits", "INTEGER").await?;
+        self.ensure_column("Message", "deletes", "INTEGER").await?;
+        self.ensure_column("Message", "history", "TEXT").await?;
+        self.ensure_column("Message", "deleted", "BOOLEAN NOT NULL DEFAULT 0")
+            .await?;
+        self.ensure_column("Message", "ref_sender", "TEXT").await?;
+        self.ensure_column("Message", "ref_counter", "INTEGER")
+            .await?;
+        self.ensure_column("Message", "conversation_key", "BLOB")
+            .await?;
+        Ok(())
+    }
+
+    async fn create_table_retired_key(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS RetiredKey (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            private_key BLOB NOT NULL,
+            retired_at TEXT NOT NULL
+        );",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn create_table_reaction(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS Reaction (
+            chat_id TEXT NOT NULL,
+            target_sender TEXT NOT NULL,
+            target_counter INTEGER NOT NULL,
+            sender TEXT NOT NULL,
+            emoji TEXT NOT NULL,
+            PRIMARY KEY (chat_id, target_sender, target_counter, sender)
+        );",
+        )
+        .execute(&self.pool)
+        .await?;
         Ok(())
     }
 
@@ -77,31 +175,194 @@ impl Database {
 
     pub async fn create_message(
         &self,
-        sender_istid: &str,
-        receiver_istid: &str,
-        timestamp: &str,
-        content: &str,
+        data: &MessageData,
         secret_key: &[u8],
-        receive_counter: i64,
-        sent_counter: i64,
         server_id: i64,
+        revision: Option<Revision>,
     ) -> Result<StoredMessage, sqlx::Error> {
         let message = sqlx::query_as::<_, StoredMessage>(
-            "INSERT INTO Message (sender_istid, receiver_istid, timestamp, content, secret_key, receive_counter, sent_counter, server_id) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING *",
+            "INSERT INTO Message (sender_istid, receiver_istid, timestamp, content, secret_key, receive_counter, sent_counter, server_id, attachment, expires_at, timer, kind, edits, deletes, ref_sender, ref_counter, conversation_key) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17) RETURNING *",
         )
-        .bind(sender_istid)
-        .bind(receiver_istid)
-        .bind(timestamp)
-        .bind(content)
+        .bind(&data.sender_istid)
+        .bind(&data.receiver_istid)
+        .bind(&data.timestamp)
+        .bind(&data.content)
         .bind(secret_key)
-        .bind(receive_counter)
-        .bind(sent_counter)
+        .bind(data.receive_counter)
+        .bind(data.sent_counter)
+        .bind(server_id)
+        .bind(attachment_json(data))
+        .bind(data.expires_at)
+        .bind(data.timer)
+        .bind(message_kind(data))
+        .bind(revision.and_then(|r| r.edits()))
+        .bind(revision.and_then(|r| r.deletes()))
+        .bind(message_ref(data).map(|r| &r.sender))
+        .bind(message_ref(data).map(|r| r.counter))
+        .bind(&data.conversation_key)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(message)
+    }
+
+    pub async fn create_group_message(
+        &self,
+        data: &MessageData,
+        secret_key: &[u8],
+        server_id: i64,
+        group_id: i64,
+        revision: Option<Revision>,
+    ) -> Result<StoredMessage, sqlx::Error> {
+        let message = sqlx::query_as::<_, StoredMessage>(
+            "INSERT INTO Message (sender_istid, receiver_istid, timestamp, content, secret_key, receive_counter, sent_counter, server_id, group_id, attachment, expires_at, timer, kind, edits, deletes, ref_sender, ref_counter) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17) RETURNING *",
+        )
+        .bind(&data.sender_istid)
+        .bind(group_chat_id(group_id))
+        .bind(&data.timestamp)
+        .bind(&data.content)
+        .bind(secret_key)
+        .bind(data.receive_counter)
+        .bind(data.sent_counter)
         .bind(server_id)
+        .bind(group_id)
+        .bind(attachment_json(data))
+        .bind(data.expires_at)
+        .bind(data.timer)
+        .bind(message_kind(data))
+        .bind(revision.and_then(|r| r.edits()))
+        .bind(revision.and_then(|r| r.deletes()))
+        .bind(message_ref(data).map(|r| &r.sender))
+        .bind(message_ref(data).map(|r| r.counter))
         .fetch_one(&self.pool)
         .await?;
         Ok(message)
     }
 
+    // revisions only apply to a message of the same sender in the same conversation
+    pub async fn apply_revision(
+        &self,
+        revision: &StoredMessage,
+    ) -> Result<Option<StoredMessage>, sqlx::Error> {
+        let Some(kind) = revision.revision() else {
+            return Ok(None);
+        };
+        let target = sqlx::query_as::<_, StoredMessage>(
+            "SELECT * FROM Message m WHERE m.server_id = $1 AND m.sender_istid = $2 AND m.receiver_istid = $3 AND m.edits IS NULL AND m.deletes IS NULL AND m.deleted = 0",
+        )
+        .bind(kind.message_id())
+        .bind(&revision.sender_istid)
+        .bind(&revision.receiver_istid)
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some(target) = target else {
+            return Ok(None);
+        };
+        let query = match kind {
+            Revision::Edit(_) => {
+                let mut history = target.history();
+                history.push(target.content.clone());
+                sqlx::query_as::<_, StoredMessage>(
+                    "UPDATE Message SET content = $1, history = $2 WHERE id = $3 RETURNING *",
+                )
+                .bind(&revision.content)
+                .bind(serde_json::to_string(&history).ok())
+            }
+            Revision::Delete(_) => sqlx::query_as::<_, StoredMessage>(
+                "UPDATE Message SET content = '', attachment = NULL, history = NULL, deleted = 1 WHERE id = $1 RETURNING *",
+            ),
+        };
+        let message = query.bind(target.id).fetch_one(&self.pool).await?;
+        Ok(Some(message))
+    }
+
+    // edits that arrived before the message they change, as happens when paging back
+    pub async fn get_revisions_of(
+        &self,
+        message: &StoredMessage,
+    ) -> Result<Vec<StoredMessage>, sqlx::Error> {
+        let revisions = sqlx::query_as::<_, StoredMessage>(
+            "SELECT * FROM Message m WHERE m.sender_istid = $1 AND m.receiver_istid = $2 AND (m.edits = $3 OR m.deletes = $3) ORDER BY m.server_id",
+        )
+        .bind(&message.sender_istid)
+        .bind(&message.receiver_istid)
+        .bind(message.server_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(revisions)
+    }
+
+    pub async fn has_newer_reaction(&self, reaction: &StoredMessage) -> Result<bool, sqlx::Error> {
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM Message m WHERE m.kind = 'reaction' AND m.sender_istid = $1 AND m.receiver_istid = $2 AND m.ref_sender = $3 AND m.ref_counter = $4 AND m.server_id > $5",
+        )
+        .bind(&reaction.sender_istid)
+        .bind(&reaction.receiver_istid)
+        .bind(&reaction.ref_sender)
+        .bind(reaction.ref_counter)
+        .bind(reaction.server_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count > 0)
+    }
+
+    pub async fn set_reaction(
+        &self,
+        chat_id: &str,
+        reaction: &StoredMessage,
+    ) -> Result<Option<StoredReaction>, sqlx::Error> {
+        let Some(target) = reaction.reference() else {
+            return Ok(None);
+        };
+        // an empty reaction takes the previous one back
+        if reaction.content.is_empty() {
+            sqlx::query(
+                "DELETE FROM Reaction WHERE chat_id = $1 AND target_sender = $2 AND target_counter = $3 AND sender = $4",
+            )
+            .bind(chat_id)
+            .bind(&target.sender)
+            .bind(target.counter)
+            .bind(&reaction.sender_istid)
+            .execute(&self.pool)
+            .await?;
+            return Ok(None);
+        }
+        let reaction = sqlx::query_as::<_, StoredReaction>(
+            "INSERT INTO Reaction (chat_id, target_sender, target_counter, sender, emoji) VALUES ($1, $2, $3, $4, $5) ON CONFLICT (chat_id, target_sender, target_counter, sender) DO UPDATE SET emoji = excluded.emoji RETURNING *",
+        )
+        .bind(chat_id)
+        .bind(&target.sender)
+        .bind(target.counter)
+        .bind(&reaction.sender_istid)
+        .bind(&reaction.content)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(Some(reaction))
+    }
+
+    pub async fn get_all_reactions(&self) -> Result<Vec<StoredReaction>, sqlx::Error> {
+        let reactions = sqlx::query_as::<_, StoredReaction>("SELECT * FROM Reaction")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(reactions)
+    }
+
+    pub async fn delete_expired_messages(&self, now: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM Message WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn get_last_group_message_id(&self) -> Result<i64, sqlx::Error> {
+        let max = sqlx::query_scalar(
+            "SELECT COALESCE(MAX(server_id), -1) from Message m where m.group_id IS NOT NULL",
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(max)
+    }
+
     pub async fn get_all_contacts(&self) -> Result<Vec<Contact>, sqlx::Error> {
         let contacts = sqlx::query_as::<_, Contact>("SELECT * FROM Contact c")
             .fetch_all(&self.pool)
@@ -122,7 +383,7 @@ impl Database {
         contact: &Contact,
     ) -> Result<Vec<StoredMessage>, sqlx::Error> {
         let messages = sqlx::query_as::<_, StoredMessage>(
-            "SELECT * FROM Message m WHERE m.sender_istid = $1 OR m.receiver_istid = $1",
+            "SELECT * FROM Message m WHERE (m.sender_istid = $1 OR m.receiver_istid = $1) AND m.edits IS NULL AND m.deletes IS NULL AND m.kind != 'reaction'",
         )
         .bind(&contact.id)
         .fetch_all(&self.pool)
@@ -130,21 +391,239 @@ impl Database {
         Ok(messages)
     }
 
+    pub async fn get_messages_with(&self, id: &str) -> Result<Vec<StoredMessage>, sqlx::Error> {
+        let messages = sqlx::query_as::<_, StoredMessage>(
+            "SELECT * FROM Message m WHERE (m.sender_istid = $1 OR m.receiver_istid = $1) AND m.edits IS NULL AND m.deletes IS NULL AND m.kind != 'reaction'",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(messages)
+    }
+
+    pub async fn delete_messages_with(&self, id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM Message WHERE sender_istid = $1 OR receiver_istid = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM Reaction WHERE chat_id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
     pub async fn get_last_sent_message_id(&self, my_id: &str) -> Result<i32, sqlx::Error> {
-        let max =
-            sqlx::query_scalar("SELECT MAX(server_id) from Message m where m.sender_istid = $1")
-                .bind(my_id)
-                .fetch_one(&self.pool)
-                .await?;
+        let max = sqlx::query_scalar(
+            "SELECT COALESCE(MAX(server_id), -1) from Message m where m.sender_istid = $1 AND m.group_id IS NULL",
+        )
+        .bind(my_id)
+        .fetch_one(&self.pool)
+        .await?;
         Ok(max)
     }
 
     pub async fn get_last_received_message_id(&self, my_id: &str) -> Result<i32, sqlx::Error> {
-        let max =
-            sqlx::query_scalar("SELECT MAX(server_id) from Message m where m.receiver_istid = $1")
-                .bind(my_id)
-                .fetch_one(&self.pool)
-                .await?;
+        let max = sqlx::query_scalar(
+            "SELECT COALESCE(MAX(server_id), -1) from Message m where m.receiver_istid = $1",
+        )
+        .bind(my_id)
+        .fetch_one(&self.pool)
+        .await?;
         Ok(max)
     }
+
+    // the key of the latest message that carried one, in either direction
+    pub async fn get_conversation_key(&self, id: &str) -> Result<Option<Vec<u8>>, sqlx::Error> {
+        let key = sqlx::query_scalar(
+            "SELECT m.conversation_key FROM Message m WHERE (m.sender_istid = $1 OR m.receiver_istid = $1) AND m.group_id IS NULL AND m.conversation_key IS NOT NULL ORDER BY m.timestamp DESC LIMIT 1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(key)
+    }
+
+    // every key the conversation was tagged with, a race or a device without history picks a new one
+    pub async fn get_conversation_keys(&self, id: &str) -> Result<Vec<Vec<u8>>, sqlx::Error> {
+        let keys = sqlx::query_scalar(
+            "SELECT DISTINCT m.conversation_key FROM Message m WHERE (m.sender_istid = $1 OR m.receiver_istid = $1) AND m.group_id IS NULL AND m.conversation_key IS NOT NULL",
+        )
+        .bind(id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(keys)
+    }
+
+    // cursors for paging back one tag, the oldest received and sent copies held locally
+    pub async fn get_oldest_server_ids(
+        &self,
+        my_id: &str,
+        id: &str,
+        key: &[u8],
+    ) -> Result<(Option<i64>, Option<i64>), sqlx::Error> {
+        let ids = sqlx::query_as(
+            "SELECT (SELECT MIN(server_id) FROM Message m WHERE m.sender_istid = $2 AND m.receiver_istid = $1 AND m.conversation_key = $3 AND m.server_id >= 0), (SELECT MIN(server_id) FROM Message m WHERE m.sender_istid = $1 AND m.receiver_istid = $2 AND m.conversation_key = $3 AND m.server_id >= 0)",
+        )
+        .bind(my_id)
+        .bind(id)
+        .bind(key)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(ids)
+    }
+
+    pub async fn has_sent_message(&self, my_id: &str, server_id: i64) -> Result<bool, sqlx::Error> {
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM Message m WHERE m.sender_istid = $1 AND m.server_id = $2 AND m.group_id IS NULL",
+        )
+        .bind(my_id)
+        .bind(server_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count > 0)
+    }
+
+    pub async fn has_received_message(
+        &self,
+        my_id: &str,
+        server_id: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM Message m WHERE m.receiver_istid = $1 AND m.server_id = $2",
+        )
+        .bind(my_id)
+        .bind(server_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count > 0)
+    }
+
+    pub async fn create_system_message(
+        &self,
+        contact_id: &str,
+        my_id: &str,
+        content: &str,
+        receive_counter: i64,
+        sent_counter: i64,
+    ) -> Result<StoredMessage, sqlx::Error> {
+        let message = sqlx::query_as::<_, StoredMessage>(
+            "INSERT INTO Message (sender_istid, receiver_istid, timestamp, content, secret_key, receive_counter, sent_counter, server_id, kind) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9) RETURNING *",
+        )
+        .bind(contact_id)
+        .bind(my_id)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .bind(content)
+        .bind(Vec::<u8>::new())
+        .bind(receive_counter)
+        .bind(sent_counter)
+        .bind(-1)
+        .bind(MessageKind::System)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(message)
+    }
+
+    pub async fn get_all_stored_messages(&self) -> Result<Vec<StoredMessage>, sqlx::Error> {
+        let messages = sqlx::query_as::<_, StoredMessage>(
+            "SELECT * FROM Message m WHERE m.server_id >= 0 AND m.group_id IS NULL",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(messages)
+    }
+
+    pub async fn update_contact_key(&self, id: &str, public_key: &[u8]) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE Contact SET public_key = $1, pending_key = NULL WHERE id = $2")
+            .bind(public_key)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_contact_name(&self, id: &str, name: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE Contact SET name = $1 WHERE id = $2")
+            .bind(name)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn add_retired_key(&self, private_key: &[u8]) -> Result<i64, sqlx::Error> {
+        let id = sqlx::query_scalar(
+            "INSERT INTO RetiredKey (private_key, retired_at) VALUES ($1, $2) RETURNING id",
+        )
+        .bind(private_key)
+        .bind(chrono::Utc::now().to_rfc3339())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    pub async fn delete_retired_key(&self, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM RetiredKey WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_retired_keys(&self) -> Result<Vec<Vec<u8>>, sqlx::Error> {
+        let keys = sqlx::query_scalar("SELECT private_key FROM RetiredKey ORDER BY id DESC")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(keys)
+    }
+
+    pub async fn mark_contact_deleted(&self, id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE Contact SET deleted = 1 WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_contact_pending_key(
+        &self,
+        id: &str,
+        pending_key: Option<&[u8]>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE Contact SET pending_key = $1 WHERE id = $2")
+            .bind(pending_key)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn accept_contact_key(&self, id: &str) -> Result<Contact, sqlx::Error> {
+        let contact = sqlx::query_as::<_, Contact>(
+            "UPDATE Contact SET public_key = pending_key, pending_key = NULL WHERE id = $1 AND pending_key IS NOT NULL RETURNING *",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(contact)
+    }
+}
+
+fn attachment_json(data: &MessageData) -> Option<String> {
+    data.attachment
+        .as_ref()
+        .and_then(|a| serde_json::to_string(a).ok())
+}
+
+// timer changes are shown like the locally recorded notices
+fn message_kind(data: &MessageData) -> MessageKind {
+    match (data.timer, &data.reacts_to) {
+        (Some(_), _) => MessageKind::System,
+        (None, Some(_)) => MessageKind::Reaction,
+        (None, None) => MessageKind::Message,
+    }
+}
+
+fn message_ref(data: &MessageData) -> Option<&MessageRef> {
+    data.reply_to.as_ref().or(data.reacts_to.as_ref())
 }
@@ -1,10 +1,23 @@
+use protocol::stoc::Revision;
 use sqlx::prelude::FromRow;
 
+use crate::{attachment::Attachment, message_data::MessageRef};
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq)]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+pub enum MessageKind {
+    Message,
+    System,
+    Reaction,
+}
+
 #[derive(FromRow, Debug, Clone)]
 pub struct Contact {
     pub id: String,
     pub name: String,
     pub public_key: Vec<u8>,
+    pub pending_key: Option<Vec<u8>>,
+    pub deleted: bool,
 }
 
 #[derive(FromRow, Debug, Clone)]
@@ -18,4 +31,50 @@ pub struct StoredMessage {
     pub receive_counter: i64,
     pub sent_counter: i64,
     pub server_id: i64,
+    pub kind: MessageKind,
+    pub attachment: Option<String>,
+    pub expires_at: Option<i64>,
+    pub timer: Option<i64>,
+    pub edits: Option<i64>,
+    pub deletes: Option<i64>,
+    pub history: Option<String>,
+    pub deleted: bool,
+    pub ref_sender: Option<String>,
+    pub ref_counter: Option<i64>,
+    pub conversation_key: Option<Vec<u8>>,
+}
+
+#[derive(FromRow, Debug, Clone)]
+pub struct StoredReaction {
+    pub chat_id: String,
+    pub target_sender: String,
+    pub target_counter: i64,
+    pub sender: String,
+    pub emoji: String,
+}
+
+impl StoredMessage {
+    pub fn attachment(&self) -> Option<Attachment> {
+        serde_json::from_str(self.attachment.as_deref()?).ok()
+    }
+
+    // the message quoted by a reply or reacted to by a reaction
+    pub fn reference(&self) -> Option<MessageRef> {
+        Some(MessageRef::new(
+            self.ref_sender.as_deref()?,
+            self.ref_counter?,
+        ))
+    }
+
+    pub fn revision(&self) -> Option<Revision> {
+        Revision::from_columns(self.edits, self.deletes)
+    }
+
+    // previous contents of an edited message, oldest first
+    pub fn history(&self) -> Vec<String> {
+        self.history
+            .as_deref()
+            .and_then(|h| serde_json::from_str(h).ok())
+            .unwrap_or_default()
+    }
 }
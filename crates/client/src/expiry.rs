@@ -0,0 +1,42 @@
+use protocol::validation::MAX_MESSAGE_TIMER;
+
+const UNITS: [(char, i64, &str); 4] = [
+    ('w', 7 * 24 * 60 * 60, "week"),
+    ('d', 24 * 60 * 60, "day"),
+    ('h', 60 * 60, "hour"),
+    ('m', 60, "minute"),
+];
+
+// accepts "off" or an amount followed by a unit, e.g. "30m", "1h", "1d" or "1w", up to the
+// longest timer the server accepts
+pub fn parse_timer(value: &str) -> Option<i64> {
+    let value = value.trim().to_lowercase();
+    if value == "off" || value == "0" {
+        return Some(0);
+    }
+    let unit = value.chars().last()?;
+    let amount: i64 = value[..value.len() - unit.len_utf8()].parse().ok()?;
+    let (_, seconds, _) = UNITS.iter().find(|(u, _, _)| *u == unit)?;
+    match amount > 0 {
+        true => amount
+            .checked_mul(*seconds)
+            .filter(|s| *s <= MAX_MESSAGE_TIMER),
+        false => None,
+    }
+}
+
+pub fn describe_timer(seconds: i64) -> String {
+    if seconds <= 0 {
+        return "off".to_string();
+    }
+    for (_, unit, name) in UNITS {
+        if seconds % unit == 0 {
+            let amount = seconds / unit;
+            return match amount {
+                1 => format!("1 {}", name),
+                n => format!("{} {}s", n, name),
+            };
+        }
+    }
+    format!("{} seconds", seconds)
+}
@@ -1,2 +1,3 @@
+pub mod attachment;
 pub mod client_handler;
 pub mod message_data;
@@ -1,6 +1,8 @@
 mod app;
+mod attachment;
 mod client_handler;
 mod db;
+mod expiry;
 mod logger;
 mod message_data;
 mod notifications;
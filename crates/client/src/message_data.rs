@@ -1,5 +1,9 @@
+use cryptolib::RsaPrivateKey;
+use protocol::stoc::GroupMessage;
 use serde::{Deserialize, Serialize};
 
+use crate::attachment::Attachment;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct MessageData {
     pub sender_istid: String,
@@ -8,6 +12,45 @@ pub struct MessageData {
     pub content: String,
     pub receive_counter: i64,
     pub sent_counter: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub attachment: Option<Attachment>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timer: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reply_to: Option<MessageRef>,
+    // a reaction carries its emoji as the content
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reacts_to: Option<MessageRef>,
+    // shared by both participants so either can tag the conversation for the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversation_key: Option<Vec<u8>>,
+}
+
+// the server only ever sees this keyed hash of the participants, never who they are
+pub fn conversation_tag(key: &[u8], user: &str, other: &str) -> String {
+    let mut participants = [user, other];
+    participants.sort();
+    let mut data = key.to_vec();
+    data.extend(participants.join("\n").as_bytes());
+    cryptolib::hash_bytes(&data)
+}
+
+// identifies a message within its conversation by who sent it and their counter
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct MessageRef {
+    pub sender: String,
+    pub counter: i64,
+}
+
+impl MessageRef {
+    pub fn new(sender: &str, counter: i64) -> Self {
+        Self {
+            sender: sender.to_string(),
+            counter,
+        }
+    }
 }
 
 impl MessageData {
@@ -25,9 +68,46 @@ impl MessageData {
             content: String::from(content),
             receive_counter,
             sent_counter,
+            attachment: None,
+            expires_at: None,
+            timer: None,
+            reply_to: None,
+            reacts_to: None,
+            conversation_key: None,
         }
     }
 
+    pub fn attachment(mut self, attachment: Option<Attachment>) -> Self {
+        self.attachment = attachment;
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: Option<i64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    // a message carrying a timer changes the expiry timer of its conversation
+    pub fn timer(mut self, timer: Option<i64>) -> Self {
+        self.timer = timer;
+        self
+    }
+
+    pub fn reply_to(mut self, reply_to: Option<MessageRef>) -> Self {
+        self.reply_to = reply_to;
+        self
+    }
+
+    pub fn reacts_to(mut self, reacts_to: Option<MessageRef>) -> Self {
+        self.reacts_to = reacts_to;
+        self
+    }
+
+    pub fn conversation_key(mut self, conversation_key: Option<Vec<u8>>) -> Self {
+        self.conversation_key = conversation_key;
+        self
+    }
+
     pub fn encrypt(&self, secret_key: &[u8]) -> anyhow::Result<Vec<u8>> {
         let data = serde_json::to_string(self)?;
         let (mut ciphertext, mut nonce) = match cryptolib::protect(data.as_bytes(), secret_key) {
@@ -48,4 +128,41 @@ impl MessageData {
         let message = serde_json::from_str::<MessageData>(&json)?;
         Ok(message)
     }
+
+    pub fn open(
+        data: &[u8],
+        wrapped_key: &[u8],
+        private_keys: &[RsaPrivateKey],
+    ) -> anyhow::Result<(MessageData, Vec<u8>)> {
+        for private_key in private_keys {
+            let Ok(secret_key) = cryptolib::decrypt_key_with_priv_key(wrapped_key, private_key)
+            else {
+                continue;
+            };
+            if let Ok(message) = Self::decrypt(data, &secret_key) {
+                return Ok((message, secret_key));
+            }
+        }
+        Err(anyhow::Error::msg("No private key could open the message"))
+    }
+
+    pub fn open_group(
+        message: &GroupMessage,
+        private_keys: &[RsaPrivateKey],
+    ) -> anyhow::Result<(MessageData, Vec<u8>)> {
+        let (data, secret_key) = Self::open(&message.contents, &message.secret_key, private_keys)?;
+        // the server vouches for the sender, the ciphertext must agree with it
+        if data.sender_istid != message.sender
+            || data.receiver_istid != group_chat_id(message.group_id)
+        {
+            return Err(anyhow::Error::msg(
+                "Group message does not match its envelope",
+            ));
+        }
+        Ok((data, secret_key))
+    }
+}
+
+pub fn group_chat_id(group_id: i64) -> String {
+    format!("group:{}", group_id)
 }
@@ -1,72 +1,169 @@
 use std::sync::Arc;
 
 use cryptolib::RsaPrivateKey;
-use futures_util::TryStreamExt;
-use protocol::stoc::Message;
+use futures_util::{SinkExt, TryStreamExt};
+use protocol::{
+    ctos::ClientFrame,
+    stoc::{Group, GroupMessage, KeyRotation, Message, Notification, Presence, UserProfile},
+};
 use reqwest_websocket::{Message as WSMessage, WebSocket};
-use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::{mpsc::UnboundedSender, watch};
 
 use crate::{
     db::{structs::StoredMessage, Database},
     message_data::MessageData,
 };
 
+#[derive(Debug)]
+pub enum NotificationEvent {
+    Message(StoredMessage),
+    Presence(Presence),
+    KeyChanged(KeyRotation),
+    ProfileUpdated(UserProfile),
+    AccountDeleted(String),
+    GroupMessage(StoredMessage),
+    GroupChanged(Group),
+    GroupRemoved(i64),
+}
+
+enum Delivery {
+    Stored(Box<StoredMessage>),
+    Duplicate,
+    Rejected,
+    Failed,
+}
+
 pub async fn notification_handler(
     mut websocket: WebSocket,
-    sender: UnboundedSender<StoredMessage>,
+    sender: UnboundedSender<NotificationEvent>,
     db: Arc<Database>,
-    private_key: RsaPrivateKey,
+    private_keys: watch::Receiver<Vec<RsaPrivateKey>>,
 ) -> Result<(), reqwest_websocket::Error> {
     log::info!("WS handler started!");
     while let Some(notify) = websocket.try_next().await? {
         log::info!("Received notify: {:?}", notify);
         if let WSMessage::Text(text) = notify {
-            let message = match serde_json::from_str::<Message>(&text) {
+            let notification = match serde_json::from_str::<Notification>(&text) {
                 Ok(v) => v,
                 Err(e) => {
-                    log::error!("Failed to deserialized received message: {e}");
+                    log::error!("Failed to deserialized received notification: {e}");
                     continue;
                 }
             };
-            log::info!("MESSAGE: {:?}", message);
-            let secret_key = message.secret_key;
-            let secret_key = match cryptolib::decrypt_key_with_priv_key(&secret_key, &private_key) {
-                Ok(v) => v,
-                Err(e) => {
-                    log::error!("Failed to decrypt secret key: {e}");
-                    continue;
-                }
-            };
-            let data = match MessageData::decrypt(&message.contents, &secret_key) {
-                Ok(v) => v,
-                Err(_) => {
-                    log::warn!("Coudln't decrypt message, integrity failed!");
-                    continue;
-                }
-            };
-            match db
-                .create_message(
-                    &data.sender_istid,
-                    &data.receiver_istid,
-                    &data.timestamp,
-                    &data.content,
-                    &secret_key,
-                    data.receive_counter,
-                    data.sent_counter,
-                    message.id,
-                )
-                .await
-            {
-                Ok(stored) => {
-                    if let Err(e) = sender.send(stored) {
-                        log::error!("Failed to send message notification to UI: {e}");
+            let event = match notification {
+                Notification::Message(message) => {
+                    let id = message.id;
+                    let keys = private_keys.borrow().clone();
+                    let delivery = handle_message(message, &db, &keys).await;
+                    if !matches!(delivery, Delivery::Failed) {
+                        let ack = serde_json::to_string(&ClientFrame::Ack { id })
+                            .expect("Failed to serialize ack frame");
+                        websocket.send(WSMessage::Text(ack)).await?;
+                    }
+                    match delivery {
+                        Delivery::Stored(stored) => NotificationEvent::Message(*stored),
+                        _ => continue,
                     }
                 }
-                Err(e) => {
-                    log::error!("Failed to store message in database: {e}")
+                Notification::Presence(presence) => NotificationEvent::Presence(presence),
+                Notification::KeyChanged(rotation) => NotificationEvent::KeyChanged(rotation),
+                Notification::ProfileUpdated(profile) => NotificationEvent::ProfileUpdated(profile),
+                Notification::AccountDeleted(id) => NotificationEvent::AccountDeleted(id),
+                Notification::GroupMessage(message) => {
+                    let keys = private_keys.borrow().clone();
+                    match handle_group_message(message, &db, &keys).await {
+                        Delivery::Stored(stored) => NotificationEvent::GroupMessage(*stored),
+                        _ => continue,
+                    }
                 }
+                Notification::GroupChanged(group) => NotificationEvent::GroupChanged(group),
+                Notification::GroupRemoved(id) => NotificationEvent::GroupRemoved(id),
+            };
+            if let Err(e) = sender.send(event) {
+                log::error!("Failed to send notification to UI: {e}");
             }
         }
     }
     Ok(())
 }
+
+async fn handle_message(
+    message: Message,
+    db: &Database,
+    private_keys: &[RsaPrivateKey],
+) -> Delivery {
+    log::info!("MESSAGE: {:?}", message);
+    let (data, secret_key) =
+        match MessageData::open(&message.contents, &message.secret_key, private_keys) {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("Couldn't decrypt message: {e}");
+                return Delivery::Rejected;
+            }
+        };
+    match db
+        .has_received_message(&data.receiver_istid, message.id)
+        .await
+    {
+        Ok(true) => {
+            log::info!("Message {} already stored, skipping", message.id);
+            return Delivery::Duplicate;
+        }
+        Ok(false) => (),
+        Err(e) => {
+            log::error!("Failed to check stored messages: {e}");
+            return Delivery::Failed;
+        }
+    }
+    match db
+        .create_message(&data, &secret_key, message.id, message.revision)
+        .await
+    {
+        Ok(stored) => Delivery::Stored(Box::new(stored)),
+        Err(e) => {
+            log::error!("Failed to store message in database: {e}");
+            Delivery::Failed
+        }
+    }
+}
+
+async fn handle_group_message(
+    message: GroupMessage,
+    db: &Database,
+    private_keys: &[RsaPrivateKey],
+) -> Delivery {
+    let (data, secret_key) = match MessageData::open_group(&message, private_keys) {
+        Ok(v) => v,
+        Err(e) => {
+            log::warn!("Couldn't open group message: {e}");
+            return Delivery::Rejected;
+        }
+    };
+    match db
+        .has_received_message(&data.receiver_istid, message.id)
+        .await
+    {
+        Ok(true) => return Delivery::Duplicate,
+        Ok(false) => (),
+        Err(e) => {
+            log::error!("Failed to check stored messages: {e}");
+            return Delivery::Failed;
+        }
+    }
+    match db
+        .create_group_message(
+            &data,
+            &secret_key,
+            message.id,
+            message.group_id,
+            message.revision,
+        )
+        .await
+    {
+        Ok(stored) => Delivery::Stored(Box::new(stored)),
+        Err(e) => {
+            log::error!("Failed to store group message in database: {e}");
+            Delivery::Failed
+        }
+    }
+}
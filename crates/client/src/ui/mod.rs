@@ -16,6 +16,7 @@ use widgets::logger::{LoggerWidget, LoggerWidgetState};
 use crate::{
     app::{App, AppEvent},
     logger::LoggerRecord,
+    notifications::NotificationEvent,
 };
 
 mod event_handler;
@@ -43,6 +44,8 @@ pub async fn run_ui<B: Backend>(
         }
         process_logs(&mut log_receiver, &mut logger_state);
         process_notifications(app).await?;
+        app.check_contact_keys_if_due().await;
+        app.expire_messages_if_due().await;
         if event::poll(RENDER_TICKRATE.saturating_sub(last_draw.elapsed()))? {
             process_input(&mut router, app, &mut logger_state).await?;
         }
@@ -84,26 +87,49 @@ fn process_logs(log_receiver: &mut UnboundedReceiver<LoggerRecord>, state: &mut
 
 async fn process_notifications(app: &mut App) -> anyhow::Result<()> {
     if let Some(receiver) = &mut app.notification_receiver {
-        if let Ok(message) = receiver.try_recv() {
-            log::info!("Received notification: {:?}", message);
-            let counter = message.sent_counter;
-            let last_counter = match app.messages.get(&message.sender_istid) {
-                Some(messages) => messages
-                    .iter()
-                    .map(|m| m.sent_counter)
-                    .max()
-                    .unwrap_or_default(),
-                None => 0,
-            };
-            if counter != last_counter + 1 {
-                log::warn!(
-                    "Received message with wrong counter, expected: {}, got: {}",
-                    last_counter + 1,
-                    counter
-                );
+        if let Ok(notification) = receiver.try_recv() {
+            log::info!("Received notification: {:?}", notification);
+            match notification {
+                NotificationEvent::Message(message) => {
+                    let counter = message.sent_counter;
+                    let last_counter = match app.messages.get(&message.sender_istid) {
+                        Some(messages) => messages
+                            .iter()
+                            .map(|m| m.sent_counter)
+                            .max()
+                            .unwrap_or_default(),
+                        None => 0,
+                    };
+                    if counter != last_counter + 1 && message.revision().is_none() {
+                        log::warn!(
+                            "Received message with wrong counter, expected: {}, got: {}",
+                            last_counter + 1,
+                            counter
+                        );
+                    }
+                    app.add_message(message.sender_istid.clone(), message.clone())
+                        .await?;
+                }
+                NotificationEvent::Presence(presence) => {
+                    app.presence.insert(presence.id.clone(), presence);
+                }
+                NotificationEvent::KeyChanged(rotation) => {
+                    app.handle_key_change(rotation).await?;
+                }
+                NotificationEvent::ProfileUpdated(profile) => {
+                    app.update_contact_name(&profile.id, &profile.name).await?;
+                }
+                NotificationEvent::AccountDeleted(id) => {
+                    app.mark_contact_deleted(&id).await?;
+                }
+                NotificationEvent::GroupMessage(message) => {
+                    app.add_group_message(message).await?;
+                }
+                NotificationEvent::GroupChanged(group) => app.update_group(group),
+                NotificationEvent::GroupRemoved(id) => {
+                    app.handle_group_removed(id).await?;
+                }
             }
-            app.add_message(message.sender_istid.clone(), message.clone())
-                .await?;
         }
     }
     Ok(())
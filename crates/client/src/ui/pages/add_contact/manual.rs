@@ -1,5 +1,6 @@
 use base64::Engine;
 use crossterm::event::{Event, KeyCode};
+use protocol::validation::{DisplayName, FieldResult, IstId};
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Layout, Rect},
@@ -224,6 +225,14 @@ impl AsyncStatefulEventHandler<Event> for ManualContactState {
                                     self.result = AddManualResult::Error(e.to_string());
                                     return;
                                 }
+                                let validation = IstId::parse(id)
+                                    .field("IST ID")
+                                    .and_then(|_| DisplayName::parse(name).field("Name"));
+                                if let Err(e) = validation {
+                                    self.result =
+                                        AddManualResult::Error(format!("{} {}", e.field, e.reason));
+                                    return;
+                                }
                                 if let Some(current_user) = &app.current_user {
                                     if current_user.id.to_lowercase() == id.to_lowercase() {
                                         self.result = AddManualResult::Error(
@@ -1,4 +1,5 @@
 use crossterm::event::{Event, KeyCode};
+use protocol::validation::IstId;
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Layout, Rect},
@@ -145,67 +146,90 @@ impl AsyncStatefulEventHandler<Event> for SearchContactState {
             self.text_box.handle_event(event.clone());
         }
         match event {
-            Event::Key(event) => {
-                match event.code {
-                    KeyCode::Up => self.selected_element = SelectedElement::TextBox,
-                    KeyCode::Down => self.selected_element = SelectedElement::Button,
-                    _ => {
-                        if self.selected_element == SelectedElement::Button {
-                            if event.code == KeyCode::Enter {
-                                let id = &self.text_box.text;
-                                if id.trim().is_empty() {
+            Event::Key(event) => match event.code {
+                KeyCode::Up => self.selected_element = SelectedElement::TextBox,
+                KeyCode::Down => self.selected_element = SelectedElement::Button,
+                _ => {
+                    if self.selected_element == SelectedElement::Button {
+                        if event.code == KeyCode::Enter {
+                            let id = &self.text_box.text;
+                            if let Err(e) = IstId::parse(id) {
+                                self.result = SearchContactResult::Error(format!("IST ID {}!", e));
+                                return;
+                            } else if let Some(user_id) = &app.current_user {
+                                if user_id.id.to_lowercase() == id.to_lowercase() {
                                     self.result = SearchContactResult::Error(
-                                        "IST ID cannot be empty!".to_string(),
+                                        "You cannot add yourself!".to_string(),
                                     );
                                     return;
-                                } else if let Some(user_id) = &app.current_user {
-                                    if user_id.id.to_lowercase() == id.to_lowercase() {
-                                        self.result = SearchContactResult::Error(
-                                            "You cannot add yourself!".to_string(),
-                                        );
-                                        return;
-                                    }
                                 }
-                                match app.net_client.get_user(id).await {
-                                    Ok(response) => {
-                                        match app
-                                            .add_contact(
-                                                &response.id,
-                                                &response.name,
-                                                &response.public_key,
-                                            )
-                                            .await
-                                        {
-                                            Ok(_) => {
-                                                self.result = SearchContactResult::Success(
-                                                    format!("Contact '{}' has ben added!", id),
-                                                );
-                                            }
-                                            Err(_) => self.result = SearchContactResult::Error(
+                            }
+                            match app.net_client.get_user(id).await {
+                                Ok(response)
+                                    if app.contacts.iter().any(|c| c.id == response.id) =>
+                                {
+                                    self.result = match app
+                                        .review_contact_key(&response.id, &response.public_key)
+                                        .await
+                                    {
+                                        Ok(true) => SearchContactResult::Error(format!(
+                                            "Contact '{}' already exists!",
+                                            id
+                                        )),
+                                        Ok(false) => SearchContactResult::Error(format!(
+                                            "The key of '{}' has changed, review it in the chat!",
+                                            id
+                                        )),
+                                        Err(e) => SearchContactResult::Error(format!("{}", e)),
+                                    };
+                                }
+                                Ok(response) => {
+                                    match app
+                                        .add_contact(
+                                            &response.id,
+                                            &response.name,
+                                            &response.public_key,
+                                        )
+                                        .await
+                                    {
+                                        Ok(_) => {
+                                            self.result = SearchContactResult::Success(format!(
+                                                "Contact '{}' has ben added!",
+                                                id
+                                            ));
+                                        }
+                                        Err(_) => {
+                                            self.result = SearchContactResult::Error(
                                                 "Failed to add contact, probably another exists?"
                                                     .to_string(),
-                                            ),
-                                        };
-                                    }
-                                    Err(e) => {
-                                        if let Some(status) = e.status() {
-                                            if status == StatusCode::NOT_FOUND {
-                                                self.result = SearchContactResult::Error(format!(
-                                                    "Contact with id '{}' not found",
-                                                    id
-                                                ));
-                                                return;
-                                            }
+                                            )
+                                        }
+                                    };
+                                }
+                                Err(e) => {
+                                    if let Some(status) = e.status() {
+                                        if status == StatusCode::NOT_FOUND {
+                                            self.result = SearchContactResult::Error(format!(
+                                                "Contact with id '{}' not found",
+                                                id
+                                            ));
+                                            return;
+                                        }
+                                        if status == StatusCode::TOO_MANY_REQUESTS {
+                                            self.result = SearchContactResult::Error(
+                                                "Too many searches, try again later!".to_string(),
+                                            );
+                                            return;
                                         }
-                                        self.result = SearchContactResult::Error(format!("{}", e));
-                                        return;
                                     }
+                                    self.result = SearchContactResult::Error(format!("{}", e));
+                                    return;
                                 }
                             }
                         }
                     }
                 }
-            }
+            },
             _ => (),
         }
     }
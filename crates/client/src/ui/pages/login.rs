@@ -107,21 +107,28 @@ impl AsyncStatefulEventHandler<AppEvent> for LoginPage {
                                     Ok(ws) => {
                                         let (sender, receiver) = mpsc::unbounded_channel();
                                         app.notification_receiver = Some(receiver);
-                                        let private_key =
-                                            app.current_user.as_ref().unwrap().private_key.clone();
+                                        let private_keys = app.private_keys.subscribe();
                                         let db_clone = app.db.as_ref().unwrap().clone();
                                         tokio::spawn(async move {
                                             if let Err(e) = notifications::notification_handler(
                                                 ws,
                                                 sender,
                                                 db_clone,
-                                                private_key,
+                                                private_keys,
                                             )
                                             .await
                                             {
                                                 log::error!("Websocket terminated due to {}", e);
                                             }
                                         });
+                                        let contacts =
+                                            app.contacts.iter().map(|c| c.id.clone()).collect();
+                                        app.subscribe_presence(contacts).await;
+                                        app.check_contact_keys();
+                                        app.load_presence_settings().await;
+                                        app.load_blocks().await;
+                                        app.load_requests().await;
+                                        app.load_groups().await;
                                         app.current_page = Pages::Main;
                                     }
                                     Err(e) => {
@@ -137,12 +144,13 @@ impl AsyncStatefulEventHandler<AppEvent> for LoginPage {
                             Err(e) => match e {
                                 LoginError::RequestError(e) => {
                                     if let Some(status) = e.status() {
-                                        if status == StatusCode::NOT_FOUND {
-                                            self.state.login_result = LoginResult::UserNotFound;
+                                        if status == StatusCode::UNAUTHORIZED {
+                                            self.state.login_result =
+                                                LoginResult::InvalidCredentials;
                                             return;
                                         }
-                                        if status == StatusCode::UNAUTHORIZED {
-                                            self.state.login_result = LoginResult::WrongPassword;
+                                        if status == StatusCode::TOO_MANY_REQUESTS {
+                                            self.state.login_result = LoginResult::TooManyAttempts;
                                             return;
                                         }
                                     }
@@ -14,8 +14,8 @@ pub enum LoginResult {
     None,
     UsernameEmpty,
     PasswordEmpty,
-    UserNotFound,
-    WrongPassword,
+    InvalidCredentials,
+    TooManyAttempts,
     Error,
 }
 
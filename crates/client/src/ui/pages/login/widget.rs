@@ -74,8 +74,8 @@ fn create_notificaton<'a>(result: LoginResult, app: &mut App) -> Option<Paragrap
         }
         LoginResult::UsernameEmpty => ("IST ID is required!", error_style),
         LoginResult::PasswordEmpty => ("Password is required!", error_style),
-        LoginResult::UserNotFound => ("IST ID not registered!", error_style),
-        LoginResult::WrongPassword => ("Wrong password", error_style),
+        LoginResult::InvalidCredentials => ("Invalid IST ID or password!", error_style),
+        LoginResult::TooManyAttempts => ("Too many attempts, try again later!", error_style),
         LoginResult::Error => ("An error ocurred processing your request!", error_style),
     };
     Some(Paragraph::new(text).style(style).centered())
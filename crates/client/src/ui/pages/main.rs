@@ -1,17 +1,21 @@
-use crossterm::event::{Event, KeyCode};
+use std::cmp;
+
+use crossterm::event::{Event, KeyCode, KeyModifiers};
 use logout_popup::LogoutPopupState;
-use profile_popup::ProfilePopupState;
 use ratatui::layout::{self, Constraint, Rect};
 use state::{MainState, MessagesTab, SelectedTab, ShowingPopup};
+use tabs::{contacts::ContactsTabState, messages::MessagesTabState, requests::RequestsTabState};
 
 use crate::{
     app::{App, AppEvent, Pages},
     db::structs::Contact,
+    expiry::parse_timer,
     ui::event_handler::{AsyncStatefulEventHandler, EventHandler},
 };
 
+const QUICK_REACTIONS: [&str; 5] = ["👍", "❤", "😂", "😮", "😢"];
+
 mod logout_popup;
-mod profile_popup;
 mod state;
 mod tabs;
 mod widget;
@@ -50,9 +54,9 @@ pub struct MainPage {
 }
 
 impl MainPage {
-    pub fn new(contacts: Vec<Contact>, chats: Vec<String>) -> Self {
+    pub fn new(contacts: Vec<Contact>, chats: Vec<String>, n_requests: usize) -> Self {
         Self {
-            state: MainState::new(contacts, chats),
+            state: MainState::new(contacts, chats, n_requests),
         }
     }
 }
@@ -67,14 +71,6 @@ impl AsyncStatefulEventHandler<AppEvent> for MainPage {
                     self.handle_popup(event, app, logout.clone()).await;
                     return;
                 }
-                if let ShowingPopup::Profile(_) = self.state.poup {
-                    if let Event::Key(event) = event {
-                        if event.code == KeyCode::Esc {
-                            self.state.poup = ShowingPopup::None;
-                        }
-                    }
-                    return;
-                }
                 if let Event::Key(event) = event {
                     let key = event.code;
                     match key {
@@ -96,10 +92,39 @@ impl AsyncStatefulEventHandler<AppEvent> for MainPage {
                             if !(self.state.selected_tab
                                 == SelectedTab::Messages(MessagesTab::ChatMessages))
                             {
-                                self.state.poup = ShowingPopup::Profile(ProfilePopupState::new(
-                                    app.theme.background,
-                                ));
+                                app.load_sessions().await;
+                                app.current_page = Pages::Profile;
+                            }
+                        }
+                        KeyCode::Char('b') | KeyCode::Char('B')
+                            if self.state.selected_tab == SelectedTab::Contacts =>
+                        {
+                            let Some(contact_id) = self
+                                .state
+                                .contacts_state
+                                .selected()
+                                .and_then(|index| app.contacts.get(index))
+                                .map(|c| c.id.clone())
+                            else {
+                                return;
+                            };
+                            if let Err(e) = app.toggle_block(&contact_id).await {
+                                log::error!("Failed to update block of {contact_id}: {e}");
                             }
+                            let n_chats = app.chat_ids().count();
+                            self.state.messages_state = MessagesTabState::new(n_chats);
+                            return;
+                        }
+                        KeyCode::Char('a')
+                        | KeyCode::Char('A')
+                        | KeyCode::Char('d')
+                        | KeyCode::Char('D')
+                        | KeyCode::Char('x')
+                        | KeyCode::Char('X')
+                            if self.state.selected_tab == SelectedTab::Requests =>
+                        {
+                            self.handle_request(key, app).await;
+                            return;
                         }
                         KeyCode::Char('+') => {
                             if !(self.state.selected_tab
@@ -108,6 +133,28 @@ impl AsyncStatefulEventHandler<AppEvent> for MainPage {
                                 app.current_page = Pages::AddContact;
                             }
                         }
+                        KeyCode::Char('g') | KeyCode::Char('G')
+                            if !(self.state.selected_tab
+                                == SelectedTab::Messages(MessagesTab::ChatMessages)) =>
+                        {
+                            app.current_page = Pages::NewGroup;
+                        }
+                        KeyCode::Up
+                            if !event.modifiers.contains(KeyModifiers::SHIFT)
+                                && self.state.selected_tab
+                                    == SelectedTab::Messages(MessagesTab::ChatMessages)
+                                && self.state.messages_state.at_oldest() =>
+                        {
+                            self.load_older_messages(app).await;
+                        }
+                        KeyCode::Char(digit @ '1'..='5')
+                            if event.modifiers.contains(KeyModifiers::ALT)
+                                && self.state.selected_tab
+                                    == SelectedTab::Messages(MessagesTab::ChatMessages) =>
+                        {
+                            self.react(app, digit).await;
+                            return;
+                        }
                         KeyCode::Enter => {
                             if self.state.selected_tab
                                 == SelectedTab::Messages(MessagesTab::ChatMessages)
@@ -118,10 +165,35 @@ impl AsyncStatefulEventHandler<AppEvent> for MainPage {
                                 let (contact_index, message) =
                                     self.state.messages_state.get_send_message().unwrap();
 
-                                if app.messages.keys().nth(contact_index).is_none() {
+                                if app.chat_ids().nth(contact_index).is_none() {
+                                    return;
+                                }
+                                let contact_id = app.chat_ids().nth(contact_index).unwrap();
+                                let chat_id = contact_id.clone();
+                                if let Some(result) =
+                                    handle_chat_command(app, &chat_id, &message).await
+                                {
+                                    match result {
+                                        Ok(_) => self.state.messages_state.clear_input(),
+                                        Err(e) => log::warn!("Failed to handle chat command: {e}"),
+                                    }
+                                    return;
+                                }
+                                if let Some(reply_to) = self.state.messages_state.replying_to() {
+                                    match app.reply(&chat_id, &message, reply_to).await {
+                                        Ok(_) => self.state.messages_state.clear_input(),
+                                        Err(e) => log::warn!("Failed to send reply: {e}"),
+                                    }
+                                    return;
+                                }
+                                let contact_id = app.chat_ids().nth(contact_index).unwrap();
+                                if app.groups.contains_key(contact_id) {
+                                    match handle_group_input(app, &chat_id, &message).await {
+                                        Ok(_) => self.state.messages_state.clear_input(),
+                                        Err(e) => log::warn!("Failed to send group message: {e}"),
+                                    }
                                     return;
                                 }
-                                let contact_id = app.messages.keys().nth(contact_index).unwrap();
                                 // iterate through contacts and find the contact with the same id
                                 let contact = app
                                     .contacts
@@ -136,16 +208,41 @@ impl AsyncStatefulEventHandler<AppEvent> for MainPage {
                                     Ok(_) => {
                                         self.state.messages_state.clear_input();
                                     }
-                                    Err(_) => {}
+                                    Err(e) => log::warn!("Failed to send message: {e}"),
                                 }
                             }
                         }
+                        KeyCode::F(2)
+                            if self.state.selected_tab
+                                == SelectedTab::Messages(MessagesTab::ChatMessages) =>
+                        {
+                            let Some(contact_index) =
+                                self.state.messages_state.opened_contact_index()
+                            else {
+                                return;
+                            };
+                            let Some(contact_id) = app.chat_ids().nth(contact_index).cloned()
+                            else {
+                                return;
+                            };
+                            let pending = app
+                                .contacts
+                                .iter()
+                                .any(|c| c.id == contact_id && c.pending_key.is_some());
+                            if pending {
+                                if let Err(e) = app.accept_contact_key(&contact_id).await {
+                                    log::error!("Failed to accept key of {contact_id}: {e}");
+                                }
+                            }
+                            return;
+                        }
                         _ => (),
                     }
                 }
                 match self.state.selected_tab {
                     SelectedTab::Messages(_) => self.state.messages_state.handle_event(event),
                     SelectedTab::Contacts => self.state.contacts_state.handle_event(event),
+                    SelectedTab::Requests => self.state.requests_state.handle_event(event),
                 }
             }
             _ => (),
@@ -154,6 +251,59 @@ impl AsyncStatefulEventHandler<AppEvent> for MainPage {
 }
 
 impl MainPage {
+    fn opened_chat_id(&self, app: &App) -> Option<String> {
+        self.state
+            .messages_state
+            .opened_contact_index()
+            .and_then(|index| app.chat_ids().nth(index))
+            .cloned()
+    }
+
+    async fn load_older_messages(&mut self, app: &mut App) {
+        let Some(chat_id) = self.opened_chat_id(app) else {
+            return;
+        };
+        if let Err(e) = app.load_older_messages(&chat_id).await {
+            log::warn!("Failed to load older messages of {chat_id}: {e}");
+        }
+    }
+
+    async fn react(&mut self, app: &mut App, digit: char) {
+        let Some(target) = self.state.messages_state.selected_message() else {
+            return;
+        };
+        let Some(chat_id) = self.opened_chat_id(app) else {
+            return;
+        };
+        let emoji = QUICK_REACTIONS[digit as usize - '1' as usize];
+        if let Err(e) = app.react(&chat_id, target, emoji).await {
+            log::warn!("Failed to react in {chat_id}: {e}");
+        }
+    }
+
+    async fn handle_request(&mut self, key: KeyCode, app: &mut App) {
+        let Some(sender_id) = self
+            .state
+            .requests_state
+            .selected()
+            .and_then(|index| app.requests.keys().nth(index))
+            .cloned()
+        else {
+            return;
+        };
+        let result = match key {
+            KeyCode::Char('a') | KeyCode::Char('A') => app.accept_request(&sender_id).await,
+            KeyCode::Char('x') | KeyCode::Char('X') => app.decline_request(&sender_id, true).await,
+            _ => app.decline_request(&sender_id, false).await,
+        };
+        if let Err(e) = result {
+            log::error!("Failed to answer request from {sender_id}: {e}");
+        }
+        self.state.requests_state = RequestsTabState::new(app.requests.len());
+        self.state.messages_state = MessagesTabState::new(app.chat_ids().count());
+        self.state.contacts_state = ContactsTabState::new(cmp::max(app.contacts.len(), 1));
+    }
+
     async fn handle_popup(&mut self, event: Event, app: &mut App, mut logout: LogoutPopupState) {
         logout.handle_event(event.clone());
         self.state.poup = ShowingPopup::Logout(logout.clone());
@@ -172,3 +322,50 @@ impl MainPage {
         }
     }
 }
+
+async fn handle_chat_command(
+    app: &mut App,
+    chat_id: &str,
+    input: &str,
+) -> Option<anyhow::Result<()>> {
+    let input = input.trim();
+    if let Some(path) = input.strip_prefix("/attach ") {
+        return Some(app.send_attachment(chat_id, path.trim()).await);
+    }
+    if let Some(content) = input.strip_prefix("/edit ") {
+        return Some(app.edit_last_message(chat_id, content.trim()).await);
+    }
+    if input == "/delete" {
+        return Some(app.delete_last_message(chat_id).await);
+    }
+    if let Some(value) = input.strip_prefix("/timer ") {
+        return Some(match parse_timer(value.trim()) {
+            Some(seconds) => app.set_chat_timer(chat_id, seconds).await,
+            None => Err(anyhow::Error::msg(
+                "Invalid timer, use e.g. 30m, 1h, 1d, 1w or off, at most 4w",
+            )),
+        });
+    }
+    match input.strip_prefix("/save") {
+        Some("") => Some(app.save_attachment(chat_id, None).await),
+        Some(dir) if dir.starts_with(' ') => {
+            Some(app.save_attachment(chat_id, Some(dir.trim())).await)
+        }
+        _ => None,
+    }
+}
+
+async fn handle_group_input(app: &mut App, chat_id: &str, input: &str) -> anyhow::Result<()> {
+    let mut words = input.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("/invite"), Some(user_id)) => app.invite_to_group(chat_id, user_id).await,
+        (Some("/kick"), Some(user_id)) => app.remove_from_group(chat_id, user_id).await,
+        (Some("/admin"), Some(user_id)) => app.set_group_admin(chat_id, user_id, true).await,
+        (Some("/unadmin"), Some(user_id)) => app.set_group_admin(chat_id, user_id, false).await,
+        (Some("/leave"), None) => {
+            let user_id = app.current_user.as_ref().unwrap().id.clone();
+            app.remove_from_group(chat_id, &user_id).await
+        }
+        _ => app.send_group_message(chat_id, input).await,
+    }
+}
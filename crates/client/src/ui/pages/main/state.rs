@@ -4,14 +4,14 @@ use crate::db::structs::Contact;
 
 use super::{
     logout_popup::LogoutPopupState,
-    profile_popup::ProfilePopupState,
-    tabs::{contacts::ContactsTabState, messages::MessagesTabState},
+    tabs::{contacts::ContactsTabState, messages::MessagesTabState, requests::RequestsTabState},
 };
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum SelectedTab {
     Messages(MessagesTab),
     Contacts,
+    Requests,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -24,13 +24,13 @@ pub enum MessagesTab {
 pub enum ShowingPopup {
     None,
     Logout(LogoutPopupState),
-    Profile(ProfilePopupState),
 }
 
 pub struct MainState {
     pub selected_tab: SelectedTab,
     pub contacts_state: ContactsTabState,
     pub messages_state: MessagesTabState,
+    pub requests_state: RequestsTabState,
     pub poup: ShowingPopup,
 }
 
@@ -38,7 +38,8 @@ impl MainState {
     pub fn switch_tab(&mut self) {
         self.selected_tab = match self.selected_tab {
             SelectedTab::Messages(_) => SelectedTab::Contacts,
-            SelectedTab::Contacts => SelectedTab::Messages(MessagesTab::Chatlist),
+            SelectedTab::Contacts => SelectedTab::Requests,
+            SelectedTab::Requests => SelectedTab::Messages(MessagesTab::Chatlist),
         };
     }
 
@@ -62,12 +63,13 @@ impl MainState {
 }
 
 impl MainState {
-    pub fn new(contacts: Vec<Contact>, chats: Vec<String>) -> Self {
+    pub fn new(contacts: Vec<Contact>, chats: Vec<String>, n_requests: usize) -> Self {
         let n_contacts = cmp::max(contacts.len(), 1);
         Self {
             selected_tab: SelectedTab::Messages(MessagesTab::Chatlist),
             contacts_state: ContactsTabState::new(n_contacts),
             messages_state: MessagesTabState::new(chats.len()),
+            requests_state: RequestsTabState::new(n_requests),
             poup: ShowingPopup::None,
         }
     }
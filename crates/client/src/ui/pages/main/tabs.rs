@@ -1,2 +1,3 @@
 pub mod contacts;
 pub mod messages;
+pub mod requests;
@@ -13,6 +13,7 @@ use crate::{
         widgets::{
             contact_info::ContactInfo,
             contact_list::{ContactList, ContactListState},
+            presence::last_seen_text,
             title::TitleWidget,
         },
     },
@@ -28,6 +29,10 @@ impl ContactsTabState {
             list_state: ContactListState::new(size),
         }
     }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.list_state.selected()
+    }
 }
 
 pub struct ContactsTab<'a> {
@@ -60,6 +65,7 @@ impl<'a> StatefulWidget for ContactsTab<'a> {
             .style(self.app.theme.text_style().add_modifier(Modifier::BOLD));
         title.render(header_area, buf);
         let list = ContactList::new(&self.app.contacts)
+            .presence(&self.app.presence)
             .with_scrollbar()
             .selected_prefix(" >> ")
             .selected_style(self.app.theme.accent_style().add_modifier(Modifier::BOLD))
@@ -67,7 +73,12 @@ impl<'a> StatefulWidget for ContactsTab<'a> {
         list.render(list_area, buf, &mut state.list_state);
         if let Some(selected) = state.list_state.selected() {
             if let Some(contact) = self.app.contacts.get(selected) {
+                let status = match self.app.blocked.contains(&contact.id) {
+                    true => "blocked".to_string(),
+                    false => last_seen_text(self.app.presence.get(&contact.id)),
+                };
                 let contact_info = ContactInfo::from(contact)
+                    .status(status)
                     .picture_style(self.app.theme.accent_style())
                     .name_style(self.app.theme.warn_style())
                     .id_style(self.app.theme.subtext_stye())
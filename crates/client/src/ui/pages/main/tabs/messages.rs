@@ -8,6 +8,7 @@ use ratatui::{
 
 use crate::{
     app::App,
+    message_data::MessageRef,
     ui::{
         event_handler::EventHandler,
         widgets::{
@@ -63,6 +64,22 @@ impl MessagesTabState {
     pub fn clear_input(&mut self) {
         self.openchat_state.clear_input();
     }
+
+    pub fn opened_contact_index(&self) -> Option<usize> {
+        self.chatlist_state.selected()
+    }
+
+    pub fn selected_message(&self) -> Option<MessageRef> {
+        self.openchat_state.selected_message()
+    }
+
+    pub fn replying_to(&self) -> Option<MessageRef> {
+        self.openchat_state.replying_to()
+    }
+
+    pub fn at_oldest(&self) -> bool {
+        self.openchat_state.at_oldest()
+    }
 }
 
 pub struct MessagesTab<'a> {
@@ -92,9 +109,15 @@ impl<'a> StatefulWidget for MessagesTab<'a> {
         header.render(header_area, buf);
         let list: DMList<'_> = match state.selected_page {
             SelectedPage::Chatlist => DMList::new(&self.app.messages)
+                .hidden(&self.app.blocked)
+                .presence(&self.app.presence)
+                .groups(&self.app.groups)
                 .with_scrollbar()
                 .inner_block(Block::bordered().border_type(BorderType::Thick)),
             SelectedPage::ChatMessages => DMList::new(&self.app.messages)
+                .hidden(&self.app.blocked)
+                .presence(&self.app.presence)
+                .groups(&self.app.groups)
                 .with_scrollbar()
                 .inner_block(Block::bordered()),
         };
@@ -118,7 +141,7 @@ impl<'a> StatefulWidget for MessagesTab<'a> {
         let selected_chat = state.chatlist_state.selected().unwrap();
 
         // get the contact id associated with the selected chat
-        let keys: Vec<&String> = self.app.messages.keys().collect();
+        let keys: Vec<&String> = self.app.chat_ids().collect();
 
         // if the selected chat index is out of bounds, render a tip
         if selected_chat >= keys.len() {
@@ -127,6 +150,24 @@ impl<'a> StatefulWidget for MessagesTab<'a> {
         }
 
         let selected_chat_contact_id = keys[selected_chat];
+        let sender_id = self.app.current_user.as_ref().unwrap().id.as_str();
+        let reactions = match self.app.reactions.get(selected_chat_contact_id) {
+            Some(reactions) => reactions.as_slice(),
+            None => &[],
+        };
+
+        // group chats are rendered from the group itself, they have no contact
+        if let Some(group) = self.app.groups.get(selected_chat_contact_id) {
+            let messages = match self.app.messages.get(selected_chat_contact_id) {
+                Some(messages) => messages.as_slice(),
+                None => &[],
+            };
+            let opened_chat = OpenedChat::group(group, messages, &sender_id)
+                .timer(self.app.chat_timer(selected_chat_contact_id))
+                .reactions(reactions);
+            opened_chat.render(messages_area, buf, &mut state.openchat_state);
+            return;
+        }
 
         // get the contact id of the selected chat
         // filter the contacts to get the contact with the selected chat id
@@ -145,11 +186,11 @@ impl<'a> StatefulWidget for MessagesTab<'a> {
         let opened_contact = opened_contact.unwrap();
 
         if let Some(messages) = &self.app.messages.get(opened_contact.id.as_str()) {
-            let sender_id = self.app.current_user.as_ref().unwrap().id.as_str();
-            let opened_chat = OpenedChat::new(&opened_contact, messages, &sender_id);
+            let opened_chat = OpenedChat::new(&opened_contact, messages, &sender_id)
+                .timer(self.app.chat_timer(&opened_contact.id))
+                .reactions(reactions);
             opened_chat.render(messages_area, buf, &mut state.openchat_state);
         } else {
-            let sender_id = self.app.current_user.as_ref().unwrap().id.as_str();
             let opened_chat = OpenedChat::new(&opened_contact, &[], &sender_id);
             opened_chat.render(messages_area, buf, &mut state.openchat_state);
         }
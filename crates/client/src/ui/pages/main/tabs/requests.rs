@@ -0,0 +1,103 @@
+use crossterm::event::Event;
+use ratatui::{
+    buffer::Buffer,
+    layout::{Alignment, Constraint, Layout, Rect},
+    style::Modifier,
+    text::Line,
+    widgets::{Block, BorderType, Paragraph, StatefulWidget, Widget, Wrap},
+};
+
+use crate::{
+    app::App,
+    ui::{
+        event_handler::EventHandler,
+        widgets::{
+            dm_list::{DMList, DMListState},
+            title::TitleWidget,
+        },
+    },
+};
+
+const TIP_NO_REQUEST: &str = "Select a request to read it";
+const REQUEST_ACTIONS: &str = "'a' to accept, 'd' to decline, 'x' to decline and block";
+
+pub struct RequestsTabState {
+    list_state: DMListState,
+}
+
+impl RequestsTabState {
+    pub fn new(size: usize) -> Self {
+        Self {
+            list_state: DMListState::new(size),
+        }
+    }
+
+    pub fn selected(&self) -> Option<usize> {
+        self.list_state.selected()
+    }
+}
+
+pub struct RequestsTab<'a> {
+    app: &'a mut App,
+}
+
+impl<'a> RequestsTab<'a> {
+    pub fn new(app: &'a mut App) -> Self {
+        Self { app }
+    }
+}
+
+impl<'a> StatefulWidget for RequestsTab<'a> {
+    type State = RequestsTabState;
+
+    fn render(self, area: Rect, buf: &mut Buffer, state: &mut Self::State) {
+        let [side_bar, info_area] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .areas(area);
+        let [header_area, list_area] =
+            Layout::vertical([Constraint::Length(5), Constraint::Min(0)]).areas(side_bar);
+        let [_, preview_area] =
+            Layout::vertical([Constraint::Length(5), Constraint::Min(0)]).areas(info_area);
+        let title = TitleWidget::new("REQUESTS")
+            .block(
+                Block::bordered()
+                    .border_type(BorderType::Thick)
+                    .style(self.app.theme.text_style()),
+            )
+            .style(self.app.theme.text_style().add_modifier(Modifier::BOLD));
+        title.render(header_area, buf);
+        let list = DMList::new(&self.app.requests)
+            .with_scrollbar()
+            .inner_block(Block::bordered().border_type(BorderType::Thick));
+        list.render(list_area, buf, &mut state.list_state);
+
+        let selected = state
+            .list_state
+            .selected()
+            .and_then(|index| self.app.requests.iter().nth(index));
+        let Some((sender_id, messages)) = selected else {
+            Paragraph::new(TIP_NO_REQUEST)
+                .alignment(Alignment::Center)
+                .style(self.app.theme.subtext_stye())
+                .render(preview_area, buf);
+            return;
+        };
+        let mut lines: Vec<Line> = messages
+            .iter()
+            .map(|m| Line::from(format!("{}: {}", m.sender_istid, m.content)))
+            .collect();
+        lines.push(Line::default());
+        lines.push(Line::from(REQUEST_ACTIONS).style(self.app.theme.subtext_stye()));
+        let preview = Paragraph::new(lines)
+            .wrap(Wrap { trim: false })
+            .style(self.app.theme.text_style())
+            .block(Block::bordered().title(format!(" Message request from {} ", sender_id)));
+        preview.render(preview_area, buf);
+    }
+}
+
+impl EventHandler<Event> for RequestsTabState {
+    fn handle_event(&mut self, event: Event) {
+        self.list_state.handle_event(event);
+    }
+}
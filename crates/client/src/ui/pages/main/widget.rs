@@ -1,4 +1,3 @@
-use base64::Engine as _;
 use ratatui::{
     buffer::Buffer,
     layout::Rect,
@@ -11,9 +10,8 @@ use crate::{app::App, ui::widgets::messagist_text::MessageISTText};
 
 use super::{
     logout_popup::LogoutPopup,
-    profile_popup::ProfilePopup,
     state::{SelectedTab, ShowingPopup},
-    tabs::{contacts::ContactsTab, messages::MessagesTab},
+    tabs::{contacts::ContactsTab, messages::MessagesTab, requests::RequestsTab},
     Layout, MainPage,
 };
 
@@ -23,7 +21,7 @@ impl StatefulWidget for &mut MainPage {
     fn render(self, area: Rect, buf: &mut Buffer, app: &mut Self::State) {
         let layout = Layout::new(area);
         let title = MessageISTText::new();
-        let footer = Paragraph::new("Use TAB to cycle between tabs, arrow keys for navigation, '+' to add a contact, 'u' to check your profile.")
+        let footer = Paragraph::new("Use TAB to cycle between tabs, arrow keys for navigation, '+' to add a contact, 'g' to create a group, 'b' to block or unblock a contact, 'a'/'d'/'x' to accept, decline or block a request, 'u' to edit your profile. In a chat, type /attach <path> to send a file /save to store the last one, /edit <text> or /delete to change your last message and /timer <1h|1d|1w|off> for disappearing messages. Select a message with SHIFT+arrows, reply with CTRL+R and react with ALT+1-5.")
             .centered()
             .style(app.theme.subtext_stye());
         title.render(layout.title, buf);
@@ -31,11 +29,16 @@ impl StatefulWidget for &mut MainPage {
         let block = Block::bordered().border_type(BorderType::Thick);
         let inner = block.inner(layout.body);
         block.render(layout.body, buf);
-        let mut tabs = Tabs::new(["  Messages  ", "  Contacts  "])
-            .padding("  ", "  ")
-            .divider("<->")
-            .style(app.theme.text_style())
-            .highlight_style(app.theme.accent_style().add_modifier(Modifier::BOLD));
+        let requests = format!("  Requests ({})  ", app.requests.len());
+        let mut tabs = Tabs::new([
+            "  Messages  ".to_string(),
+            "  Contacts  ".to_string(),
+            requests,
+        ])
+        .padding("  ", "  ")
+        .divider("<->")
+        .style(app.theme.text_style())
+        .highlight_style(app.theme.accent_style().add_modifier(Modifier::BOLD));
         match self.state.selected_tab {
             SelectedTab::Messages(_) => {
                 let widget = MessagesTab::new(app);
@@ -47,6 +50,11 @@ impl StatefulWidget for &mut MainPage {
                 widget.render(inner, buf, &mut self.state.contacts_state);
                 tabs = tabs.select(1);
             }
+            SelectedTab::Requests => {
+                let widget = RequestsTab::new(app);
+                widget.render(inner, buf, &mut self.state.requests_state);
+                tabs = tabs.select(2);
+            }
         };
         match &self.state.poup {
             ShowingPopup::None => (),
@@ -57,19 +65,6 @@ impl StatefulWidget for &mut MainPage {
                 widget.render(area, buf, &mut state);
                 self.state.poup = ShowingPopup::Logout(state);
             }
-            ShowingPopup::Profile(state) => {
-                let mut state = state.clone();
-                let area = area.inner_centered(area.width / 3, area.height - area.height / 10);
-                let user = app.current_user.as_ref().expect("No user");
-
-                let pub_key_bytes = cryptolib::utils::public_key_to_bytes(user.public_key.clone())
-                    .expect("Failed to transform key to bytes");
-                let pub_key = base64::engine::general_purpose::STANDARD.encode(&pub_key_bytes);
-
-                let widget = ProfilePopup::new(&user.name, &user.id, &pub_key, &app.theme);
-                widget.render(area, buf, &mut state);
-                self.state.poup = ShowingPopup::Profile(state);
-            }
         }
         tabs.render(layout.tabs, buf);
     }
@@ -3,4 +3,6 @@ pub mod connect;
 pub mod entry;
 pub mod login;
 pub mod main;
+pub mod new_group;
+pub mod profile;
 pub mod register;
@@ -0,0 +1,221 @@
+use crossterm::event::{Event, KeyCode};
+use protocol::validation::{DisplayName, FieldResult, IstId};
+use ratatui::{
+    buffer::Buffer,
+    layout::{Constraint, Layout, Rect},
+    text::Line,
+    widgets::{Block, BorderType, Paragraph, StatefulWidget, Widget},
+};
+
+use crate::{
+    app::{App, AppEvent, Pages},
+    ui::{
+        event_handler::{AsyncStatefulEventHandler, EventHandler},
+        widgets::{
+            button::{Button, ButtonState},
+            cursor::Cursor,
+            text_box::{TextBox, TextBoxState},
+        },
+    },
+};
+
+const FOOTER: &str = "Use TAB to switch between fields, ENTER to create the group, ESC to return";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FocusedElement {
+    Name,
+    Members,
+    Create,
+}
+
+#[derive(Clone)]
+enum NewGroupResult {
+    None,
+    Success(String),
+    Error(String),
+}
+
+pub struct NewGroupPage {
+    name_text_box: TextBoxState,
+    members_text_box: TextBoxState,
+    focused: FocusedElement,
+    result: NewGroupResult,
+}
+
+impl NewGroupPage {
+    pub fn new() -> Self {
+        Self {
+            name_text_box: TextBoxState::default(),
+            members_text_box: TextBoxState::default(),
+            focused: FocusedElement::Name,
+            result: NewGroupResult::None,
+        }
+    }
+
+    fn next_focus(&mut self) {
+        self.focused = match self.focused {
+            FocusedElement::Name => FocusedElement::Members,
+            FocusedElement::Members => FocusedElement::Create,
+            FocusedElement::Create => FocusedElement::Create,
+        }
+    }
+
+    fn prev_focus(&mut self) {
+        self.focused = match self.focused {
+            FocusedElement::Name => FocusedElement::Name,
+            FocusedElement::Members => FocusedElement::Name,
+            FocusedElement::Create => FocusedElement::Members,
+        }
+    }
+
+    async fn create_group(&mut self, app: &mut App) {
+        let name = self.name_text_box.text.trim().to_string();
+        let members: Vec<String> = self
+            .members_text_box
+            .text
+            .split(',')
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+            .collect();
+        if let Err(e) = DisplayName::parse(&name).field("Name") {
+            self.result = NewGroupResult::Error(format!("{} {}", e.field, e.reason));
+            return;
+        }
+        if let Some(Err(e)) = members
+            .iter()
+            .map(|id| IstId::parse(id).field("Member"))
+            .find(|r| r.is_err())
+        {
+            self.result = NewGroupResult::Error(format!("{} {}", e.field, e.reason));
+            return;
+        }
+        match app.create_group(&name, &members).await {
+            Ok(_) => {
+                self.name_text_box.clear();
+                self.members_text_box.clear();
+                self.result = NewGroupResult::Success(format!("Group {name} has been created!"));
+            }
+            Err(e) => {
+                log::error!("Failed to create group: {e}");
+                self.result = NewGroupResult::Error(format!("Failed to create group: {e}"));
+            }
+        }
+    }
+}
+
+impl StatefulWidget for &mut NewGroupPage {
+    type State = App;
+
+    fn render(self, area: Rect, buf: &mut Buffer, app: &mut Self::State) {
+        let [_, header, _, notification, body, footer, _] = Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(3),
+            Constraint::Min(11),
+            Constraint::Length(1),
+            Constraint::Length(2),
+        ])
+        .areas(area);
+        let [_, form, _] = Layout::horizontal([
+            Constraint::Fill(1),
+            Constraint::Percentage(40),
+            Constraint::Fill(1),
+        ])
+        .areas(body);
+        let [name_area, members_area, create_area] = Layout::vertical([Constraint::Length(3); 3])
+            .margin(1)
+            .areas(form);
+
+        Line::from("New group")
+            .centered()
+            .style(app.theme.accent_style())
+            .render(header, buf);
+        Paragraph::new(FOOTER)
+            .centered()
+            .style(app.theme.subtext_stye())
+            .render(footer, buf);
+        let (text, style) = match &self.result {
+            NewGroupResult::None => (None, app.theme.text_style()),
+            NewGroupResult::Success(msg) => (Some(msg), app.theme.success_style()),
+            NewGroupResult::Error(msg) => (Some(msg), app.theme.error_style()),
+        };
+        if let Some(text) = text {
+            Paragraph::new(text.clone())
+                .style(style)
+                .centered()
+                .render(notification, buf);
+        }
+
+        let base_block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .style(app.theme.text_style());
+        let name_block = base_block.clone().title("Name");
+        let members_block = base_block.title("Members (comma separated IST IDs)");
+        let mut name = TextBox::new()
+            .block(name_block.clone())
+            .style(app.theme.text_style());
+        let mut members = TextBox::new()
+            .block(members_block.clone())
+            .placeholder(" e.g ist1112270, ist1103254")
+            .placeholder_style(app.theme.subtext_stye())
+            .style(app.theme.text_style());
+        let mut create = Button::new("Create Group").colors(app.theme.button_colors());
+        let cursor = Cursor::default().style(app.theme.text_style());
+        match self.focused {
+            FocusedElement::Name => {
+                name = name
+                    .cursor(cursor)
+                    .block(name_block.style(app.theme.accent_style()))
+            }
+            FocusedElement::Members => {
+                members = members
+                    .cursor(cursor)
+                    .block(members_block.style(app.theme.accent_style()))
+            }
+            FocusedElement::Create => create = create.state(ButtonState::Selected),
+        }
+        name.render(name_area, buf, &mut self.name_text_box);
+        members.render(members_area, buf, &mut self.members_text_box);
+        create.render(create_area, buf);
+    }
+}
+
+impl AsyncStatefulEventHandler<AppEvent> for NewGroupPage {
+    type State = App;
+
+    async fn handle_event(&mut self, event: AppEvent, app: &mut Self::State) {
+        if let AppEvent::Input(event) = event {
+            if let Event::Key(key) = &event {
+                match key.code {
+                    KeyCode::Down | KeyCode::Tab => {
+                        self.next_focus();
+                        return;
+                    }
+                    KeyCode::Up | KeyCode::BackTab => {
+                        self.prev_focus();
+                        return;
+                    }
+                    KeyCode::Esc => {
+                        app.current_page = Pages::Main;
+                        return;
+                    }
+                    KeyCode::Enter => {
+                        if self.focused == FocusedElement::Create {
+                            self.create_group(app).await;
+                        } else {
+                            self.next_focus();
+                        }
+                        return;
+                    }
+                    _ => (),
+                }
+            }
+            match self.focused {
+                FocusedElement::Name => self.name_text_box.handle_event(event),
+                FocusedElement::Members => self.members_text_box.handle_event(event),
+                FocusedElement::Create => (),
+            }
+        }
+    }
+}
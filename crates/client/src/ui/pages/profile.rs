@@ -0,0 +1,89 @@
+use ratatui::layout::{self, Constraint, Rect};
+use state::ProfileState;
+
+mod handler;
+mod state;
+mod widget;
+
+#[derive(Default)]
+pub struct Layout {
+    header: Rect,
+    notification: Rect,
+    card: Rect,
+    sessions: Rect,
+    name: Rect,
+    save_name: Rect,
+    current_password: Rect,
+    new_password: Rect,
+    re_password: Rect,
+    change_password: Rect,
+    delete_account: Rect,
+    footer: Rect,
+}
+
+impl Layout {
+    fn new(area: Rect, n_sessions: usize) -> Self {
+        let [_, header, _, notification, body, footer, _] = layout::Layout::vertical([
+            Constraint::Length(2),
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(3),
+            Constraint::Min(23),
+            Constraint::Length(1),
+            Constraint::Length(2),
+        ])
+        .areas(area);
+        let [_, info, _, form, _] = layout::Layout::horizontal([
+            Constraint::Fill(1),
+            Constraint::Percentage(40),
+            Constraint::Length(4),
+            Constraint::Percentage(30),
+            Constraint::Fill(1),
+        ])
+        .areas(body);
+        let [card, sessions] = layout::Layout::vertical([
+            Constraint::Percentage(100),
+            Constraint::Length(n_sessions as u16 + 2),
+        ])
+        .areas(info);
+        let [name, save_name, current_password, new_password, re_password, change_password, delete_account] =
+            layout::Layout::vertical([
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+                Constraint::Length(3),
+            ])
+            .margin(1)
+            .areas(form);
+
+        Self {
+            header,
+            notification,
+            card,
+            sessions,
+            name,
+            save_name,
+            current_password,
+            new_password,
+            re_password,
+            change_password,
+            delete_account,
+            footer,
+        }
+    }
+}
+
+pub struct ProfilePage {
+    state: ProfileState,
+}
+
+impl ProfilePage {
+    pub fn new(name: &str) -> Self {
+        Self {
+            state: ProfileState::new(name),
+        }
+    }
+}
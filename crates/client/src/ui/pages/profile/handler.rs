@@ -0,0 +1,193 @@
+use crate::{
+    app::{App, AppEvent, Pages},
+    ui::event_handler::{AsyncStatefulEventHandler, EventHandler},
+};
+use crossterm::event::{Event, KeyCode};
+use protocol::{
+    stoc::ApiError,
+    validation::{validate_password, DisplayName, FieldResult},
+};
+use reqwest::StatusCode;
+
+use super::{
+    state::{FocusedElement, ProfileResult},
+    ProfilePage,
+};
+
+impl AsyncStatefulEventHandler<AppEvent> for ProfilePage {
+    type State = App;
+
+    async fn handle_event(&mut self, event: AppEvent, app: &mut Self::State) {
+        if let AppEvent::Input(event) = event {
+            if let Event::Key(event) = event {
+                match event.code {
+                    KeyCode::Down | KeyCode::Tab => {
+                        self.state.next_focus();
+                        return;
+                    }
+                    KeyCode::Up | KeyCode::BackTab => {
+                        self.state.prev_focus();
+                        return;
+                    }
+                    KeyCode::Esc => {
+                        app.current_page = Pages::Main;
+                        return;
+                    }
+                    KeyCode::F(2) => {
+                        let hidden = !app.presence_hidden;
+                        if let Err(e) = app.set_presence_hidden(hidden).await {
+                            log::error!("Failed to update presence settings: {e}");
+                            self.state.profile_result = ProfileResult::Error(error_text(&e));
+                        }
+                        return;
+                    }
+                    KeyCode::F(3) => {
+                        match app.revoke_other_sessions().await {
+                            Ok(_) => {
+                                self.state.profile_result =
+                                    ProfileResult::Success("Other sessions revoked!".to_string())
+                            }
+                            Err(e) => {
+                                log::error!("Failed to revoke sessions: {e}");
+                                self.state.profile_result = ProfileResult::Error(error_text(&e));
+                            }
+                        }
+                        return;
+                    }
+                    KeyCode::F(4) => {
+                        match app.rotate_key().await {
+                            Ok(_) => {
+                                self.state.profile_result =
+                                    ProfileResult::Success("Key rotated!".to_string())
+                            }
+                            Err(e) => {
+                                log::error!("Failed to rotate key: {e}");
+                                self.state.profile_result = ProfileResult::Error(format!(
+                                    "Failed to rotate key: {}",
+                                    error_text(&e)
+                                ));
+                            }
+                        }
+                        return;
+                    }
+                    KeyCode::Enter => match self.state.focused {
+                        FocusedElement::SaveName => {
+                            self.save_name(app).await;
+                            return;
+                        }
+                        FocusedElement::ChangePassword => {
+                            self.change_password(app).await;
+                            return;
+                        }
+                        FocusedElement::DeleteAccount => {
+                            self.delete_account(app).await;
+                            return;
+                        }
+                        _ => (),
+                    },
+                    _ => (),
+                }
+            }
+            match self.state.focused {
+                FocusedElement::Name => self.state.name_text_box.handle_event(event),
+                FocusedElement::CurrentPassword => {
+                    self.state.current_password_text_box.handle_event(event)
+                }
+                FocusedElement::NewPassword => self.state.new_password_text_box.handle_event(event),
+                FocusedElement::ConfirmPassword => {
+                    self.state.re_password_text_box.handle_event(event)
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+impl ProfilePage {
+    async fn save_name(&mut self, app: &mut App) {
+        let name = self.state.name_text_box.text.clone();
+        if let Err(e) = DisplayName::parse(&name).field("Name") {
+            self.state.profile_result = ProfileResult::Error(format!("{} {}", e.field, e.reason));
+            return;
+        }
+        match app.update_name(&name).await {
+            Ok(_) => {
+                self.state.profile_result = ProfileResult::Success("Name updated!".to_string())
+            }
+            Err(e) => {
+                log::error!("Failed to update name: {e}");
+                self.state.profile_result = ProfileResult::Error(error_text(&e));
+            }
+        }
+    }
+
+    async fn change_password(&mut self, app: &mut App) {
+        let current_password = self.state.current_password_text_box.text.clone();
+        let new_password = self.state.new_password_text_box.text.clone();
+        let re_password = &self.state.re_password_text_box.text;
+        if current_password.is_empty() {
+            self.state.profile_result =
+                ProfileResult::Error("Current password cannot be empty".to_string());
+            return;
+        }
+        if let Err(e) = validate_password(&new_password).field("New password") {
+            self.state.profile_result = ProfileResult::Error(format!("{} {}", e.field, e.reason));
+            return;
+        }
+        if new_password != *re_password {
+            self.state.profile_result = ProfileResult::Error("Passwords do not match".to_string());
+            return;
+        }
+        match app.change_password(&current_password, &new_password).await {
+            Ok(_) => {
+                self.state.clear_passwords();
+                self.state.profile_result = ProfileResult::Success(
+                    "Password changed, other sessions were signed out!".to_string(),
+                );
+            }
+            Err(e) => {
+                log::error!("Failed to change password: {e}");
+                self.state.profile_result = ProfileResult::Error(error_text(&e));
+            }
+        }
+    }
+}
+
+impl ProfilePage {
+    async fn delete_account(&mut self, app: &mut App) {
+        let password = self.state.current_password_text_box.text.clone();
+        if password.is_empty() {
+            self.state.profile_result = ProfileResult::Error(
+                "Enter your current password to delete your account".to_string(),
+            );
+            return;
+        }
+        if !self.state.confirm_delete {
+            self.state.confirm_delete = true;
+            self.state.profile_result = ProfileResult::Error(
+                "This cannot be undone! Press ENTER again to delete your account".to_string(),
+            );
+            return;
+        }
+        self.state.confirm_delete = false;
+        match app.delete_account(&password).await {
+            Ok(_) => app.should_quit = true,
+            Err(e) => {
+                log::error!("Failed to delete account: {e}");
+                self.state.profile_result = ProfileResult::Error(error_text(&e));
+            }
+        }
+    }
+}
+
+fn error_text(e: &anyhow::Error) -> String {
+    if let Some(error) = e.downcast_ref::<ApiError>() {
+        return format!("{}!", error);
+    }
+    if let Some(status) = e.downcast_ref::<reqwest::Error>().and_then(|e| e.status()) {
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            return "Too many attempts, try again later!".to_string();
+        }
+    }
+    format!("ERROR: {}", e)
+}
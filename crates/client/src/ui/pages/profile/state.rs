@@ -0,0 +1,84 @@
+use crate::ui::widgets::text_box::{CursorMovement, TextBoxState};
+
+use super::Layout;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FocusedElement {
+    Name,
+    SaveName,
+    CurrentPassword,
+    NewPassword,
+    ConfirmPassword,
+    ChangePassword,
+    DeleteAccount,
+}
+
+#[derive(Clone)]
+pub enum ProfileResult {
+    None,
+    Success(String),
+    Error(String),
+}
+
+pub struct ProfileState {
+    pub name_text_box: TextBoxState,
+    pub current_password_text_box: TextBoxState,
+    pub new_password_text_box: TextBoxState,
+    pub re_password_text_box: TextBoxState,
+    pub layout: Layout,
+    pub focused: FocusedElement,
+    pub profile_result: ProfileResult,
+    pub confirm_delete: bool,
+}
+
+impl ProfileState {
+    pub fn new(name: &str) -> Self {
+        let mut name_text_box = TextBoxState {
+            text: name.to_string(),
+            ..Default::default()
+        };
+        name_text_box.move_cursor(CursorMovement::End);
+        Self {
+            name_text_box,
+            current_password_text_box: TextBoxState::default(),
+            new_password_text_box: TextBoxState::default(),
+            re_password_text_box: TextBoxState::default(),
+            layout: Layout::default(),
+            focused: FocusedElement::Name,
+            profile_result: ProfileResult::None,
+            confirm_delete: false,
+        }
+    }
+
+    pub fn next_focus(&mut self) {
+        self.focused = match self.focused {
+            FocusedElement::Name => FocusedElement::SaveName,
+            FocusedElement::SaveName => FocusedElement::CurrentPassword,
+            FocusedElement::CurrentPassword => FocusedElement::NewPassword,
+            FocusedElement::NewPassword => FocusedElement::ConfirmPassword,
+            FocusedElement::ConfirmPassword => FocusedElement::ChangePassword,
+            FocusedElement::ChangePassword => FocusedElement::DeleteAccount,
+            FocusedElement::DeleteAccount => FocusedElement::DeleteAccount,
+        };
+        self.confirm_delete = false;
+    }
+
+    pub fn prev_focus(&mut self) {
+        self.focused = match self.focused {
+            FocusedElement::Name => FocusedElement::Name,
+            FocusedElement::SaveName => FocusedElement::Name,
+            FocusedElement::CurrentPassword => FocusedElement::SaveName,
+            FocusedElement::NewPassword => FocusedElement::CurrentPassword,
+            FocusedElement::ConfirmPassword => FocusedElement::NewPassword,
+            FocusedElement::ChangePassword => FocusedElement::ConfirmPassword,
+            FocusedElement::DeleteAccount => FocusedElement::ChangePassword,
+        };
+        self.confirm_delete = false;
+    }
+
+    pub fn clear_passwords(&mut self) {
+        self.current_password_text_box.clear();
+        self.new_password_text_box.clear();
+        self.re_password_text_box.clear();
+    }
+}
@@ -0,0 +1,195 @@
+use base64::Engine as _;
+use chrono::{DateTime, Local};
+use protocol::stoc::Session;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    text::Line,
+    widgets::{Block, BorderType, Paragraph, StatefulWidget, Widget},
+};
+
+use crate::{
+    app::App,
+    ui::widgets::{
+        button::{Button, ButtonState},
+        contact_info::ContactInfo,
+        cursor::Cursor,
+        text_box::TextBox,
+    },
+};
+
+use super::{
+    state::{FocusedElement, ProfileResult, ProfileState},
+    Layout, ProfilePage,
+};
+
+const FOOTER: &str = "Use TAB to switch between fields, ENTER to confirm, F2 to toggle presence visibility, F3 to revoke other sessions, F4 to rotate your key, ESC to return";
+
+impl StatefulWidget for &mut ProfilePage {
+    type State = App;
+
+    fn render(self, area: Rect, buf: &mut Buffer, app: &mut Self::State) {
+        self.state.layout = Layout::new(area, app.sessions.len());
+        let header = Line::from("Your profile")
+            .centered()
+            .style(app.theme.accent_style());
+        let footer = Paragraph::new(FOOTER)
+            .centered()
+            .style(app.theme.subtext_stye());
+        header.render(self.state.layout.header, buf);
+        footer.render(self.state.layout.footer, buf);
+        if let Some(notification) = create_notification(&self.state.profile_result, app) {
+            notification.render(self.state.layout.notification, buf);
+        }
+        if let Some(user) = &app.current_user {
+            let pub_key_bytes = cryptolib::utils::public_key_to_bytes(user.public_key.clone())
+                .expect("Failed to transform key to bytes");
+            let pub_key = base64::engine::general_purpose::STANDARD.encode(&pub_key_bytes);
+            let presence = match app.presence_hidden {
+                true => "presence hidden",
+                false => "presence visible",
+            };
+            let card = ContactInfo::new(&user.name, &user.id, &pub_key)
+                .status(presence)
+                .picture_style(app.theme.accent_style())
+                .name_style(app.theme.warn_style())
+                .id_style(app.theme.subtext_stye())
+                .public_key_style(app.theme.text_style());
+            card.render(self.state.layout.card, buf);
+        }
+        let mut lines = vec![Line::from("Active sessions")
+            .centered()
+            .style(app.theme.text_style())];
+        lines.extend(app.sessions.iter().map(|session| {
+            let style = match session.current {
+                true => app.theme.accent_style(),
+                false => app.theme.subtext_stye(),
+            };
+            Line::from(session_text(session)).centered().style(style)
+        }));
+        Paragraph::new(lines).render(self.state.layout.sessions, buf);
+
+        let (name, save_name, current_password, new_password, re_password, change_password) =
+            create_field_widgets(&self.state, app);
+        let mut delete_account =
+            Button::new("Delete Account").colors(app.theme.negative_button_colors());
+        if self.state.focused == FocusedElement::DeleteAccount {
+            delete_account = delete_account.state(ButtonState::Selected);
+        }
+        delete_account.render(self.state.layout.delete_account, buf);
+        save_name.render(self.state.layout.save_name, buf);
+        change_password.render(self.state.layout.change_password, buf);
+        name.render(self.state.layout.name, buf, &mut self.state.name_text_box);
+        current_password.render(
+            self.state.layout.current_password,
+            buf,
+            &mut self.state.current_password_text_box,
+        );
+        new_password.render(
+            self.state.layout.new_password,
+            buf,
+            &mut self.state.new_password_text_box,
+        );
+        re_password.render(
+            self.state.layout.re_password,
+            buf,
+            &mut self.state.re_password_text_box,
+        );
+    }
+}
+
+fn create_notification<'a>(result: &ProfileResult, app: &App) -> Option<Paragraph<'a>> {
+    let (text, style) = match result {
+        ProfileResult::None => return None,
+        ProfileResult::Success(msg) => (msg, app.theme.success_style()),
+        ProfileResult::Error(msg) => (msg, app.theme.error_style()),
+    };
+    Some(Paragraph::new(text.clone()).style(style).centered())
+}
+
+fn session_text(session: &Session) -> String {
+    let last_used = match DateTime::from_timestamp(session.last_used, 0) {
+        Some(datetime) => datetime
+            .with_timezone(&Local)
+            .format("%d/%m/%Y %H:%M")
+            .to_string(),
+        None => "unknown".to_string(),
+    };
+    match session.current {
+        true => format!("{} (this device) - last used {}", session.device, last_used),
+        false => format!("{} - last used {}", session.device, last_used),
+    }
+}
+
+fn create_field_widgets<'a>(
+    state: &ProfileState,
+    app: &App,
+) -> (
+    TextBox<'a>,
+    Button<'a>,
+    TextBox<'a>,
+    TextBox<'a>,
+    TextBox<'a>,
+    Button<'a>,
+) {
+    let base_block = Block::bordered()
+        .border_type(BorderType::Rounded)
+        .style(app.theme.text_style());
+    let name_block = base_block.clone().title("Name");
+    let current_password_block = base_block.clone().title("Current Password");
+    let new_password_block = base_block.clone().title("New Password");
+    let re_password_block = base_block.title("Confirm New Password");
+    let mut name = TextBox::new()
+        .block(name_block.clone())
+        .style(app.theme.text_style());
+    let mut current_password = TextBox::new()
+        .censored()
+        .block(current_password_block.clone())
+        .style(app.theme.text_style());
+    let mut new_password = TextBox::new()
+        .censored()
+        .block(new_password_block.clone())
+        .style(app.theme.text_style());
+    let mut re_password = TextBox::new()
+        .censored()
+        .block(re_password_block.clone())
+        .style(app.theme.text_style());
+    let mut save_name = Button::new("Save Name").colors(app.theme.button_colors());
+    let mut change_password = Button::new("Change Password").colors(app.theme.button_colors());
+    let cursor = Cursor::default().style(app.theme.text_style());
+    match state.focused {
+        FocusedElement::Name => {
+            name = name
+                .cursor(cursor)
+                .block(name_block.style(app.theme.accent_style()))
+        }
+        FocusedElement::SaveName => save_name = save_name.state(ButtonState::Selected),
+        FocusedElement::CurrentPassword => {
+            current_password = current_password
+                .cursor(cursor)
+                .block(current_password_block.style(app.theme.accent_style()))
+        }
+        FocusedElement::NewPassword => {
+            new_password = new_password
+                .cursor(cursor)
+                .block(new_password_block.style(app.theme.accent_style()))
+        }
+        FocusedElement::ConfirmPassword => {
+            re_password = re_password
+                .cursor(cursor)
+                .block(re_password_block.style(app.theme.accent_style()))
+        }
+        FocusedElement::ChangePassword => {
+            change_password = change_password.state(ButtonState::Selected)
+        }
+        FocusedElement::DeleteAccount => (),
+    }
+    (
+        name,
+        save_name,
+        current_password,
+        new_password,
+        re_password,
+        change_password,
+    )
+}
@@ -3,6 +3,10 @@ use crate::{
     ui::event_handler::{AsyncStatefulEventHandler, EventHandler},
 };
 use crossterm::event::{Event, KeyCode};
+use protocol::{
+    stoc::ApiError,
+    validation::{validate_password, DisplayName, FieldResult, IstId},
+};
 use reqwest::StatusCode;
 
 use super::{
@@ -35,21 +39,27 @@ impl AsyncStatefulEventHandler<AppEvent> for RegisterPage {
                             let name = &self.state.name_text_box.text;
                             let password = &self.state.password_text_box.text;
                             let re_password = &self.state.re_password_text_box.text;
-                            if let Some((_, e)) = [id, name, password, re_password]
-                                .iter()
-                                .zip([
-                                    "IST ID cannot be empty",
-                                    "Name cannot be empty",
-                                    "Password cannot be empty",
-                                    "Password confirmation cannot be empty",
-                                ])
-                                .filter(|(f, _)| f.trim().is_empty())
-                                .next()
-                            {
-                                self.state.register_result = RegisterResult::Error(e.to_string());
+                            if re_password.is_empty() {
+                                self.state.register_result = RegisterResult::Error(
+                                    "Password confirmation cannot be empty".to_string(),
+                                );
+                                return;
+                            }
+                            let validation = IstId::parse(id)
+                                .field("IST ID")
+                                .and_then(|_| DisplayName::parse(name).field("Name"))
+                                .and_then(|_| validate_password(password).field("Password"));
+                            if let Err(e) = validation {
+                                self.state.register_result =
+                                    RegisterResult::Error(format!("{} {}", e.field, e.reason));
                                 return;
                             }
-                            let (_, puk) = match app.gen_key_pair(id).await {
+                            if password != re_password {
+                                self.state.register_result =
+                                    RegisterResult::Error("Passwords do not match".to_string());
+                                return;
+                            }
+                            let (prk, puk) = match app.gen_key_pair(id).await {
                                 Ok(v) => v,
                                 Err(e) => {
                                     self.state.register_result =
@@ -65,7 +75,7 @@ impl AsyncStatefulEventHandler<AppEvent> for RegisterPage {
                             };
                             match app
                                 .net_client
-                                .register(id, name, password, &key_bytes)
+                                .register(id, name, password, &key_bytes, &prk)
                                 .await
                             {
                                 Ok(_) => {
@@ -73,11 +83,19 @@ impl AsyncStatefulEventHandler<AppEvent> for RegisterPage {
                                     app.current_page = Pages::Login
                                 }
                                 Err(e) => {
-                                    if let Some(status) = e.status() {
-                                        if status == StatusCode::FORBIDDEN {
+                                    if let Some(error) = e.downcast_ref::<ApiError>() {
+                                        self.state.register_result =
+                                            RegisterResult::Error(format!("{}!", error));
+                                        return;
+                                    }
+                                    if let Some(status) =
+                                        e.downcast_ref::<reqwest::Error>().and_then(|e| e.status())
+                                    {
+                                        if status == StatusCode::TOO_MANY_REQUESTS {
                                             self.state.register_result = RegisterResult::Error(
-                                                "A user with that ID already exists!".to_string(),
+                                                "Too many attempts, try again later!".to_string(),
                                             );
+                                            return;
                                         }
                                     }
                                     self.state.register_result =
@@ -6,7 +6,7 @@ use super::{
     event_handler::AsyncStatefulEventHandler,
     pages::{
         add_contact::AddContactPage, connect::ConnectPage, entry::EntryPage, login::LoginPage,
-        main::MainPage, register::RegisterPage,
+        main::MainPage, new_group::NewGroupPage, profile::ProfilePage, register::RegisterPage,
     },
 };
 
@@ -17,6 +17,8 @@ pub struct Router {
     register_page: RegisterPage,
     main_page: MainPage,
     add_contact_page: AddContactPage,
+    profile_page: ProfilePage,
+    new_group_page: NewGroupPage,
     current_page: Pages,
 }
 
@@ -27,8 +29,10 @@ impl Router {
             entry_page: EntryPage::new(),
             login_page: LoginPage::new(),
             register_page: RegisterPage::new(),
-            main_page: MainPage::new(vec![], vec![]),
+            main_page: MainPage::new(vec![], vec![], 0),
             add_contact_page: AddContactPage::new(Color::default()),
+            profile_page: ProfilePage::new(""),
+            new_group_page: NewGroupPage::new(),
             current_page: Pages::Connect,
         }
     }
@@ -44,12 +48,17 @@ impl Router {
                 Pages::Main => {
                     let mut contacts = app.contacts.clone();
                     contacts.sort_by(|c1, c2| c1.name.chars().cmp(c2.name.chars()));
-                    let chat_ids = app.messages.keys().cloned().collect();
-                    self.main_page = MainPage::new(contacts, chat_ids);
+                    let chat_ids = app.chat_ids().cloned().collect();
+                    self.main_page = MainPage::new(contacts, chat_ids, app.requests.len());
                 }
                 Pages::AddContact => {
                     self.add_contact_page = AddContactPage::new(app.theme.background)
                 }
+                Pages::Profile => {
+                    let name = app.current_user.as_ref().map(|u| u.name.as_str());
+                    self.profile_page = ProfilePage::new(name.unwrap_or_default());
+                }
+                Pages::NewGroup => self.new_group_page = NewGroupPage::new(),
             }
         }
     }
@@ -62,6 +71,8 @@ impl Router {
             Pages::Register => self.register_page.render(area, buf, app),
             Pages::Main => self.main_page.render(area, buf, app),
             Pages::AddContact => self.add_contact_page.render(area, buf, app),
+            Pages::Profile => self.profile_page.render(area, buf, app),
+            Pages::NewGroup => self.new_group_page.render(area, buf, app),
         }
     }
 }
@@ -77,6 +88,8 @@ impl AsyncStatefulEventHandler<AppEvent> for Router {
             Pages::Register => self.register_page.handle_event(event, app).await,
             Pages::Main => self.main_page.handle_event(event, app).await,
             Pages::AddContact => self.add_contact_page.handle_event(event, app).await,
+            Pages::Profile => self.profile_page.handle_event(event, app).await,
+            Pages::NewGroup => self.new_group_page.handle_event(event, app).await,
         }
     }
 }
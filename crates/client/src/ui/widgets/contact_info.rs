@@ -23,6 +23,7 @@ pub struct ContactInfo<'a> {
     name: String,
     id: String,
     public_key: String,
+    status: Option<String>,
     picture_style: Style,
     name_style: Style,
     id_style: Style,
@@ -36,6 +37,7 @@ impl<'a> ContactInfo<'a> {
             name: name.to_string(),
             id: id.to_string(),
             public_key: public_key.to_string(),
+            status: None,
             picture_style: Style::default(),
             name_style: Style::default(),
             id_style: Style::default(),
@@ -44,6 +46,11 @@ impl<'a> ContactInfo<'a> {
         }
     }
 
+    pub fn status<T: Into<String>>(mut self, status: T) -> Self {
+        self.status = Some(status.into());
+        self
+    }
+
     pub fn picture_style(mut self, style: Style) -> Self {
         self.picture_style = style;
         self
@@ -70,8 +77,14 @@ impl<'a> Widget for ContactInfo<'a> {
     where
         Self: Sized,
     {
-        let fields = [self.name, format!("@{}", self.id), self.public_key];
-        let styles = [self.name_style, self.id_style, self.public_key_style];
+        let mut fields = vec![self.name, format!("@{}", self.id)];
+        let mut styles = vec![self.name_style, self.id_style];
+        if let Some(status) = self.status {
+            fields.push(status);
+            styles.push(self.id_style);
+        }
+        fields.push(self.public_key);
+        styles.push(self.public_key_style);
         let mut lines = PICTURE
             .split('\n')
             .map(|x| {
@@ -1,7 +1,11 @@
+use std::collections::HashMap;
+
+use protocol::stoc::Presence;
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Layout, Rect},
     style::Style,
+    text::{Line, Span},
     widgets::{
         Block, List, ListItem, ListState, Scrollbar, ScrollbarState, StatefulWidget, Widget,
     },
@@ -9,6 +13,8 @@ use ratatui::{
 
 use crate::db::structs::Contact;
 
+use super::presence::presence_dot;
+
 #[derive(Default)]
 pub struct ContactListState {
     scroll_state: ScrollbarState,
@@ -49,6 +55,7 @@ pub struct ContactList<'a> {
     block: Option<Block<'a>>,
     inner_block: Option<Block<'a>>,
     scrollbar: bool,
+    presence: Option<&'a HashMap<String, Presence>>,
 }
 
 impl<'a> ContactList<'a> {
@@ -62,9 +69,15 @@ impl<'a> ContactList<'a> {
             block: None,
             inner_block: None,
             scrollbar: false,
+            presence: None,
         }
     }
 
+    pub fn presence(mut self, presence: &'a HashMap<String, Presence>) -> Self {
+        self.presence = Some(presence);
+        self
+    }
+
     pub fn selected_style(mut self, style: Style) -> Self {
         self.selected_style = style;
         self
@@ -104,7 +117,13 @@ impl<'a> StatefulWidget for ContactList<'a> {
             } else {
                 (c.name.to_string(), self.item_style)
             };
-            ListItem::new(text).style(style)
+            let presence = self.presence.and_then(|p| p.get(&c.id));
+            let line = Line::from(vec![
+                presence_dot(presence),
+                Span::raw(" "),
+                Span::raw(text),
+            ]);
+            ListItem::new(line).style(style)
         });
         let area = if let Some(block) = self.block {
             let area = block.inner(area);
@@ -1,15 +1,20 @@
+use protocol::stoc::Presence;
 use ratatui::{
     layout::{Constraint, Layout},
-    style::Style,
-    text::Line,
+    style::{Color, Style},
+    text::{Line, Span},
     widgets::{Block, Widget},
 };
 
+use super::presence::{last_seen_text, presence_dot};
+
 pub struct DMCard<'a> {
     contact_name: String,
     name_style: Style,
     message: Option<String>,
     message_style: Style,
+    presence: Option<Presence>,
+    last_seen_style: Style,
     block: Option<Block<'a>>,
 }
 
@@ -20,6 +25,8 @@ impl<'a> DMCard<'a> {
             name_style: Style::default(),
             message: None,
             message_style: Style::default(),
+            presence: None,
+            last_seen_style: Style::default().fg(Color::Gray),
             block: None,
         }
     }
@@ -29,6 +36,11 @@ impl<'a> DMCard<'a> {
         self
     }
 
+    pub fn presence(mut self, presence: Option<&Presence>) -> Self {
+        self.presence = presence.cloned();
+        self
+    }
+
     pub fn block(mut self, block: Block<'a>) -> Self {
         self.block = Some(block);
         self
@@ -47,9 +59,17 @@ impl<'a> Widget for DMCard<'a> {
         } else {
             area
         };
-        let [name_area, msg_area] =
-            Layout::vertical([Constraint::Length(1), Constraint::Length(1)]).areas(area);
-        let name = Line::from(self.contact_name).style(self.name_style);
+        let [name_area, msg_area, last_seen_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(area);
+        let name = Line::from(vec![
+            presence_dot(self.presence.as_ref()),
+            Span::raw(" "),
+            Span::styled(self.contact_name, self.name_style),
+        ]);
         name.render(name_area, buf);
         let message = match &self.message {
             Some(v) => v,
@@ -57,5 +77,8 @@ impl<'a> Widget for DMCard<'a> {
         };
         let message = Line::from(message).style(self.message_style);
         message.render(msg_area, buf);
+        let last_seen =
+            Line::from(last_seen_text(self.presence.as_ref())).style(self.last_seen_style);
+        last_seen.render(last_seen_area, buf);
     }
 }
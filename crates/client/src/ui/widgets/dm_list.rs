@@ -1,6 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crossterm::event::{Event, KeyCode};
+use protocol::stoc::{Group, Presence};
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Layout, Rect},
@@ -11,7 +12,7 @@ use tui_widget_list::{ListBuilder, ListState, ListView};
 
 use crate::{db::structs::StoredMessage, ui::event_handler::EventHandler};
 
-use super::dm_card::DMCard;
+use super::{dm_card::DMCard, opened_chat::DELETED_MESSAGE};
 
 pub struct DMListState {
     scrollbar_state: ScrollbarState,
@@ -50,6 +51,9 @@ pub struct DMList<'a> {
     inner_block: Option<Block<'a>>,
     scrollbar: bool,
     chats: &'a HashMap<String, Vec<StoredMessage>>,
+    presence: Option<&'a HashMap<String, Presence>>,
+    hidden: Option<&'a HashSet<String>>,
+    groups: Option<&'a HashMap<String, Group>>,
 }
 
 impl<'a> DMList<'a> {
@@ -60,9 +64,27 @@ impl<'a> DMList<'a> {
             inner_block: None,
             scrollbar: false,
             chats,
+            presence: None,
+            hidden: None,
+            groups: None,
         }
     }
 
+    pub fn presence(mut self, presence: &'a HashMap<String, Presence>) -> Self {
+        self.presence = Some(presence);
+        self
+    }
+
+    pub fn hidden(mut self, hidden: &'a HashSet<String>) -> Self {
+        self.hidden = Some(hidden);
+        self
+    }
+
+    pub fn groups(mut self, groups: &'a HashMap<String, Group>) -> Self {
+        self.groups = Some(groups);
+        self
+    }
+
     pub fn inner_block(mut self, block: Block<'a>) -> Self {
         self.inner_block = Some(block);
         self
@@ -97,25 +119,44 @@ impl<'a> StatefulWidget for DMList<'a> {
         } else {
             area
         };
-        let contacts = self.chats.clone();
+        let hidden = self.hidden.cloned().unwrap_or_default();
+        let contacts: Vec<(String, Vec<StoredMessage>)> = self
+            .chats
+            .iter()
+            .filter(|(id, _)| !hidden.contains(*id))
+            .map(|(id, messages)| (id.clone(), messages.clone()))
+            .collect();
+        let n_chats = contacts.len();
+        let presence = self.presence.cloned().unwrap_or_default();
+        let names: HashMap<String, String> = self
+            .groups
+            .map(|groups| {
+                groups
+                    .iter()
+                    .map(|(id, group)| (id.clone(), format!("# {}", group.name)))
+                    .collect()
+            })
+            .unwrap_or_default();
         let selected = state.list_state.selected;
         let builder = ListBuilder::new(move |context| {
-            let (contact, messages) = contacts
-                .iter()
-                .nth(context.index)
-                .expect("Invalid contact index!");
+            let (contact, messages) = contacts.get(context.index).expect("Invalid contact index!");
             let last_message = messages.last();
+            let name = names.get(contact).unwrap_or(contact);
             let mut card = match last_message {
-                Some(last_message) => DMCard::new(contact).message(last_message.content.clone()),
-                None => DMCard::new(contact),
+                Some(last_message) if last_message.deleted => {
+                    DMCard::new(name).message(DELETED_MESSAGE.to_string())
+                }
+                Some(last_message) => DMCard::new(name).message(last_message.content.clone()),
+                None => DMCard::new(name),
             };
+            card = card.presence(presence.get(contact));
             let is_selected = selected.is_some() && selected.unwrap() == context.index;
             if is_selected {
                 card = card.block(Block::new().style(Style::new().bg(Color::DarkGray)));
             }
-            (card, 3)
+            (card, 4)
         });
-        let mut view = ListView::new(builder, self.chats.len())
+        let mut view = ListView::new(builder, n_chats)
             .infinite_scrolling(false)
             .style(self.style);
         if let Some(block) = self.inner_block {
@@ -7,5 +7,6 @@ pub mod dm_list;
 pub mod logger;
 pub mod messagist_text;
 pub mod opened_chat;
+pub mod presence;
 pub mod text_box;
 pub mod title;
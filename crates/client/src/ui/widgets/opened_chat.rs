@@ -1,10 +1,12 @@
-use std::{collections::VecDeque, usize};
+use std::collections::{HashMap, VecDeque};
 
 use chrono::DateTime;
-use crossterm::event::{Event, KeyCode};
+use crossterm::event::{Event, KeyCode, KeyModifiers};
+use protocol::stoc::Group;
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
     widgets::{
         Block, BorderType, Borders, List, ListItem, ListState, Padding, Paragraph, Scrollbar,
         ScrollbarState, StatefulWidget, Widget,
@@ -12,7 +14,9 @@ use ratatui::{
 };
 
 use crate::{
-    db::structs::{Contact, StoredMessage},
+    db::structs::{Contact, MessageKind, StoredMessage, StoredReaction},
+    expiry::describe_timer,
+    message_data::MessageRef,
     ui::event_handler::EventHandler,
 };
 
@@ -21,11 +25,19 @@ use super::{
     text_box::{TextBox, TextBoxState},
 };
 
+pub const DELETED_MESSAGE: &str = "message deleted";
+const QUOTE_LENGTH: usize = 40;
+
 #[derive(Default)]
 pub struct OpenedChatState {
     scroll_offset: usize,
     input_state: TextBoxState,
     selected_element: SelectedElement,
+    // counted back from the newest message
+    selected_message: Option<usize>,
+    selected_ref: Option<MessageRef>,
+    replying_to: Option<MessageRef>,
+    at_oldest: bool,
 }
 
 #[derive(Debug, Default, PartialEq)]
@@ -41,11 +53,47 @@ impl OpenedChatState {
             scroll_offset: 0,
             input_state: TextBoxState::default(),
             selected_element: SelectedElement::None,
+            selected_message: None,
+            selected_ref: None,
+            replying_to: None,
+            at_oldest: false,
         }
     }
 
     pub fn clear_input(&mut self) {
         self.input_state.clear();
+        self.replying_to = None;
+    }
+
+    pub fn selected_message(&self) -> Option<MessageRef> {
+        self.selected_ref.clone()
+    }
+
+    pub fn replying_to(&self) -> Option<MessageRef> {
+        self.replying_to.clone()
+    }
+
+    // scrolled up to the oldest message held locally
+    pub fn at_oldest(&self) -> bool {
+        self.at_oldest
+    }
+
+    fn select_older(&mut self) {
+        self.selected_message = Some(self.selected_message.map_or(0, |i| i + 1));
+    }
+
+    fn select_newer(&mut self) {
+        self.selected_message = self.selected_message.and_then(|i| i.checked_sub(1));
+        if self.selected_message.is_none() {
+            self.selected_ref = None;
+        }
+    }
+
+    fn toggle_reply(&mut self) {
+        self.replying_to = match self.replying_to {
+            Some(_) => None,
+            None => self.selected_ref.clone(),
+        };
     }
 
     pub fn get_input_message(&self) -> Option<String> {
@@ -74,18 +122,112 @@ impl OpenedChatState {
 
 pub struct OpenedChat<'a> {
     self_id: &'a str,
-    contact: &'a Contact,
+    title: &'a str,
+    warning: Option<&'a str>,
+    timer: i64,
+    senders: Option<HashMap<&'a str, &'a str>>,
     messages: &'a [StoredMessage],
+    reactions: &'a [StoredReaction],
 }
 
 impl<'a> OpenedChat<'a> {
     pub fn new(contact: &'a Contact, messages: &'a [StoredMessage], self_id: &'a &str) -> Self {
+        let warning = match (contact.deleted, &contact.pending_key) {
+            (true, _) => Some("Account deleted"),
+            (false, Some(_)) => Some("Key changed! Press F2 to accept"),
+            (false, None) => None,
+        };
+        Self {
+            self_id,
+            title: &contact.name,
+            warning,
+            timer: 0,
+            senders: None,
+            messages,
+            reactions: &[],
+        }
+    }
+
+    pub fn group(group: &'a Group, messages: &'a [StoredMessage], self_id: &'a &str) -> Self {
+        let warning = match group.members.iter().any(|m| m.id == *self_id) {
+            true => None,
+            false => Some("You left this group"),
+        };
+        let senders = group
+            .members
+            .iter()
+            .map(|m| (m.id.as_str(), m.name.as_str()))
+            .collect();
         Self {
             self_id,
-            contact,
+            title: &group.name,
+            warning,
+            timer: 0,
+            senders: Some(senders),
             messages,
+            reactions: &[],
         }
     }
+
+    pub fn timer(mut self, timer: i64) -> Self {
+        self.timer = timer;
+        self
+    }
+
+    pub fn reactions(mut self, reactions: &'a [StoredReaction]) -> Self {
+        self.reactions = reactions;
+        self
+    }
+
+    fn sender_name(&self, sender: &'a str) -> &'a str {
+        if sender == self.self_id {
+            return "You";
+        }
+        match &self.senders {
+            Some(senders) => senders.get(sender).copied().unwrap_or(sender),
+            None => self.title,
+        }
+    }
+
+    fn find(&self, message_ref: &MessageRef) -> Option<&'a StoredMessage> {
+        self.messages.iter().find(|m| {
+            m.kind == MessageKind::Message
+                && m.sender_istid == message_ref.sender
+                && m.sent_counter == message_ref.counter
+        })
+    }
+
+    fn quote(&self, message_ref: &MessageRef) -> String {
+        let snippet = match self.find(message_ref) {
+            Some(message) => message_text(message).chars().take(QUOTE_LENGTH).collect(),
+            None => String::from("unknown message"),
+        };
+        format!("{}: {}", self.sender_name(&message_ref.sender), snippet)
+    }
+
+    // reactions under a message, counted per emoji in the order they first appear
+    fn reaction_summary(&self, message: &StoredMessage) -> Option<String> {
+        let mut counts: Vec<(&str, usize)> = vec![];
+        for reaction in self.reactions.iter().filter(|r| {
+            r.target_sender == message.sender_istid && r.target_counter == message.sent_counter
+        }) {
+            match counts
+                .iter_mut()
+                .find(|(emoji, _)| *emoji == reaction.emoji)
+            {
+                Some((_, count)) => *count += 1,
+                None => counts.push((&reaction.emoji, 1)),
+            }
+        }
+        if counts.is_empty() {
+            return None;
+        }
+        let summary: Vec<String> = counts
+            .iter()
+            .map(|(emoji, count)| format!("{} {}", emoji, count))
+            .collect();
+        Some(summary.join("  "))
+    }
 }
 
 impl<'a> StatefulWidget for OpenedChat<'a> {
@@ -105,19 +247,15 @@ impl<'a> StatefulWidget for OpenedChat<'a> {
         .areas(area);
 
         // contact_name | _ | more
-        let [chatname_area, _, more_area] = Layout::horizontal([
+        let [chatname_area, warning_area, more_area] = Layout::horizontal([
             Constraint::Length(20),
             Constraint::Fill(1),
             Constraint::Length(3),
         ])
         .areas(header);
 
-        // if the contact name is longer than 20 characters, truncate it
-        let contact_name = if self.contact.name.len() > 20 {
-            &self.contact.name[..20]
-        } else {
-            &self.contact.name
-        };
+        // if the chat name is longer than 20 characters, truncate it
+        let contact_name: String = self.title.chars().take(20).collect();
 
         let header_block = Block::default().borders(Borders::ALL);
         header_block.render(header, buf);
@@ -127,9 +265,26 @@ impl<'a> StatefulWidget for OpenedChat<'a> {
             Paragraph::new(contact_name).block(Block::default().padding(Padding::new(1, 0, 1, 1)));
         chat_name.render(chatname_area, buf);
 
-        // render the more button
-        let more = Paragraph::new("⋮").block(Block::default().padding(Padding::new(0, 1, 1, 1)));
-        more.render(more_area, buf);
+        // render the more button, or the chat warning
+        match self.warning {
+            Some(warning) => {
+                let warning = Paragraph::new(warning)
+                    .right_aligned()
+                    .block(Block::default().padding(Padding::new(0, 1, 1, 1)));
+                warning.render(warning_area, buf);
+            }
+            None => {
+                if self.timer > 0 {
+                    let timer = Paragraph::new(format!("⏱ {}", describe_timer(self.timer)))
+                        .right_aligned()
+                        .block(Block::default().padding(Padding::new(0, 1, 1, 1)));
+                    timer.render(warning_area, buf);
+                }
+                let more =
+                    Paragraph::new("⋮").block(Block::default().padding(Padding::new(0, 1, 1, 1)));
+                more.render(more_area, buf);
+            }
+        }
 
         // render the messages
         let [messages_list_area, scrollbar_area] =
@@ -140,27 +295,75 @@ impl<'a> StatefulWidget for OpenedChat<'a> {
         // select the messages to be displayed
         let sorted_messages = sort_messages(self.messages);
 
+        // the selection is clamped to the oldest message that can be replied or reacted to
+        let selectable: Vec<&StoredMessage> = sorted_messages
+            .iter()
+            .filter(|m| m.kind == MessageKind::Message && !m.deleted)
+            .collect();
+        state.selected_message = state
+            .selected_message
+            .map(|i| i.min(selectable.len().saturating_sub(1)));
+        let selected = state
+            .selected_message
+            .and_then(|i| selectable.iter().rev().nth(i))
+            .map(|m| m.id);
+        state.selected_ref = state
+            .selected_message
+            .and_then(|i| selectable.iter().rev().nth(i))
+            .map(|m| MessageRef::new(&m.sender_istid, m.sent_counter));
+
         let mut items = vec![];
         for message in sorted_messages.iter() {
+            if message.kind == MessageKind::System {
+                let line = format!("── {} ──", message.content);
+                let pad_len = max_width.saturating_sub(line.chars().count()) / 2;
+                items.push(ListItem::new(format!("{}{}", " ".repeat(pad_len), line)));
+                continue;
+            }
             let lines = convert_message_to_lines(message, (max_width / 2) - 3);
             let mut lines = wrap_message_with_round_border(lines);
             let datetime =
                 DateTime::parse_from_rfc3339(&message.timestamp).expect("Failed to convert date");
-            lines.insert(0, datetime.format("%d/%m/%Y %H:%M:%S").to_string());
+            let datetime = datetime.format("%d/%m/%Y %H:%M:%S").to_string();
+            let header = match &self.senders {
+                Some(senders) if message.sender_istid != self.self_id => {
+                    let sender = message.sender_istid.as_str();
+                    let name = senders.get(sender).copied().unwrap_or(sender);
+                    format!("{} · {}", name, datetime)
+                }
+                _ => datetime,
+            };
+            let header = match message.history.is_some() {
+                true => format!("{} (edited)", header),
+                false => header,
+            };
+            lines.insert(0, header);
+            if let Some(reply_to) = message.reference() {
+                let quote: String = self.quote(&reply_to).chars().take(max_width / 2).collect();
+                lines.insert(1, format!("↪ {}", quote));
+            }
+            if let Some(summary) = self.reaction_summary(message) {
+                lines.push(summary);
+            }
+            let style = match selected == Some(message.id) {
+                true => Style::default().add_modifier(Modifier::REVERSED),
+                false => Style::default(),
+            };
             for line in lines {
                 if message.sender_istid == self.self_id {
-                    let pad_len = max_width - line.chars().count();
+                    let pad_len = max_width.saturating_sub(line.chars().count());
                     let mut string = String::new();
                     for _ in 0..pad_len {
                         string.push(' ');
                     }
                     string.push_str(&line);
-                    items.push(ListItem::new(string));
+                    items.push(ListItem::new(string).style(style));
                 } else {
-                    items.push(ListItem::new(line));
+                    items.push(ListItem::new(line).style(style));
                 }
             }
         }
+        state.at_oldest = state.scroll_offset >= items.len();
         let scroll_count = items.len().saturating_sub(state.scroll_offset);
         let mut scroll_state = ScrollbarState::new(items.len()).position(scroll_count);
         let mut list_state = ListState::default();
@@ -169,7 +372,11 @@ impl<'a> StatefulWidget for OpenedChat<'a> {
         StatefulWidget::render(list, messages_list_area, buf, &mut list_state);
         let scrollbar = Scrollbar::default();
         scrollbar.render(scrollbar_area, buf, &mut scroll_state);
-        let mut text_box = TextBox::new().block(Block::bordered().border_type(BorderType::Rounded));
+        let mut input_block = Block::bordered().border_type(BorderType::Rounded);
+        if let Some(reply_to) = &state.replying_to {
+            input_block = input_block.title(format!("Replying to {}", self.quote(reply_to)));
+        }
+        let mut text_box = TextBox::new().block(input_block);
         if state.selected_element == SelectedElement::Input {
             text_box = text_box.cursor(Cursor::default());
         }
@@ -177,6 +384,14 @@ impl<'a> StatefulWidget for OpenedChat<'a> {
     }
 }
 
+fn message_text(message: &StoredMessage) -> String {
+    match message.attachment() {
+        _ if message.deleted => DELETED_MESSAGE.to_string(),
+        Some(attachment) => attachment.describe(),
+        None => message.content.clone(),
+    }
+}
+
 fn sort_messages(messages: &[StoredMessage]) -> VecDeque<StoredMessage> {
     let mut messages: Vec<StoredMessage> = messages.to_vec();
     messages.sort_by(|a, b| {
@@ -191,7 +406,8 @@ fn sort_messages(messages: &[StoredMessage]) -> VecDeque<StoredMessage> {
 fn convert_message_to_lines(message: &StoredMessage, max_width: usize) -> Vec<String> {
     let mut lines = Vec::new();
     let mut current_line = String::new();
-    for word in message.content.split_whitespace() {
+    let content = message_text(message);
+    for word in content.split_whitespace() {
         if current_line.len() + word.len() > max_width {
             lines.push(current_line.clone());
             current_line.clear();
@@ -227,6 +443,21 @@ fn wrap_message_with_round_border(lines: Vec<String>) -> Vec<String> {
 impl EventHandler<Event> for OpenedChatState {
     fn handle_event(&mut self, event: Event) {
         match event {
+            Event::Key(event)
+                if event.modifiers.contains(KeyModifiers::SHIFT)
+                    && matches!(event.code, KeyCode::Up | KeyCode::Down) =>
+            {
+                match event.code {
+                    KeyCode::Up => self.select_older(),
+                    _ => self.select_newer(),
+                }
+            }
+            Event::Key(event)
+                if event.modifiers.contains(KeyModifiers::CONTROL)
+                    && event.code == KeyCode::Char('r') =>
+            {
+                self.toggle_reply()
+            }
             Event::Key(event) => match event.code {
                 KeyCode::Up => {
                     self.next();
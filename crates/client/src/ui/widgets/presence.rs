@@ -0,0 +1,35 @@
+use chrono::{DateTime, Local};
+use protocol::stoc::Presence;
+use ratatui::{
+    style::{Color, Style},
+    text::Span,
+};
+
+const DOT: &str = "●";
+
+pub fn presence_dot<'a>(presence: Option<&Presence>) -> Span<'a> {
+    let color = match presence {
+        Some(presence) if presence.online => Color::Green,
+        _ => Color::DarkGray,
+    };
+    Span::styled(DOT, Style::new().fg(color))
+}
+
+pub fn last_seen_text(presence: Option<&Presence>) -> String {
+    let Some(presence) = presence else {
+        return "last seen unavailable".to_string();
+    };
+    if presence.online {
+        return "online".to_string();
+    }
+    match presence
+        .last_seen
+        .and_then(|ts| DateTime::from_timestamp(ts, 0))
+    {
+        Some(datetime) => format!(
+            "last seen {}",
+            datetime.with_timezone(&Local).format("%d/%m/%Y %H:%M")
+        ),
+        None => "last seen unavailable".to_string(),
+    }
+}
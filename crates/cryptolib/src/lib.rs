@@ -3,7 +3,9 @@ use chacha20poly1305::{
     aead::{Aead, AeadCore, KeyInit, OsRng},
     ChaCha20Poly1305,
 };
-use rsa::Pkcs1v15Encrypt;
+use rand::RngCore;
+use rsa::{Pkcs1v15Encrypt, Pkcs1v15Sign};
+use sha2::{Digest, Sha256};
 
 pub use rsa::{
     pkcs8::{DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, LineEnding},
@@ -58,6 +60,24 @@ pub fn generate_secret_key() -> Vec<u8> {
     key.to_vec()
 }
 
+pub fn generate_nonce() -> Vec<u8> {
+    let mut nonce = vec![0u8; 32];
+    OsRng.fill_bytes(&mut nonce);
+    nonce
+}
+
+pub fn generate_token() -> String {
+    hex::encode(generate_nonce())
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+pub fn hash_bytes(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
 pub fn encrypt_key_with_pub_key(key: &[u8], pub_key: &RsaPublicKey) -> Result<Vec<u8>, rsa::Error> {
     let encrypted = pub_key.encrypt(&mut OsRng::default(), Pkcs1v15Encrypt, key)?;
     Ok(encrypted)
@@ -70,3 +90,16 @@ pub fn decrypt_key_with_priv_key(
     let decrypted = priv_key.decrypt(Pkcs1v15Encrypt, key)?;
     Ok(decrypted)
 }
+
+pub fn sign(data: &[u8], priv_key: &RsaPrivateKey) -> Result<Vec<u8>, rsa::Error> {
+    let digest = Sha256::digest(data);
+    let signature = priv_key.sign(Pkcs1v15Sign::new::<Sha256>(), &digest)?;
+    Ok(signature)
+}
+
+pub fn verify_signature(data: &[u8], signature: &[u8], pub_key: &RsaPublicKey) -> bool {
+    let digest = Sha256::digest(data);
+    pub_key
+        .verify(Pkcs1v15Sign::new::<Sha256>(), &digest, signature)
+        .is_ok()
+}
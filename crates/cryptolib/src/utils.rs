@@ -2,6 +2,7 @@ use rsa::pkcs8::DecodePrivateKey;
 use rsa::pkcs8::DecodePublicKey;
 use rsa::pkcs8::EncodePrivateKey;
 use rsa::pkcs8::EncodePublicKey;
+use rsa::traits::PublicKeyParts;
 use rsa::RsaPrivateKey;
 use rsa::RsaPublicKey;
 
@@ -34,3 +35,7 @@ pub fn private_key_to_bytes(key: RsaPrivateKey) -> rsa::pkcs8::Result<Vec<u8>> {
 pub fn private_key_from_bytes(bytes: &[u8]) -> rsa::pkcs8::Result<RsaPrivateKey> {
     Ok(RsaPrivateKey::from_pkcs8_der(bytes)?)
 }
+
+pub fn public_key_bits(key: &RsaPublicKey) -> usize {
+    key.size() * 8
+}
@@ -1,20 +1,65 @@
 use serde::{Deserialize, Serialize};
 
+use crate::stoc::Revision;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Register {
     pub id: String,
     pub name: String,
     pub password: String,
     pub public_key: Vec<u8>,
+    pub proof: KeyProof,
 }
 
 impl Register {
-    pub fn new(id: &str, name: &str, password: &str, public_key: &[u8]) -> Self {
+    pub fn new(id: &str, name: &str, password: &str, public_key: &[u8], proof: KeyProof) -> Self {
         Self {
             id: id.to_string(),
             name: name.to_string(),
             password: password.to_string(),
             public_key: public_key.to_vec(),
+            proof,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct UpdateProfile {
+    pub name: String,
+}
+
+impl UpdateProfile {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChangePassword {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+impl ChangePassword {
+    pub fn new(current_password: &str, new_password: &str) -> Self {
+        Self {
+            current_password: current_password.to_string(),
+            new_password: new_password.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteAccount {
+    pub password: String,
+}
+
+impl DeleteAccount {
+    pub fn new(password: &str) -> Self {
+        Self {
+            password: password.to_string(),
         }
     }
 }
@@ -23,13 +68,70 @@ impl Register {
 pub struct Login {
     pub username: String,
     pub password: String,
+    #[serde(default)]
+    pub device: String,
+    #[serde(default)]
+    pub proof: Option<KeyProof>,
 }
 
 impl Login {
-    pub fn new(username: &str, password: &str) -> Self {
+    pub fn new(username: &str, password: &str, device: &str) -> Self {
         Self {
             username: username.to_string(),
             password: password.to_string(),
+            device: device.to_string(),
+            proof: None,
+        }
+    }
+
+    pub fn proof(mut self, proof: KeyProof) -> Self {
+        self.proof = Some(proof);
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeyProof {
+    pub nonce: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl KeyProof {
+    pub fn new(nonce: &[u8], signature: &[u8]) -> Self {
+        Self {
+            nonce: nonce.to_vec(),
+            signature: signature.to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RequestChallenge {
+    pub username: String,
+}
+
+impl RequestChallenge {
+    pub fn new(username: &str) -> Self {
+        Self {
+            username: username.to_string(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct KeyLogin {
+    pub username: String,
+    pub proof: KeyProof,
+    #[serde(default)]
+    pub device: String,
+}
+
+impl KeyLogin {
+    pub fn new(username: &str, proof: KeyProof, device: &str) -> Self {
+        Self {
+            username: username.to_string(),
+            proof,
+            device: device.to_string(),
         }
     }
 }
@@ -40,6 +142,15 @@ pub struct SendMessage {
     pub contents: Vec<u8>,
     pub my_secret_key: Vec<u8>,
     pub recipient_secret_key: Vec<u8>,
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    #[serde(default)]
+    pub revision: Option<Revision>,
+    // opaque tag the participants derive from a secret only they share
+    #[serde(default)]
+    pub conversation: Option<String>,
 }
 
 impl SendMessage {
@@ -54,6 +165,189 @@ impl SendMessage {
             contents: contents.to_vec(),
             my_secret_key: secret_key.to_vec(),
             recipient_secret_key: recipient_secret_key.to_vec(),
+            attachments: vec![],
+            expires_at: None,
+            revision: None,
+            conversation: None,
         }
     }
+
+    pub fn attachments(mut self, attachments: &[String]) -> Self {
+        self.attachments = attachments.to_vec();
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: Option<i64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    pub fn revision(mut self, revision: Option<Revision>) -> Self {
+        self.revision = revision;
+        self
+    }
+
+    pub fn conversation(mut self, conversation: Option<String>) -> Self {
+        self.conversation = conversation;
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CreateGroup {
+    pub name: String,
+    pub members: Vec<String>,
+}
+
+impl CreateGroup {
+    pub fn new(name: &str, members: &[String]) -> Self {
+        Self {
+            name: name.to_string(),
+            members: members.to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SendGroupMessage {
+    pub epoch: i64,
+    pub contents: Vec<u8>,
+    #[serde(default)]
+    pub attachments: Vec<String>,
+    #[serde(default)]
+    pub expires_at: Option<i64>,
+    #[serde(default)]
+    pub revision: Option<Revision>,
+}
+
+impl SendGroupMessage {
+    pub fn new(epoch: i64, contents: &[u8]) -> Self {
+        Self {
+            epoch,
+            contents: contents.to_vec(),
+            attachments: vec![],
+            expires_at: None,
+            revision: None,
+        }
+    }
+
+    pub fn attachments(mut self, attachments: &[String]) -> Self {
+        self.attachments = attachments.to_vec();
+        self
+    }
+
+    pub fn expires_at(mut self, expires_at: Option<i64>) -> Self {
+        self.expires_at = expires_at;
+        self
+    }
+
+    pub fn revision(mut self, revision: Option<Revision>) -> Self {
+        self.revision = revision;
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupKey {
+    pub user_id: String,
+    pub secret_key: Vec<u8>,
+}
+
+impl GroupKey {
+    pub fn new(user_id: &str, secret_key: &[u8]) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            secret_key: secret_key.to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DistributeGroupKey {
+    pub epoch: i64,
+    pub keys: Vec<GroupKey>,
+}
+
+impl DistributeGroupKey {
+    pub fn new(epoch: i64, keys: Vec<GroupKey>) -> Self {
+        Self { epoch, keys }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WrappedKey {
+    pub id: i64,
+    pub secret_key: Vec<u8>,
+}
+
+impl WrappedKey {
+    pub fn new(id: i64, secret_key: &[u8]) -> Self {
+        Self {
+            id,
+            secret_key: secret_key.to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RotateKey {
+    pub public_key: Vec<u8>,
+    pub proof: KeyProof,
+}
+
+impl RotateKey {
+    pub fn new(public_key: &[u8], proof: KeyProof) -> Self {
+        Self {
+            public_key: public_key.to_vec(),
+            proof,
+        }
+    }
+}
+
+// a page of message keys wrapped for the new key, sent after the rotation with its nonce
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RewrapKeys {
+    pub nonce: Vec<u8>,
+    pub inbound: Vec<WrappedKey>,
+    pub outbound: Vec<WrappedKey>,
+}
+
+impl RewrapKeys {
+    pub fn new(nonce: &[u8], inbound: Vec<WrappedKey>, outbound: Vec<WrappedKey>) -> Self {
+        Self {
+            nonce: nonce.to_vec(),
+            inbound,
+            outbound,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SubscribePresence {
+    pub users: Vec<String>,
+}
+
+impl SubscribePresence {
+    pub fn new(users: &[String]) -> Self {
+        Self {
+            users: users.to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct PresenceSettings {
+    pub hidden: bool,
+}
+
+impl PresenceSettings {
+    pub fn new(hidden: bool) -> Self {
+        Self { hidden }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", content = "data")]
+pub enum ClientFrame {
+    Ack { id: i64 },
 }
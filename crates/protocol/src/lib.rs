@@ -1,2 +1,3 @@
 pub mod ctos;
 pub mod stoc;
+pub mod validation;
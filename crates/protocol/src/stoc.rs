@@ -1,5 +1,9 @@
+use std::fmt::Display;
+
 use serde::{Deserialize, Serialize};
 
+use crate::validation::ValidationError;
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ResponseGetUser {
     pub id: String,
@@ -7,11 +11,51 @@ pub struct ResponseGetUser {
     pub public_key: Vec<u8>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Message {
     pub id: i64,
     pub contents: Vec<u8>,
     pub secret_key: Vec<u8>,
+    #[serde(default)]
+    pub revision: Option<Revision>,
+}
+
+// an edit or delete of an earlier message, referenced by the server id the receiver knows it by
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Revision {
+    Edit(i64),
+    Delete(i64),
+}
+
+impl Revision {
+    pub fn from_columns(edits: Option<i64>, deletes: Option<i64>) -> Option<Self> {
+        match (edits, deletes) {
+            (Some(id), _) => Some(Revision::Edit(id)),
+            (None, Some(id)) => Some(Revision::Delete(id)),
+            (None, None) => None,
+        }
+    }
+
+    pub fn message_id(&self) -> i64 {
+        match self {
+            Revision::Edit(id) | Revision::Delete(id) => *id,
+        }
+    }
+
+    pub fn edits(&self) -> Option<i64> {
+        match self {
+            Revision::Edit(id) => Some(*id),
+            Revision::Delete(_) => None,
+        }
+    }
+
+    pub fn deletes(&self) -> Option<i64> {
+        match self {
+            Revision::Edit(_) => None,
+            Revision::Delete(id) => Some(*id),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -19,3 +63,255 @@ pub struct ResponseGetMessage {
     pub inbound: Vec<Message>,
     pub outbound: Vec<Message>,
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Presence {
+    pub id: String,
+    pub online: bool,
+    pub last_seen: Option<i64>,
+}
+
+impl Presence {
+    pub fn new(id: &str, online: bool, last_seen: Option<i64>) -> Self {
+        Self {
+            id: id.to_string(),
+            online,
+            last_seen,
+        }
+    }
+
+    pub fn hidden(id: &str) -> Self {
+        Self::new(id, false, None)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "data")]
+pub enum Notification {
+    Message(Message),
+    Presence(Presence),
+    KeyChanged(KeyRotation),
+    ProfileUpdated(UserProfile),
+    AccountDeleted(String),
+    GroupMessage(GroupMessage),
+    GroupChanged(Group),
+    GroupRemoved(i64),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UserProfile {
+    pub id: String,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Session {
+    pub id: i64,
+    pub device: String,
+    pub created_at: i64,
+    pub last_used: i64,
+    pub expires_at: i64,
+    pub current: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContactRequest {
+    pub sender: String,
+    pub message_count: i32,
+    pub created_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupRole {
+    Admin,
+    Member,
+}
+
+impl GroupRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GroupRole::Admin => "admin",
+            GroupRole::Member => "member",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "admin" => Some(GroupRole::Admin),
+            "member" => Some(GroupRole::Member),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupMember {
+    pub id: String,
+    pub name: String,
+    pub public_key: Vec<u8>,
+    pub role: GroupRole,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Blob {
+    pub id: String,
+    pub size: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Group {
+    pub id: i64,
+    pub name: String,
+    pub epoch: i64,
+    pub members: Vec<GroupMember>,
+    pub secret_key: Option<Vec<u8>>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GroupMessage {
+    pub id: i64,
+    pub group_id: i64,
+    // empty once the sender has deleted their account
+    pub sender: String,
+    pub epoch: i64,
+    pub contents: Vec<u8>,
+    pub secret_key: Vec<u8>,
+    #[serde(default)]
+    pub revision: Option<Revision>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockedUser {
+    pub id: String,
+    pub blocked_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChallengePurpose {
+    Login,
+    Register,
+    RotateKey,
+}
+
+impl ChallengePurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChallengePurpose::Login => "login",
+            ChallengePurpose::Register => "register",
+            ChallengePurpose::RotateKey => "rotate_key",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Challenge {
+    pub purpose: ChallengePurpose,
+    pub nonce: Vec<u8>,
+    pub expires_at: i64,
+}
+
+impl Challenge {
+    pub fn payload(purpose: ChallengePurpose, username: &str, nonce: &[u8]) -> Vec<u8> {
+        let mut payload = format!("messagist-{}:{}:", purpose.as_str(), username).into_bytes();
+        payload.extend_from_slice(nonce);
+        payload
+    }
+
+    pub fn signed_payload(&self, username: &str) -> Vec<u8> {
+        Self::payload(self.purpose, username, &self.nonce)
+    }
+
+    pub fn rotation_payload(username: &str, nonce: &[u8], public_key: &[u8]) -> Vec<u8> {
+        let mut payload = Self::payload(ChallengePurpose::RotateKey, username, nonce);
+        payload.extend_from_slice(public_key);
+        payload
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KeyRotation {
+    pub id: String,
+    pub previous_key: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub nonce: Vec<u8>,
+    pub signature: Vec<u8>,
+    pub rotated_at: i64,
+}
+
+impl KeyRotation {
+    pub fn signed_payload(&self) -> Vec<u8> {
+        Challenge::rotation_payload(&self.id, &self.nonce, &self.public_key)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "error", content = "details", rename_all = "snake_case")]
+pub enum ApiError {
+    MalformedKey,
+    WeakKey {
+        min_bits: usize,
+    },
+    InvalidProof,
+    UserExists,
+    InvalidCredentials,
+    StaleKey,
+    StaleGroupKey {
+        epoch: i64,
+    },
+    InvalidField {
+        field: String,
+        reason: ValidationError,
+    },
+    MalformedBody,
+    PayloadTooLarge {
+        limit: Option<u64>,
+    },
+    TooManyRequests,
+    NotFound,
+    QuotaExceeded {
+        quota: i64,
+    },
+    Internal,
+}
+
+impl Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiError::MalformedKey => write!(f, "The public key is malformed"),
+            ApiError::WeakKey { min_bits } => {
+                write!(f, "The public key must have at least {} bits", min_bits)
+            }
+            ApiError::InvalidProof => write!(f, "The key possession proof is invalid"),
+            ApiError::UserExists => write!(f, "A user with that ID already exists"),
+            ApiError::InvalidCredentials => write!(f, "The current password is incorrect"),
+            ApiError::StaleKey => write!(f, "The key was changed by another session"),
+            ApiError::StaleGroupKey { epoch } => {
+                write!(
+                    f,
+                    "The group key was rotated, the current epoch is {}",
+                    epoch
+                )
+            }
+            ApiError::InvalidField { field, reason } => write!(f, "Field '{}' {}", field, reason),
+            ApiError::MalformedBody => write!(f, "The request body is malformed"),
+            ApiError::PayloadTooLarge { limit: Some(limit) } => {
+                write!(f, "The request body exceeds the limit of {} bytes", limit)
+            }
+            ApiError::PayloadTooLarge { limit: None } => write!(f, "The request body is too large"),
+            ApiError::TooManyRequests => write!(f, "Too many requests, try again later"),
+            ApiError::NotFound => write!(f, "The requested resource does not exist"),
+            ApiError::QuotaExceeded { quota } => {
+                write!(
+                    f,
+                    "The storage quota of {} bytes was exceeded, delete sent attachments to free it",
+                    quota
+                )
+            }
+            ApiError::Internal => write!(f, "An internal server error occurred"),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
@@ -0,0 +1,442 @@
+use std::{
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ctos::{
+    ChangePassword, CreateGroup, DeleteAccount, DistributeGroupKey, GroupKey, KeyLogin, KeyProof,
+    Login, PresenceSettings, Register, RequestChallenge, RewrapKeys, RotateKey, SendGroupMessage,
+    SendMessage, SubscribePresence, UpdateProfile, WrappedKey,
+};
+
+pub const IST_ID_PREFIX: &str = "ist";
+pub const IST_ID_MIN_DIGITS: usize = 6;
+pub const IST_ID_MAX_DIGITS: usize = 10;
+pub const MAX_ID_LENGTH: usize = IST_ID_PREFIX.len() + IST_ID_MAX_DIGITS;
+pub const MAX_NAME_LENGTH: usize = 64;
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+pub const MAX_DEVICE_LENGTH: usize = 128;
+pub const MAX_KEY_LENGTH: usize = 2048;
+pub const MAX_NONCE_LENGTH: usize = 64;
+pub const MAX_MESSAGE_LENGTH: usize = 64 * 1024;
+pub const MAX_PRESENCE_SUBSCRIPTIONS: usize = 1024;
+pub const MAX_WRAPPED_KEYS: usize = 256;
+pub const MAX_GROUP_MEMBERS: usize = 256;
+pub const MAX_ATTACHMENTS: usize = 8;
+pub const MAX_BLOB_ID_LENGTH: usize = 64;
+// the default `blob` limit of the server
+pub const MAX_BLOB_SIZE: u64 = 16 * 1024 * 1024;
+pub const CONVERSATION_TAG_LENGTH: usize = 64;
+pub const MAX_MESSAGE_TIMER: i64 = 4 * 7 * 24 * 60 * 60;
+// expiry times are stamped with the sender's clock
+const MAX_CLOCK_SKEW: i64 = 5 * 60;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ValidationError {
+    Empty,
+    TooShort { min: usize },
+    TooLong { max: usize },
+    InvalidFormat,
+    OutOfRange,
+}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValidationError::Empty => write!(f, "cannot be empty"),
+            ValidationError::TooShort { min } => write!(f, "must have at least {} characters", min),
+            ValidationError::TooLong { max } => write!(f, "must have at most {} characters", max),
+            ValidationError::InvalidFormat => write!(f, "has an invalid format"),
+            ValidationError::OutOfRange => write!(f, "is out of range"),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub reason: ValidationError,
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Field '{}' {}", self.field, self.reason)
+    }
+}
+
+impl std::error::Error for FieldError {}
+
+pub trait FieldResult<T> {
+    fn field(self, field: &str) -> Result<T, FieldError>;
+}
+
+impl<T> FieldResult<T> for Result<T, ValidationError> {
+    fn field(self, field: &str) -> Result<T, FieldError> {
+        self.map_err(|reason| FieldError {
+            field: field.to_string(),
+            reason,
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct IstId(String);
+
+impl IstId {
+    pub fn parse(value: &str) -> Result<Self, ValidationError> {
+        if value.is_empty() {
+            return Err(ValidationError::Empty);
+        }
+        let Some(digits) = value.strip_prefix(IST_ID_PREFIX) else {
+            return Err(ValidationError::InvalidFormat);
+        };
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            return Err(ValidationError::InvalidFormat);
+        }
+        if digits.len() < IST_ID_MIN_DIGITS {
+            return Err(ValidationError::TooShort {
+                min: IST_ID_PREFIX.len() + IST_ID_MIN_DIGITS,
+            });
+        }
+        if digits.len() > IST_ID_MAX_DIGITS {
+            return Err(ValidationError::TooLong { max: MAX_ID_LENGTH });
+        }
+        Ok(Self(value.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for IstId {
+    type Error = ValidationError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<IstId> for String {
+    fn from(value: IstId) -> Self {
+        value.0
+    }
+}
+
+impl Display for IstId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct DisplayName(String);
+
+impl DisplayName {
+    pub fn parse(value: &str) -> Result<Self, ValidationError> {
+        if value.trim().is_empty() {
+            return Err(ValidationError::Empty);
+        }
+        if value != value.trim() || value.chars().any(char::is_control) {
+            return Err(ValidationError::InvalidFormat);
+        }
+        if value.chars().count() > MAX_NAME_LENGTH {
+            return Err(ValidationError::TooLong {
+                max: MAX_NAME_LENGTH,
+            });
+        }
+        Ok(Self(value.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl TryFrom<String> for DisplayName {
+    type Error = ValidationError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<DisplayName> for String {
+    fn from(value: DisplayName) -> Self {
+        value.0
+    }
+}
+
+impl Display for DisplayName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub fn validate_password(password: &str) -> Result<(), ValidationError> {
+    let length = password.chars().count();
+    if length == 0 {
+        return Err(ValidationError::Empty);
+    }
+    if length < MIN_PASSWORD_LENGTH {
+        return Err(ValidationError::TooShort {
+            min: MIN_PASSWORD_LENGTH,
+        });
+    }
+    if length > MAX_PASSWORD_LENGTH {
+        return Err(ValidationError::TooLong {
+            max: MAX_PASSWORD_LENGTH,
+        });
+    }
+    Ok(())
+}
+
+pub fn validate_text(value: &str, max: usize) -> Result<(), ValidationError> {
+    if value.is_empty() {
+        return Err(ValidationError::Empty);
+    }
+    if value.chars().count() > max {
+        return Err(ValidationError::TooLong { max });
+    }
+    Ok(())
+}
+
+pub fn validate_bytes(value: &[u8], max: usize) -> Result<(), ValidationError> {
+    if value.is_empty() {
+        return Err(ValidationError::Empty);
+    }
+    if value.len() > max {
+        return Err(ValidationError::TooLong { max });
+    }
+    Ok(())
+}
+
+fn validate_attachments(attachments: &[String]) -> Result<(), ValidationError> {
+    if attachments.len() > MAX_ATTACHMENTS {
+        return Err(ValidationError::TooLong {
+            max: MAX_ATTACHMENTS,
+        });
+    }
+    for attachment in attachments {
+        validate_text(attachment, MAX_BLOB_ID_LENGTH)?;
+    }
+    Ok(())
+}
+
+// conversation tags are hex encoded SHA-256 digests
+pub fn validate_conversation(tag: &str) -> Result<(), ValidationError> {
+    if tag.len() != CONVERSATION_TAG_LENGTH || !tag.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(ValidationError::InvalidFormat);
+    }
+    Ok(())
+}
+
+// an expiry has to lie in the future, at most one timer away
+fn validate_expiry(expires_at: i64) -> Result<(), ValidationError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    if expires_at <= now || expires_at > now + MAX_MESSAGE_TIMER + MAX_CLOCK_SKEW {
+        return Err(ValidationError::OutOfRange);
+    }
+    Ok(())
+}
+
+fn validate_device(device: &str) -> Result<(), ValidationError> {
+    if device.chars().count() > MAX_DEVICE_LENGTH {
+        return Err(ValidationError::TooLong {
+            max: MAX_DEVICE_LENGTH,
+        });
+    }
+    Ok(())
+}
+
+pub trait Validate {
+    fn validate(&self) -> Result<(), FieldError>;
+}
+
+impl Validate for KeyProof {
+    fn validate(&self) -> Result<(), FieldError> {
+        validate_bytes(&self.nonce, MAX_NONCE_LENGTH).field("nonce")?;
+        validate_bytes(&self.signature, MAX_KEY_LENGTH).field("signature")
+    }
+}
+
+impl Validate for Register {
+    fn validate(&self) -> Result<(), FieldError> {
+        IstId::parse(&self.id).field("id")?;
+        DisplayName::parse(&self.name).field("name")?;
+        validate_password(&self.password).field("password")?;
+        validate_bytes(&self.public_key, MAX_KEY_LENGTH).field("public_key")?;
+        self.proof.validate()
+    }
+}
+
+impl Validate for UpdateProfile {
+    fn validate(&self) -> Result<(), FieldError> {
+        DisplayName::parse(&self.name).field("name").map(|_| ())
+    }
+}
+
+impl Validate for ChangePassword {
+    fn validate(&self) -> Result<(), FieldError> {
+        validate_text(&self.current_password, MAX_PASSWORD_LENGTH).field("current_password")?;
+        validate_password(&self.new_password).field("new_password")
+    }
+}
+
+impl Validate for DeleteAccount {
+    fn validate(&self) -> Result<(), FieldError> {
+        validate_text(&self.password, MAX_PASSWORD_LENGTH).field("password")
+    }
+}
+
+impl Validate for Login {
+    fn validate(&self) -> Result<(), FieldError> {
+        validate_text(&self.username, MAX_ID_LENGTH).field("username")?;
+        validate_text(&self.password, MAX_PASSWORD_LENGTH).field("password")?;
+        validate_device(&self.device).field("device")?;
+        match &self.proof {
+            Some(proof) => proof.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Validate for RequestChallenge {
+    fn validate(&self) -> Result<(), FieldError> {
+        validate_text(&self.username, MAX_ID_LENGTH).field("username")
+    }
+}
+
+impl Validate for KeyLogin {
+    fn validate(&self) -> Result<(), FieldError> {
+        validate_text(&self.username, MAX_ID_LENGTH).field("username")?;
+        validate_device(&self.device).field("device")?;
+        self.proof.validate()
+    }
+}
+
+impl Validate for SendMessage {
+    fn validate(&self) -> Result<(), FieldError> {
+        validate_text(&self.recipient, MAX_ID_LENGTH).field("recipient")?;
+        validate_bytes(&self.contents, MAX_MESSAGE_LENGTH).field("contents")?;
+        validate_bytes(&self.my_secret_key, MAX_KEY_LENGTH).field("my_secret_key")?;
+        validate_bytes(&self.recipient_secret_key, MAX_KEY_LENGTH).field("recipient_secret_key")?;
+        if let Some(conversation) = &self.conversation {
+            validate_conversation(conversation).field("conversation")?;
+        }
+        if let Some(expires_at) = self.expires_at {
+            validate_expiry(expires_at).field("expires_at")?;
+        }
+        validate_attachments(&self.attachments).field("attachments")
+    }
+}
+
+impl Validate for CreateGroup {
+    fn validate(&self) -> Result<(), FieldError> {
+        DisplayName::parse(&self.name).field("name")?;
+        if self.members.len() >= MAX_GROUP_MEMBERS {
+            return Err(ValidationError::TooLong {
+                max: MAX_GROUP_MEMBERS - 1,
+            })
+            .field("members");
+        }
+        for member in &self.members {
+            validate_text(member, MAX_ID_LENGTH).field("members")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for SendGroupMessage {
+    fn validate(&self) -> Result<(), FieldError> {
+        validate_bytes(&self.contents, MAX_MESSAGE_LENGTH).field("contents")?;
+        if let Some(expires_at) = self.expires_at {
+            validate_expiry(expires_at).field("expires_at")?;
+        }
+        validate_attachments(&self.attachments).field("attachments")
+    }
+}
+
+impl Validate for GroupKey {
+    fn validate(&self) -> Result<(), FieldError> {
+        validate_text(&self.user_id, MAX_ID_LENGTH).field("user_id")?;
+        validate_bytes(&self.secret_key, MAX_KEY_LENGTH).field("secret_key")
+    }
+}
+
+impl Validate for DistributeGroupKey {
+    fn validate(&self) -> Result<(), FieldError> {
+        if self.keys.len() > MAX_GROUP_MEMBERS {
+            return Err(ValidationError::TooLong {
+                max: MAX_GROUP_MEMBERS,
+            })
+            .field("keys");
+        }
+        for key in &self.keys {
+            key.validate()?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for WrappedKey {
+    fn validate(&self) -> Result<(), FieldError> {
+        validate_bytes(&self.secret_key, MAX_KEY_LENGTH).field("secret_key")
+    }
+}
+
+impl Validate for RotateKey {
+    fn validate(&self) -> Result<(), FieldError> {
+        validate_bytes(&self.public_key, MAX_KEY_LENGTH).field("public_key")?;
+        self.proof.validate()
+    }
+}
+
+impl Validate for RewrapKeys {
+    fn validate(&self) -> Result<(), FieldError> {
+        validate_bytes(&self.nonce, MAX_NONCE_LENGTH).field("nonce")?;
+        for (field, keys) in [("inbound", &self.inbound), ("outbound", &self.outbound)] {
+            if keys.len() > MAX_WRAPPED_KEYS {
+                return Err(ValidationError::TooLong {
+                    max: MAX_WRAPPED_KEYS,
+                })
+                .field(field);
+            }
+            for key in keys {
+                key.validate()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Validate for SubscribePresence {
+    fn validate(&self) -> Result<(), FieldError> {
+        if self.users.len() > MAX_PRESENCE_SUBSCRIPTIONS {
+            return Err(ValidationError::TooLong {
+                max: MAX_PRESENCE_SUBSCRIPTIONS,
+            })
+            .field("users");
+        }
+        for user in &self.users {
+            validate_text(user, MAX_ID_LENGTH).field("users")?;
+        }
+        Ok(())
+    }
+}
+
+impl Validate for PresenceSettings {
+    fn validate(&self) -> Result<(), FieldError> {
+        Ok(())
+    }
+}
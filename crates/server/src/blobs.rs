@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use rocket::{time::OffsetDateTime, tokio};
+
+use crate::{config::BlobConfig, db::Database};
+
+pub fn spawn_collector(db: Database, config: BlobConfig) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(config.gc_interval.max(1)));
+        loop {
+            interval.tick().await;
+            collect_garbage(&db, &config).await;
+        }
+    });
+}
+
+async fn collect_garbage(db: &Database, config: &BlobConfig) {
+    // freshly uploaded blobs are kept for a while so the message referencing them can be sent
+    let before = OffsetDateTime::now_utc().unix_timestamp() - config.grace_period;
+    match db.delete_unreferenced_blobs(before).await {
+        Ok(0) => (),
+        Ok(n) => log::info!("Collected {} unreferenced blobs", n),
+        Err(e) => log::error!("Failed to collect unreferenced blobs: {}", e),
+    }
+}
@@ -0,0 +1,64 @@
+use protocol::{
+    ctos::KeyProof,
+    stoc::{Challenge, ChallengePurpose},
+};
+use rocket::time::OffsetDateTime;
+
+use crate::db::Database;
+
+pub async fn issue_challenge(
+    db: &Database,
+    user_id: &str,
+    purpose: ChallengePurpose,
+    lifetime: i64,
+) -> Result<Challenge, sqlx::Error> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    if let Err(e) = db.delete_expired_challenges(now).await {
+        log::error!("Failed to delete expired challenges: {}", e);
+    }
+    let nonce = cryptolib::generate_nonce();
+    let expires_at = now + lifetime;
+    db.create_challenge(&nonce, user_id, purpose.as_str(), expires_at)
+        .await?;
+    Ok(Challenge {
+        purpose,
+        nonce,
+        expires_at,
+    })
+}
+
+pub async fn verify_proof(
+    db: &Database,
+    user_id: &str,
+    purpose: ChallengePurpose,
+    proof: &KeyProof,
+    public_key: &[u8],
+) -> Result<bool, sqlx::Error> {
+    let payload = Challenge::payload(purpose, user_id, &proof.nonce);
+    verify_signed(db, user_id, purpose, proof, public_key, &payload).await
+}
+
+pub async fn verify_signed(
+    db: &Database,
+    user_id: &str,
+    purpose: ChallengePurpose,
+    proof: &KeyProof,
+    public_key: &[u8],
+    payload: &[u8],
+) -> Result<bool, sqlx::Error> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    if !db
+        .take_challenge(&proof.nonce, user_id, purpose.as_str(), now)
+        .await?
+    {
+        return Ok(false);
+    }
+    let Ok(public_key) = cryptolib::utils::public_key_from_bytes(public_key) else {
+        return Ok(false);
+    };
+    Ok(cryptolib::verify_signature(
+        payload,
+        &proof.signature,
+        &public_key,
+    ))
+}
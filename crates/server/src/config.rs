@@ -0,0 +1,230 @@
+use rocket::serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum NotifyBackendKind {
+    Memory,
+    Postgres,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(crate = "rocket::serde", rename_all = "lowercase")]
+pub enum StoreBackendKind {
+    Postgres,
+    Sqlite,
+    Memory,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct DatabaseConfig {
+    #[serde(default = "default_database_backend")]
+    pub backend: StoreBackendKind,
+    // database file of the sqlite backend
+    #[serde(default = "default_database_path")]
+    pub path: String,
+    #[serde(default = "default_database_name")]
+    pub name: String,
+    #[serde(default = "default_database_user")]
+    pub user: String,
+    #[serde(default = "default_database_tls")]
+    pub tls: bool,
+    // apply pending schema migrations on startup, otherwise run `server migrate` first
+    #[serde(default = "default_database_migrate")]
+    pub migrate: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct ServerConfig {
+    #[serde(default)]
+    pub purge_acked_messages: bool,
+    #[serde(default = "default_notify_backend")]
+    pub notify_backend: NotifyBackendKind,
+    #[serde(default = "default_instance_id")]
+    pub instance_id: String,
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    #[serde(default = "default_session_lifetime")]
+    pub session_lifetime: i64,
+    #[serde(default = "default_request_message_limit")]
+    pub request_message_limit: i32,
+    #[serde(default)]
+    pub blobs: BlobConfig,
+    #[serde(default = "default_expiry_interval")]
+    pub expiry_interval: u64,
+    #[serde(default = "default_heartbeat_interval")]
+    pub heartbeat_interval: u64,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde", default)]
+pub struct AuthConfig {
+    pub key_login: bool,
+    pub key_second_factor: bool,
+    pub challenge_lifetime: i64,
+    pub min_key_bits: usize,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde", default)]
+pub struct BlobConfig {
+    pub quota: i64,
+    pub grace_period: i64,
+    pub gc_interval: u64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct BucketConfig {
+    pub capacity: f64,
+    pub refill_rate: f64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct RouteLimitConfig {
+    pub ip: BucketConfig,
+    pub account: BucketConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde", default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    pub login: RouteLimitConfig,
+    pub register: RouteLimitConfig,
+    pub send_message: RouteLimitConfig,
+    pub get_user: RouteLimitConfig,
+    pub lockout_threshold: u32,
+    pub lockout_base: u64,
+    pub lockout_max: u64,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            backend: default_database_backend(),
+            path: default_database_path(),
+            name: default_database_name(),
+            user: default_database_user(),
+            tls: default_database_tls(),
+            migrate: default_database_migrate(),
+        }
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            key_login: true,
+            key_second_factor: false,
+            challenge_lifetime: 60,
+            min_key_bits: 2048,
+        }
+    }
+}
+
+impl Default for BlobConfig {
+    fn default() -> Self {
+        Self {
+            quota: 256 * 1024 * 1024,
+            grace_period: 60 * 60,
+            gc_interval: 5 * 60,
+        }
+    }
+}
+
+impl BucketConfig {
+    fn new(capacity: f64, refill_rate: f64) -> Self {
+        Self {
+            capacity,
+            refill_rate,
+        }
+    }
+}
+
+impl RouteLimitConfig {
+    fn new(ip: BucketConfig, account: BucketConfig) -> Self {
+        Self { ip, account }
+    }
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            login: RouteLimitConfig::new(
+                BucketConfig::new(20.0, 1.0 / 3.0),
+                BucketConfig::new(5.0, 1.0 / 60.0),
+            ),
+            register: RouteLimitConfig::new(
+                BucketConfig::new(5.0, 1.0 / 60.0),
+                BucketConfig::new(3.0, 1.0 / 60.0),
+            ),
+            send_message: RouteLimitConfig::new(
+                BucketConfig::new(60.0, 2.0),
+                BucketConfig::new(30.0, 1.0),
+            ),
+            get_user: RouteLimitConfig::new(
+                BucketConfig::new(60.0, 2.0),
+                BucketConfig::new(30.0, 1.0),
+            ),
+            lockout_threshold: 5,
+            lockout_base: 30,
+            lockout_max: 60 * 60,
+        }
+    }
+}
+
+fn default_notify_backend() -> NotifyBackendKind {
+    NotifyBackendKind::Memory
+}
+
+fn default_instance_id() -> String {
+    "default".to_string()
+}
+
+fn default_session_lifetime() -> i64 {
+    60 * 60 * 24 * 7
+}
+
+fn default_request_message_limit() -> i32 {
+    3
+}
+
+fn default_expiry_interval() -> u64 {
+    60
+}
+
+fn default_heartbeat_interval() -> u64 {
+    30
+}
+
+fn default_database_backend() -> StoreBackendKind {
+    StoreBackendKind::Postgres
+}
+
+fn default_database_path() -> String {
+    "messagist.db".to_string()
+}
+
+fn default_database_name() -> String {
+    "messagist".to_string()
+}
+
+fn default_database_user() -> String {
+    "messagist_server".to_string()
+}
+
+fn default_database_tls() -> bool {
+    true
+}
+
+fn default_database_migrate() -> bool {
+    true
+}
@@ -0,0 +1,34 @@
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RequestStatus {
+    Pending,
+    Accepted,
+    Declined,
+}
+
+impl RequestStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestStatus::Pending => "pending",
+            RequestStatus::Accepted => "accepted",
+            RequestStatus::Declined => "declined",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pending" => Some(RequestStatus::Pending),
+            "accepted" => Some(RequestStatus::Accepted),
+            "declined" => Some(RequestStatus::Declined),
+            _ => None,
+        }
+    }
+}
+
+// decided by the store in the same transaction that stores the message: replying to someone
+// who reached out first accepts their request, otherwise the sender's own request applies and
+// a pending one only lets a few messages through
+pub enum Consent {
+    Deliver,
+    Drop,
+    Limited,
+}
@@ -0,0 +1,1438 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error,
+    fmt,
+};
+
+use protocol::{
+    ctos::{GroupKey as WrappedGroupKey, SendGroupMessage, SendMessage, WrappedKey},
+    stoc::{GroupRole, KeyRotation, Revision},
+    validation::MAX_GROUP_MEMBERS,
+};
+use rocket::tokio::sync::Mutex;
+use sqlx::error::{DatabaseError, ErrorKind};
+
+use crate::consent::{Consent, RequestStatus};
+
+use super::{
+    migrations::Migration,
+    store::Store,
+    structs::{
+        Block, ContactRequest, Delivery, Group, GroupKey, GroupMember, GroupMessage, Invite,
+        Message, PresenceInfo, Session, User,
+    },
+    utils::{MessageUserInfo, INBOUND, OUTBOUND},
+};
+
+// the constraint errors the sql stores would raise, so handlers behave the same on every backend
+#[derive(Debug)]
+enum Violation {
+    Unique,
+    ForeignKey,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::Unique => write!(f, "duplicate key value violates unique constraint"),
+            Violation::ForeignKey => write!(f, "insert violates foreign key constraint"),
+        }
+    }
+}
+
+impl Error for Violation {}
+
+impl DatabaseError for Violation {
+    fn message(&self) -> &str {
+        match self {
+            Violation::Unique => "duplicate key value violates unique constraint",
+            Violation::ForeignKey => "insert violates foreign key constraint",
+        }
+    }
+
+    fn as_error(&self) -> &(dyn Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn as_error_mut(&mut self) -> &mut (dyn Error + Send + Sync + 'static) {
+        self
+    }
+
+    fn into_error(self: Box<Self>) -> Box<dyn Error + Send + Sync + 'static> {
+        self
+    }
+
+    fn kind(&self) -> ErrorKind {
+        match self {
+            Violation::Unique => ErrorKind::UniqueViolation,
+            Violation::ForeignKey => ErrorKind::ForeignKeyViolation,
+        }
+    }
+}
+
+struct UserRow {
+    user: User,
+    last_seen: Option<i64>,
+    presence_hidden: bool,
+    rotation_nonce: Option<Vec<u8>>,
+}
+
+struct MessageRow {
+    sender_id: Option<String>,
+    recipient_id: Option<String>,
+    content: Vec<u8>,
+    expires_at: Option<i64>,
+    edits: Option<i64>,
+    deletes: Option<i64>,
+    conversation: Option<String>,
+}
+
+struct MessageKey {
+    message_id: i64,
+    user_id: String,
+    wrapped_key: Vec<u8>,
+    direction: &'static str,
+}
+
+struct Challenge {
+    user_id: String,
+    purpose: String,
+    expires_at: i64,
+}
+
+struct Member {
+    group_id: i64,
+    user_id: String,
+    role: String,
+    joined_at: i64,
+}
+
+struct GroupMessageRow {
+    group_id: i64,
+    sender_id: Option<String>,
+    epoch: i64,
+    content: Vec<u8>,
+    expires_at: Option<i64>,
+    edits: Option<i64>,
+    deletes: Option<i64>,
+}
+
+struct BlobRow {
+    owner_id: Option<String>,
+    data: Vec<u8>,
+    created_at: i64,
+}
+
+#[derive(PartialEq)]
+enum BlobReference {
+    Message(i64),
+    GroupMessage(i64),
+}
+
+// the rows of every table; key rotations are left out as nothing reads them back
+#[derive(Default)]
+struct Tables {
+    sequence: i64,
+    users: BTreeMap<String, UserRow>,
+    messages: BTreeMap<i64, MessageRow>,
+    message_keys: Vec<MessageKey>,
+    pending_deliveries: BTreeMap<i64, String>,
+    presence_subscriptions: BTreeSet<(String, String)>,
+    sessions: BTreeMap<i64, Session>,
+    challenges: HashMap<Vec<u8>, Challenge>,
+    blocks: BTreeMap<(String, String), i64>,
+    contact_requests: BTreeMap<(String, String), ContactRequest>,
+    groups: BTreeMap<i64, Group>,
+    group_members: Vec<Member>,
+    group_keys: BTreeMap<(i64, i64, String), Vec<u8>>,
+    group_messages: BTreeMap<i64, GroupMessageRow>,
+    group_inbox: BTreeSet<(String, i64)>,
+    blobs: HashMap<String, BlobRow>,
+    blob_references: Vec<(String, BlobReference)>,
+}
+
+impl Tables {
+    // ids are never reused, clients page through messages by id
+    fn next_id(&mut self) -> i64 {
+        self.sequence += 1;
+        self.sequence
+    }
+
+    fn require_user(&self, id: &str) -> Result<(), sqlx::Error> {
+        if self.users.contains_key(id) {
+            Ok(())
+        } else {
+            Err(Violation::ForeignKey.into())
+        }
+    }
+
+    fn message_copy(&self, message_id: i64, user_id: &str, direction: &str) -> Option<Message> {
+        let key = self.message_keys.iter().find(|k| {
+            k.message_id == message_id && k.user_id == user_id && k.direction == direction
+        })?;
+        let message = self.messages.get(&message_id)?;
+        Some(Message {
+            id: message_id,
+            user_id: key.user_id.clone(),
+            content: message.content.clone(),
+            secret_key: key.wrapped_key.clone(),
+            edits: message.edits,
+            deletes: message.deletes,
+        })
+    }
+
+    // the newest copies matching the filter, in ascending order
+    fn message_copies(
+        &self,
+        user_id: &str,
+        direction: &str,
+        filter: impl Fn(i64, &MessageRow) -> bool,
+        limit: Option<i64>,
+    ) -> Vec<Message> {
+        let mut ids: Vec<i64> = self
+            .message_keys
+            .iter()
+            .filter(|k| k.user_id == user_id && k.direction == direction)
+            .map(|k| k.message_id)
+            .filter(|id| self.messages.get(id).is_some_and(|m| filter(*id, m)))
+            .collect();
+        ids.sort_unstable();
+        if let Some(limit) = limit {
+            let limit = limit.max(0) as usize;
+            ids.drain(..ids.len().saturating_sub(limit));
+        }
+        ids.into_iter()
+            .filter_map(|id| self.message_copy(id, user_id, direction))
+            .collect()
+    }
+
+    fn delete_message(&mut self, id: i64) {
+        self.messages.remove(&id);
+        self.message_keys.retain(|k| k.message_id != id);
+        self.pending_deliveries.remove(&id);
+        self.blob_references
+            .retain(|(_, r)| *r != BlobReference::Message(id));
+    }
+
+    // messages are only kept while someone still holds a key for them
+    fn delete_orphaned_messages(&mut self, ids: &[i64]) {
+        for id in ids {
+            if !self.message_keys.iter().any(|k| k.message_id == *id) {
+                self.delete_message(*id);
+            }
+        }
+    }
+
+    // checks the revised message was sent by the sender, returning who it was sent to
+    fn check_revision(
+        &self,
+        sender: &str,
+        revision: Revision,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let id = revision.message_id();
+        match self.messages.get(&id) {
+            Some(m)
+                if m.sender_id.as_deref() == Some(sender)
+                    && m.edits.is_none()
+                    && m.deletes.is_none() =>
+            {
+                Ok(m.recipient_id.clone())
+            }
+            _ => Err(sqlx::Error::RowNotFound),
+        }
+    }
+
+    fn apply_revision(&mut self, sender: &str, revision: Revision) {
+        if let Revision::Delete(id) = revision {
+            let revised: Vec<i64> = self
+                .messages
+                .iter()
+                .filter(|(m_id, m)| {
+                    m.sender_id.as_deref() == Some(sender) && (**m_id == id || m.edits == Some(id))
+                })
+                .map(|(m_id, _)| *m_id)
+                .collect();
+            for id in revised {
+                self.delete_message(id);
+            }
+        }
+    }
+
+    // see consent::Consent
+    fn take_consent(&mut self, sender: &str, recipient: &str, limit: i32, now: i64) -> Consent {
+        if sender == recipient {
+            return Consent::Deliver;
+        }
+        let reply = (recipient.to_string(), sender.to_string());
+        if let Some(request) = self.contact_requests.get_mut(&reply) {
+            match RequestStatus::parse(&request.status) {
+                Some(RequestStatus::Pending) => {
+                    request.status = RequestStatus::Accepted.as_str().to_string();
+                    return Consent::Deliver;
+                }
+                Some(RequestStatus::Accepted) => return Consent::Deliver,
+                _ => (),
+            }
+        }
+        let request = self
+            .contact_requests
+            .entry((sender.to_string(), recipient.to_string()))
+            .or_insert_with(|| ContactRequest {
+                sender_id: sender.to_string(),
+                recipient_id: recipient.to_string(),
+                status: RequestStatus::Pending.as_str().to_string(),
+                message_count: 0,
+                created_at: now,
+            });
+        match RequestStatus::parse(&request.status) {
+            Some(RequestStatus::Accepted) => Consent::Deliver,
+            Some(RequestStatus::Declined) => Consent::Drop,
+            _ if request.message_count < limit => {
+                request.message_count += 1;
+                Consent::Deliver
+            }
+            _ => Consent::Limited,
+        }
+    }
+
+    fn insert_message(&mut self, sender: &str, recipient: &str, message: &SendMessage) -> i64 {
+        let id = self.next_id();
+        let revision = message.revision;
+        self.messages.insert(
+            id,
+            MessageRow {
+                sender_id: Some(sender.to_string()),
+                recipient_id: Some(recipient.to_string()),
+                content: message.contents.clone(),
+                expires_at: message.expires_at,
+                edits: revision.and_then(|r| r.edits()),
+                deletes: revision.and_then(|r| r.deletes()),
+                conversation: message.conversation.clone(),
+            },
+        );
+        id
+    }
+
+    fn insert_message_key(
+        &mut self,
+        message_id: i64,
+        user: &MessageUserInfo,
+        direction: &'static str,
+    ) -> Message {
+        self.message_keys.push(MessageKey {
+            message_id,
+            user_id: user.id.clone(),
+            wrapped_key: user.secret_key.clone(),
+            direction,
+        });
+        self.message_copy(message_id, &user.id, direction)
+            .expect("message key was just inserted")
+    }
+
+    fn link_blobs(&mut self, owner: &str, blobs: &[String], reference: impl Fn() -> BlobReference) {
+        for blob in blobs {
+            let owned = self
+                .blobs
+                .get(blob)
+                .is_some_and(|b| b.owner_id.as_deref() == Some(owner));
+            if owned {
+                self.blob_references.push((blob.clone(), reference()));
+            }
+        }
+    }
+
+    fn delete_group_message(&mut self, id: i64) {
+        self.group_messages.remove(&id);
+        self.group_inbox.retain(|(_, message_id)| *message_id != id);
+        self.blob_references
+            .retain(|(_, r)| *r != BlobReference::GroupMessage(id));
+    }
+
+    fn delete_group(&mut self, id: i64) {
+        self.groups.remove(&id);
+        self.group_members.retain(|m| m.group_id != id);
+        self.group_keys
+            .retain(|(group_id, _, _), _| *group_id != id);
+        let messages: Vec<i64> = self
+            .group_messages
+            .iter()
+            .filter(|(_, m)| m.group_id == id)
+            .map(|(id, _)| *id)
+            .collect();
+        for message in messages {
+            self.delete_group_message(message);
+        }
+    }
+
+    fn settle_groups(&mut self, groups: &[i64]) {
+        for group_id in groups {
+            let Some(group) = self.groups.get_mut(group_id) else {
+                continue;
+            };
+            // every membership change invalidates the current group key
+            group.key_epoch += 1;
+            let admin = GroupRole::Admin.as_str();
+            let members = self
+                .group_members
+                .iter_mut()
+                .filter(|m| m.group_id == *group_id);
+            let mut oldest: Option<&mut Member> = None;
+            let mut has_admin = false;
+            for member in members {
+                has_admin |= member.role == admin;
+                if oldest
+                    .as_ref()
+                    .is_none_or(|o| (member.joined_at, &member.user_id) < (o.joined_at, &o.user_id))
+                {
+                    oldest = Some(member);
+                }
+            }
+            match oldest {
+                Some(member) if !has_admin => member.role = admin.to_string(),
+                Some(_) => (),
+                None => self.delete_group(*group_id),
+            }
+        }
+    }
+
+    fn is_contact(&self, user_id: &str, other: &str) -> bool {
+        [(user_id, other), (other, user_id)]
+            .iter()
+            .any(|(sender, recipient)| {
+                self.contact_requests
+                    .get(&(sender.to_string(), recipient.to_string()))
+                    .is_some_and(|r| r.status == RequestStatus::Accepted.as_str())
+            })
+    }
+
+    fn is_member(&self, group_id: i64, user_id: &str) -> bool {
+        self.group_members
+            .iter()
+            .any(|m| m.group_id == group_id && m.user_id == user_id)
+    }
+}
+
+// keeps everything in process memory, meant for tests and local development
+pub struct MemoryStore {
+    tables: Mutex<Tables>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore {
+            tables: Mutex::new(Tables::default()),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Store for MemoryStore {
+    async fn migrate(&self) -> Result<Vec<&'static Migration>, sqlx::Error> {
+        Ok(Vec::new())
+    }
+
+    async fn create_user(
+        &self,
+        id: &str,
+        name: &str,
+        password_hash: &str,
+        public_key: &[u8],
+    ) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        if tables.users.contains_key(id) {
+            return Err(Violation::Unique.into());
+        }
+        let user = User {
+            id: id.to_string(),
+            name: name.to_string(),
+            password_hash: password_hash.to_string(),
+            public_key: public_key.to_vec(),
+        };
+        tables.users.insert(
+            id.to_string(),
+            UserRow {
+                user,
+                last_seen: None,
+                presence_hidden: false,
+                rotation_nonce: None,
+            },
+        );
+        Ok(())
+    }
+
+    async fn get_user_by_id(&self, id: &str) -> Result<User, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        let row = tables.users.get(id).ok_or(sqlx::Error::RowNotFound)?;
+        Ok(row.user.clone())
+    }
+
+    async fn update_user_name(&self, id: &str, name: &str) -> Result<User, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let row = tables.users.get_mut(id).ok_or(sqlx::Error::RowNotFound)?;
+        row.user.name = name.to_string();
+        Ok(row.user.clone())
+    }
+
+    async fn change_password(
+        &self,
+        id: &str,
+        previous_hash: &str,
+        password_hash: &str,
+        keep_session: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        match tables.users.get_mut(id) {
+            Some(row) if row.user.password_hash == previous_hash => {
+                row.user.password_hash = password_hash.to_string();
+            }
+            _ => return Ok(false),
+        }
+        tables
+            .sessions
+            .retain(|s_id, s| s.user_id != id || *s_id == keep_session);
+        Ok(true)
+    }
+
+    async fn delete_user(&self, id: &str) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let tables = &mut *tables;
+        let groups: Vec<i64> = tables
+            .group_members
+            .iter()
+            .filter(|m| m.user_id == id)
+            .map(|m| m.group_id)
+            .collect();
+        let messages: Vec<i64> = tables
+            .message_keys
+            .iter()
+            .filter(|k| k.user_id == id)
+            .map(|k| k.message_id)
+            .collect();
+        tables.message_keys.retain(|k| k.user_id != id);
+        tables.delete_orphaned_messages(&messages);
+        tables.pending_deliveries.retain(|_, user_id| user_id != id);
+        tables
+            .presence_subscriptions
+            .retain(|(subscriber, target)| subscriber != id && target != id);
+        tables.sessions.retain(|_, s| s.user_id != id);
+        tables.challenges.retain(|_, c| c.user_id != id);
+        tables
+            .blocks
+            .retain(|(blocker, blocked), _| blocker != id && blocked != id);
+        tables
+            .contact_requests
+            .retain(|(sender, recipient), _| sender != id && recipient != id);
+        tables.group_inbox.retain(|(user_id, _)| user_id != id);
+        tables.group_keys.retain(|(_, _, user_id), _| user_id != id);
+        tables.group_members.retain(|m| m.user_id != id);
+        for blob in tables.blobs.values_mut() {
+            if blob.owner_id.as_deref() == Some(id) {
+                blob.owner_id = None;
+            }
+        }
+        tables.settle_groups(&groups);
+        for message in tables.group_messages.values_mut() {
+            if message.sender_id.as_deref() == Some(id) {
+                message.sender_id = None;
+            }
+        }
+        for message in tables.messages.values_mut() {
+            if message.sender_id.as_deref() == Some(id) {
+                message.sender_id = None;
+            }
+            if message.recipient_id.as_deref() == Some(id) {
+                message.recipient_id = None;
+            }
+        }
+        tables.users.remove(id);
+        Ok(())
+    }
+
+    async fn create_message(
+        &self,
+        sender: &MessageUserInfo,
+        receiver: &MessageUserInfo,
+        message: &SendMessage,
+        request_limit: i32,
+        now: i64,
+    ) -> Result<Delivery, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        if let Some(revision) = message.revision {
+            match tables.check_revision(&sender.id, revision)? {
+                Some(user_id) if user_id == receiver.id => (),
+                _ => return Err(sqlx::Error::RowNotFound),
+            }
+        }
+        tables.require_user(&sender.id)?;
+        tables.require_user(&receiver.id)?;
+        let consent = tables.take_consent(&sender.id, &receiver.id, request_limit, now);
+        if let Consent::Limited = consent {
+            return Ok(Delivery::Limited);
+        }
+        if let Some(revision) = message.revision {
+            tables.apply_revision(&sender.id, revision);
+        }
+        let id = tables.insert_message(&sender.id, &receiver.id, message);
+        let out_msg = tables.insert_message_key(id, sender, OUTBOUND);
+        // the sender keeps its copy so the decline is not revealed
+        if let Consent::Drop = consent {
+            tables.link_blobs(&sender.id, &message.attachments, || {
+                BlobReference::Message(id)
+            });
+            return Ok(Delivery::Dropped(out_msg));
+        }
+        let in_msg = tables.insert_message_key(id, receiver, INBOUND);
+        tables.pending_deliveries.insert(id, receiver.id.clone());
+        tables.link_blobs(&sender.id, &message.attachments, || {
+            BlobReference::Message(id)
+        });
+        Ok(Delivery::Delivered(in_msg, out_msg))
+    }
+
+    async fn create_out_message(
+        &self,
+        sender: &MessageUserInfo,
+        recipient: &str,
+        message: &SendMessage,
+    ) -> Result<Message, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        if let Some(revision) = message.revision {
+            tables.check_revision(&sender.id, revision)?;
+        }
+        tables.require_user(&sender.id)?;
+        if let Some(revision) = message.revision {
+            tables.apply_revision(&sender.id, revision);
+        }
+        let id = tables.insert_message(&sender.id, recipient, message);
+        let out_msg = tables.insert_message_key(id, sender, OUTBOUND);
+        tables.link_blobs(&sender.id, &message.attachments, || {
+            BlobReference::Message(id)
+        });
+        Ok(out_msg)
+    }
+
+    async fn get_in_messages(
+        &self,
+        user: &User,
+        after: i64,
+        limit: Option<i64>,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(tables.message_copies(&user.id, INBOUND, |id, _| id > after, limit))
+    }
+
+    async fn get_out_messages(
+        &self,
+        user: &User,
+        after: i64,
+        limit: Option<i64>,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(tables.message_copies(&user.id, OUTBOUND, |id, _| id > after, limit))
+    }
+
+    async fn get_conversation_in_messages(
+        &self,
+        user: &User,
+        conversation: Option<&str>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(tables.message_copies(
+            &user.id,
+            INBOUND,
+            |id, m| {
+                conversation.is_none_or(|c| m.conversation.as_deref() == Some(c))
+                    && before.is_none_or(|b| id < b)
+            },
+            Some(limit),
+        ))
+    }
+
+    async fn get_conversation_out_messages(
+        &self,
+        user: &User,
+        conversation: Option<&str>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(tables.message_copies(
+            &user.id,
+            OUTBOUND,
+            |id, m| {
+                conversation.is_none_or(|c| m.conversation.as_deref() == Some(c))
+                    && before.is_none_or(|b| id < b)
+            },
+            Some(limit),
+        ))
+    }
+
+    async fn get_pending_messages(&self, user_id: &str) -> Result<Vec<Message>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(tables
+            .pending_deliveries
+            .iter()
+            .filter(|(_, recipient)| *recipient == user_id)
+            .filter_map(|(id, _)| tables.message_copy(*id, user_id, INBOUND))
+            .collect())
+    }
+
+    async fn ack_message(
+        &self,
+        user_id: &str,
+        message_id: i64,
+        purge: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        if tables
+            .pending_deliveries
+            .get(&message_id)
+            .map(String::as_str)
+            != Some(user_id)
+        {
+            return Ok(false);
+        }
+        tables.pending_deliveries.remove(&message_id);
+        if purge {
+            tables.message_keys.retain(|k| {
+                k.message_id != message_id || k.user_id != user_id || k.direction != INBOUND
+            });
+            tables.delete_orphaned_messages(&[message_id]);
+        }
+        Ok(true)
+    }
+
+    async fn get_presence_info(&self, id: &str) -> Result<PresenceInfo, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        let row = tables.users.get(id).ok_or(sqlx::Error::RowNotFound)?;
+        Ok(PresenceInfo {
+            id: id.to_string(),
+            last_seen: row.last_seen,
+            presence_hidden: row.presence_hidden,
+        })
+    }
+
+    async fn update_last_seen(&self, id: &str, last_seen: i64) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        if let Some(row) = tables.users.get_mut(id) {
+            row.last_seen = Some(last_seen);
+        }
+        Ok(())
+    }
+
+    async fn set_presence_hidden(&self, id: &str, hidden: bool) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        if let Some(row) = tables.users.get_mut(id) {
+            row.presence_hidden = hidden;
+        }
+        Ok(())
+    }
+
+    async fn add_presence_subscription(
+        &self,
+        subscriber: &str,
+        target: &str,
+    ) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        tables.require_user(subscriber)?;
+        tables.require_user(target)?;
+        tables
+            .presence_subscriptions
+            .insert((subscriber.to_string(), target.to_string()));
+        Ok(())
+    }
+
+    async fn get_presence_subscribers(&self, target: &str) -> Result<Vec<String>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(tables
+            .presence_subscriptions
+            .iter()
+            .filter(|(subscriber, t)| t == target && tables.is_contact(subscriber, t))
+            .map(|(subscriber, _)| subscriber.clone())
+            .collect())
+    }
+
+    async fn create_session(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+        device: &str,
+        now: i64,
+        expires_at: i64,
+    ) -> Result<Session, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        tables.require_user(user_id)?;
+        if tables.sessions.values().any(|s| s.token_hash == token_hash) {
+            return Err(Violation::Unique.into());
+        }
+        let session = Session {
+            id: tables.next_id(),
+            token_hash: token_hash.to_string(),
+            user_id: user_id.to_string(),
+            device: device.to_string(),
+            created_at: now,
+            last_used: now,
+            expires_at,
+        };
+        tables.sessions.insert(session.id, session.clone());
+        Ok(session)
+    }
+
+    async fn get_session_by_token(
+        &self,
+        token_hash: &str,
+        now: i64,
+    ) -> Result<Option<Session>, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let session = tables
+            .sessions
+            .values_mut()
+            .find(|s| s.token_hash == token_hash && s.expires_at > now);
+        Ok(session.map(|session| {
+            session.last_used = now;
+            session.clone()
+        }))
+    }
+
+    async fn session_exists(&self, id: i64) -> Result<bool, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(tables.sessions.contains_key(&id))
+    }
+
+    async fn get_user_sessions(&self, user_id: &str) -> Result<Vec<Session>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        let mut sessions: Vec<Session> = tables
+            .sessions
+            .values()
+            .filter(|s| s.user_id == user_id)
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|s| Reverse(s.last_used));
+        Ok(sessions)
+    }
+
+    async fn delete_session(&self, user_id: &str, id: i64) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        if tables
+            .sessions
+            .get(&id)
+            .is_none_or(|s| s.user_id != user_id)
+        {
+            return Ok(false);
+        }
+        tables.sessions.remove(&id);
+        Ok(true)
+    }
+
+    async fn delete_other_sessions(&self, user_id: &str, keep: i64) -> Result<u64, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let before = tables.sessions.len();
+        tables
+            .sessions
+            .retain(|id, s| s.user_id != user_id || *id == keep);
+        Ok((before - tables.sessions.len()) as u64)
+    }
+
+    async fn delete_expired_sessions(&self, now: i64) -> Result<u64, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let before = tables.sessions.len();
+        tables.sessions.retain(|_, s| s.expires_at > now);
+        Ok((before - tables.sessions.len()) as u64)
+    }
+
+    async fn create_challenge(
+        &self,
+        nonce: &[u8],
+        user_id: &str,
+        purpose: &str,
+        expires_at: i64,
+    ) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        if tables.challenges.contains_key(nonce) {
+            return Err(Violation::Unique.into());
+        }
+        tables.challenges.insert(
+            nonce.to_vec(),
+            Challenge {
+                user_id: user_id.to_string(),
+                purpose: purpose.to_string(),
+                expires_at,
+            },
+        );
+        Ok(())
+    }
+
+    async fn take_challenge(
+        &self,
+        nonce: &[u8],
+        user_id: &str,
+        purpose: &str,
+        now: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let matches = tables
+            .challenges
+            .get(nonce)
+            .is_some_and(|c| c.user_id == user_id && c.purpose == purpose);
+        if !matches {
+            return Ok(false);
+        }
+        let challenge = tables.challenges.remove(nonce);
+        Ok(challenge.is_some_and(|c| c.expires_at > now))
+    }
+
+    async fn delete_expired_challenges(&self, now: i64) -> Result<u64, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let before = tables.challenges.len();
+        tables.challenges.retain(|_, c| c.expires_at > now);
+        Ok((before - tables.challenges.len()) as u64)
+    }
+
+    async fn rotate_user_key(&self, rotation: &KeyRotation) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        match tables.users.get_mut(&rotation.id) {
+            Some(row) if row.user.public_key == rotation.previous_key => {
+                row.user.public_key = rotation.public_key.clone();
+                row.rotation_nonce = Some(rotation.nonce.clone());
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn rewrap_message_keys(
+        &self,
+        user_id: &str,
+        nonce: &[u8],
+        inbound: &[WrappedKey],
+        outbound: &[WrappedKey],
+    ) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        match tables.users.get(user_id) {
+            Some(row) if row.rotation_nonce.as_deref() == Some(nonce) => (),
+            _ => return Ok(false),
+        }
+        for (direction, keys) in [(INBOUND, inbound), (OUTBOUND, outbound)] {
+            for key in keys {
+                let row = tables.message_keys.iter_mut().find(|k| {
+                    k.message_id == key.id && k.user_id == user_id && k.direction == direction
+                });
+                if let Some(row) = row {
+                    row.wrapped_key = key.secret_key.clone();
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    async fn add_block(
+        &self,
+        blocker: &str,
+        blocked: &str,
+        blocked_at: i64,
+    ) -> Result<(), sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        tables.require_user(blocker)?;
+        tables.require_user(blocked)?;
+        tables
+            .blocks
+            .entry((blocker.to_string(), blocked.to_string()))
+            .or_insert(blocked_at);
+        Ok(())
+    }
+
+    async fn remove_block(&self, blocker: &str, blocked: &str) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let removed = tables
+            .blocks
+            .remove(&(blocker.to_string(), blocked.to_string()));
+        Ok(removed.is_some())
+    }
+
+    async fn get_blocks(&self, blocker: &str) -> Result<Vec<Block>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        let mut blocks: Vec<Block> = tables
+            .blocks
+            .iter()
+            .filter(|((b, _), _)| b == blocker)
+            .map(|((_, blocked), blocked_at)| Block {
+                blocked_id: blocked.clone(),
+                blocked_at: *blocked_at,
+            })
+            .collect();
+        blocks.sort_by_key(|b| b.blocked_at);
+        Ok(blocks)
+    }
+
+    async fn is_blocked(&self, blocker: &str, blocked: &str) -> Result<bool, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(tables
+            .blocks
+            .contains_key(&(blocker.to_string(), blocked.to_string())))
+    }
+
+    async fn set_contact_request_status(
+        &self,
+        sender: &str,
+        recipient: &str,
+        status: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        match tables
+            .contact_requests
+            .get_mut(&(sender.to_string(), recipient.to_string()))
+        {
+            Some(request) => {
+                request.status = status.to_string();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn get_contact_requests(
+        &self,
+        recipient: &str,
+        status: &str,
+    ) -> Result<Vec<ContactRequest>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        let mut requests: Vec<ContactRequest> = tables
+            .contact_requests
+            .values()
+            .filter(|r| r.recipient_id == recipient && r.status == status)
+            .cloned()
+            .collect();
+        requests.sort_by_key(|r| r.created_at);
+        Ok(requests)
+    }
+
+    async fn is_contact(&self, user_id: &str, other: &str) -> Result<bool, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(tables.is_contact(user_id, other))
+    }
+
+    async fn get_contacts(&self, user_id: &str) -> Result<Vec<String>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        let contacts: BTreeSet<String> = tables
+            .contact_requests
+            .values()
+            .filter(|r| r.status == RequestStatus::Accepted.as_str())
+            .filter_map(
+                |r| match (r.sender_id == user_id, r.recipient_id == user_id) {
+                    (true, _) => Some(r.recipient_id.clone()),
+                    (_, true) => Some(r.sender_id.clone()),
+                    _ => None,
+                },
+            )
+            .collect();
+        Ok(contacts.into_iter().collect())
+    }
+
+    async fn create_group(
+        &self,
+        name: &str,
+        creator: &str,
+        members: &[String],
+        created_at: i64,
+    ) -> Result<Group, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        tables.require_user(creator)?;
+        for member in members {
+            tables.require_user(member)?;
+        }
+        let group = Group {
+            id: tables.next_id(),
+            name: name.to_string(),
+            key_epoch: 0,
+        };
+        tables.groups.insert(group.id, group.clone());
+        tables.group_members.push(Member {
+            group_id: group.id,
+            user_id: creator.to_string(),
+            role: GroupRole::Admin.as_str().to_string(),
+            joined_at: created_at,
+        });
+        for member in members {
+            if !tables.is_member(group.id, member) {
+                tables.group_members.push(Member {
+                    group_id: group.id,
+                    user_id: member.clone(),
+                    role: GroupRole::Member.as_str().to_string(),
+                    joined_at: created_at,
+                });
+            }
+        }
+        Ok(group)
+    }
+
+    async fn get_group(&self, id: i64) -> Result<Option<Group>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(tables.groups.get(&id).cloned())
+    }
+
+    async fn get_user_groups(&self, user_id: &str) -> Result<Vec<Group>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(tables
+            .groups
+            .values()
+            .filter(|g| tables.is_member(g.id, user_id))
+            .cloned()
+            .collect())
+    }
+
+    async fn get_group_members(&self, group_id: i64) -> Result<Vec<GroupMember>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        let mut members: Vec<&Member> = tables
+            .group_members
+            .iter()
+            .filter(|m| m.group_id == group_id)
+            .collect();
+        members.sort_by(|a, b| (a.joined_at, &a.user_id).cmp(&(b.joined_at, &b.user_id)));
+        Ok(members
+            .into_iter()
+            .filter_map(|m| {
+                let user = &tables.users.get(&m.user_id)?.user;
+                Some(GroupMember {
+                    user_id: m.user_id.clone(),
+                    name: user.name.clone(),
+                    public_key: user.public_key.clone(),
+                    role: m.role.clone(),
+                })
+            })
+            .collect())
+    }
+
+    async fn get_group_keys(
+        &self,
+        group_id: i64,
+        epoch: i64,
+    ) -> Result<Vec<GroupKey>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(tables
+            .group_keys
+            .iter()
+            .filter(|((g, e, _), _)| *g == group_id && *e == epoch)
+            .map(|((_, _, user_id), secret_key)| GroupKey {
+                user_id: user_id.clone(),
+                secret_key: secret_key.clone(),
+            })
+            .collect())
+    }
+
+    async fn add_group_member(
+        &self,
+        group_id: i64,
+        user_id: &str,
+        joined_at: i64,
+    ) -> Result<Invite, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        if tables.is_member(group_id, user_id) {
+            return Ok(Invite::AlreadyMember);
+        }
+        tables.require_user(user_id)?;
+        let members = tables
+            .group_members
+            .iter()
+            .filter(|m| m.group_id == group_id)
+            .count();
+        if members >= MAX_GROUP_MEMBERS {
+            return Ok(Invite::GroupFull);
+        }
+        let group = tables
+            .groups
+            .get_mut(&group_id)
+            .ok_or(Violation::ForeignKey)?;
+        group.key_epoch += 1;
+        tables.group_members.push(Member {
+            group_id,
+            user_id: user_id.to_string(),
+            role: GroupRole::Member.as_str().to_string(),
+            joined_at,
+        });
+        Ok(Invite::Added)
+    }
+
+    async fn remove_group_member(&self, group_id: i64, user_id: &str) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        if !tables.is_member(group_id, user_id) {
+            return Ok(false);
+        }
+        tables
+            .group_members
+            .retain(|m| m.group_id != group_id || m.user_id != user_id);
+        tables.settle_groups(&[group_id]);
+        Ok(true)
+    }
+
+    async fn set_group_role(
+        &self,
+        group_id: i64,
+        user_id: &str,
+        role: GroupRole,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let admin = GroupRole::Admin.as_str();
+        let other_admin = tables
+            .group_members
+            .iter()
+            .any(|m| m.group_id == group_id && m.user_id != user_id && m.role == admin);
+        let member = tables
+            .group_members
+            .iter_mut()
+            .find(|m| m.group_id == group_id && m.user_id == user_id);
+        match member {
+            Some(member)
+                if member.role == role.as_str() || role.as_str() == admin || other_admin =>
+            {
+                member.role = role.as_str().to_string();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn store_group_keys(
+        &self,
+        group_id: i64,
+        epoch: i64,
+        keys: &[WrappedGroupKey],
+    ) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let current = tables.groups.get(&group_id).map(|g| g.key_epoch);
+        if current != Some(epoch) || keys.is_empty() {
+            return Ok(false);
+        }
+        let distributed = tables
+            .group_keys
+            .keys()
+            .any(|(g, e, _)| *g == group_id && *e == epoch);
+        if distributed {
+            return Ok(false);
+        }
+        let mut users = BTreeSet::new();
+        for key in keys {
+            tables.require_user(&key.user_id)?;
+            if !users.insert(&key.user_id) {
+                return Err(Violation::Unique.into());
+            }
+        }
+        for key in keys {
+            tables.group_keys.insert(
+                (group_id, epoch, key.user_id.clone()),
+                key.secret_key.clone(),
+            );
+        }
+        Ok(true)
+    }
+
+    async fn create_group_message(
+        &self,
+        group_id: i64,
+        sender: &str,
+        message: &SendGroupMessage,
+        _sent_at: i64,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let current = tables.groups.get(&group_id).map(|g| g.key_epoch);
+        if current != Some(message.epoch) {
+            return Ok(None);
+        }
+        let has_key =
+            tables
+                .group_keys
+                .contains_key(&(group_id, message.epoch, sender.to_string()));
+        if !has_key {
+            return Ok(None);
+        }
+        if let Some(revision) = message.revision {
+            let target = revision.message_id();
+            let revisable = tables.group_messages.get(&target).is_some_and(|m| {
+                m.group_id == group_id
+                    && m.sender_id.as_deref() == Some(sender)
+                    && m.edits.is_none()
+                    && m.deletes.is_none()
+            });
+            if !revisable {
+                return Err(sqlx::Error::RowNotFound);
+            }
+            if let Revision::Delete(_) = revision {
+                let revised: Vec<i64> = tables
+                    .group_messages
+                    .iter()
+                    .filter(|(id, m)| {
+                        m.group_id == group_id && (**id == target || m.edits == Some(target))
+                    })
+                    .map(|(id, _)| *id)
+                    .collect();
+                for id in revised {
+                    tables.delete_group_message(id);
+                }
+            }
+        }
+        let id = tables.next_id();
+        tables.group_messages.insert(
+            id,
+            GroupMessageRow {
+                group_id,
+                sender_id: Some(sender.to_string()),
+                epoch: message.epoch,
+                content: message.contents.clone(),
+                expires_at: message.expires_at,
+                edits: message.revision.and_then(|r| r.edits()),
+                deletes: message.revision.and_then(|r| r.deletes()),
+            },
+        );
+        let members: Vec<String> = tables
+            .group_members
+            .iter()
+            .filter(|m| m.group_id == group_id)
+            .map(|m| m.user_id.clone())
+            .collect();
+        for member in members {
+            tables.group_inbox.insert((member, id));
+        }
+        tables.link_blobs(sender, &message.attachments, || {
+            BlobReference::GroupMessage(id)
+        });
+        Ok(Some(id))
+    }
+
+    async fn get_group_messages(
+        &self,
+        user_id: &str,
+        after: i64,
+    ) -> Result<Vec<GroupMessage>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        let mut messages: Vec<GroupMessage> = tables
+            .group_inbox
+            .iter()
+            .filter(|(u, id)| u == user_id && *id > after)
+            .filter_map(|(_, id)| {
+                let m = tables.group_messages.get(id)?;
+                let secret_key =
+                    tables
+                        .group_keys
+                        .get(&(m.group_id, m.epoch, user_id.to_string()))?;
+                Some(GroupMessage {
+                    id: *id,
+                    group_id: m.group_id,
+                    sender_id: m.sender_id.clone(),
+                    epoch: m.epoch,
+                    content: m.content.clone(),
+                    secret_key: secret_key.clone(),
+                    edits: m.edits,
+                    deletes: m.deletes,
+                })
+            })
+            .collect();
+        messages.sort_by_key(|m| m.id);
+        Ok(messages)
+    }
+
+    async fn delete_expired_messages(&self, now: i64) -> Result<u64, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let expired = |expires_at: Option<i64>| expires_at.is_some_and(|e| e <= now);
+        let messages: Vec<i64> = tables
+            .messages
+            .iter()
+            .filter(|(_, m)| expired(m.expires_at))
+            .map(|(id, _)| *id)
+            .collect();
+        let group_messages: Vec<i64> = tables
+            .group_messages
+            .iter()
+            .filter(|(_, m)| expired(m.expires_at))
+            .map(|(id, _)| *id)
+            .collect();
+        let deleted = (messages.len() + group_messages.len()) as u64;
+        for id in messages {
+            tables.delete_message(id);
+        }
+        for id in group_messages {
+            tables.delete_group_message(id);
+        }
+        Ok(deleted)
+    }
+
+    async fn create_blob(
+        &self,
+        id: &str,
+        owner: &str,
+        data: &[u8],
+        quota: i64,
+        created_at: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let used: i64 = tables
+            .blobs
+            .values()
+            .filter(|b| b.owner_id.as_deref() == Some(owner))
+            .map(|b| b.data.len() as i64)
+            .sum();
+        if used + data.len() as i64 > quota {
+            return Ok(false);
+        }
+        tables.require_user(owner)?;
+        if tables.blobs.contains_key(id) {
+            return Err(Violation::Unique.into());
+        }
+        tables.blobs.insert(
+            id.to_string(),
+            BlobRow {
+                owner_id: Some(owner.to_string()),
+                data: data.to_vec(),
+                created_at,
+            },
+        );
+        Ok(true)
+    }
+
+    async fn owns_blobs(&self, owner: &str, ids: &[String]) -> Result<bool, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        Ok(ids.iter().all(|id| {
+            tables
+                .blobs
+                .get(id)
+                .is_some_and(|b| b.owner_id.as_deref() == Some(owner))
+        }))
+    }
+
+    async fn get_blob(&self, id: &str, user_id: &str) -> Result<Option<Vec<u8>>, sqlx::Error> {
+        let tables = self.tables.lock().await;
+        let Some(blob) = tables.blobs.get(id) else {
+            return Ok(None);
+        };
+        let shared = tables
+            .blob_references
+            .iter()
+            .filter(|(blob_id, _)| blob_id == id)
+            .any(|(_, reference)| match reference {
+                BlobReference::Message(message_id) => tables
+                    .message_keys
+                    .iter()
+                    .any(|k| k.message_id == *message_id && k.user_id == user_id),
+                BlobReference::GroupMessage(message_id) => tables
+                    .group_inbox
+                    .contains(&(user_id.to_string(), *message_id)),
+            });
+        if blob.owner_id.as_deref() == Some(user_id) || shared {
+            Ok(Some(blob.data.clone()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn delete_unreferenced_blobs(&self, before: i64) -> Result<u64, sqlx::Error> {
+        let mut tables = self.tables.lock().await;
+        let tables = &mut *tables;
+        let before_len = tables.blobs.len();
+        let references = &tables.blob_references;
+        tables.blobs.retain(|id, b| {
+            b.created_at >= before || references.iter().any(|(blob_id, _)| blob_id == id)
+        });
+        Ok((before_len - tables.blobs.len()) as u64)
+    }
+}
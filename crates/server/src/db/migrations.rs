@@ -0,0 +1,69 @@
+pub struct Migration {
+    pub version: i64,
+    pub description: &'static str,
+    pub sql: &'static str,
+}
+
+// applied in order, each exactly once; never edit a migration that has shipped,
+// add a new one instead
+pub const POSTGRES_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: include_str!("../../../../database/migrations/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "user lookup indexes",
+        sql: include_str!("../../../../database/migrations/0002_user_indexes.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "server-side created_at timestamps",
+        sql: include_str!("../../../../database/migrations/0003_created_at.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "ids of sent copies from before the Messages table",
+        sql: include_str!("../../../../database/migrations/0004_legacy_sent_ids.sql"),
+    },
+    Migration {
+        version: 5,
+        description: "recipients of messages",
+        sql: include_str!("../../../../database/migrations/0005_message_recipients.sql"),
+    },
+    Migration {
+        version: 6,
+        description: "group messages outlive their sender",
+        sql: include_str!("../../../../database/migrations/0006_group_message_senders.sql"),
+    },
+    Migration {
+        version: 7,
+        description: "ids of message copies",
+        sql: include_str!("../../../../database/migrations/0007_message_copy_ids.sql"),
+    },
+];
+
+// sqlite databases start out with the schema postgres has after the migrations above
+pub const SQLITE_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "initial schema",
+        sql: include_str!("../../../../database/migrations/sqlite/0001_initial.sql"),
+    },
+    Migration {
+        version: 2,
+        description: "recipients of messages",
+        sql: include_str!("../../../../database/migrations/sqlite/0002_message_recipients.sql"),
+    },
+    Migration {
+        version: 3,
+        description: "group messages outlive their sender",
+        sql: include_str!("../../../../database/migrations/sqlite/0003_group_message_senders.sql"),
+    },
+    Migration {
+        version: 4,
+        description: "ids of message copies",
+        sql: include_str!("../../../../database/migrations/sqlite/0004_message_copy_ids.sql"),
+    },
+];
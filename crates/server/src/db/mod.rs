@@ -1,6 +1,13 @@
-mod database;
+mod memory;
+mod migrations;
+mod postgres;
+mod sqlite;
+mod store;
 pub mod structs;
 mod utils;
 
-pub use database::Database;
+pub use memory::MemoryStore;
+pub use postgres::PostgresStore;
+pub use sqlite::SqliteStore;
+pub use store::Database;
 pub use utils::MessageUserInfo;
@@ -0,0 +1,1296 @@
+use protocol::{
+    ctos::{GroupKey as WrappedGroupKey, SendGroupMessage, SendMessage, WrappedKey},
+    stoc::{GroupRole, KeyRotation, Revision},
+    validation::MAX_GROUP_MEMBERS,
+};
+use sqlx::{
+    sqlite::{SqliteConnectOptions, SqliteConnection, SqliteJournalMode, SqlitePoolOptions},
+    Connection, Executor, Pool, Sqlite,
+};
+
+use crate::consent::{Consent, RequestStatus};
+
+use super::{
+    migrations::{Migration, SQLITE_MIGRATIONS},
+    store::Store,
+    structs::{
+        Block, ContactRequest, Delivery, Group, GroupKey, GroupMember, GroupMessage, Invite,
+        Message, PresenceInfo, Session, User,
+    },
+    utils::{MessageUserInfo, INBOUND, MESSAGE_COPY, OUTBOUND},
+};
+
+// sqlite has a single writer, taking the lock up front keeps transactions that read before
+// they write from failing halfway instead of waiting for their turn
+const BEGIN_WRITE: &str = "BEGIN IMMEDIATE";
+
+type Transaction = sqlx::Transaction<'static, Sqlite>;
+
+#[derive(Clone)]
+pub struct SqliteStore {
+    pool: Pool<Sqlite>,
+}
+
+impl SqliteStore {
+    pub async fn new(path: &str) -> Result<SqliteStore, sqlx::Error> {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .foreign_keys(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(5)
+            .connect_with(options)
+            .await?;
+        Ok(SqliteStore { pool })
+    }
+
+    async fn apply_migrations(
+        conn: &mut SqliteConnection,
+    ) -> Result<Vec<&'static Migration>, sqlx::Error> {
+        let mut tx = conn.begin_with(BEGIN_WRITE).await?;
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER PRIMARY KEY, description TEXT NOT NULL, applied_at INTEGER NOT NULL)",
+        )
+        .execute(&mut *tx)
+        .await?;
+        let current: i64 =
+            sqlx::query_scalar("SELECT COALESCE(MAX(version), 0) FROM schema_version")
+                .fetch_one(&mut *tx)
+                .await?;
+
+        let mut applied = Vec::new();
+        for migration in SQLITE_MIGRATIONS {
+            if migration.version <= current {
+                continue;
+            }
+            tx.execute(sqlx::raw_sql(migration.sql)).await?;
+            sqlx::query(
+                "INSERT INTO schema_version (version, description, applied_at) VALUES ($1, $2, CAST(strftime('%s', 'now') AS INTEGER))",
+            )
+            .bind(migration.version)
+            .bind(migration.description)
+            .execute(&mut *tx)
+            .await?;
+            applied.push(migration);
+        }
+        let violations = sqlx::query("PRAGMA foreign_key_check")
+            .fetch_all(&mut *tx)
+            .await?;
+        if !violations.is_empty() {
+            return Err(sqlx::Error::Configuration(
+                "migrations left rows pointing at missing ones".into(),
+            ));
+        }
+        tx.commit().await?;
+        Ok(applied)
+    }
+
+    // created_at is stamped by the database
+    async fn insert_message(
+        tx: &mut Transaction,
+        sender: &str,
+        recipient: &str,
+        message: &SendMessage,
+    ) -> Result<i64, sqlx::Error> {
+        let revision = message.revision;
+        sqlx::query_scalar(
+            "INSERT INTO Messages (sender_id, recipient_id, content, expires_at, edits, deletes, conversation) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id",
+        )
+        .bind(sender)
+        .bind(recipient)
+        .bind(&message.contents)
+        .bind(message.expires_at)
+        .bind(revision.and_then(|r| r.edits()))
+        .bind(revision.and_then(|r| r.deletes()))
+        .bind(&message.conversation)
+        .fetch_one(&mut **tx)
+        .await
+    }
+
+    async fn insert_message_key(
+        tx: &mut Transaction,
+        message_id: i64,
+        user: &MessageUserInfo,
+        direction: &str,
+        revision: Option<Revision>,
+    ) -> Result<Message, sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO MessageKeys (message_id, copy_id, user_id, wrapped_key, direction, edits, deletes) VALUES ($1, $1, $2, $3, $4, $5, $6)",
+        )
+        .bind(message_id)
+        .bind(&user.id)
+        .bind(&user.secret_key)
+        .bind(direction)
+        .bind(revision.and_then(|r| r.edits()))
+        .bind(revision.and_then(|r| r.deletes()))
+        .execute(&mut **tx)
+        .await?;
+        sqlx::query_as::<_, Message>(&format!(
+            "{} WHERE k.message_id = $1 AND k.user_id = $2 AND k.direction = $3",
+            MESSAGE_COPY
+        ))
+        .bind(message_id)
+        .bind(&user.id)
+        .bind(direction)
+        .fetch_one(&mut **tx)
+        .await
+    }
+
+    async fn revise_message(
+        tx: &mut Transaction,
+        sender: &str,
+        revision: Revision,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let id = revision.message_id();
+        let receiver: Option<String> = sqlx::query_scalar(
+            "SELECT recipient_id FROM Messages WHERE id = $1 AND sender_id = $2 AND edits IS NULL AND deletes IS NULL",
+        )
+        .bind(id)
+        .bind(sender)
+        .fetch_one(&mut **tx)
+        .await?;
+        if let Revision::Delete(_) = revision {
+            sqlx::query("DELETE FROM Messages WHERE sender_id = $1 AND (id = $2 OR edits = $2)")
+                .bind(sender)
+                .bind(id)
+                .execute(&mut **tx)
+                .await?;
+        }
+        Ok(receiver)
+    }
+
+    // see consent::Consent, the write transaction keeps the counts exact
+    async fn take_consent(
+        tx: &mut Transaction,
+        sender: &str,
+        recipient: &str,
+        limit: i32,
+        now: i64,
+    ) -> Result<Consent, sqlx::Error> {
+        if sender == recipient {
+            return Ok(Consent::Deliver);
+        }
+        let reply: Option<String> = sqlx::query_scalar(
+            "SELECT status FROM ContactRequests WHERE sender_id = $1 AND recipient_id = $2",
+        )
+        .bind(recipient)
+        .bind(sender)
+        .fetch_optional(&mut **tx)
+        .await?;
+        match reply.as_deref().and_then(RequestStatus::parse) {
+            Some(RequestStatus::Pending) => {
+                sqlx::query(
+                    "UPDATE ContactRequests SET status = $1 WHERE sender_id = $2 AND recipient_id = $3",
+                )
+                .bind(RequestStatus::Accepted.as_str())
+                .bind(recipient)
+                .bind(sender)
+                .execute(&mut **tx)
+                .await?;
+                return Ok(Consent::Deliver);
+            }
+            Some(RequestStatus::Accepted) => return Ok(Consent::Deliver),
+            _ => (),
+        }
+        let (status, message_count): (String, i32) = sqlx::query_as(
+            "INSERT INTO ContactRequests (sender_id, recipient_id, status, created_at) VALUES ($1, $2, $3, $4) ON CONFLICT (sender_id, recipient_id) DO UPDATE SET sender_id = excluded.sender_id RETURNING status, message_count",
+        )
+        .bind(sender)
+        .bind(recipient)
+        .bind(RequestStatus::Pending.as_str())
+        .bind(now)
+        .fetch_one(&mut **tx)
+        .await?;
+        match RequestStatus::parse(&status) {
+            Some(RequestStatus::Accepted) => Ok(Consent::Deliver),
+            Some(RequestStatus::Declined) => Ok(Consent::Drop),
+            _ if message_count < limit => {
+                sqlx::query(
+                    "UPDATE ContactRequests SET message_count = message_count + 1 WHERE sender_id = $1 AND recipient_id = $2",
+                )
+                .bind(sender)
+                .bind(recipient)
+                .execute(&mut **tx)
+                .await?;
+                Ok(Consent::Deliver)
+            }
+            _ => Ok(Consent::Limited),
+        }
+    }
+
+    async fn delete_orphaned_messages(
+        tx: &mut Transaction,
+        ids: &[i64],
+    ) -> Result<(), sqlx::Error> {
+        for id in ids {
+            sqlx::query(
+                "DELETE FROM Messages WHERE id = $1 AND NOT EXISTS (SELECT 1 FROM MessageKeys k WHERE k.message_id = Messages.id)",
+            )
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn get_message_copies(
+        &self,
+        user: &User,
+        direction: &str,
+        after: i64,
+        limit: Option<i64>,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        let messages = sqlx::query_as::<_, Message>(&format!(
+            "SELECT * FROM ({} WHERE k.user_id = $1 AND k.direction = $2 AND k.copy_id > $3 ORDER BY k.copy_id DESC LIMIT COALESCE($4, -1)) msg ORDER BY msg.id",
+            MESSAGE_COPY
+        ))
+        .bind(&user.id)
+        .bind(direction)
+        .bind(after)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(messages)
+    }
+
+    async fn get_conversation_copies(
+        &self,
+        user: &User,
+        direction: &str,
+        conversation: Option<&str>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        let messages = sqlx::query_as::<_, Message>(&format!(
+            "SELECT * FROM ({} WHERE k.user_id = $1 AND k.direction = $2 AND ($3 IS NULL OR m.conversation = $3) AND ($4 IS NULL OR k.copy_id < $4) ORDER BY k.copy_id DESC LIMIT $5) msg ORDER BY msg.id",
+            MESSAGE_COPY
+        ))
+        .bind(&user.id)
+        .bind(direction)
+        .bind(conversation)
+        .bind(before)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(messages)
+    }
+
+    async fn settle_groups(tx: &mut Transaction, groups: &[i64]) -> Result<(), sqlx::Error> {
+        for group_id in groups {
+            // every membership change invalidates the current group key
+            sqlx::query("UPDATE Groups SET key_epoch = key_epoch + 1 WHERE id = $1")
+                .bind(group_id)
+                .execute(&mut **tx)
+                .await?;
+            sqlx::query(
+                "UPDATE GroupMembers SET role = $1 WHERE group_id = $2 AND user_id = (SELECT m.user_id FROM GroupMembers m WHERE m.group_id = $2 ORDER BY m.joined_at, m.user_id LIMIT 1) AND NOT EXISTS (SELECT 1 FROM GroupMembers a WHERE a.group_id = $2 AND a.role = $1)",
+            )
+            .bind(GroupRole::Admin.as_str())
+            .bind(group_id)
+            .execute(&mut **tx)
+            .await?;
+            sqlx::query(
+                "DELETE FROM Groups WHERE id = $1 AND NOT EXISTS (SELECT 1 FROM GroupMembers m WHERE m.group_id = Groups.id)",
+            )
+            .bind(group_id)
+            .execute(&mut **tx)
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn link_blobs(
+        tx: &mut Transaction,
+        owner: &str,
+        blobs: &[String],
+        column: &str,
+        message_id: i64,
+    ) -> Result<(), sqlx::Error> {
+        let query = format!(
+            "INSERT INTO BlobReferences (blob_id, {}) SELECT id, $1 FROM Blobs WHERE owner_id = $2 AND id = $3",
+            column
+        );
+        for blob in blobs {
+            sqlx::query(&query)
+                .bind(message_id)
+                .bind(owner)
+                .bind(blob)
+                .execute(&mut **tx)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[rocket::async_trait]
+impl Store for SqliteStore {
+    async fn migrate(&self) -> Result<Vec<&'static Migration>, sqlx::Error> {
+        // rebuilding a table must not cascade into the ones pointing at it, foreign keys can
+        // only be turned off outside a transaction and are checked before it commits instead
+        let mut conn = self.pool.acquire().await?;
+        sqlx::query("PRAGMA foreign_keys = OFF")
+            .execute(&mut *conn)
+            .await?;
+        let applied = Self::apply_migrations(&mut conn).await;
+        sqlx::query("PRAGMA foreign_keys = ON")
+            .execute(&mut *conn)
+            .await?;
+        applied
+    }
+
+    async fn create_user(
+        &self,
+        id: &str,
+        name: &str,
+        password_hash: &str,
+        public_key: &[u8],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO Users (id, name, password_hash, public_key) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(id)
+        .bind(name)
+        .bind(password_hash)
+        .bind(public_key)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_user_by_id(&self, id: &str) -> Result<User, sqlx::Error> {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM Users u WHERE u.id = $1")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(user)
+    }
+
+    async fn update_user_name(&self, id: &str, name: &str) -> Result<User, sqlx::Error> {
+        let user =
+            sqlx::query_as::<_, User>("UPDATE Users SET name = $1 WHERE id = $2 RETURNING *")
+                .bind(name)
+                .bind(id)
+                .fetch_one(&self.pool)
+                .await?;
+        Ok(user)
+    }
+
+    async fn change_password(
+        &self,
+        id: &str,
+        previous_hash: &str,
+        password_hash: &str,
+        keep_session: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        let result =
+            sqlx::query("UPDATE Users SET password_hash = $1 WHERE id = $2 AND password_hash = $3")
+                .bind(password_hash)
+                .bind(id)
+                .bind(previous_hash)
+                .execute(&mut *tx)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query("DELETE FROM Sessions WHERE user_id = $1 AND id <> $2")
+            .bind(id)
+            .bind(keep_session)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn delete_user(&self, id: &str) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        let groups: Vec<i64> =
+            sqlx::query_scalar("SELECT group_id FROM GroupMembers WHERE user_id = $1")
+                .bind(id)
+                .fetch_all(&mut *tx)
+                .await?;
+        let messages: Vec<i64> =
+            sqlx::query_scalar("DELETE FROM MessageKeys WHERE user_id = $1 RETURNING message_id")
+                .bind(id)
+                .fetch_all(&mut *tx)
+                .await?;
+        Self::delete_orphaned_messages(&mut tx, &messages).await?;
+        for query in [
+            "DELETE FROM PendingDeliveries WHERE user_id = $1",
+            "DELETE FROM PresenceSubscriptions WHERE subscriber_id = $1 OR target_id = $1",
+            "DELETE FROM Sessions WHERE user_id = $1",
+            "DELETE FROM AuthChallenges WHERE user_id = $1",
+            "DELETE FROM KeyRotations WHERE user_id = $1",
+            "DELETE FROM Blocks WHERE blocker_id = $1 OR blocked_id = $1",
+            "DELETE FROM ContactRequests WHERE sender_id = $1 OR recipient_id = $1",
+            "DELETE FROM GroupInbox WHERE user_id = $1",
+            "UPDATE GroupMessages SET sender_id = NULL WHERE sender_id = $1",
+            "DELETE FROM GroupKeys WHERE user_id = $1",
+            "DELETE FROM GroupMembers WHERE user_id = $1",
+            "UPDATE Blobs SET owner_id = NULL WHERE owner_id = $1",
+        ] {
+            sqlx::query(query).bind(id).execute(&mut *tx).await?;
+        }
+        Self::settle_groups(&mut tx, &groups).await?;
+        sqlx::query("DELETE FROM Users WHERE id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn create_message(
+        &self,
+        sender: &MessageUserInfo,
+        receiver: &MessageUserInfo,
+        message: &SendMessage,
+        request_limit: i32,
+        now: i64,
+    ) -> Result<Delivery, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        if let Some(revision) = message.revision {
+            match Self::revise_message(&mut tx, &sender.id, revision).await? {
+                Some(user_id) if user_id == receiver.id => (),
+                _ => return Err(sqlx::Error::RowNotFound),
+            }
+        }
+        let consent =
+            Self::take_consent(&mut tx, &sender.id, &receiver.id, request_limit, now).await?;
+        if let Consent::Limited = consent {
+            return Ok(Delivery::Limited);
+        }
+        let id = Self::insert_message(&mut tx, &sender.id, &receiver.id, message).await?;
+        let out_msg =
+            Self::insert_message_key(&mut tx, id, sender, OUTBOUND, message.revision).await?;
+        // the sender keeps its copy so the decline is not revealed
+        if let Consent::Drop = consent {
+            Self::link_blobs(&mut tx, &sender.id, &message.attachments, "message_id", id).await?;
+            tx.commit().await?;
+            return Ok(Delivery::Dropped(out_msg));
+        }
+        let in_msg =
+            Self::insert_message_key(&mut tx, id, receiver, INBOUND, message.revision).await?;
+
+        sqlx::query("INSERT INTO PendingDeliveries (message_id, user_id) VALUES ($1, $2)")
+            .bind(id)
+            .bind(&receiver.id)
+            .execute(&mut *tx)
+            .await?;
+
+        Self::link_blobs(&mut tx, &sender.id, &message.attachments, "message_id", id).await?;
+
+        tx.commit().await?;
+        Ok(Delivery::Delivered(in_msg, out_msg))
+    }
+
+    async fn create_out_message(
+        &self,
+        sender: &MessageUserInfo,
+        recipient: &str,
+        message: &SendMessage,
+    ) -> Result<Message, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        if let Some(revision) = message.revision {
+            Self::revise_message(&mut tx, &sender.id, revision).await?;
+        }
+        let id = Self::insert_message(&mut tx, &sender.id, recipient, message).await?;
+        let out_msg =
+            Self::insert_message_key(&mut tx, id, sender, OUTBOUND, message.revision).await?;
+        Self::link_blobs(&mut tx, &sender.id, &message.attachments, "message_id", id).await?;
+        tx.commit().await?;
+        Ok(out_msg)
+    }
+
+    async fn get_in_messages(
+        &self,
+        user: &User,
+        after: i64,
+        limit: Option<i64>,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        self.get_message_copies(user, INBOUND, after, limit).await
+    }
+
+    async fn get_out_messages(
+        &self,
+        user: &User,
+        after: i64,
+        limit: Option<i64>,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        self.get_message_copies(user, OUTBOUND, after, limit).await
+    }
+
+    async fn get_conversation_in_messages(
+        &self,
+        user: &User,
+        conversation: Option<&str>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        self.get_conversation_copies(user, INBOUND, conversation, before, limit)
+            .await
+    }
+
+    async fn get_conversation_out_messages(
+        &self,
+        user: &User,
+        conversation: Option<&str>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Message>, sqlx::Error> {
+        self.get_conversation_copies(user, OUTBOUND, conversation, before, limit)
+            .await
+    }
+
+    async fn get_pending_messages(&self, user_id: &str) -> Result<Vec<Message>, sqlx::Error> {
+        let messages = sqlx::query_as::<_, Message>(&format!(
+            "{} JOIN PendingDeliveries p ON p.message_id = k.message_id AND p.user_id = k.user_id WHERE k.user_id = $1 AND k.direction = $2 ORDER BY k.copy_id",
+            MESSAGE_COPY
+        ))
+        .bind(user_id)
+        .bind(INBOUND)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(messages)
+    }
+
+    async fn ack_message(
+        &self,
+        user_id: &str,
+        message_id: i64,
+        purge: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        let result =
+            sqlx::query("DELETE FROM PendingDeliveries WHERE message_id = $1 AND user_id = $2")
+                .bind(message_id)
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await?;
+        let acked = result.rows_affected() > 0;
+        if acked && purge {
+            sqlx::query(
+                "DELETE FROM MessageKeys WHERE message_id = $1 AND user_id = $2 AND direction = $3",
+            )
+            .bind(message_id)
+            .bind(user_id)
+            .bind(INBOUND)
+            .execute(&mut *tx)
+            .await?;
+            Self::delete_orphaned_messages(&mut tx, &[message_id]).await?;
+        }
+        tx.commit().await?;
+        Ok(acked)
+    }
+
+    async fn get_presence_info(&self, id: &str) -> Result<PresenceInfo, sqlx::Error> {
+        let info = sqlx::query_as::<_, PresenceInfo>(
+            "SELECT u.id, u.last_seen, u.presence_hidden FROM Users u WHERE u.id = $1",
+        )
+        .bind(id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(info)
+    }
+
+    async fn update_last_seen(&self, id: &str, last_seen: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE Users SET last_seen = $2 WHERE id = $1")
+            .bind(id)
+            .bind(last_seen)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn set_presence_hidden(&self, id: &str, hidden: bool) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE Users SET presence_hidden = $2 WHERE id = $1")
+            .bind(id)
+            .bind(hidden)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn add_presence_subscription(
+        &self,
+        subscriber: &str,
+        target: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO PresenceSubscriptions (subscriber_id, target_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(subscriber)
+        .bind(target)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn get_presence_subscribers(&self, target: &str) -> Result<Vec<String>, sqlx::Error> {
+        let subscribers = sqlx::query_scalar(
+            "SELECT s.subscriber_id FROM PresenceSubscriptions s WHERE s.target_id = $1 AND EXISTS (SELECT 1 FROM ContactRequests r WHERE ((r.sender_id = s.subscriber_id AND r.recipient_id = s.target_id) OR (r.sender_id = s.target_id AND r.recipient_id = s.subscriber_id)) AND r.status = $2)",
+        )
+        .bind(target)
+        .bind(RequestStatus::Accepted.as_str())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(subscribers)
+    }
+
+    async fn create_session(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+        device: &str,
+        now: i64,
+        expires_at: i64,
+    ) -> Result<Session, sqlx::Error> {
+        let session = sqlx::query_as::<_, Session>(
+            "INSERT INTO Sessions (token_hash, user_id, device, created_at, last_used, expires_at) VALUES ($1, $2, $3, $4, $4, $5) RETURNING *",
+        )
+        .bind(token_hash)
+        .bind(user_id)
+        .bind(device)
+        .bind(now)
+        .bind(expires_at)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(session)
+    }
+
+    async fn get_session_by_token(
+        &self,
+        token_hash: &str,
+        now: i64,
+    ) -> Result<Option<Session>, sqlx::Error> {
+        let session = sqlx::query_as::<_, Session>(
+            "UPDATE Sessions SET last_used = $2 WHERE token_hash = $1 AND expires_at > $2 RETURNING *",
+        )
+        .bind(token_hash)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(session)
+    }
+
+    async fn session_exists(&self, id: i64) -> Result<bool, sqlx::Error> {
+        let exists = sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM Sessions s WHERE s.id = $1)")
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(exists)
+    }
+
+    async fn get_user_sessions(&self, user_id: &str) -> Result<Vec<Session>, sqlx::Error> {
+        let sessions = sqlx::query_as::<_, Session>(
+            "SELECT * FROM Sessions s WHERE s.user_id = $1 ORDER BY s.last_used DESC",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(sessions)
+    }
+
+    async fn delete_session(&self, user_id: &str, id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM Sessions WHERE id = $1 AND user_id = $2")
+            .bind(id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_other_sessions(&self, user_id: &str, keep: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM Sessions WHERE user_id = $1 AND id <> $2")
+            .bind(user_id)
+            .bind(keep)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn delete_expired_sessions(&self, now: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM Sessions WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn create_challenge(
+        &self,
+        nonce: &[u8],
+        user_id: &str,
+        purpose: &str,
+        expires_at: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO AuthChallenges (nonce, user_id, purpose, expires_at) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(nonce)
+        .bind(user_id)
+        .bind(purpose)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn take_challenge(
+        &self,
+        nonce: &[u8],
+        user_id: &str,
+        purpose: &str,
+        now: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let expires_at: Option<i64> = sqlx::query_scalar(
+            "DELETE FROM AuthChallenges WHERE nonce = $1 AND user_id = $2 AND purpose = $3 RETURNING expires_at",
+        )
+        .bind(nonce)
+        .bind(user_id)
+        .bind(purpose)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(expires_at.is_some_and(|expires_at| expires_at > now))
+    }
+
+    async fn delete_expired_challenges(&self, now: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM AuthChallenges WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    async fn rotate_user_key(&self, rotation: &KeyRotation) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        let result =
+            sqlx::query("UPDATE Users SET public_key = $1 WHERE id = $2 AND public_key = $3")
+                .bind(&rotation.public_key)
+                .bind(&rotation.id)
+                .bind(&rotation.previous_key)
+                .execute(&mut *tx)
+                .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        sqlx::query(
+            "INSERT INTO KeyRotations (user_id, previous_key, public_key, nonce, signature, rotated_at) VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(&rotation.id)
+        .bind(&rotation.previous_key)
+        .bind(&rotation.public_key)
+        .bind(&rotation.nonce)
+        .bind(&rotation.signature)
+        .bind(rotation.rotated_at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn rewrap_message_keys(
+        &self,
+        user_id: &str,
+        nonce: &[u8],
+        inbound: &[WrappedKey],
+        outbound: &[WrappedKey],
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        let current: Option<i64> = sqlx::query_scalar(
+            "SELECT r.id FROM Users u JOIN KeyRotations r ON r.user_id = u.id AND r.public_key = u.public_key WHERE u.id = $1 AND r.nonce = $2",
+        )
+        .bind(user_id)
+        .bind(nonce)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if current.is_none() {
+            return Ok(false);
+        }
+        for (direction, keys) in [(INBOUND, inbound), (OUTBOUND, outbound)] {
+            for key in keys {
+                sqlx::query(
+                    "UPDATE MessageKeys SET wrapped_key = $1 WHERE copy_id = $2 AND user_id = $3 AND direction = $4",
+                )
+                .bind(&key.secret_key)
+                .bind(key.id)
+                .bind(user_id)
+                .bind(direction)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn add_block(
+        &self,
+        blocker: &str,
+        blocked: &str,
+        blocked_at: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO Blocks (blocker_id, blocked_id, blocked_at) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+        )
+        .bind(blocker)
+        .bind(blocked)
+        .bind(blocked_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_block(&self, blocker: &str, blocked: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM Blocks WHERE blocker_id = $1 AND blocked_id = $2")
+            .bind(blocker)
+            .bind(blocked)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_blocks(&self, blocker: &str) -> Result<Vec<Block>, sqlx::Error> {
+        let blocks = sqlx::query_as::<_, Block>(
+            "SELECT blocked_id, blocked_at FROM Blocks WHERE blocker_id = $1 ORDER BY blocked_at",
+        )
+        .bind(blocker)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(blocks)
+    }
+
+    async fn is_blocked(&self, blocker: &str, blocked: &str) -> Result<bool, sqlx::Error> {
+        let blocked = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM Blocks WHERE blocker_id = $1 AND blocked_id = $2)",
+        )
+        .bind(blocker)
+        .bind(blocked)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(blocked)
+    }
+
+    async fn set_contact_request_status(
+        &self,
+        sender: &str,
+        recipient: &str,
+        status: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE ContactRequests SET status = $1 WHERE sender_id = $2 AND recipient_id = $3",
+        )
+        .bind(status)
+        .bind(sender)
+        .bind(recipient)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_contact_requests(
+        &self,
+        recipient: &str,
+        status: &str,
+    ) -> Result<Vec<ContactRequest>, sqlx::Error> {
+        let requests = sqlx::query_as::<_, ContactRequest>(
+            "SELECT * FROM ContactRequests WHERE recipient_id = $1 AND status = $2 ORDER BY created_at",
+        )
+        .bind(recipient)
+        .bind(status)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(requests)
+    }
+
+    async fn is_contact(&self, user_id: &str, other: &str) -> Result<bool, sqlx::Error> {
+        let contact = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM ContactRequests WHERE ((sender_id = $1 AND recipient_id = $2) OR (sender_id = $2 AND recipient_id = $1)) AND status = $3)",
+        )
+        .bind(user_id)
+        .bind(other)
+        .bind(RequestStatus::Accepted.as_str())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(contact)
+    }
+
+    async fn get_contacts(&self, user_id: &str) -> Result<Vec<String>, sqlx::Error> {
+        let contacts = sqlx::query_scalar(
+            "SELECT recipient_id FROM ContactRequests WHERE sender_id = $1 AND status = $2 UNION SELECT sender_id FROM ContactRequests WHERE recipient_id = $1 AND status = $2",
+        )
+        .bind(user_id)
+        .bind(RequestStatus::Accepted.as_str())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(contacts)
+    }
+
+    async fn create_group(
+        &self,
+        name: &str,
+        creator: &str,
+        members: &[String],
+        created_at: i64,
+    ) -> Result<Group, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        let group = sqlx::query_as::<_, Group>(
+            "INSERT INTO Groups (name, created_at) VALUES ($1, $2) RETURNING *",
+        )
+        .bind(name)
+        .bind(created_at)
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO GroupMembers (group_id, user_id, role, joined_at) VALUES ($1, $2, $3, $4)",
+        )
+        .bind(group.id)
+        .bind(creator)
+        .bind(GroupRole::Admin.as_str())
+        .bind(created_at)
+        .execute(&mut *tx)
+        .await?;
+        for member in members {
+            sqlx::query(
+                "INSERT INTO GroupMembers (group_id, user_id, role, joined_at) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+            )
+            .bind(group.id)
+            .bind(member)
+            .bind(GroupRole::Member.as_str())
+            .bind(created_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(group)
+    }
+
+    async fn get_group(&self, id: i64) -> Result<Option<Group>, sqlx::Error> {
+        let group = sqlx::query_as::<_, Group>("SELECT * FROM Groups WHERE id = $1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(group)
+    }
+
+    async fn get_user_groups(&self, user_id: &str) -> Result<Vec<Group>, sqlx::Error> {
+        let groups = sqlx::query_as::<_, Group>(
+            "SELECT g.* FROM Groups g JOIN GroupMembers m ON m.group_id = g.id WHERE m.user_id = $1 ORDER BY g.id",
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(groups)
+    }
+
+    async fn get_group_members(&self, group_id: i64) -> Result<Vec<GroupMember>, sqlx::Error> {
+        let members = sqlx::query_as::<_, GroupMember>(
+            "SELECT m.user_id, u.name, u.public_key, m.role FROM GroupMembers m JOIN Users u ON u.id = m.user_id WHERE m.group_id = $1 ORDER BY m.joined_at, m.user_id",
+        )
+        .bind(group_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(members)
+    }
+
+    async fn get_group_keys(
+        &self,
+        group_id: i64,
+        epoch: i64,
+    ) -> Result<Vec<GroupKey>, sqlx::Error> {
+        let keys = sqlx::query_as::<_, GroupKey>(
+            "SELECT user_id, secret_key FROM GroupKeys WHERE group_id = $1 AND epoch = $2",
+        )
+        .bind(group_id)
+        .bind(epoch)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(keys)
+    }
+
+    async fn add_group_member(
+        &self,
+        group_id: i64,
+        user_id: &str,
+        joined_at: i64,
+    ) -> Result<Invite, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        let result = sqlx::query(
+            "INSERT INTO GroupMembers (group_id, user_id, role, joined_at) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+        )
+        .bind(group_id)
+        .bind(user_id)
+        .bind(GroupRole::Member.as_str())
+        .bind(joined_at)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(Invite::AlreadyMember);
+        }
+        let members: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM GroupMembers WHERE group_id = $1")
+                .bind(group_id)
+                .fetch_one(&mut *tx)
+                .await?;
+        if members > MAX_GROUP_MEMBERS as i64 {
+            return Ok(Invite::GroupFull);
+        }
+        sqlx::query("UPDATE Groups SET key_epoch = key_epoch + 1 WHERE id = $1")
+            .bind(group_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(Invite::Added)
+    }
+
+    async fn remove_group_member(&self, group_id: i64, user_id: &str) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        let result = sqlx::query("DELETE FROM GroupMembers WHERE group_id = $1 AND user_id = $2")
+            .bind(group_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        Self::settle_groups(&mut tx, &[group_id]).await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn set_group_role(
+        &self,
+        group_id: i64,
+        user_id: &str,
+        role: GroupRole,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE GroupMembers SET role = $1 WHERE group_id = $2 AND user_id = $3 AND (role = $1 OR $1 = $4 OR EXISTS (SELECT 1 FROM GroupMembers a WHERE a.group_id = $2 AND a.user_id <> $3 AND a.role = $4))",
+        )
+        .bind(role.as_str())
+        .bind(group_id)
+        .bind(user_id)
+        .bind(GroupRole::Admin.as_str())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn store_group_keys(
+        &self,
+        group_id: i64,
+        epoch: i64,
+        keys: &[WrappedGroupKey],
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        let current: Option<i64> = sqlx::query_scalar("SELECT key_epoch FROM Groups WHERE id = $1")
+            .bind(group_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if current != Some(epoch) || keys.is_empty() {
+            return Ok(false);
+        }
+        let distributed: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM GroupKeys WHERE group_id = $1 AND epoch = $2)",
+        )
+        .bind(group_id)
+        .bind(epoch)
+        .fetch_one(&mut *tx)
+        .await?;
+        if distributed {
+            return Ok(false);
+        }
+        for key in keys {
+            sqlx::query(
+                "INSERT INTO GroupKeys (group_id, epoch, user_id, secret_key) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(group_id)
+            .bind(epoch)
+            .bind(&key.user_id)
+            .bind(&key.secret_key)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn create_group_message(
+        &self,
+        group_id: i64,
+        sender: &str,
+        message: &SendGroupMessage,
+        sent_at: i64,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        let current: Option<i64> = sqlx::query_scalar("SELECT key_epoch FROM Groups WHERE id = $1")
+            .bind(group_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if current != Some(message.epoch) {
+            return Ok(None);
+        }
+        let has_key: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM GroupKeys WHERE group_id = $1 AND epoch = $2 AND user_id = $3)",
+        )
+        .bind(group_id)
+        .bind(message.epoch)
+        .bind(sender)
+        .fetch_one(&mut *tx)
+        .await?;
+        if !has_key {
+            return Ok(None);
+        }
+        if let Some(revision) = message.revision {
+            let target = revision.message_id();
+            sqlx::query_scalar::<_, i64>(
+                "SELECT id FROM GroupMessages WHERE id = $1 AND group_id = $2 AND sender_id = $3 AND edits IS NULL AND deletes IS NULL",
+            )
+            .bind(target)
+            .bind(group_id)
+            .bind(sender)
+            .fetch_one(&mut *tx)
+            .await?;
+            if let Revision::Delete(_) = revision {
+                sqlx::query(
+                    "DELETE FROM GroupMessages WHERE group_id = $1 AND (id = $2 OR edits = $2)",
+                )
+                .bind(group_id)
+                .bind(target)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        let id: i64 = sqlx::query_scalar(
+            "INSERT INTO GroupMessages (group_id, sender_id, epoch, content, sent_at, expires_at, edits, deletes) VALUES ($1, $2, $3, $4, $5, $6, $7, $8) RETURNING id",
+        )
+        .bind(group_id)
+        .bind(sender)
+        .bind(message.epoch)
+        .bind(&message.contents)
+        .bind(sent_at)
+        .bind(message.expires_at)
+        .bind(message.revision.and_then(|r| r.edits()))
+        .bind(message.revision.and_then(|r| r.deletes()))
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO GroupInbox (user_id, message_id) SELECT user_id, $1 FROM GroupMembers WHERE group_id = $2",
+        )
+        .bind(id)
+        .bind(group_id)
+        .execute(&mut *tx)
+        .await?;
+        Self::link_blobs(
+            &mut tx,
+            sender,
+            &message.attachments,
+            "group_message_id",
+            id,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok(Some(id))
+    }
+
+    async fn get_group_messages(
+        &self,
+        user_id: &str,
+        after: i64,
+    ) -> Result<Vec<GroupMessage>, sqlx::Error> {
+        let messages = sqlx::query_as::<_, GroupMessage>(
+            "SELECT m.id, m.group_id, m.sender_id, m.epoch, m.content, k.secret_key, m.edits, m.deletes FROM GroupInbox i JOIN GroupMessages m ON m.id = i.message_id JOIN GroupKeys k ON k.group_id = m.group_id AND k.epoch = m.epoch AND k.user_id = i.user_id WHERE i.user_id = $1 AND m.id > $2 ORDER BY m.id",
+        )
+        .bind(user_id)
+        .bind(after)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(messages)
+    }
+
+    async fn delete_expired_messages(&self, now: i64) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        let mut deleted = 0;
+        for query in [
+            "DELETE FROM Messages WHERE expires_at <= $1",
+            "DELETE FROM GroupMessages WHERE expires_at <= $1",
+        ] {
+            deleted += sqlx::query(query)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(deleted)
+    }
+
+    async fn create_blob(
+        &self,
+        id: &str,
+        owner: &str,
+        data: &[u8],
+        quota: i64,
+        created_at: i64,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin_with(BEGIN_WRITE).await?;
+        let used: i64 =
+            sqlx::query_scalar("SELECT COALESCE(SUM(size), 0) FROM Blobs WHERE owner_id = $1")
+                .bind(owner)
+                .fetch_one(&mut *tx)
+                .await?;
+        if used + data.len() as i64 > quota {
+            return Ok(false);
+        }
+        sqlx::query(
+            "INSERT INTO Blobs (id, owner_id, size, data, created_at) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(id)
+        .bind(owner)
+        .bind(data.len() as i64)
+        .bind(data)
+        .bind(created_at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn owns_blobs(&self, owner: &str, ids: &[String]) -> Result<bool, sqlx::Error> {
+        for id in ids {
+            let owned: bool = sqlx::query_scalar(
+                "SELECT EXISTS (SELECT 1 FROM Blobs WHERE owner_id = $1 AND id = $2)",
+            )
+            .bind(owner)
+            .bind(id)
+            .fetch_one(&self.pool)
+            .await?;
+            if !owned {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn get_blob(&self, id: &str, user_id: &str) -> Result<Option<Vec<u8>>, sqlx::Error> {
+        let data = sqlx::query_scalar(
+            "SELECT b.data FROM Blobs b WHERE b.id = $1 AND (b.owner_id = $2 OR EXISTS (SELECT 1 FROM BlobReferences r LEFT JOIN MessageKeys k ON k.message_id = r.message_id AND k.user_id = $2 LEFT JOIN GroupInbox g ON g.message_id = r.group_message_id AND g.user_id = $2 WHERE r.blob_id = b.id AND $2 IN (k.user_id, g.user_id)))",
+        )
+        .bind(id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(data)
+    }
+
+    async fn delete_unreferenced_blobs(&self, before: i64) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "DELETE FROM Blobs WHERE created_at < $1 AND NOT EXISTS (SELECT 1 FROM BlobReferences r WHERE r.blob_id = Blobs.id)",
+        )
+        .bind(before)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
@@ -0,0 +1,298 @@
+use std::{ops::Deref, sync::Arc};
+
+use protocol::{
+    ctos::{GroupKey as WrappedGroupKey, SendGroupMessage, SendMessage, WrappedKey},
+    stoc::{GroupRole, KeyRotation},
+};
+
+use super::{
+    migrations::Migration,
+    structs::{
+        Block, ContactRequest, Delivery, Group, GroupKey, GroupMember, GroupMessage, Invite,
+        Message, PresenceInfo, Session, User,
+    },
+    utils::MessageUserInfo,
+};
+
+// everything the handlers persist; a missing row is reported as sqlx::Error::RowNotFound and
+// a reference to an unknown user as a foreign key violation, whatever the backend
+#[rocket::async_trait]
+pub trait Store: Send + Sync {
+    async fn migrate(&self) -> Result<Vec<&'static Migration>, sqlx::Error>;
+
+    async fn create_user(
+        &self,
+        id: &str,
+        name: &str,
+        password_hash: &str,
+        public_key: &[u8],
+    ) -> Result<(), sqlx::Error>;
+
+    async fn get_user_by_id(&self, id: &str) -> Result<User, sqlx::Error>;
+
+    async fn update_user_name(&self, id: &str, name: &str) -> Result<User, sqlx::Error>;
+
+    async fn change_password(
+        &self,
+        id: &str,
+        previous_hash: &str,
+        password_hash: &str,
+        keep_session: i64,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn delete_user(&self, id: &str) -> Result<(), sqlx::Error>;
+
+    // checks and counts the receiver's consent together with storing the message
+    async fn create_message(
+        &self,
+        sender: &MessageUserInfo,
+        receiver: &MessageUserInfo,
+        message: &SendMessage,
+        request_limit: i32,
+        now: i64,
+    ) -> Result<Delivery, sqlx::Error>;
+
+    async fn create_out_message(
+        &self,
+        sender: &MessageUserInfo,
+        recipient: &str,
+        message: &SendMessage,
+    ) -> Result<Message, sqlx::Error>;
+
+    // with a limit only the newest messages after the cursor are returned
+    async fn get_in_messages(
+        &self,
+        user: &User,
+        after: i64,
+        limit: Option<i64>,
+    ) -> Result<Vec<Message>, sqlx::Error>;
+
+    async fn get_out_messages(
+        &self,
+        user: &User,
+        after: i64,
+        limit: Option<i64>,
+    ) -> Result<Vec<Message>, sqlx::Error>;
+
+    // a page of one conversation, or of the whole mailbox without a tag, the newest messages
+    // before the cursor in ascending order
+    async fn get_conversation_in_messages(
+        &self,
+        user: &User,
+        conversation: Option<&str>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Message>, sqlx::Error>;
+
+    async fn get_conversation_out_messages(
+        &self,
+        user: &User,
+        conversation: Option<&str>,
+        before: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<Message>, sqlx::Error>;
+
+    async fn get_pending_messages(&self, user_id: &str) -> Result<Vec<Message>, sqlx::Error>;
+
+    async fn ack_message(
+        &self,
+        user_id: &str,
+        message_id: i64,
+        purge: bool,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn get_presence_info(&self, id: &str) -> Result<PresenceInfo, sqlx::Error>;
+
+    async fn update_last_seen(&self, id: &str, last_seen: i64) -> Result<(), sqlx::Error>;
+
+    async fn set_presence_hidden(&self, id: &str, hidden: bool) -> Result<(), sqlx::Error>;
+
+    async fn add_presence_subscription(
+        &self,
+        subscriber: &str,
+        target: &str,
+    ) -> Result<(), sqlx::Error>;
+
+    // only subscribers who are still contacts of the target
+    async fn get_presence_subscribers(&self, target: &str) -> Result<Vec<String>, sqlx::Error>;
+
+    async fn create_session(
+        &self,
+        token_hash: &str,
+        user_id: &str,
+        device: &str,
+        now: i64,
+        expires_at: i64,
+    ) -> Result<Session, sqlx::Error>;
+
+    async fn get_session_by_token(
+        &self,
+        token_hash: &str,
+        now: i64,
+    ) -> Result<Option<Session>, sqlx::Error>;
+
+    async fn session_exists(&self, id: i64) -> Result<bool, sqlx::Error>;
+
+    async fn get_user_sessions(&self, user_id: &str) -> Result<Vec<Session>, sqlx::Error>;
+
+    async fn delete_session(&self, user_id: &str, id: i64) -> Result<bool, sqlx::Error>;
+
+    async fn delete_other_sessions(&self, user_id: &str, keep: i64) -> Result<u64, sqlx::Error>;
+
+    async fn delete_expired_sessions(&self, now: i64) -> Result<u64, sqlx::Error>;
+
+    async fn create_challenge(
+        &self,
+        nonce: &[u8],
+        user_id: &str,
+        purpose: &str,
+        expires_at: i64,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn take_challenge(
+        &self,
+        nonce: &[u8],
+        user_id: &str,
+        purpose: &str,
+        now: i64,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn delete_expired_challenges(&self, now: i64) -> Result<u64, sqlx::Error>;
+
+    async fn rotate_user_key(&self, rotation: &KeyRotation) -> Result<bool, sqlx::Error>;
+
+    // only accepted for the user's latest rotation, identified by its challenge nonce
+    async fn rewrap_message_keys(
+        &self,
+        user_id: &str,
+        nonce: &[u8],
+        inbound: &[WrappedKey],
+        outbound: &[WrappedKey],
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn add_block(
+        &self,
+        blocker: &str,
+        blocked: &str,
+        blocked_at: i64,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn remove_block(&self, blocker: &str, blocked: &str) -> Result<bool, sqlx::Error>;
+
+    async fn get_blocks(&self, blocker: &str) -> Result<Vec<Block>, sqlx::Error>;
+
+    async fn is_blocked(&self, blocker: &str, blocked: &str) -> Result<bool, sqlx::Error>;
+
+    async fn set_contact_request_status(
+        &self,
+        sender: &str,
+        recipient: &str,
+        status: &str,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn get_contact_requests(
+        &self,
+        recipient: &str,
+        status: &str,
+    ) -> Result<Vec<ContactRequest>, sqlx::Error>;
+
+    // an accepted request in either direction
+    async fn is_contact(&self, user_id: &str, other: &str) -> Result<bool, sqlx::Error>;
+
+    async fn get_contacts(&self, user_id: &str) -> Result<Vec<String>, sqlx::Error>;
+
+    async fn create_group(
+        &self,
+        name: &str,
+        creator: &str,
+        members: &[String],
+        created_at: i64,
+    ) -> Result<Group, sqlx::Error>;
+
+    async fn get_group(&self, id: i64) -> Result<Option<Group>, sqlx::Error>;
+
+    async fn get_user_groups(&self, user_id: &str) -> Result<Vec<Group>, sqlx::Error>;
+
+    async fn get_group_members(&self, group_id: i64) -> Result<Vec<GroupMember>, sqlx::Error>;
+
+    async fn get_group_keys(&self, group_id: i64, epoch: i64)
+        -> Result<Vec<GroupKey>, sqlx::Error>;
+
+    async fn add_group_member(
+        &self,
+        group_id: i64,
+        user_id: &str,
+        joined_at: i64,
+    ) -> Result<Invite, sqlx::Error>;
+
+    async fn remove_group_member(&self, group_id: i64, user_id: &str) -> Result<bool, sqlx::Error>;
+
+    async fn set_group_role(
+        &self,
+        group_id: i64,
+        user_id: &str,
+        role: GroupRole,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn store_group_keys(
+        &self,
+        group_id: i64,
+        epoch: i64,
+        keys: &[WrappedGroupKey],
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn create_group_message(
+        &self,
+        group_id: i64,
+        sender: &str,
+        message: &SendGroupMessage,
+        sent_at: i64,
+    ) -> Result<Option<i64>, sqlx::Error>;
+
+    async fn get_group_messages(
+        &self,
+        user_id: &str,
+        after: i64,
+    ) -> Result<Vec<GroupMessage>, sqlx::Error>;
+
+    async fn delete_expired_messages(&self, now: i64) -> Result<u64, sqlx::Error>;
+
+    // the quota covers every stored blob of the owner, a blob is only freed once no message
+    // references it any more, when those are deleted or expire
+    async fn create_blob(
+        &self,
+        id: &str,
+        owner: &str,
+        data: &[u8],
+        quota: i64,
+        created_at: i64,
+    ) -> Result<bool, sqlx::Error>;
+
+    async fn owns_blobs(&self, owner: &str, ids: &[String]) -> Result<bool, sqlx::Error>;
+
+    async fn get_blob(&self, id: &str, user_id: &str) -> Result<Option<Vec<u8>>, sqlx::Error>;
+
+    async fn delete_unreferenced_blobs(&self, before: i64) -> Result<u64, sqlx::Error>;
+}
+
+// the store picked by the configuration, shared by the handlers and background tasks
+#[derive(Clone)]
+pub struct Database {
+    store: Arc<dyn Store>,
+}
+
+impl Database {
+    pub fn new(store: impl Store + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+        }
+    }
+}
+
+impl Deref for Database {
+    type Target = dyn Store;
+
+    fn deref(&self) -> &Self::Target {
+        self.store.as_ref()
+    }
+}
@@ -1,3 +1,4 @@
+use protocol::stoc::Revision;
 use sqlx::prelude::FromRow;
 
 #[derive(FromRow, Debug, Clone)]
@@ -15,4 +16,99 @@ pub struct Message {
     pub user_id: String,
     pub content: Vec<u8>,
     pub secret_key: Vec<u8>,
+    pub edits: Option<i64>,
+    pub deletes: Option<i64>,
+}
+
+impl Message {
+    pub fn revision(&self) -> Option<Revision> {
+        Revision::from_columns(self.edits, self.deletes)
+    }
+}
+
+// the receiver's and sender's copies when delivered, only the sender's when dropped
+pub enum Delivery {
+    Delivered(Message, Message),
+    Dropped(Message),
+    Limited,
+}
+
+pub enum Invite {
+    Added,
+    AlreadyMember,
+    // the group already has MAX_GROUP_MEMBERS members
+    GroupFull,
+}
+
+#[derive(FromRow, Debug, Clone)]
+pub struct PresenceInfo {
+    pub id: String,
+    pub last_seen: Option<i64>,
+    pub presence_hidden: bool,
+}
+
+#[derive(FromRow, Debug, Clone)]
+#[allow(dead_code)]
+pub struct Session {
+    pub id: i64,
+    pub token_hash: String,
+    pub user_id: String,
+    pub device: String,
+    pub created_at: i64,
+    pub last_used: i64,
+    pub expires_at: i64,
+}
+
+#[derive(FromRow, Debug, Clone)]
+pub struct Block {
+    pub blocked_id: String,
+    pub blocked_at: i64,
+}
+
+#[derive(FromRow, Debug, Clone)]
+pub struct ContactRequest {
+    pub sender_id: String,
+    pub recipient_id: String,
+    pub status: String,
+    pub message_count: i32,
+    pub created_at: i64,
+}
+
+#[derive(FromRow, Debug, Clone)]
+pub struct Group {
+    pub id: i64,
+    pub name: String,
+    pub key_epoch: i64,
+}
+
+#[derive(FromRow, Debug, Clone)]
+pub struct GroupMember {
+    pub user_id: String,
+    pub name: String,
+    pub public_key: Vec<u8>,
+    pub role: String,
+}
+
+#[derive(FromRow, Debug, Clone)]
+pub struct GroupKey {
+    pub user_id: String,
+    pub secret_key: Vec<u8>,
+}
+
+#[derive(FromRow, Debug, Clone)]
+pub struct GroupMessage {
+    pub id: i64,
+    pub group_id: i64,
+    pub sender_id: Option<String>,
+    pub epoch: i64,
+    pub content: Vec<u8>,
+    pub secret_key: Vec<u8>,
+    pub edits: Option<i64>,
+    pub deletes: Option<i64>,
+}
+
+impl GroupMessage {
+    pub fn revision(&self) -> Option<Revision> {
+        Revision::from_columns(self.edits, self.deletes)
+    }
 }
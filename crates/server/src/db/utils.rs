@@ -1,3 +1,9 @@
+pub const INBOUND: &str = "in";
+pub const OUTBOUND: &str = "out";
+// one participant's copy of a message, the shared ciphertext with their own wrapped key and the
+// ids they know it and the message it revises by
+pub const MESSAGE_COPY: &str = "SELECT k.copy_id AS id, k.user_id, m.content, k.wrapped_key AS secret_key, k.edits, k.deletes FROM Messages m JOIN MessageKeys k ON k.message_id = m.id";
+
 pub struct MessageUserInfo {
     pub id: String,
     pub secret_key: Vec<u8>,
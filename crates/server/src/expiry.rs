@@ -0,0 +1,20 @@
+use std::time::Duration;
+
+use rocket::{time::OffsetDateTime, tokio};
+
+use crate::db::Database;
+
+pub fn spawn_reaper(db: Database, interval: u64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval.max(1)));
+        loop {
+            interval.tick().await;
+            let now = OffsetDateTime::now_utc().unix_timestamp();
+            match db.delete_expired_messages(now).await {
+                Ok(0) => (),
+                Ok(n) => log::info!("Deleted {} expired messages", n),
+                Err(e) => log::error!("Failed to delete expired messages: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,79 @@
+use protocol::stoc::{self, GroupRole, Notification};
+
+use crate::{
+    db::{
+        structs::{Group, GroupKey, GroupMember},
+        Database,
+    },
+    notify::NotifyService,
+};
+
+pub struct GroupState {
+    pub group: Group,
+    pub members: Vec<GroupMember>,
+    pub keys: Vec<GroupKey>,
+}
+
+impl GroupState {
+    pub async fn load(db: &Database, group_id: i64) -> Result<Option<GroupState>, sqlx::Error> {
+        let Some(group) = db.get_group(group_id).await? else {
+            return Ok(None);
+        };
+        let members = db.get_group_members(group.id).await?;
+        let keys = db.get_group_keys(group.id, group.key_epoch).await?;
+        Ok(Some(GroupState {
+            group,
+            members,
+            keys,
+        }))
+    }
+
+    pub fn role(&self, user_id: &str) -> Option<GroupRole> {
+        self.members
+            .iter()
+            .find(|m| m.user_id == user_id)
+            .and_then(|m| GroupRole::parse(&m.role))
+    }
+
+    pub fn view(&self, user_id: &str) -> stoc::Group {
+        stoc::Group {
+            id: self.group.id,
+            name: self.group.name.clone(),
+            epoch: self.group.key_epoch,
+            members: self
+                .members
+                .iter()
+                .map(|m| stoc::GroupMember {
+                    id: m.user_id.clone(),
+                    name: m.name.clone(),
+                    public_key: m.public_key.clone(),
+                    role: GroupRole::parse(&m.role).unwrap_or(GroupRole::Member),
+                })
+                .collect(),
+            secret_key: self
+                .keys
+                .iter()
+                .find(|k| k.user_id == user_id)
+                .map(|k| k.secret_key.clone()),
+        }
+    }
+}
+
+pub async fn publish_group(db: &Database, notify_service: &NotifyService, group_id: i64) {
+    let state = match GroupState::load(db, group_id).await {
+        Ok(Some(v)) => v,
+        Ok(None) => return,
+        Err(e) => {
+            log::error!("Failed to load group {}: {}", group_id, e);
+            return;
+        }
+    };
+    for member in &state.members {
+        notify_service
+            .publish(
+                &member.user_id,
+                Notification::GroupChanged(state.view(&member.user_id)),
+            )
+            .await;
+    }
+}
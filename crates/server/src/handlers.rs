@@ -1,42 +1,145 @@
-use std::time::Duration;
+use std::{sync::OnceLock, time::Duration};
 
 use protocol::{
-    ctos::{Login, Register, SendMessage},
-    stoc::{self, Message, ResponseGetMessage, ResponseGetUser},
+    ctos::{
+        ChangePassword, ClientFrame, CreateGroup, DeleteAccount, DistributeGroupKey, KeyLogin,
+        Login, PresenceSettings, Register, RequestChallenge, RewrapKeys, RotateKey,
+        SendGroupMessage, SendMessage, SubscribePresence, UpdateProfile,
+    },
+    stoc::{
+        self, ApiError, Blob, BlockedUser, Challenge, ChallengePurpose, ContactRequest, Group,
+        GroupMessage, GroupRole, KeyRotation, Message, Notification, Presence, ResponseGetMessage,
+        ResponseGetUser, UserProfile,
+    },
+    validation::{self, ValidationError},
 };
 use rocket::{
-    futures::SinkExt,
+    data::{Data, Limits},
+    futures::{SinkExt, StreamExt},
     http::{Cookie, CookieJar, Status},
     serde::json::Json,
+    time::OffsetDateTime,
     tokio, Shutdown, State,
 };
 use rocket_ws::{Channel, WebSocket};
 
 use crate::{
-    db::{Database, MessageUserInfo},
+    challenge,
+    config::ServerConfig,
+    consent::RequestStatus,
+    db::{
+        structs::{Delivery, Invite, User},
+        Database, MessageUserInfo,
+    },
+    groups::{self, GroupState},
     notify::NotifyService,
-    session::ClientSession,
+    presence,
+    rate_limit::{
+        GetUserPolicy, LoginPolicy, RateLimit, RateLimiter, RegisterPolicy, SendMessagePolicy,
+    },
+    session::{ClientSession, SESSION_COOKIE},
+    user_cache::UserCacheService,
+    validated::Valid,
 };
 
 type RequestResult<S> = Result<S, Status>;
+type ApiResult<S> = Result<S, (Status, Json<ApiError>)>;
+
+const MAX_MISSED_HEARTBEATS: u32 = 2;
+const DEFAULT_HISTORY_PAGE: i64 = 50;
+const MAX_HISTORY_PAGE: i64 = 200;
+
+fn api_error(status: Status, error: ApiError) -> (Status, Json<ApiError>) {
+    (status, Json(error))
+}
+
+fn dummy_password_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| cryptolib::hash_password("").unwrap_or_default())
+}
 
 #[head("/hello")]
 pub async fn hello() -> RequestResult<()> {
     RequestResult::Ok(())
 }
 
+#[post("/users/challenge", data = "<body>")]
+pub async fn register_challenge(
+    body: Valid<RequestChallenge>,
+    _limit: RateLimit<'_, RegisterPolicy>,
+    db: &State<Database>,
+    config: &State<ServerConfig>,
+) -> RequestResult<Json<Challenge>> {
+    match challenge::issue_challenge(
+        db,
+        &body.username,
+        ChallengePurpose::Register,
+        config.auth.challenge_lifetime,
+    )
+    .await
+    {
+        Ok(challenge) => Ok(Json(challenge)),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
 #[post("/users", data = "<body>")]
-pub async fn register(body: Json<Register>, db: &State<Database>) -> RequestResult<()> {
+pub async fn register(
+    body: Valid<Register>,
+    limit: RateLimit<'_, RegisterPolicy>,
+    db: &State<Database>,
+    config: &State<ServerConfig>,
+) -> ApiResult<()> {
+    if limit.check_account(&body.id).await.is_err() {
+        return Err(api_error(
+            Status::TooManyRequests,
+            ApiError::TooManyRequests,
+        ));
+    }
+    let Ok(public_key) = cryptolib::utils::public_key_from_bytes(&body.public_key) else {
+        log::info!("Denied registration: malformed public key!");
+        return Err(api_error(Status::BadRequest, ApiError::MalformedKey));
+    };
+    let min_bits = config.auth.min_key_bits;
+    if cryptolib::utils::public_key_bits(&public_key) < min_bits {
+        log::info!("Denied registration: weak public key!");
+        return Err(api_error(
+            Status::BadRequest,
+            ApiError::WeakKey { min_bits },
+        ));
+    }
+    match challenge::verify_proof(
+        db,
+        &body.id,
+        ChallengePurpose::Register,
+        &body.proof,
+        &body.public_key,
+    )
+    .await
+    {
+        Ok(true) => (),
+        Ok(false) => {
+            log::info!("Denied registration: invalid key possession proof!");
+            return Err(api_error(Status::BadRequest, ApiError::InvalidProof));
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(api_error(Status::InternalServerError, ApiError::Internal));
+        }
+    }
     let password_hash = match cryptolib::hash_password(&body.password) {
         Ok(v) => v,
         Err(e) => {
             log::error!("Password hashing failed {}", e);
-            return Err(Status::InternalServerError);
+            return Err(api_error(Status::InternalServerError, ApiError::Internal));
         }
     };
     if let Ok(_) = db.get_user_by_id(&body.id).await {
         log::info!("Denied registration: user already exists!");
-        return Err(Status::Forbidden);
+        return Err(api_error(Status::Forbidden, ApiError::UserExists));
     };
     match db
         .create_user(&body.id, &body.name, &password_hash, &body.public_key)
@@ -45,24 +148,176 @@ pub async fn register(body: Json<Register>, db: &State<Database>) -> RequestResu
         Ok(_) => Ok(()),
         Err(e) => {
             log::error!("Failed to create user: {}", e);
-            Err(Status::InternalServerError)
+            Err(api_error(Status::InternalServerError, ApiError::Internal))
         }
     }
 }
 
 #[post("/login", data = "<body>")]
 pub async fn login(
-    body: Json<Login>,
+    body: Valid<Login>,
+    limit: RateLimit<'_, LoginPolicy>,
     db: &State<Database>,
+    config: &State<ServerConfig>,
     cookies: &CookieJar<'_>,
 ) -> RequestResult<Json<ResponseGetUser>> {
-    let Ok(user) = db.get_user_by_id(&body.username).await else {
-        return RequestResult::Err(Status::NotFound);
+    let limiter = limit.limiter();
+    limiter.check_lockout(&body.username).await?;
+    limit.check_account(&body.username).await?;
+    let user = match db.get_user_by_id(&body.username).await {
+        Ok(v) => Some(v),
+        Err(sqlx::Error::RowNotFound) => None,
+        Err(e) => {
+            log::error!("{}", e);
+            return RequestResult::Err(Status::InternalServerError);
+        }
+    };
+    let password_hash = match &user {
+        Some(user) => user.password_hash.as_str(),
+        None => dummy_password_hash(),
+    };
+    let valid = cryptolib::verify_hashed_password(&body.password, password_hash);
+    let known = user.is_some();
+    let Some(user) = user.filter(|_| valid) else {
+        limiter.record_failure(&body.username, known).await;
+        return RequestResult::Err(Status::Unauthorized);
+    };
+    let proof_valid = match &body.proof {
+        Some(proof) => {
+            match challenge::verify_proof(
+                db,
+                &user.id,
+                ChallengePurpose::Login,
+                proof,
+                &user.public_key,
+            )
+            .await
+            {
+                Ok(v) => v,
+                Err(e) => {
+                    log::error!("{}", e);
+                    return RequestResult::Err(Status::InternalServerError);
+                }
+            }
+        }
+        None => !config.auth.key_second_factor,
+    };
+    if !proof_valid {
+        limiter.record_failure(&user.id, true).await;
+        return RequestResult::Err(Status::Unauthorized);
+    }
+    limiter.record_success(&user.id).await;
+    start_session(user, &body.device, db, config, cookies).await
+}
+
+#[post("/login/challenge", data = "<body>")]
+pub async fn login_challenge(
+    body: Valid<RequestChallenge>,
+    _limit: RateLimit<'_, LoginPolicy>,
+    db: &State<Database>,
+    config: &State<ServerConfig>,
+) -> RequestResult<Json<Challenge>> {
+    match challenge::issue_challenge(
+        db,
+        &body.username,
+        ChallengePurpose::Login,
+        config.auth.challenge_lifetime,
+    )
+    .await
+    {
+        Ok(challenge) => Ok(Json(challenge)),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[post("/login/key", data = "<body>")]
+pub async fn key_login(
+    body: Valid<KeyLogin>,
+    limit: RateLimit<'_, LoginPolicy>,
+    db: &State<Database>,
+    config: &State<ServerConfig>,
+    cookies: &CookieJar<'_>,
+) -> RequestResult<Json<ResponseGetUser>> {
+    if !config.auth.key_login {
+        return RequestResult::Err(Status::Forbidden);
+    }
+    let limiter = limit.limiter();
+    limiter.check_lockout(&body.username).await?;
+    limit.check_account(&body.username).await?;
+    let user = match db.get_user_by_id(&body.username).await {
+        Ok(v) => Some(v),
+        Err(sqlx::Error::RowNotFound) => None,
+        Err(e) => {
+            log::error!("{}", e);
+            return RequestResult::Err(Status::InternalServerError);
+        }
+    };
+    let public_key = match &user {
+        Some(user) => user.public_key.as_slice(),
+        None => &[],
+    };
+    let valid = match challenge::verify_proof(
+        db,
+        &body.username,
+        ChallengePurpose::Login,
+        &body.proof,
+        public_key,
+    )
+    .await
+    {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{}", e);
+            return RequestResult::Err(Status::InternalServerError);
+        }
     };
-    if !cryptolib::verify_hashed_password(&body.password, &user.password_hash) {
+    let known = user.is_some();
+    let Some(user) = user.filter(|_| valid) else {
+        limiter.record_failure(&body.username, known).await;
         return RequestResult::Err(Status::Unauthorized);
+    };
+    limiter.record_success(&user.id).await;
+    start_session(user, &body.device, db, config, cookies).await
+}
+
+async fn start_session(
+    user: User,
+    device: &str,
+    db: &Database,
+    config: &ServerConfig,
+    cookies: &CookieJar<'_>,
+) -> RequestResult<Json<ResponseGetUser>> {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    if let Err(e) = db.delete_expired_sessions(now).await {
+        log::error!("Failed to delete expired sessions: {}", e);
+    }
+    let token = cryptolib::generate_token();
+    let expires_at = now + config.session_lifetime;
+    let device = if device.is_empty() {
+        "unknown device"
+    } else {
+        device
+    };
+    if let Err(e) = db
+        .create_session(
+            &cryptolib::hash_token(&token),
+            &user.id,
+            device,
+            now,
+            expires_at,
+        )
+        .await
+    {
+        log::error!("{}", e);
+        return RequestResult::Err(Status::InternalServerError);
+    }
+    let mut cookie = Cookie::new(SESSION_COOKIE, token);
+    if let Ok(expires) = OffsetDateTime::from_unix_timestamp(expires_at) {
+        cookie.set_expires(expires);
     }
-    let cookie = Cookie::new("user", user.id.to_string());
     cookies.add_private(cookie);
     let response = ResponseGetUser {
         id: user.id,
@@ -73,20 +328,93 @@ pub async fn login(
 }
 
 #[post("/logout")]
-pub async fn logout(_session: ClientSession, cookies: &CookieJar<'_>) -> RequestResult<()> {
-    let Some(cookie) = cookies.get_private("user") else {
+pub async fn logout(
+    session: ClientSession,
+    db: &State<Database>,
+    cookies: &CookieJar<'_>,
+) -> RequestResult<()> {
+    let Some(cookie) = cookies.get_private(SESSION_COOKIE) else {
         return RequestResult::Err(Status::NotFound);
     };
     cookies.remove_private(cookie);
+    if let Err(e) = db
+        .delete_session(&session.user.id, session.session_id)
+        .await
+    {
+        log::error!("{}", e);
+        return RequestResult::Err(Status::InternalServerError);
+    }
+    Ok(())
+}
+
+#[get("/sessions")]
+pub async fn get_sessions(
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<Json<Vec<stoc::Session>>> {
+    let sessions = match db.get_user_sessions(&session.user.id).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{}", e);
+            return RequestResult::Err(Status::InternalServerError);
+        }
+    };
+    let response = sessions
+        .into_iter()
+        .map(|s| stoc::Session {
+            id: s.id,
+            device: s.device,
+            created_at: s.created_at,
+            last_used: s.last_used,
+            expires_at: s.expires_at,
+            current: s.id == session.session_id,
+        })
+        .collect();
+    Ok(Json(response))
+}
+
+#[delete("/sessions/<id>")]
+pub async fn revoke_session(
+    id: i64,
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<()> {
+    if id == session.session_id {
+        return RequestResult::Err(Status::BadRequest);
+    }
+    match db.delete_session(&session.user.id, id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => RequestResult::Err(Status::NotFound),
+        Err(e) => {
+            log::error!("{}", e);
+            RequestResult::Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[delete("/sessions")]
+pub async fn revoke_other_sessions(
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<()> {
+    if let Err(e) = db
+        .delete_other_sessions(&session.user.id, session.session_id)
+        .await
+    {
+        log::error!("{}", e);
+        return RequestResult::Err(Status::InternalServerError);
+    }
     Ok(())
 }
 
 #[get("/users/<username>")]
 pub async fn get_user(
     username: &str,
-    _session: ClientSession,
+    session: ClientSession,
+    limit: RateLimit<'_, GetUserPolicy>,
     db: &State<Database>,
 ) -> RequestResult<Json<ResponseGetUser>> {
+    limit.check_account(&session.user.id).await?;
     let Ok(user) = db.get_user_by_id(&username).await else {
         return Err(Status::NotFound);
     };
@@ -99,37 +427,90 @@ pub async fn get_user(
 
 #[post("/messages", data = "<body>")]
 pub async fn send_message(
-    body: Json<SendMessage>,
+    body: Valid<SendMessage>,
     session: ClientSession,
+    limit: RateLimit<'_, SendMessagePolicy>,
     db: &State<Database>,
+    config: &State<ServerConfig>,
     notify_service: &State<NotifyService>,
 ) -> RequestResult<Json<Message>> {
+    limit.check_account(&session.user.id).await?;
     let Ok(recipient) = db.get_user_by_id(&body.recipient).await else {
         return RequestResult::Err(Status::NotFound);
     };
+    match db.owns_blobs(&session.user.id, &body.attachments).await {
+        Ok(true) => (),
+        Ok(false) => return RequestResult::Err(Status::NotFound),
+        Err(e) => {
+            log::error!("{}", e);
+            return RequestResult::Err(Status::InternalServerError);
+        }
+    }
     let sender_info = MessageUserInfo::new(&session.user.id, &body.my_secret_key);
+    let blocked = match db.is_blocked(&recipient.id, &session.user.id).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{}", e);
+            return RequestResult::Err(Status::InternalServerError);
+        }
+    };
+    // keep the sender's copy so the block is not revealed, but never deliver it
+    if blocked {
+        return match db
+            .create_out_message(&sender_info, &recipient.id, &body)
+            .await
+        {
+            Ok(out_msg) => RequestResult::Ok(Json(Message {
+                id: out_msg.id,
+                revision: out_msg.revision(),
+                contents: out_msg.content,
+                secret_key: out_msg.secret_key,
+            })),
+            Err(sqlx::Error::RowNotFound) => RequestResult::Err(Status::NotFound),
+            Err(e) => {
+                log::error!("{}", e);
+                RequestResult::Err(Status::InternalServerError)
+            }
+        };
+    }
     let receiver_info = MessageUserInfo::new(&recipient.id, &body.recipient_secret_key);
-    match db
-        .create_message(&sender_info, &receiver_info, &body.contents)
-        .await
-    {
-        Ok((in_msg, out_msg)) => {
-            if let Some(queue) = notify_service.store.get_client_queue(&recipient.id).await {
-                log::info!("CLient WS connected sendiing notification");
-                let notification = stoc::Message {
-                    id: in_msg.id,
-                    contents: in_msg.content,
-                    secret_key: in_msg.secret_key,
-                };
-                queue.push(notification);
+    let delivery = db
+        .create_message(
+            &sender_info,
+            &receiver_info,
+            &body,
+            config.request_message_limit,
+            OffsetDateTime::now_utc().unix_timestamp(),
+        )
+        .await;
+    match delivery {
+        Ok(Delivery::Delivered(in_msg, out_msg)) => {
+            let notification = stoc::Message {
+                id: in_msg.id,
+                revision: in_msg.revision(),
+                contents: in_msg.content,
+                secret_key: in_msg.secret_key,
             };
+            notify_service
+                .publish(&recipient.id, Notification::Message(notification))
+                .await;
             let response = Message {
                 id: out_msg.id,
+                revision: out_msg.revision(),
                 contents: out_msg.content,
                 secret_key: out_msg.secret_key,
             };
             RequestResult::Ok(Json(response))
         }
+        // a declined request looks like a delivered message to the sender
+        Ok(Delivery::Dropped(out_msg)) => RequestResult::Ok(Json(Message {
+            id: out_msg.id,
+            revision: out_msg.revision(),
+            contents: out_msg.content,
+            secret_key: out_msg.secret_key,
+        })),
+        Ok(Delivery::Limited) => RequestResult::Err(Status::Forbidden),
+        Err(sqlx::Error::RowNotFound) => RequestResult::Err(Status::NotFound),
         Err(e) => {
             log::error!("{}", e);
             return RequestResult::Err(Status::InternalServerError);
@@ -137,16 +518,18 @@ pub async fn send_message(
     }
 }
 
-#[get("/messages?<after>&<out_after>")]
+#[get("/messages?<after>&<out_after>&<limit>")]
 pub async fn get_messages(
     after: i64,
     out_after: Option<i64>,
+    limit: Option<i64>,
     session: ClientSession,
     db: &State<Database>,
 ) -> RequestResult<Json<ResponseGetMessage>> {
     let mut inbound = vec![];
     let mut outbound = vec![];
-    let in_msgs = match db.get_in_messages(&session.user, after).await {
+    let limit = limit.map(|limit| limit.max(0));
+    let in_msgs = match db.get_in_messages(&session.user, after, limit).await {
         Ok(v) => v,
         Err(e) => {
             log::error!("{}", e);
@@ -156,17 +539,19 @@ pub async fn get_messages(
     for msg in in_msgs {
         inbound.push(Message {
             id: msg.id,
+            revision: msg.revision(),
             contents: msg.content,
             secret_key: msg.secret_key,
         });
     }
     if let Some(out_after) = out_after {
-        let Ok(out_msgs) = db.get_out_messages(&session.user, out_after).await else {
+        let Ok(out_msgs) = db.get_out_messages(&session.user, out_after, limit).await else {
             return RequestResult::Err(Status::InternalServerError);
         };
         for msg in out_msgs {
             outbound.push(Message {
                 id: msg.id,
+                revision: msg.revision(),
                 contents: msg.content,
                 secret_key: msg.secret_key,
             });
@@ -175,36 +560,1155 @@ pub async fn get_messages(
     Ok(Json(ResponseGetMessage { inbound, outbound }))
 }
 
+// pages back through a single conversation, received and sent copies have separate cursors
+#[get("/conversations/<tag>/messages?<before>&<out_before>&<limit>")]
+pub async fn get_conversation_messages(
+    tag: &str,
+    before: Option<i64>,
+    out_before: Option<i64>,
+    limit: Option<i64>,
+    session: ClientSession,
+    db: &State<Database>,
+) -> ApiResult<Json<ResponseGetMessage>> {
+    if let Err(reason) = validation::validate_conversation(tag) {
+        return Err(api_error(
+            Status::UnprocessableEntity,
+            ApiError::InvalidField {
+                field: "tag".to_string(),
+                reason,
+            },
+        ));
+    }
+    history_page(db, &session.user, Some(tag), before, out_before, limit).await
+}
+
+// pages back through every conversation, for a device that knows no tag of the one it wants
+#[get("/messages/history?<before>&<out_before>&<limit>")]
+pub async fn get_message_history(
+    before: Option<i64>,
+    out_before: Option<i64>,
+    limit: Option<i64>,
+    session: ClientSession,
+    db: &State<Database>,
+) -> ApiResult<Json<ResponseGetMessage>> {
+    history_page(db, &session.user, None, before, out_before, limit).await
+}
+
+async fn history_page(
+    db: &Database,
+    user: &User,
+    tag: Option<&str>,
+    before: Option<i64>,
+    out_before: Option<i64>,
+    limit: Option<i64>,
+) -> ApiResult<Json<ResponseGetMessage>> {
+    let limit = limit
+        .unwrap_or(DEFAULT_HISTORY_PAGE)
+        .clamp(1, MAX_HISTORY_PAGE);
+    let in_msgs = db
+        .get_conversation_in_messages(user, tag, before, limit)
+        .await;
+    let out_msgs = db
+        .get_conversation_out_messages(user, tag, out_before, limit)
+        .await;
+    let (in_msgs, out_msgs) = match (in_msgs, out_msgs) {
+        (Ok(in_msgs), Ok(out_msgs)) => (in_msgs, out_msgs),
+        (Err(e), _) | (_, Err(e)) => {
+            log::error!("{}", e);
+            return Err(api_error(Status::InternalServerError, ApiError::Internal));
+        }
+    };
+    let into_message = |msg: crate::db::structs::Message| Message {
+        id: msg.id,
+        revision: msg.revision(),
+        contents: msg.content,
+        secret_key: msg.secret_key,
+    };
+    Ok(Json(ResponseGetMessage {
+        inbound: in_msgs.into_iter().map(into_message).collect(),
+        outbound: out_msgs.into_iter().map(into_message).collect(),
+    }))
+}
+
 #[post("/notifications")]
 pub async fn notifications(
     ws: WebSocket,
     session: ClientSession,
+    db: &State<Database>,
+    config: &State<ServerConfig>,
     notify_service: &State<NotifyService>,
     shutdown: Shutdown,
 ) -> Channel<'static> {
-    let store = notify_service.store.clone();
-    let queue = store.create_client_queue(&session.user.id).await;
+    let db = db.inner().clone();
+    let purge_acked = config.purge_acked_messages;
+    let heartbeat_interval = Duration::from_secs(config.heartbeat_interval.max(1));
+    let notify_service = notify_service.inner().clone();
+    let mut connection = notify_service.connect(&session.user.id).await;
+    match db.get_pending_messages(&session.user.id).await {
+        Ok(pending) => {
+            for msg in pending {
+                connection.replay(Notification::Message(Message {
+                    id: msg.id,
+                    revision: msg.revision(),
+                    contents: msg.content,
+                    secret_key: msg.secret_key,
+                }));
+            }
+        }
+        Err(e) => log::error!("Failed to load pending messages: {}", e),
+    }
+    presence::publish_presence(&db, &notify_service, &session.user.id).await;
     ws.channel(move |mut stream| {
         Box::pin(async move {
+            let start = tokio::time::Instant::now() + heartbeat_interval;
+            let mut heartbeat = tokio::time::interval_at(start, heartbeat_interval);
+            let mut missed_heartbeats = 0;
             loop {
-                if let Some(message) = queue.try_pop() {
-                    let Ok(json) = serde_json::to_string(&message) else {
-                        break;
-                    };
-                    if let Err(_) = stream.send(rocket_ws::Message::text(json)).await {
+                tokio::select! {
+                    // used for graceful shutdown
+                    _ = shutdown.clone() => {
+                        log::info!("Shutting down websocket!");
                         break;
                     }
+                    notification = connection.next() => {
+                        let Ok(json) = serde_json::to_string(&notification) else {
+                            break;
+                        };
+                        if stream.send(rocket_ws::Message::text(json)).await.is_err() {
+                            break;
+                        }
+                    }
+                    frame = stream.next() => match frame {
+                        Some(Ok(rocket_ws::Message::Text(text))) => {
+                            missed_heartbeats = 0;
+                            handle_client_frame(&text, &session.user.id, &db, purge_acked).await;
+                        }
+                        Some(Ok(rocket_ws::Message::Close(_))) | Some(Err(_)) | None => break,
+                        Some(Ok(_)) => missed_heartbeats = 0,
+                    },
+                    _ = heartbeat.tick() => {
+                        if missed_heartbeats >= MAX_MISSED_HEARTBEATS {
+                            log::info!("Websocket of {} stopped responding!", session.user.id);
+                            break;
+                        }
+                        if let Ok(false) = db.session_exists(session.session_id).await {
+                            log::info!("Session of {} was revoked!", session.user.id);
+                            break;
+                        }
+                        missed_heartbeats += 1;
+                        if stream.send(rocket_ws::Message::Ping(vec![])).await.is_err() {
+                            break;
+                        }
+                    }
                 }
-                // used for graceful shutdown
-                if let Ok(_) =
-                    tokio::time::timeout(Duration::from_millis(500), shutdown.clone()).await
-                {
-                    log::info!("Shutting down websocket!");
-                    break;
-                }
             }
-            store.remove_client_queue(&session.user.id).await;
+            if notify_service
+                .disconnect(&session.user.id, connection.id)
+                .await
+            {
+                presence::mark_offline(&db, &notify_service, &session.user.id).await;
+            }
             Ok(())
         })
     })
 }
+
+async fn handle_client_frame(text: &str, user_id: &str, db: &Database, purge_acked: bool) {
+    let frame = match serde_json::from_str::<ClientFrame>(text) {
+        Ok(v) => v,
+        Err(e) => {
+            log::warn!("Received invalid frame from {}: {}", user_id, e);
+            return;
+        }
+    };
+    match frame {
+        ClientFrame::Ack { id } => {
+            if let Err(e) = db.ack_message(user_id, id, purge_acked).await {
+                log::error!("Failed to ack message {} for {}: {}", id, user_id, e);
+            }
+        }
+    }
+}
+
+#[get("/users/<username>/presence")]
+pub async fn get_presence(
+    username: &str,
+    session: ClientSession,
+    db: &State<Database>,
+    notify_service: &State<NotifyService>,
+) -> RequestResult<Json<Presence>> {
+    match presence::get_presence_for(db, notify_service, &session.user.id, username).await {
+        Ok(presence) => Ok(Json(presence)),
+        Err(sqlx::Error::RowNotFound) => Err(Status::NotFound),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[get("/users/me/presence")]
+pub async fn get_presence_settings(
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<Json<PresenceSettings>> {
+    match db.get_presence_info(&session.user.id).await {
+        Ok(info) => Ok(Json(PresenceSettings::new(info.presence_hidden))),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[put("/users/me/presence", data = "<body>")]
+pub async fn update_presence_settings(
+    body: Valid<PresenceSettings>,
+    session: ClientSession,
+    db: &State<Database>,
+    notify_service: &State<NotifyService>,
+) -> RequestResult<()> {
+    if let Err(e) = db.set_presence_hidden(&session.user.id, body.hidden).await {
+        log::error!("{}", e);
+        return Err(Status::InternalServerError);
+    }
+    presence::publish_presence(db, notify_service, &session.user.id).await;
+    Ok(())
+}
+
+#[post("/presence/subscriptions", data = "<body>")]
+pub async fn subscribe_presence(
+    body: Valid<SubscribePresence>,
+    session: ClientSession,
+    db: &State<Database>,
+    notify_service: &State<NotifyService>,
+) -> RequestResult<Json<Vec<Presence>>> {
+    let mut presences = vec![];
+    for target in &body.users {
+        if *target == session.user.id {
+            continue;
+        }
+        // only contacts can follow someone's presence, others are skipped like unknown users
+        match db.is_contact(&session.user.id, target).await {
+            Ok(true) => (),
+            Ok(false) => continue,
+            Err(e) => {
+                log::error!("{}", e);
+                return Err(Status::InternalServerError);
+            }
+        }
+        match db.add_presence_subscription(&session.user.id, target).await {
+            Ok(_) => (),
+            Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => continue,
+            Err(e) => {
+                log::error!("{}", e);
+                return Err(Status::InternalServerError);
+            }
+        }
+        match presence::get_presence(db, notify_service, target).await {
+            Ok(presence) => presences.push(presence),
+            Err(e) => log::error!("Failed to get presence of {}: {}", target, e),
+        }
+    }
+    Ok(Json(presences))
+}
+
+#[patch("/users/me", data = "<body>")]
+pub async fn update_profile(
+    body: Valid<UpdateProfile>,
+    session: ClientSession,
+    db: &State<Database>,
+    notify_service: &State<NotifyService>,
+    user_cache_service: &State<UserCacheService>,
+) -> RequestResult<Json<ResponseGetUser>> {
+    let user = match db.update_user_name(&session.user.id, &body.name).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Failed to update profile of {}: {}", session.user.id, e);
+            return Err(Status::InternalServerError);
+        }
+    };
+    user_cache_service.cache.lock().await.store(user.clone());
+    notify_service.invalidate_user(&user.id).await;
+    let profile = UserProfile {
+        id: user.id.clone(),
+        name: user.name.clone(),
+    };
+    let contacts = match db.get_contacts(&user.id).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Failed to get contacts of {}: {}", user.id, e);
+            vec![]
+        }
+    };
+    for contact in contacts {
+        notify_service
+            .publish(&contact, Notification::ProfileUpdated(profile.clone()))
+            .await;
+    }
+    Ok(Json(ResponseGetUser {
+        id: user.id,
+        name: user.name,
+        public_key: user.public_key,
+    }))
+}
+
+async fn verify_password(
+    limiter: &RateLimiter,
+    db: &Database,
+    user_id: &str,
+    password: &str,
+    action: &str,
+) -> ApiResult<User> {
+    if limiter.check_lockout(user_id).await.is_err() {
+        return Err(api_error(
+            Status::TooManyRequests,
+            ApiError::TooManyRequests,
+        ));
+    }
+    let user = match db.get_user_by_id(user_id).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(api_error(Status::InternalServerError, ApiError::Internal));
+        }
+    };
+    if !cryptolib::verify_hashed_password(password, &user.password_hash) {
+        log::info!("Denied {} of {}: wrong password!", action, user.id);
+        limiter.record_failure(&user.id, true).await;
+        return Err(api_error(
+            Status::Unauthorized,
+            ApiError::InvalidCredentials,
+        ));
+    }
+    limiter.record_success(&user.id).await;
+    Ok(user)
+}
+
+#[post("/users/me/password", data = "<body>")]
+pub async fn change_password(
+    body: Valid<ChangePassword>,
+    session: ClientSession,
+    limit: RateLimit<'_, LoginPolicy>,
+    db: &State<Database>,
+    notify_service: &State<NotifyService>,
+    user_cache_service: &State<UserCacheService>,
+) -> ApiResult<()> {
+    let mut user = verify_password(
+        limit.limiter(),
+        db,
+        &session.user.id,
+        &body.current_password,
+        "password change",
+    )
+    .await?;
+    let password_hash = match cryptolib::hash_password(&body.new_password) {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Password hashing failed {}", e);
+            return Err(api_error(Status::InternalServerError, ApiError::Internal));
+        }
+    };
+    match db
+        .change_password(
+            &user.id,
+            &user.password_hash,
+            &password_hash,
+            session.session_id,
+        )
+        .await
+    {
+        Ok(true) => (),
+        Ok(false) => return Err(api_error(Status::Conflict, ApiError::InvalidCredentials)),
+        Err(e) => {
+            log::error!("Failed to change password of {}: {}", user.id, e);
+            return Err(api_error(Status::InternalServerError, ApiError::Internal));
+        }
+    }
+    log::info!("Changed password of {}", user.id);
+    user.password_hash = password_hash;
+    notify_service.invalidate_user(&user.id).await;
+    user_cache_service.cache.lock().await.store(user);
+    Ok(())
+}
+
+#[delete("/users/me", data = "<body>")]
+pub async fn delete_account(
+    body: Valid<DeleteAccount>,
+    session: ClientSession,
+    limit: RateLimit<'_, LoginPolicy>,
+    db: &State<Database>,
+    notify_service: &State<NotifyService>,
+    user_cache_service: &State<UserCacheService>,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<()> {
+    let user = verify_password(
+        limit.limiter(),
+        db,
+        &session.user.id,
+        &body.password,
+        "account deletion",
+    )
+    .await?;
+    let contacts = match db.get_contacts(&user.id).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Failed to get contacts of {}: {}", user.id, e);
+            vec![]
+        }
+    };
+    let groups = match db.get_user_groups(&user.id).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Failed to get groups of {}: {}", user.id, e);
+            vec![]
+        }
+    };
+    if let Err(e) = db.delete_user(&user.id).await {
+        log::error!("Failed to delete account of {}: {}", user.id, e);
+        return Err(api_error(Status::InternalServerError, ApiError::Internal));
+    }
+    log::info!("Deleted account of {}", user.id);
+    user_cache_service.cache.lock().await.remove(&user.id);
+    notify_service.invalidate_user(&user.id).await;
+    if let Some(cookie) = cookies.get_private(SESSION_COOKIE) {
+        cookies.remove_private(cookie);
+    }
+    for contact in contacts {
+        notify_service
+            .publish(&contact, Notification::AccountDeleted(user.id.clone()))
+            .await;
+    }
+    for group in groups {
+        groups::publish_group(db, notify_service, group.id).await;
+    }
+    Ok(())
+}
+
+#[get("/blocks")]
+pub async fn get_blocks(
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<Json<Vec<BlockedUser>>> {
+    match db.get_blocks(&session.user.id).await {
+        Ok(blocks) => Ok(Json(
+            blocks
+                .into_iter()
+                .map(|b| BlockedUser {
+                    id: b.blocked_id,
+                    blocked_at: b.blocked_at,
+                })
+                .collect(),
+        )),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[put("/blocks/<username>")]
+pub async fn block_user(
+    username: &str,
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<()> {
+    if username == session.user.id {
+        return Err(Status::BadRequest);
+    }
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    match db.add_block(&session.user.id, username, now).await {
+        Ok(_) => Ok(()),
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => Err(Status::NotFound),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[delete("/blocks/<username>")]
+pub async fn unblock_user(
+    username: &str,
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<()> {
+    match db.remove_block(&session.user.id, username).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Status::NotFound),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[get("/requests")]
+pub async fn get_requests(
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<Json<Vec<ContactRequest>>> {
+    match db
+        .get_contact_requests(&session.user.id, RequestStatus::Pending.as_str())
+        .await
+    {
+        Ok(requests) => Ok(Json(
+            requests
+                .into_iter()
+                .map(|r| ContactRequest {
+                    sender: r.sender_id,
+                    message_count: r.message_count,
+                    created_at: r.created_at,
+                })
+                .collect(),
+        )),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+async fn answer_request(
+    db: &Database,
+    sender: &str,
+    recipient: &str,
+    status: RequestStatus,
+) -> RequestResult<()> {
+    match db
+        .set_contact_request_status(sender, recipient, status.as_str())
+        .await
+    {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Status::NotFound),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[post("/requests/<username>/accept")]
+pub async fn accept_request(
+    username: &str,
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<()> {
+    answer_request(db, username, &session.user.id, RequestStatus::Accepted).await
+}
+
+#[post("/requests/<username>/decline")]
+pub async fn decline_request(
+    username: &str,
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<()> {
+    answer_request(db, username, &session.user.id, RequestStatus::Declined).await
+}
+
+#[post("/users/me/key/challenge")]
+pub async fn rotate_key_challenge(
+    session: ClientSession,
+    db: &State<Database>,
+    config: &State<ServerConfig>,
+) -> RequestResult<Json<Challenge>> {
+    match challenge::issue_challenge(
+        db,
+        &session.user.id,
+        ChallengePurpose::RotateKey,
+        config.auth.challenge_lifetime,
+    )
+    .await
+    {
+        Ok(challenge) => Ok(Json(challenge)),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[put("/users/me/key", data = "<body>")]
+pub async fn rotate_key(
+    body: Valid<RotateKey>,
+    session: ClientSession,
+    db: &State<Database>,
+    config: &State<ServerConfig>,
+    notify_service: &State<NotifyService>,
+    user_cache_service: &State<UserCacheService>,
+) -> ApiResult<Json<KeyRotation>> {
+    let Ok(public_key) = cryptolib::utils::public_key_from_bytes(&body.public_key) else {
+        return Err(api_error(Status::BadRequest, ApiError::MalformedKey));
+    };
+    let min_bits = config.auth.min_key_bits;
+    if cryptolib::utils::public_key_bits(&public_key) < min_bits {
+        return Err(api_error(
+            Status::BadRequest,
+            ApiError::WeakKey { min_bits },
+        ));
+    }
+    let mut user = match db.get_user_by_id(&session.user.id).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(api_error(Status::InternalServerError, ApiError::Internal));
+        }
+    };
+    let payload = Challenge::rotation_payload(&user.id, &body.proof.nonce, &body.public_key);
+    match challenge::verify_signed(
+        db,
+        &user.id,
+        ChallengePurpose::RotateKey,
+        &body.proof,
+        &user.public_key,
+        &payload,
+    )
+    .await
+    {
+        Ok(true) => (),
+        Ok(false) => {
+            log::info!("Denied key rotation of {}: invalid proof!", user.id);
+            return Err(api_error(Status::BadRequest, ApiError::InvalidProof));
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(api_error(Status::InternalServerError, ApiError::Internal));
+        }
+    }
+    let rotation = KeyRotation {
+        id: user.id.clone(),
+        previous_key: user.public_key.clone(),
+        public_key: body.public_key.clone(),
+        nonce: body.proof.nonce.clone(),
+        signature: body.proof.signature.clone(),
+        rotated_at: OffsetDateTime::now_utc().unix_timestamp(),
+    };
+    match db.rotate_user_key(&rotation).await {
+        Ok(true) => (),
+        Ok(false) => return Err(api_error(Status::Conflict, ApiError::StaleKey)),
+        Err(e) => {
+            log::error!("Failed to rotate key of {}: {}", user.id, e);
+            return Err(api_error(Status::InternalServerError, ApiError::Internal));
+        }
+    }
+    log::info!("Rotated key of {}", user.id);
+    user.public_key = rotation.public_key.clone();
+    notify_service.invalidate_user(&user.id).await;
+    user_cache_service.cache.lock().await.store(user);
+    let contacts = match db.get_contacts(&rotation.id).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Failed to get contacts of {}: {}", rotation.id, e);
+            vec![]
+        }
+    };
+    for contact in contacts {
+        notify_service
+            .publish(&contact, Notification::KeyChanged(rotation.clone()))
+            .await;
+    }
+    Ok(Json(rotation))
+}
+
+// the message keys follow the rotation in pages, wrapped for the key of that rotation
+#[put("/users/me/key/wrapped", data = "<body>")]
+pub async fn rewrap_keys(
+    body: Valid<RewrapKeys>,
+    session: ClientSession,
+    db: &State<Database>,
+) -> ApiResult<()> {
+    match db
+        .rewrap_message_keys(&session.user.id, &body.nonce, &body.inbound, &body.outbound)
+        .await
+    {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(api_error(Status::Conflict, ApiError::StaleKey)),
+        Err(e) => {
+            log::error!("Failed to rewrap keys of {}: {}", session.user.id, e);
+            Err(api_error(Status::InternalServerError, ApiError::Internal))
+        }
+    }
+}
+
+async fn load_group(db: &Database, group_id: i64, user_id: &str) -> RequestResult<GroupState> {
+    match GroupState::load(db, group_id).await {
+        Ok(Some(state)) if state.role(user_id).is_some() => Ok(state),
+        Ok(_) => Err(Status::NotFound),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+fn status_error(status: Status) -> (Status, Json<ApiError>) {
+    match status.code {
+        404 => api_error(status, ApiError::NotFound),
+        _ => api_error(status, ApiError::Internal),
+    }
+}
+
+async fn load_group_as_admin(
+    db: &Database,
+    group_id: i64,
+    user_id: &str,
+) -> RequestResult<GroupState> {
+    let state = load_group(db, group_id, user_id).await?;
+    match state.role(user_id) {
+        Some(GroupRole::Admin) => Ok(state),
+        _ => Err(Status::Forbidden),
+    }
+}
+
+async fn check_invitee(db: &Database, inviter: &str, invitee: &str) -> RequestResult<()> {
+    // only contacts can be added to a group, strangers and users who blocked the inviter look
+    // the same as unknown users
+    let contact = match db.is_contact(inviter, invitee).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(Status::InternalServerError);
+        }
+    };
+    let blocked = match db.is_blocked(invitee, inviter).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(Status::InternalServerError);
+        }
+    };
+    match contact && !blocked {
+        true => Ok(()),
+        false => Err(Status::NotFound),
+    }
+}
+
+#[post("/groups", data = "<body>")]
+pub async fn create_group(
+    body: Valid<CreateGroup>,
+    session: ClientSession,
+    db: &State<Database>,
+    notify_service: &State<NotifyService>,
+) -> RequestResult<Json<Group>> {
+    let mut members: Vec<String> = body
+        .members
+        .iter()
+        .filter(|m| **m != session.user.id)
+        .cloned()
+        .collect();
+    members.sort();
+    members.dedup();
+    for member in &members {
+        check_invitee(db, &session.user.id, member).await?;
+    }
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let group = match db
+        .create_group(&body.name, &session.user.id, &members, now)
+        .await
+    {
+        Ok(v) => v,
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            return Err(Status::NotFound)
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(Status::InternalServerError);
+        }
+    };
+    log::info!("{} created group {}", session.user.id, group.id);
+    groups::publish_group(db, notify_service, group.id).await;
+    let state = load_group(db, group.id, &session.user.id).await?;
+    Ok(Json(state.view(&session.user.id)))
+}
+
+#[get("/groups")]
+pub async fn get_groups(
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<Json<Vec<Group>>> {
+    let groups = match db.get_user_groups(&session.user.id).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(Status::InternalServerError);
+        }
+    };
+    let mut views = Vec::with_capacity(groups.len());
+    for group in groups {
+        let state = load_group(db, group.id, &session.user.id).await?;
+        views.push(state.view(&session.user.id));
+    }
+    Ok(Json(views))
+}
+
+#[get("/groups/<group_id>")]
+pub async fn get_group(
+    group_id: i64,
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<Json<Group>> {
+    let state = load_group(db, group_id, &session.user.id).await?;
+    Ok(Json(state.view(&session.user.id)))
+}
+
+#[post("/groups/<group_id>/members/<username>")]
+pub async fn invite_group_member(
+    group_id: i64,
+    username: &str,
+    session: ClientSession,
+    db: &State<Database>,
+    notify_service: &State<NotifyService>,
+) -> RequestResult<Json<Group>> {
+    load_group_as_admin(db, group_id, &session.user.id).await?;
+    check_invitee(db, &session.user.id, username).await?;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    match db.add_group_member(group_id, username, now).await {
+        Ok(Invite::Added) => (),
+        Ok(Invite::AlreadyMember) => return Err(Status::Conflict),
+        Ok(Invite::GroupFull) => return Err(Status::UnprocessableEntity),
+        Err(sqlx::Error::Database(e)) if e.is_foreign_key_violation() => {
+            return Err(Status::NotFound)
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(Status::InternalServerError);
+        }
+    }
+    log::info!(
+        "{} added {} to group {}",
+        session.user.id,
+        username,
+        group_id
+    );
+    groups::publish_group(db, notify_service, group_id).await;
+    let state = load_group(db, group_id, &session.user.id).await?;
+    Ok(Json(state.view(&session.user.id)))
+}
+
+#[delete("/groups/<group_id>/members/<username>")]
+pub async fn remove_group_member(
+    group_id: i64,
+    username: &str,
+    session: ClientSession,
+    db: &State<Database>,
+    notify_service: &State<NotifyService>,
+) -> RequestResult<()> {
+    match username == session.user.id {
+        true => load_group(db, group_id, &session.user.id).await?,
+        false => load_group_as_admin(db, group_id, &session.user.id).await?,
+    };
+    match db.remove_group_member(group_id, username).await {
+        Ok(true) => (),
+        Ok(false) => return Err(Status::NotFound),
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(Status::InternalServerError);
+        }
+    }
+    log::info!(
+        "{} removed {} from group {}",
+        session.user.id,
+        username,
+        group_id
+    );
+    notify_service
+        .publish(username, Notification::GroupRemoved(group_id))
+        .await;
+    groups::publish_group(db, notify_service, group_id).await;
+    Ok(())
+}
+
+async fn set_group_role(
+    db: &Database,
+    notify_service: &NotifyService,
+    group_id: i64,
+    session: &ClientSession,
+    username: &str,
+    role: GroupRole,
+) -> RequestResult<()> {
+    let state = load_group_as_admin(db, group_id, &session.user.id).await?;
+    if state.role(username).is_none() {
+        return Err(Status::NotFound);
+    }
+    match db.set_group_role(group_id, username, role).await {
+        Ok(true) => (),
+        // a group always keeps at least one admin
+        Ok(false) => return Err(Status::Conflict),
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(Status::InternalServerError);
+        }
+    }
+    groups::publish_group(db, notify_service, group_id).await;
+    Ok(())
+}
+
+#[put("/groups/<group_id>/admins/<username>")]
+pub async fn promote_group_member(
+    group_id: i64,
+    username: &str,
+    session: ClientSession,
+    db: &State<Database>,
+    notify_service: &State<NotifyService>,
+) -> RequestResult<()> {
+    set_group_role(
+        db,
+        notify_service,
+        group_id,
+        &session,
+        username,
+        GroupRole::Admin,
+    )
+    .await
+}
+
+#[delete("/groups/<group_id>/admins/<username>")]
+pub async fn demote_group_member(
+    group_id: i64,
+    username: &str,
+    session: ClientSession,
+    db: &State<Database>,
+    notify_service: &State<NotifyService>,
+) -> RequestResult<()> {
+    set_group_role(
+        db,
+        notify_service,
+        group_id,
+        &session,
+        username,
+        GroupRole::Member,
+    )
+    .await
+}
+
+#[put("/groups/<group_id>/keys", data = "<body>")]
+pub async fn distribute_group_key(
+    group_id: i64,
+    body: Valid<DistributeGroupKey>,
+    session: ClientSession,
+    db: &State<Database>,
+) -> ApiResult<()> {
+    let state = match load_group(db, group_id, &session.user.id).await {
+        Ok(v) => v,
+        Err(status) => return Err(status_error(status)),
+    };
+    let stale = || {
+        api_error(
+            Status::Conflict,
+            ApiError::StaleGroupKey {
+                epoch: state.group.key_epoch,
+            },
+        )
+    };
+    if body.epoch != state.group.key_epoch {
+        return Err(stale());
+    }
+    let mut members: Vec<&str> = state.members.iter().map(|m| m.user_id.as_str()).collect();
+    let mut recipients: Vec<&str> = body.keys.iter().map(|k| k.user_id.as_str()).collect();
+    members.sort();
+    recipients.sort();
+    if members != recipients {
+        return Err(stale());
+    }
+    match db.store_group_keys(group_id, body.epoch, &body.keys).await {
+        Ok(true) => {
+            log::info!(
+                "{} distributed key {} of group {}",
+                session.user.id,
+                body.epoch,
+                group_id
+            );
+            Ok(())
+        }
+        Ok(false) => Err(stale()),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(api_error(Status::InternalServerError, ApiError::Internal))
+        }
+    }
+}
+
+#[post("/groups/<group_id>/messages", data = "<body>")]
+pub async fn send_group_message(
+    group_id: i64,
+    body: Valid<SendGroupMessage>,
+    session: ClientSession,
+    limit: RateLimit<'_, SendMessagePolicy>,
+    db: &State<Database>,
+    notify_service: &State<NotifyService>,
+) -> ApiResult<Json<GroupMessage>> {
+    if let Err(status) = limit.check_account(&session.user.id).await {
+        return Err(api_error(status, ApiError::TooManyRequests));
+    }
+    let state = match load_group(db, group_id, &session.user.id).await {
+        Ok(v) => v,
+        Err(status) => return Err(status_error(status)),
+    };
+    match db.owns_blobs(&session.user.id, &body.attachments).await {
+        Ok(true) => (),
+        Ok(false) => return Err(api_error(Status::NotFound, ApiError::NotFound)),
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(api_error(Status::InternalServerError, ApiError::Internal));
+        }
+    }
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let id = match db
+        .create_group_message(group_id, &session.user.id, &body, now)
+        .await
+    {
+        Ok(Some(v)) => v,
+        Ok(None) => {
+            return Err(api_error(
+                Status::Conflict,
+                ApiError::StaleGroupKey {
+                    epoch: state.group.key_epoch,
+                },
+            ))
+        }
+        Err(sqlx::Error::RowNotFound) => {
+            return Err(api_error(Status::NotFound, ApiError::NotFound))
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(api_error(Status::InternalServerError, ApiError::Internal));
+        }
+    };
+    let keys = match db.get_group_keys(group_id, body.epoch).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(api_error(Status::InternalServerError, ApiError::Internal));
+        }
+    };
+    let message = |secret_key: &[u8]| GroupMessage {
+        id,
+        group_id,
+        sender: session.user.id.clone(),
+        epoch: body.epoch,
+        contents: body.contents.clone(),
+        secret_key: secret_key.to_vec(),
+        revision: body.revision,
+    };
+    let mut response = None;
+    for key in &keys {
+        if key.user_id == session.user.id {
+            response = Some(message(&key.secret_key));
+            continue;
+        }
+        notify_service
+            .publish(
+                &key.user_id,
+                Notification::GroupMessage(message(&key.secret_key)),
+            )
+            .await;
+    }
+    match response {
+        Some(response) => Ok(Json(response)),
+        None => Err(api_error(Status::InternalServerError, ApiError::Internal)),
+    }
+}
+
+#[post("/blobs", data = "<data>")]
+pub async fn upload_blob(
+    data: Data<'_>,
+    limits: &Limits,
+    session: ClientSession,
+    limit: RateLimit<'_, SendMessagePolicy>,
+    db: &State<Database>,
+    config: &State<ServerConfig>,
+) -> ApiResult<Json<Blob>> {
+    if let Err(status) = limit.check_account(&session.user.id).await {
+        return Err(api_error(status, ApiError::TooManyRequests));
+    }
+    let max = limits.get("blob").unwrap_or(Limits::BYTES);
+    let bytes = match data.open(max).into_bytes().await {
+        Ok(v) if v.is_complete() => v.into_inner(),
+        Ok(_) => {
+            return Err(api_error(
+                Status::PayloadTooLarge,
+                ApiError::PayloadTooLarge {
+                    limit: Some(max.as_u64()),
+                },
+            ))
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            return Err(api_error(Status::BadRequest, ApiError::MalformedBody));
+        }
+    };
+    if bytes.is_empty() {
+        return Err(api_error(
+            Status::UnprocessableEntity,
+            ApiError::InvalidField {
+                field: "data".to_string(),
+                reason: ValidationError::Empty,
+            },
+        ));
+    }
+    let id = cryptolib::generate_token();
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    match db
+        .create_blob(&id, &session.user.id, &bytes, config.blobs.quota, now)
+        .await
+    {
+        Ok(true) => Ok(Json(Blob {
+            id,
+            size: bytes.len() as i64,
+        })),
+        Ok(false) => Err(api_error(
+            Status::InsufficientStorage,
+            ApiError::QuotaExceeded {
+                quota: config.blobs.quota,
+            },
+        )),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(api_error(Status::InternalServerError, ApiError::Internal))
+        }
+    }
+}
+
+#[get("/blobs/<id>")]
+pub async fn download_blob(
+    id: &str,
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<Vec<u8>> {
+    match db.get_blob(id, &session.user.id).await {
+        Ok(Some(data)) => RequestResult::Ok(data),
+        Ok(None) => RequestResult::Err(Status::NotFound),
+        Err(e) => {
+            log::error!("{}", e);
+            RequestResult::Err(Status::InternalServerError)
+        }
+    }
+}
+
+#[get("/groups/messages?<after>")]
+pub async fn get_group_messages(
+    after: i64,
+    session: ClientSession,
+    db: &State<Database>,
+) -> RequestResult<Json<Vec<GroupMessage>>> {
+    match db.get_group_messages(&session.user.id, after).await {
+        Ok(messages) => Ok(Json(
+            messages
+                .into_iter()
+                .map(|m| GroupMessage {
+                    revision: m.revision(),
+                    id: m.id,
+                    group_id: m.group_id,
+                    sender: m.sender_id.unwrap_or_default(),
+                    epoch: m.epoch,
+                    contents: m.content,
+                    secret_key: m.secret_key,
+                })
+                .collect(),
+        )),
+        Err(e) => {
+            log::error!("{}", e);
+            Err(Status::InternalServerError)
+        }
+    }
+}
@@ -1,17 +1,30 @@
-use db::Database;
-use notify::NotifyService;
-use rocket::{config::LogLevel, figment::Figment, Config};
+use std::{env, sync::Arc};
+
+use config::{DatabaseConfig, NotifyBackendKind, ServerConfig, StoreBackendKind};
+use db::{Database, MemoryStore, PostgresStore, SqliteStore};
+use notify::{MemoryBackend, NotifyBackend, NotifyService, NotifyStore, PostgresBackend};
+use rate_limit::RateLimiter;
+use rocket::{config::LogLevel, fairing::AdHoc, figment::Figment, Config};
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use user_cache::UserCacheService;
 
 #[macro_use]
 extern crate rocket;
 
+mod blobs;
+mod challenge;
+mod config;
+mod consent;
 mod db;
+mod expiry;
+mod groups;
 mod handlers;
 mod notify;
+mod presence;
+mod rate_limit;
 mod session;
 mod user_cache;
+mod validated;
 
 fn create_config() -> Figment {
     Config::figment().merge(("log_level", LogLevel::Normal))
@@ -21,39 +34,165 @@ const CA_CERT_BYTES: &[u8] = include_bytes!("../../../certs/ca.crt");
 const CLIENT_CERT_BYTES: &[u8] = include_bytes!("../../../certs/server.crt");
 const CLIENT_KEY_BYTES: &[u8] = include_bytes!("../../../certs/server.key");
 
-#[launch]
-async fn rocket() -> _ {
-    let options = PgConnectOptions::new()
-        .ssl_mode(PgSslMode::VerifyCa)
-        .database("messagist")
-        .username("messagist_server")
-        .ssl_root_cert_from_pem(CA_CERT_BYTES.to_vec())
-        .ssl_client_key_from_pem(CLIENT_KEY_BYTES)
-        .ssl_client_cert_from_pem(CLIENT_CERT_BYTES);
-    let db = Database::new(options)
-        .await
-        .expect("Failed to connect to database!");
+#[rocket::main]
+async fn main() {
+    let config = create_config();
+    let server_config = config
+        .extract::<ServerConfig>()
+        .expect("Invalid server configuration!");
 
-    let user_cache_service = UserCacheService::new();
-    let notify_service = NotifyService::new();
+    let (db, postgres) = connect(&server_config.database).await;
 
-    let config = create_config();
+    let command = env::args().nth(1);
+    match command.as_deref() {
+        Some("migrate") => {
+            migrate(&db).await;
+            return;
+        }
+        Some(other) => panic!("Unknown command {}!", other),
+        None => (),
+    }
+    if server_config.database.migrate {
+        migrate(&db).await;
+    }
+
+    let user_cache_service = UserCacheService::new();
+    let rate_limiter = RateLimiter::new(server_config.rate_limit.clone());
+    let store = Arc::new(NotifyStore::new());
+    let backend: Arc<dyn NotifyBackend> = match server_config.notify_backend {
+        NotifyBackendKind::Memory => Arc::new(MemoryBackend::new(store.clone())),
+        NotifyBackendKind::Postgres => {
+            let postgres =
+                postgres.expect("The postgres notify backend needs a postgres database!");
+            Arc::new(
+                PostgresBackend::new(
+                    postgres,
+                    store.clone(),
+                    user_cache_service.clone(),
+                    &server_config.instance_id,
+                )
+                .await
+                .expect("Failed to start notification listener!"),
+            )
+        }
+    };
+    let notify_service = NotifyService::new(store, backend);
 
     rocket::custom(config)
         .manage(db)
         .manage(user_cache_service)
         .manage(notify_service)
+        .manage(rate_limiter)
+        .attach(AdHoc::config::<ServerConfig>())
+        .attach(AdHoc::on_liftoff("Background tasks", |rocket| {
+            Box::pin(async move {
+                if let (Some(db), Some(config)) =
+                    (rocket.state::<Database>(), rocket.state::<ServerConfig>())
+                {
+                    blobs::spawn_collector(db.clone(), config.blobs.clone());
+                    expiry::spawn_reaper(db.clone(), config.expiry_interval);
+                }
+            })
+        }))
         .mount(
             "/api",
             routes![
                 handlers::hello,
+                handlers::register_challenge,
                 handlers::register,
                 handlers::login,
+                handlers::login_challenge,
+                handlers::key_login,
                 handlers::get_user,
                 handlers::get_messages,
+                handlers::get_conversation_messages,
+                handlers::get_message_history,
                 handlers::send_message,
                 handlers::logout,
+                handlers::get_sessions,
+                handlers::revoke_session,
+                handlers::revoke_other_sessions,
                 handlers::notifications,
+                handlers::get_presence,
+                handlers::get_presence_settings,
+                handlers::update_presence_settings,
+                handlers::subscribe_presence,
+                handlers::update_profile,
+                handlers::change_password,
+                handlers::delete_account,
+                handlers::get_blocks,
+                handlers::get_requests,
+                handlers::accept_request,
+                handlers::decline_request,
+                handlers::create_group,
+                handlers::get_groups,
+                handlers::get_group,
+                handlers::invite_group_member,
+                handlers::remove_group_member,
+                handlers::promote_group_member,
+                handlers::demote_group_member,
+                handlers::distribute_group_key,
+                handlers::send_group_message,
+                handlers::get_group_messages,
+                handlers::upload_blob,
+                handlers::download_blob,
+                handlers::block_user,
+                handlers::unblock_user,
+                handlers::rotate_key_challenge,
+                handlers::rotate_key,
+                handlers::rewrap_keys,
             ],
         )
+        .register(
+            "/api",
+            catchers![
+                validated::payload_too_large,
+                validated::unprocessable_entity
+            ],
+        )
+        .launch()
+        .await
+        .expect("Failed to launch server!");
+}
+
+// the postgres store is also handed out on its own, the postgres notify backend relies on it
+async fn connect(config: &DatabaseConfig) -> (Database, Option<PostgresStore>) {
+    match config.backend {
+        StoreBackendKind::Postgres => {
+            let mut options = PgConnectOptions::new()
+                .database(&config.name)
+                .username(&config.user);
+            if config.tls {
+                options = options
+                    .ssl_mode(PgSslMode::VerifyCa)
+                    .ssl_root_cert_from_pem(CA_CERT_BYTES.to_vec())
+                    .ssl_client_key_from_pem(CLIENT_KEY_BYTES)
+                    .ssl_client_cert_from_pem(CLIENT_CERT_BYTES);
+            }
+            let store = PostgresStore::new(options)
+                .await
+                .expect("Failed to connect to database!");
+            (Database::new(store.clone()), Some(store))
+        }
+        StoreBackendKind::Sqlite => {
+            let store = SqliteStore::new(&config.path)
+                .await
+                .expect("Failed to open database!");
+            (Database::new(store), None)
+        }
+        StoreBackendKind::Memory => (Database::new(MemoryStore::new()), None),
+    }
+}
+
+async fn migrate(db: &Database) {
+    let applied = db.migrate().await.expect("Failed to migrate database!");
+    if applied.is_empty() {
+        println!("Database schema is up to date");
+    }
+    for migration in applied {
+        println!(
+            "Applied migration {}: {}",
+            migration.version, migration.description
+        );
+    }
 }
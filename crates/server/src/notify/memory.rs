@@ -0,0 +1,33 @@
+use std::sync::Arc;
+
+use protocol::stoc::Notification;
+
+use super::{NotifyBackend, NotifyStore};
+
+pub struct MemoryBackend {
+    store: Arc<NotifyStore>,
+}
+
+impl MemoryBackend {
+    pub fn new(store: Arc<NotifyStore>) -> Self {
+        Self { store }
+    }
+}
+
+#[rocket::async_trait]
+impl NotifyBackend for MemoryBackend {
+    async fn publish(&self, recipient: &str, notification: Notification) {
+        self.store.push(recipient, notification).await;
+    }
+
+    async fn register_connection(&self, _client: &str, _connection_id: u64) {}
+
+    async fn unregister_connection(&self, _client: &str, _connection_id: u64) {}
+
+    async fn is_online(&self, client: &str) -> bool {
+        self.store.is_online(client).await
+    }
+
+    // a single instance has nobody else to tell
+    async fn invalidate_user(&self, _user_id: &str) {}
+}
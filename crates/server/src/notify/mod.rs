@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use protocol::stoc::Notification;
+
+mod memory;
+mod postgres;
+mod store;
+
+pub use memory::MemoryBackend;
+pub use postgres::PostgresBackend;
+pub use store::{ClientConnection, NotifyStore};
+
+#[rocket::async_trait]
+pub trait NotifyBackend: Send + Sync {
+    async fn publish(&self, recipient: &str, notification: Notification);
+
+    async fn register_connection(&self, client: &str, connection_id: u64);
+
+    async fn unregister_connection(&self, client: &str, connection_id: u64);
+
+    async fn is_online(&self, client: &str) -> bool;
+
+    async fn invalidate_user(&self, user_id: &str);
+}
+
+#[derive(Clone)]
+pub struct NotifyService {
+    pub store: Arc<NotifyStore>,
+    backend: Arc<dyn NotifyBackend>,
+}
+
+impl NotifyService {
+    pub fn new(store: Arc<NotifyStore>, backend: Arc<dyn NotifyBackend>) -> Self {
+        Self { store, backend }
+    }
+
+    pub async fn connect(&self, client: &str) -> ClientConnection {
+        let connection = self.store.create_client_queue(client).await;
+        self.backend
+            .register_connection(client, connection.id)
+            .await;
+        connection
+    }
+
+    pub async fn disconnect(&self, client: &str, connection_id: u64) -> bool {
+        self.store.remove_client_queue(client, connection_id).await;
+        self.backend
+            .unregister_connection(client, connection_id)
+            .await;
+        !self.backend.is_online(client).await
+    }
+
+    pub async fn publish(&self, recipient: &str, notification: Notification) {
+        self.backend.publish(recipient, notification).await;
+    }
+
+    pub async fn is_online(&self, client: &str) -> bool {
+        self.backend.is_online(client).await
+    }
+
+    // drops the user from the caches of the other instances, after updating the local one
+    pub async fn invalidate_user(&self, user_id: &str) {
+        self.backend.invalidate_user(user_id).await;
+    }
+}
@@ -0,0 +1,174 @@
+use std::{sync::Arc, time::Duration};
+
+use protocol::stoc::{self, Notification};
+use rocket::tokio;
+use serde::{Deserialize, Serialize};
+
+use crate::{db::PostgresStore, user_cache::UserCacheService};
+
+use super::{NotifyBackend, NotifyStore};
+
+const CHANNEL: &str = "messagist_notifications";
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "type", content = "data")]
+enum Payload {
+    MessageRef(i64),
+    Notification(Notification),
+    // the recipient's account changed, whether or not they are connected here
+    InvalidateUser,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct Envelope {
+    origin: String,
+    recipient: String,
+    payload: Payload,
+}
+
+pub struct PostgresBackend {
+    db: PostgresStore,
+    store: Arc<NotifyStore>,
+    user_cache: UserCacheService,
+    instance_id: String,
+}
+
+impl PostgresBackend {
+    pub async fn new(
+        db: PostgresStore,
+        store: Arc<NotifyStore>,
+        user_cache: UserCacheService,
+        instance_id: &str,
+    ) -> Result<Self, sqlx::Error> {
+        db.clear_notify_connections(instance_id).await?;
+        let backend = Self {
+            db,
+            store,
+            user_cache,
+            instance_id: instance_id.to_string(),
+        };
+        backend.spawn_listener().await?;
+        Ok(backend)
+    }
+
+    async fn spawn_listener(&self) -> Result<(), sqlx::Error> {
+        let mut listener = self.db.listen(CHANNEL).await?;
+        let db = self.db.clone();
+        let store = self.store.clone();
+        let user_cache = self.user_cache.clone();
+        let instance_id = self.instance_id.clone();
+        tokio::spawn(async move {
+            loop {
+                let notification = match listener.recv().await {
+                    Ok(v) => v,
+                    Err(e) => {
+                        log::error!("Notification listener failed: {}", e);
+                        tokio::time::sleep(RETRY_INTERVAL).await;
+                        continue;
+                    }
+                };
+                let envelope = match serde_json::from_str::<Envelope>(notification.payload()) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        log::warn!("Received invalid notification payload: {}", e);
+                        continue;
+                    }
+                };
+                if envelope.origin == instance_id {
+                    continue;
+                }
+                if let Payload::InvalidateUser = envelope.payload {
+                    user_cache.cache.lock().await.remove(&envelope.recipient);
+                    continue;
+                }
+                deliver(&db, &store, envelope).await;
+            }
+        });
+        Ok(())
+    }
+
+    async fn broadcast(&self, recipient: &str, payload: Payload) {
+        let envelope = Envelope {
+            origin: self.instance_id.clone(),
+            recipient: recipient.to_string(),
+            payload,
+        };
+        let Ok(json) = serde_json::to_string(&envelope) else {
+            return;
+        };
+        if let Err(e) = self.db.publish_notification(CHANNEL, &json).await {
+            log::error!("Failed to publish notification to {}: {}", recipient, e);
+        }
+    }
+}
+
+async fn deliver(db: &PostgresStore, store: &NotifyStore, envelope: Envelope) {
+    if !store.is_online(&envelope.recipient).await {
+        return;
+    }
+    let notification = match envelope.payload {
+        Payload::MessageRef(id) => match db.get_in_message_by_id(id, &envelope.recipient).await {
+            Ok(msg) => Notification::Message(stoc::Message {
+                id: msg.id,
+                revision: msg.revision(),
+                contents: msg.content,
+                secret_key: msg.secret_key,
+            }),
+            Err(e) => {
+                log::error!("Failed to load message {}: {}", id, e);
+                return;
+            }
+        },
+        Payload::Notification(notification) => notification,
+        Payload::InvalidateUser => return,
+    };
+    store.push(&envelope.recipient, notification).await;
+}
+
+#[rocket::async_trait]
+impl NotifyBackend for PostgresBackend {
+    async fn publish(&self, recipient: &str, notification: Notification) {
+        // pg_notify payloads are limited to 8000 bytes, so messages are sent by reference
+        let payload = match &notification {
+            Notification::Message(msg) => Payload::MessageRef(msg.id),
+            _ => Payload::Notification(notification.clone()),
+        };
+        self.store.push(recipient, notification).await;
+        self.broadcast(recipient, payload).await;
+    }
+
+    async fn register_connection(&self, client: &str, connection_id: u64) {
+        if let Err(e) = self
+            .db
+            .add_notify_connection(&self.instance_id, client, connection_id as i64)
+            .await
+        {
+            log::error!("Failed to register connection of {}: {}", client, e);
+        }
+    }
+
+    async fn unregister_connection(&self, _client: &str, connection_id: u64) {
+        if let Err(e) = self
+            .db
+            .remove_notify_connection(&self.instance_id, connection_id as i64)
+            .await
+        {
+            log::error!("Failed to unregister connection {}: {}", connection_id, e);
+        }
+    }
+
+    async fn is_online(&self, client: &str) -> bool {
+        match self.db.has_notify_connection(client).await {
+            Ok(v) => v,
+            Err(e) => {
+                log::error!("Failed to check connections of {}: {}", client, e);
+                self.store.is_online(client).await
+            }
+        }
+    }
+
+    async fn invalidate_user(&self, user_id: &str) {
+        self.broadcast(user_id, Payload::InvalidateUser).await;
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use deadqueue::unlimited::Queue;
+use protocol::stoc::Notification;
+use rocket::tokio::sync::RwLock;
+
+pub type MessageQueue = Arc<Queue<Notification>>;
+
+pub struct ClientConnection {
+    pub id: u64,
+    pub queue: MessageQueue,
+    // a message stored while connecting can come both live and with the pending ones
+    replayed: HashSet<i64>,
+    sent: HashSet<i64>,
+}
+
+impl ClientConnection {
+    fn new(id: u64, queue: MessageQueue) -> Self {
+        Self {
+            id,
+            queue,
+            replayed: HashSet::new(),
+            sent: HashSet::new(),
+        }
+    }
+
+    // queues a notification loaded from storage rather than published
+    pub fn replay(&mut self, notification: Notification) {
+        if let Notification::Message(message) = &notification {
+            self.replayed.insert(message.id);
+        }
+        self.queue.push(notification);
+    }
+
+    // the next notification to send, a replayed message goes out only once
+    pub async fn next(&mut self) -> Notification {
+        loop {
+            let notification = self.queue.pop().await;
+            if let Notification::Message(message) = &notification {
+                if self.replayed.contains(&message.id) && !self.sent.insert(message.id) {
+                    continue;
+                }
+            }
+            return notification;
+        }
+    }
+}
+
+pub struct NotifyStore {
+    map: RwLock<HashMap<String, HashMap<u64, MessageQueue>>>,
+    next_connection_id: AtomicU64,
+}
+
+impl NotifyStore {
+    pub fn new() -> Self {
+        Self {
+            map: RwLock::new(HashMap::new()),
+            next_connection_id: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn create_client_queue(&self, client: &str) -> ClientConnection {
+        let mut map = self.map.write().await;
+        let id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+        let queue = Arc::new(Queue::new());
+        map.entry(client.to_string())
+            .or_default()
+            .insert(id, queue.clone());
+        ClientConnection::new(id, queue)
+    }
+
+    pub async fn remove_client_queue(&self, client: &str, connection_id: u64) -> bool {
+        let mut map = self.map.write().await;
+        let Some(connections) = map.get_mut(client) else {
+            return true;
+        };
+        connections.remove(&connection_id);
+        if connections.is_empty() {
+            map.remove(client);
+            return true;
+        }
+        false
+    }
+
+    pub async fn get_client_queues(&self, client: &str) -> Vec<MessageQueue> {
+        let map = self.map.read().await;
+        match map.get(client) {
+            Some(connections) => connections.values().cloned().collect(),
+            None => vec![],
+        }
+    }
+
+    pub async fn push(&self, client: &str, notification: Notification) -> bool {
+        let queues = self.get_client_queues(client).await;
+        for queue in &queues {
+            queue.push(notification.clone());
+        }
+        !queues.is_empty()
+    }
+
+    pub async fn is_online(&self, client: &str) -> bool {
+        let map = self.map.read().await;
+        map.contains_key(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use protocol::stoc::Message;
+    use rocket::tokio;
+
+    use super::*;
+
+    fn message(id: i64) -> Notification {
+        Notification::Message(Message {
+            id,
+            revision: None,
+            contents: vec![],
+            secret_key: vec![],
+        })
+    }
+
+    async fn next_id(connection: &mut ClientConnection) -> Option<i64> {
+        let next = tokio::time::timeout(std::time::Duration::from_millis(100), connection.next());
+        match next.await {
+            Ok(Notification::Message(message)) => Some(message.id),
+            _ => None,
+        }
+    }
+
+    #[rocket::async_test]
+    async fn message_stored_while_connecting_is_sent_once() {
+        let store = NotifyStore::new();
+        let mut connection = store.create_client_queue("alice").await;
+        // published between connecting and loading the pending messages, which include it
+        store.push("alice", message(2)).await;
+        connection.replay(message(1));
+        connection.replay(message(2));
+        assert_eq!(next_id(&mut connection).await, Some(2));
+        assert_eq!(next_id(&mut connection).await, Some(1));
+        assert_eq!(next_id(&mut connection).await, None);
+
+        // and when it is published only after the pending ones were queued
+        connection.replay(message(3));
+        store.push("alice", message(3)).await;
+        store.push("alice", message(4)).await;
+        assert_eq!(next_id(&mut connection).await, Some(3));
+        assert_eq!(next_id(&mut connection).await, Some(4));
+        assert_eq!(next_id(&mut connection).await, None);
+    }
+}
@@ -0,0 +1,61 @@
+use protocol::stoc::{Notification, Presence};
+use rocket::time::OffsetDateTime;
+
+use crate::{db::Database, notify::NotifyService};
+
+pub async fn get_presence(
+    db: &Database,
+    notify_service: &NotifyService,
+    user_id: &str,
+) -> Result<Presence, sqlx::Error> {
+    let info = db.get_presence_info(user_id).await?;
+    if info.presence_hidden {
+        return Ok(Presence::hidden(&info.id));
+    }
+    let online = notify_service.is_online(&info.id).await;
+    Ok(Presence::new(&info.id, online, info.last_seen))
+}
+
+// strangers see the same as when presence is hidden
+pub async fn get_presence_for(
+    db: &Database,
+    notify_service: &NotifyService,
+    viewer: &str,
+    user_id: &str,
+) -> Result<Presence, sqlx::Error> {
+    let presence = get_presence(db, notify_service, user_id).await?;
+    if viewer != presence.id && !db.is_contact(viewer, &presence.id).await? {
+        return Ok(Presence::hidden(&presence.id));
+    }
+    Ok(presence)
+}
+
+pub async fn publish_presence(db: &Database, notify_service: &NotifyService, user_id: &str) {
+    let presence = match get_presence(db, notify_service, user_id).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Failed to get presence of {}: {}", user_id, e);
+            return;
+        }
+    };
+    let subscribers = match db.get_presence_subscribers(user_id).await {
+        Ok(v) => v,
+        Err(e) => {
+            log::error!("Failed to get presence subscribers of {}: {}", user_id, e);
+            return;
+        }
+    };
+    for subscriber in subscribers {
+        notify_service
+            .publish(&subscriber, Notification::Presence(presence.clone()))
+            .await;
+    }
+}
+
+pub async fn mark_offline(db: &Database, notify_service: &NotifyService, user_id: &str) {
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    if let Err(e) = db.update_last_seen(user_id, now).await {
+        log::error!("Failed to update last seen of {}: {}", user_id, e);
+    }
+    publish_presence(db, notify_service, user_id).await;
+}
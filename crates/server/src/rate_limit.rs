@@ -0,0 +1,477 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    marker::PhantomData,
+    time::{Duration, Instant},
+};
+
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome},
+    tokio::sync::Mutex,
+    Request,
+};
+
+use crate::config::{RateLimitConfig, RouteLimitConfig};
+
+// keys are chosen by clients, past this the least recently used ones are forgotten
+const MAX_TRACKED_KEYS: usize = 10_000;
+
+pub trait RateLimitPolicy: Send + Sync + 'static {
+    const NAME: &'static str;
+
+    fn limits(config: &RateLimitConfig) -> &RouteLimitConfig;
+}
+
+pub struct LoginPolicy;
+
+impl RateLimitPolicy for LoginPolicy {
+    const NAME: &'static str = "login";
+
+    fn limits(config: &RateLimitConfig) -> &RouteLimitConfig {
+        &config.login
+    }
+}
+
+pub struct RegisterPolicy;
+
+impl RateLimitPolicy for RegisterPolicy {
+    const NAME: &'static str = "register";
+
+    fn limits(config: &RateLimitConfig) -> &RouteLimitConfig {
+        &config.register
+    }
+}
+
+pub struct SendMessagePolicy;
+
+impl RateLimitPolicy for SendMessagePolicy {
+    const NAME: &'static str = "send_message";
+
+    fn limits(config: &RateLimitConfig) -> &RouteLimitConfig {
+        &config.send_message
+    }
+}
+
+pub struct GetUserPolicy;
+
+impl RateLimitPolicy for GetUserPolicy {
+    const NAME: &'static str = "get_user";
+
+    fn limits(config: &RateLimitConfig) -> &RouteLimitConfig {
+        &config.get_user
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Scope {
+    Ip,
+    Account,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64) -> Self {
+        Self {
+            tokens: capacity,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn take(&mut self, capacity: f64, refill_rate: f64, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * refill_rate).min(capacity);
+        self.last_refill = now;
+        if self.tokens < 1.0 {
+            return false;
+        }
+        self.tokens -= 1.0;
+        true
+    }
+}
+
+struct FailureState {
+    failures: u32,
+    locked_until: Option<Instant>,
+    last_failure: Instant,
+}
+
+impl FailureState {
+    fn new(now: Instant) -> Self {
+        Self {
+            failures: 0,
+            locked_until: None,
+            last_failure: now,
+        }
+    }
+
+    // forgotten once it is no longer locked and the longest lockout has passed since the last failure
+    fn expired(&self, now: Instant, decay: Duration) -> bool {
+        self.locked_until.is_none_or(|until| until <= now) && now >= self.last_failure + decay
+    }
+}
+
+// a map holding at most `capacity` entries, evicting the least recently used one
+struct LruMap<V> {
+    capacity: usize,
+    entries: HashMap<String, (V, u64)>,
+    order: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl<V> LruMap<V> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    fn get_or_insert_with(&mut self, key: &str, default: impl FnOnce() -> V) -> &mut V {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some((_, used)) => {
+                self.order.remove(used);
+                *used = self.clock;
+            }
+            None => {
+                if self.entries.len() >= self.capacity {
+                    if let Some((_, oldest)) = self.order.pop_first() {
+                        self.entries.remove(&oldest);
+                    }
+                }
+                self.entries
+                    .insert(key.to_string(), (default(), self.clock));
+            }
+        }
+        self.order.insert(self.clock, key.to_string());
+        &mut self.entries.get_mut(key).unwrap().0
+    }
+
+    fn get(&self, key: &str) -> Option<&V> {
+        self.entries.get(key).map(|(value, _)| value)
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((_, used)) = self.entries.remove(key) {
+            self.order.remove(&used);
+        }
+    }
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    ip_buckets: Mutex<LruMap<TokenBucket>>,
+    account_buckets: Mutex<LruMap<TokenBucket>>,
+    // failures of existing accounts are only dropped once expired, never to make room
+    failures: Mutex<HashMap<String, FailureState>>,
+    // usernames that do not exist are made up freely, so they only evict each other
+    unknown_failures: Mutex<LruMap<FailureState>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            ip_buckets: Mutex::new(LruMap::new(MAX_TRACKED_KEYS)),
+            account_buckets: Mutex::new(LruMap::new(MAX_TRACKED_KEYS)),
+            failures: Mutex::new(HashMap::new()),
+            unknown_failures: Mutex::new(LruMap::new(MAX_TRACKED_KEYS)),
+        }
+    }
+
+    async fn take<P: RateLimitPolicy>(&self, scope: Scope, key: &str) -> bool {
+        self.take_at::<P>(scope, key, Instant::now()).await
+    }
+
+    async fn take_at<P: RateLimitPolicy>(&self, scope: Scope, key: &str, now: Instant) -> bool {
+        if !self.config.enabled {
+            return true;
+        }
+        let limits = P::limits(&self.config);
+        let (mut buckets, bucket_config) = match scope {
+            Scope::Ip => (self.ip_buckets.lock().await, &limits.ip),
+            Scope::Account => (self.account_buckets.lock().await, &limits.account),
+        };
+        buckets
+            .get_or_insert_with(&format!("{}:{}", P::NAME, key), || {
+                TokenBucket::new(bucket_config.capacity)
+            })
+            .take(bucket_config.capacity, bucket_config.refill_rate, now)
+    }
+
+    pub async fn check_lockout(&self, account: &str) -> Result<(), Status> {
+        self.check_lockout_at(account, Instant::now()).await
+    }
+
+    async fn check_lockout_at(&self, account: &str, now: Instant) -> Result<(), Status> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let locked_until = match self.failures.lock().await.get(account) {
+            Some(state) => state.locked_until,
+            None => self
+                .unknown_failures
+                .lock()
+                .await
+                .get(account)
+                .and_then(|state| state.locked_until),
+        };
+        match locked_until {
+            Some(until) if until > now => Err(Status::TooManyRequests),
+            _ => Ok(()),
+        }
+    }
+
+    // `known` tells whether the account exists, made up names are tracked apart
+    pub async fn record_failure(&self, account: &str, known: bool) {
+        self.record_failure_at(account, known, Instant::now()).await
+    }
+
+    async fn record_failure_at(&self, account: &str, known: bool, now: Instant) {
+        if !self.config.enabled {
+            return;
+        }
+        let decay = Duration::from_secs(self.config.lockout_max);
+        if !known {
+            let mut failures = self.unknown_failures.lock().await;
+            let state = failures.get_or_insert_with(account, || FailureState::new(now));
+            self.fail(state, account, decay, now);
+            return;
+        }
+        let mut failures = self.failures.lock().await;
+        if failures.len() >= MAX_TRACKED_KEYS {
+            failures.retain(|_, state| !state.expired(now, decay));
+        }
+        let state = failures
+            .entry(account.to_string())
+            .or_insert_with(|| FailureState::new(now));
+        self.fail(state, account, decay, now);
+    }
+
+    fn fail(&self, state: &mut FailureState, account: &str, decay: Duration, now: Instant) {
+        if state.expired(now, decay) {
+            state.failures = 0;
+        }
+        state.failures += 1;
+        state.last_failure = now;
+        if state.failures >= self.config.lockout_threshold {
+            let exponent = (state.failures - self.config.lockout_threshold).min(31);
+            let lockout = self
+                .config
+                .lockout_base
+                .saturating_mul(1 << exponent)
+                .min(self.config.lockout_max);
+            log::warn!("Locking out {} for {} seconds", account, lockout);
+            state.locked_until = Some(now + Duration::from_secs(lockout));
+        }
+    }
+
+    pub async fn record_success(&self, account: &str) {
+        self.failures.lock().await.remove(account);
+        self.unknown_failures.lock().await.remove(account);
+    }
+}
+
+#[derive(Debug)]
+pub enum RateLimitError {
+    Exceeded,
+    ServerError,
+}
+
+pub struct RateLimit<'r, P: RateLimitPolicy> {
+    limiter: &'r RateLimiter,
+    _policy: PhantomData<P>,
+}
+
+impl<'r, P: RateLimitPolicy> RateLimit<'r, P> {
+    pub async fn check_account(&self, account: &str) -> Result<(), Status> {
+        match self.limiter.take::<P>(Scope::Account, account).await {
+            true => Ok(()),
+            false => Err(Status::TooManyRequests),
+        }
+    }
+
+    pub fn limiter(&self) -> &'r RateLimiter {
+        self.limiter
+    }
+}
+
+#[rocket::async_trait]
+impl<'r, P: RateLimitPolicy> FromRequest<'r> for RateLimit<'r, P> {
+    type Error = RateLimitError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let limiter: &RateLimiter = match req.rocket().state() {
+            Some(v) => v,
+            None => {
+                log::error!("No RateLimiter found!");
+                return Outcome::Error((Status::InternalServerError, RateLimitError::ServerError));
+            }
+        };
+        let ip = match req.client_ip() {
+            Some(ip) => ip.to_string(),
+            None => "unknown".to_string(),
+        };
+        if !limiter.take::<P>(Scope::Ip, &ip).await {
+            log::warn!("Rate limit of {} exceeded by {}", P::NAME, ip);
+            return Outcome::Error((Status::TooManyRequests, RateLimitError::Exceeded));
+        }
+        Outcome::Success(RateLimit {
+            limiter,
+            _policy: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(RateLimitConfig::default())
+    }
+
+    #[test]
+    fn bucket_allows_burst_up_to_capacity() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(3.0);
+        for _ in 0..3 {
+            assert!(bucket.take(3.0, 1.0, now));
+        }
+        assert!(!bucket.take(3.0, 1.0, now));
+    }
+
+    #[test]
+    fn bucket_refills_over_time() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2.0);
+        assert!(bucket.take(2.0, 0.5, now));
+        assert!(bucket.take(2.0, 0.5, now));
+        assert!(!bucket.take(2.0, 0.5, now + Duration::from_secs(1)));
+        assert!(bucket.take(2.0, 0.5, now + Duration::from_secs(3)));
+        // never more than the capacity, however long it was idle
+        let later = now + Duration::from_secs(3600);
+        assert!(bucket.take(2.0, 0.5, later));
+        assert!(bucket.take(2.0, 0.5, later));
+        assert!(!bucket.take(2.0, 0.5, later));
+    }
+
+    #[rocket::async_test]
+    async fn lockout_expires() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..limiter.config.lockout_threshold {
+            assert!(limiter.check_lockout_at("ist1000001", now).await.is_ok());
+            limiter.record_failure_at("ist1000001", true, now).await;
+        }
+        assert!(limiter.check_lockout_at("ist1000001", now).await.is_err());
+        assert!(limiter.check_lockout_at("ist1000002", now).await.is_ok());
+
+        let expired = now + Duration::from_secs(limiter.config.lockout_base);
+        assert!(limiter
+            .check_lockout_at("ist1000001", expired)
+            .await
+            .is_ok());
+        // every further failure doubles the lockout
+        limiter.record_failure_at("ist1000001", true, expired).await;
+        let doubled = expired + Duration::from_secs(limiter.config.lockout_base);
+        assert!(limiter
+            .check_lockout_at("ist1000001", doubled)
+            .await
+            .is_err());
+
+        limiter.record_success("ist1000001").await;
+        assert!(limiter
+            .check_lockout_at("ist1000001", doubled)
+            .await
+            .is_ok());
+    }
+
+    #[rocket::async_test]
+    async fn tracked_keys_are_capped() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for i in 0..MAX_TRACKED_KEYS + 100 {
+            limiter
+                .take_at::<GetUserPolicy>(Scope::Ip, &i.to_string(), now)
+                .await;
+            limiter
+                .take_at::<GetUserPolicy>(Scope::Account, &i.to_string(), now)
+                .await;
+            limiter.record_failure_at(&i.to_string(), false, now).await;
+        }
+        assert_eq!(
+            limiter.ip_buckets.lock().await.entries.len(),
+            MAX_TRACKED_KEYS
+        );
+        assert_eq!(
+            limiter.account_buckets.lock().await.entries.len(),
+            MAX_TRACKED_KEYS
+        );
+        assert_eq!(
+            limiter.unknown_failures.lock().await.entries.len(),
+            MAX_TRACKED_KEYS
+        );
+    }
+
+    #[rocket::async_test]
+    async fn lockout_survives_made_up_usernames() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..limiter.config.lockout_threshold {
+            limiter.record_failure_at("ist1000001", true, now).await;
+        }
+        for i in 0..2 * MAX_TRACKED_KEYS {
+            let name = format!("made-up-{i}");
+            limiter
+                .take_at::<LoginPolicy>(Scope::Account, &name, now)
+                .await;
+            limiter.record_failure_at(&name, false, now).await;
+        }
+        assert!(limiter.check_lockout_at("ist1000001", now).await.is_err());
+    }
+
+    #[rocket::async_test]
+    async fn expired_failures_are_pruned() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..limiter.config.lockout_threshold {
+            limiter.record_failure_at("locked", true, now).await;
+        }
+        for i in 0..MAX_TRACKED_KEYS {
+            limiter.record_failure_at(&i.to_string(), true, now).await;
+        }
+        // the lockout outlasts the decay window of a single failure
+        let decay = Duration::from_secs(limiter.config.lockout_max);
+        limiter
+            .failures
+            .lock()
+            .await
+            .get_mut("locked")
+            .unwrap()
+            .locked_until = Some(now + 2 * decay);
+        let later = now + decay;
+        limiter.record_failure_at("ist1000001", true, later).await;
+        let failures = limiter.failures.lock().await;
+        assert_eq!(failures.len(), 2);
+        assert!(failures.contains_key("locked"));
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used() {
+        let mut map = LruMap::new(2);
+        *map.get_or_insert_with("a", || 0) += 1;
+        map.get_or_insert_with("b", || 0);
+        *map.get_or_insert_with("a", || 0) += 1;
+        map.get_or_insert_with("c", || 0);
+        assert_eq!(map.get("a"), Some(&2));
+        assert!(map.get("b").is_none());
+        assert_eq!(map.get("c"), Some(&0));
+    }
+}
@@ -10,14 +10,17 @@ use crate::{
     user_cache::UserCacheService,
 };
 
+pub const SESSION_COOKIE: &str = "session";
+
 #[derive(Debug)]
 pub struct ClientSession {
     pub user: User,
+    pub session_id: i64,
 }
 
 impl ClientSession {
-    fn new(user: User) -> Self {
-        Self { user }
+    fn new(user: User, session_id: i64) -> Self {
+        Self { user, session_id }
     }
 }
 
@@ -34,16 +37,30 @@ impl<'r> FromRequest<'r> for ClientSession {
     type Error = SessionError;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        let Some(cookie) = req.cookies().get_private("user") else {
+        let Some(cookie) = req.cookies().get_private(SESSION_COOKIE) else {
             return Outcome::Error((Status::Unauthorized, SessionError::NotLoggedIn));
         };
-        if let Some(expiration) = cookie.expires_datetime() {
-            if OffsetDateTime::now_utc().unix_timestamp() > expiration.unix_timestamp() {
-                req.cookies().remove(cookie);
+        let db: &Database = match req.rocket().state() {
+            Some(v) => v,
+            None => {
+                log::error!("No Database found!");
+                return Outcome::Error((Status::InternalServerError, SessionError::ServerError));
+            }
+        };
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let token_hash = cryptolib::hash_token(cookie.value());
+        let session = match db.get_session_by_token(&token_hash, now).await {
+            Ok(Some(v)) => v,
+            Ok(None) => {
+                req.cookies().remove_private(cookie);
                 return Outcome::Error((Status::Unauthorized, SessionError::Expired));
             }
-        }
-        let user_id = cookie.value();
+            Err(e) => {
+                log::error!("Failed to load session: {}", e);
+                return Outcome::Error((Status::InternalServerError, SessionError::ServerError));
+            }
+        };
+        let user_id = &session.user_id;
         let user_cache_service: &UserCacheService = match req.rocket().state() {
             Some(v) => v,
             None => {
@@ -53,19 +70,12 @@ impl<'r> FromRequest<'r> for ClientSession {
         };
         let mut user_cache = user_cache_service.cache.lock().await;
         if let Some(user) = user_cache.get(user_id) {
-            return Outcome::Success(ClientSession::new(user.clone()));
+            return Outcome::Success(ClientSession::new(user.clone(), session.id));
         }
-        let db: &Database = match req.rocket().state() {
-            Some(v) => v,
-            None => {
-                log::error!("No Database found!");
-                return Outcome::Error((Status::InternalServerError, SessionError::ServerError));
-            }
-        };
         let Ok(user) = db.get_user_by_id(user_id).await else {
             return Outcome::Error((Status::Unauthorized, SessionError::InvalidToken));
         };
         user_cache.store(user.clone());
-        Outcome::Success(ClientSession::new(user))
+        Outcome::Success(ClientSession::new(user, session.id))
     }
 }
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use rocket::tokio::sync::Mutex;
 
@@ -19,19 +19,26 @@ impl UserCache {
         self.map.insert(user.id.clone(), user);
     }
 
+    pub fn remove(&mut self, user_id: &str) {
+        self.map.remove(user_id);
+    }
+
     pub fn get(&self, user_id: &str) -> Option<&User> {
         self.map.get(user_id)
     }
 }
 
+// every instance caches on its own, changes made through another one reach it as an
+// invalidation from the notify backend, see NotifyService::invalidate_user
+#[derive(Clone)]
 pub struct UserCacheService {
-    pub cache: Mutex<UserCache>,
+    pub cache: Arc<Mutex<UserCache>>,
 }
 
 impl UserCacheService {
     pub fn new() -> Self {
         Self {
-            cache: Mutex::new(UserCache::new()),
+            cache: Arc::new(Mutex::new(UserCache::new())),
         }
     }
 }
@@ -0,0 +1,163 @@
+use std::ops::Deref;
+
+use protocol::{
+    ctos::{
+        ChangePassword, CreateGroup, DeleteAccount, DistributeGroupKey, KeyLogin, Login,
+        PresenceSettings, Register, RequestChallenge, RewrapKeys, RotateKey, SendGroupMessage,
+        SendMessage, SubscribePresence, UpdateProfile,
+    },
+    stoc::ApiError,
+    validation::Validate,
+};
+use rocket::{
+    data::{self, Data, FromData, Limits},
+    http::Status,
+    serde::{json::Json, DeserializeOwned},
+    Request,
+};
+
+pub trait BodyLimit {
+    const LIMIT: &'static str;
+}
+
+impl BodyLimit for Register {
+    const LIMIT: &'static str = "register";
+}
+
+impl BodyLimit for UpdateProfile {
+    const LIMIT: &'static str = "profile";
+}
+
+impl BodyLimit for ChangePassword {
+    const LIMIT: &'static str = "password";
+}
+
+impl BodyLimit for DeleteAccount {
+    const LIMIT: &'static str = "password";
+}
+
+impl BodyLimit for Login {
+    const LIMIT: &'static str = "login";
+}
+
+impl BodyLimit for RequestChallenge {
+    const LIMIT: &'static str = "challenge";
+}
+
+impl BodyLimit for KeyLogin {
+    const LIMIT: &'static str = "login";
+}
+
+impl BodyLimit for RotateKey {
+    const LIMIT: &'static str = "rotate_key";
+}
+
+impl BodyLimit for RewrapKeys {
+    const LIMIT: &'static str = "rewrap_keys";
+}
+
+impl BodyLimit for SendMessage {
+    const LIMIT: &'static str = "send_message";
+}
+
+impl BodyLimit for CreateGroup {
+    const LIMIT: &'static str = "group";
+}
+
+impl BodyLimit for SendGroupMessage {
+    const LIMIT: &'static str = "send_message";
+}
+
+impl BodyLimit for DistributeGroupKey {
+    const LIMIT: &'static str = "group_key";
+}
+
+impl BodyLimit for SubscribePresence {
+    const LIMIT: &'static str = "subscribe_presence";
+}
+
+impl BodyLimit for PresenceSettings {
+    const LIMIT: &'static str = "presence_settings";
+}
+
+struct BodyError(ApiError);
+
+pub struct Valid<T>(pub T);
+
+impl<T> Deref for Valid<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+fn reject<'r, T>(
+    req: &'r Request<'_>,
+    status: Status,
+    error: ApiError,
+) -> data::Outcome<'r, T, ApiError> {
+    req.local_cache(|| Some(BodyError(error.clone())));
+    data::Outcome::Error((status, error))
+}
+
+#[rocket::async_trait]
+impl<'r, T: DeserializeOwned + Validate + BodyLimit> FromData<'r> for Valid<T> {
+    type Error = ApiError;
+
+    async fn from_data(req: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        let limit = req
+            .limits()
+            .find(["json", T::LIMIT])
+            .unwrap_or(Limits::JSON);
+        let body = match data.open(limit).into_string().await {
+            Ok(body) if body.is_complete() => body.into_inner(),
+            Ok(_) => {
+                log::info!("Rejected {} body: exceeds {}", T::LIMIT, limit);
+                let error = ApiError::PayloadTooLarge {
+                    limit: Some(limit.as_u64()),
+                };
+                return reject(req, Status::PayloadTooLarge, error);
+            }
+            Err(e) => {
+                log::info!("Rejected {} body: {}", T::LIMIT, e);
+                return reject(req, Status::UnprocessableEntity, ApiError::MalformedBody);
+            }
+        };
+        let value = match serde_json::from_str::<T>(&body) {
+            Ok(v) => v,
+            Err(e) => {
+                log::info!("Rejected {} body: {}", T::LIMIT, e);
+                return reject(req, Status::UnprocessableEntity, ApiError::MalformedBody);
+            }
+        };
+        if let Err(e) = value.validate() {
+            log::info!("Rejected {} body: {}", T::LIMIT, e);
+            let error = ApiError::InvalidField {
+                field: e.field,
+                reason: e.reason,
+            };
+            return reject(req, Status::UnprocessableEntity, error);
+        }
+        data::Outcome::Success(Valid(value))
+    }
+}
+
+fn cached_error(req: &Request<'_>, default: ApiError) -> ApiError {
+    match req.local_cache(|| None::<BodyError>) {
+        Some(BodyError(error)) => error.clone(),
+        None => default,
+    }
+}
+
+#[catch(413)]
+pub fn payload_too_large(status: Status, req: &Request<'_>) -> (Status, Json<ApiError>) {
+    let error = cached_error(req, ApiError::PayloadTooLarge { limit: None });
+    (status, Json(error))
+}
+
+#[catch(422)]
+pub fn unprocessable_entity(status: Status, req: &Request<'_>) -> (Status, Json<ApiError>) {
+    let error = cached_error(req, ApiError::MalformedBody);
+    (status, Json(error))
+}
@@ -0,0 +1,61 @@
+use common::{next_notification, ServerInstance, TestUser};
+use protocol::stoc::{ApiError, Notification};
+use reqwest::StatusCode;
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+
+#[rocket::async_test]
+async fn account_deletion() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18451, &[NO_RATE_LIMIT]).await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    let mut bob_ws = bob.connect_notifications().await;
+
+    alice.send_message(&bob.id, b"hello bob").await;
+    bob.send_message(&alice.id, b"hello alice").await;
+    let _ = next_notification(&mut bob_ws).await;
+    bob.subscribe_presence(std::slice::from_ref(&alice.id))
+        .await;
+    alice
+        .subscribe_presence(std::slice::from_ref(&bob.id))
+        .await;
+
+    let response = alice.delete_account("wrong password").await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.json::<ApiError>().await.unwrap(),
+        ApiError::InvalidCredentials
+    );
+    alice
+        .delete_account("password")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    match next_notification(&mut bob_ws).await {
+        Notification::AccountDeleted(id) => assert_eq!(id, alice.id),
+        other => panic!("Unexpected notification {:?}", other),
+    }
+    assert_eq!(
+        bob.get_user(&alice.id).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        alice.get_user(&bob.id).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(alice.login().await.status(), StatusCode::UNAUTHORIZED);
+    let messages = bob.get_messages().await;
+    assert_eq!(messages.inbound.len(), 1);
+    assert_eq!(messages.outbound.len(), 1);
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    assert!(alice.get_messages().await.inbound.is_empty());
+}
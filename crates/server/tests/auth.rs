@@ -0,0 +1,115 @@
+use common::{ServerInstance, TestUser};
+use cryptolib::{RsaPrivateKey, RsaPublicKey};
+use protocol::{ctos::KeyProof, stoc::ApiError};
+use rand::rngs::OsRng;
+use reqwest::StatusCode;
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+
+#[rocket::async_test]
+async fn challenge_response_login() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18445, &[]).await;
+    let alice = TestUser::register(&server, "ist1000001").await;
+
+    let proof = alice.prove_key().await;
+    alice
+        .key_login(proof.clone())
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(alice.get_user("ist1000001").await.status(), StatusCode::OK);
+
+    let replayed = alice.key_login(proof).await;
+    assert_eq!(replayed.status(), StatusCode::UNAUTHORIZED);
+
+    let challenge = alice.login_challenge().await;
+    let other_key = RsaPrivateKey::new(&mut OsRng, 2048).unwrap();
+    let signature = cryptolib::sign(&challenge.signed_payload(&alice.id), &other_key).unwrap();
+    let forged = alice
+        .key_login(KeyProof::new(&challenge.nonce, &signature))
+        .await;
+    assert_eq!(forged.status(), StatusCode::UNAUTHORIZED);
+
+    let unknown = TestUser::unregistered(&server, "ist1000666");
+    let proof = unknown.prove_key().await;
+    assert_eq!(
+        unknown.key_login(proof).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[rocket::async_test]
+async fn key_as_second_factor() {
+    let server = ServerInstance::spawn_with_store(
+        MEMORY_STORE,
+        18446,
+        &[("ROCKET_AUTH", "{key_second_factor=true}")],
+    )
+    .await;
+    let alice = TestUser::register(&server, "ist1000001").await;
+
+    assert_eq!(alice.login().await.status(), StatusCode::UNAUTHORIZED);
+
+    let proof = alice.prove_key().await;
+    alice
+        .login_with_proof(proof)
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[rocket::async_test]
+async fn registration_key_validation() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18447, &[NO_RATE_LIMIT]).await;
+    let alice = TestUser::unregistered(&server, "ist1000001");
+    let public_key = RsaPublicKey::from(&alice.private_key);
+    let public_key = cryptolib::utils::public_key_to_bytes(public_key).unwrap();
+
+    let proof = alice.prove_registration().await;
+    let response = alice.register_with(b"not a key", proof).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json::<ApiError>().await.unwrap(),
+        ApiError::MalformedKey
+    );
+
+    let weak_key = RsaPrivateKey::new(&mut OsRng, 1024).unwrap();
+    let weak_key = cryptolib::utils::public_key_to_bytes(RsaPublicKey::from(&weak_key)).unwrap();
+    let proof = alice.prove_registration().await;
+    let response = alice.register_with(&weak_key, proof).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json::<ApiError>().await.unwrap(),
+        ApiError::WeakKey { min_bits: 2048 }
+    );
+
+    let challenge = alice.register_challenge().await;
+    let other_key = RsaPrivateKey::new(&mut OsRng, 2048).unwrap();
+    let signature = cryptolib::sign(&challenge.signed_payload(&alice.id), &other_key).unwrap();
+    let response = alice
+        .register_with(&public_key, KeyProof::new(&challenge.nonce, &signature))
+        .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json::<ApiError>().await.unwrap(),
+        ApiError::InvalidProof
+    );
+
+    let proof = alice.prove_registration().await;
+    alice
+        .register_with(&public_key, proof)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let proof = alice.prove_registration().await;
+    let response = alice.register_with(&public_key, proof).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.json::<ApiError>().await.unwrap(),
+        ApiError::UserExists
+    );
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use common::{ServerInstance, TestUser};
+use protocol::stoc::{ApiError, Blob};
+use reqwest::StatusCode;
+use rocket::tokio;
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+
+async fn upload(user: &TestUser, data: &[u8]) -> Blob {
+    user.upload_blob(data)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn download(user: &TestUser, id: &str) -> Option<Vec<u8>> {
+    let response = user.download_blob(id).await;
+    match response.status() {
+        StatusCode::OK => Some(response.bytes().await.unwrap().to_vec()),
+        StatusCode::NOT_FOUND => None,
+        status => panic!("unexpected status {}", status),
+    }
+}
+
+#[rocket::async_test]
+async fn attachments() {
+    let server = ServerInstance::spawn_with_store(
+        MEMORY_STORE,
+        18455,
+        &[
+            NO_RATE_LIMIT,
+            ("ROCKET_BLOBS", "{quota=4096}"),
+            ("ROCKET_LIMITS", "{blob=\"2KiB\"}"),
+        ],
+    )
+    .await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    let carol = TestUser::register(&server, "ist1000003").await;
+    carol.login().await.error_for_status().unwrap();
+
+    let first = upload(&alice, &[1; 1024]).await;
+    assert_eq!(first.size, 1024);
+    assert_eq!(download(&alice, &first.id).await.unwrap(), vec![1; 1024]);
+    assert!(download(&bob, &first.id).await.is_none());
+
+    let response = alice.upload_blob(&[0; 4096]).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let response = alice.upload_blob(&[]).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    // the quota counts every blob the user still owns
+    for _ in 0..3 {
+        upload(&alice, &[2; 1024]).await;
+    }
+    let response = alice.upload_blob(&[3; 16]).await;
+    assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+    assert!(matches!(
+        response.json::<ApiError>().await.unwrap(),
+        ApiError::QuotaExceeded { quota: 4096 }
+    ));
+    upload(&bob, &[3; 16]).await;
+
+    // attaching a blob grants its recipients access to it
+    alice
+        .send_attachment(&bob.id, b"file", std::slice::from_ref(&first.id))
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(download(&bob, &first.id).await.unwrap(), vec![1; 1024]);
+    assert!(download(&carol, &first.id).await.is_none());
+
+    // only the owner can attach a blob
+    let response = carol.send_attachment(&bob.id, b"file", &[first.id]).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = alice
+        .send_attachment(&bob.id, b"file", &["missing".to_string()])
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    bob.add_contact(&carol).await;
+    let group = carol
+        .create_group("Files", std::slice::from_ref(&bob.id))
+        .await;
+    carol
+        .distribute_group_key(group.id, 0, &[&carol.id, &bob.id])
+        .await
+        .error_for_status()
+        .unwrap();
+    let shared = upload(&carol, &[4; 512]).await;
+    carol
+        .send_group_attachment(group.id, 0, b"file", std::slice::from_ref(&shared.id))
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(download(&bob, &shared.id).await.unwrap(), vec![4; 512]);
+    assert!(download(&alice, &shared.id).await.is_none());
+}
+
+#[rocket::async_test]
+async fn blob_garbage_collection() {
+    let server = ServerInstance::spawn_with_store(
+        MEMORY_STORE,
+        18456,
+        &[
+            NO_RATE_LIMIT,
+            ("ROCKET_BLOBS", "{grace_period=0,gc_interval=1}"),
+        ],
+    )
+    .await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+
+    let kept = upload(&alice, b"kept").await;
+    let dropped = upload(&alice, b"dropped").await;
+    alice
+        .send_attachment(&bob.id, b"file", std::slice::from_ref(&kept.id))
+        .await
+        .error_for_status()
+        .unwrap();
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert_eq!(download(&alice, &kept.id).await.unwrap(), b"kept");
+    assert!(download(&alice, &dropped.id).await.is_none());
+
+    // the recipient keeps access after the owner deletes their account
+    alice
+        .delete_account("password")
+        .await
+        .error_for_status()
+        .unwrap();
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    assert_eq!(download(&bob, &kept.id).await.unwrap(), b"kept");
+
+    bob.delete_account("password")
+        .await
+        .error_for_status()
+        .unwrap();
+}
@@ -0,0 +1,46 @@
+use common::{next_notification, ServerInstance, TestUser};
+use protocol::stoc::Notification;
+use reqwest::StatusCode;
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+
+#[rocket::async_test]
+async fn block_list() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18452, &[]).await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+
+    assert_eq!(bob.block(&bob.id).await.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        bob.block("ist1000666").await.status(),
+        StatusCode::NOT_FOUND
+    );
+    bob.block(&alice.id).await.error_for_status().unwrap();
+    bob.block(&alice.id).await.error_for_status().unwrap();
+    let blocks = bob.get_blocks().await;
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].id, alice.id);
+
+    let response = alice.try_send_message(&bob.id, b"blocked").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(alice.get_messages().await.outbound.len(), 1);
+    assert!(bob.get_messages().await.inbound.is_empty());
+    bob.send_message(&alice.id, b"still allowed").await;
+    assert_eq!(alice.get_messages().await.inbound.len(), 1);
+
+    bob.unblock(&alice.id).await.error_for_status().unwrap();
+    assert_eq!(bob.unblock(&alice.id).await.status(), StatusCode::NOT_FOUND);
+    assert!(bob.get_blocks().await.is_empty());
+    let mut bob_ws = bob.connect_notifications().await;
+    alice.send_message(&bob.id, b"unblocked").await;
+    match next_notification(&mut bob_ws).await {
+        Notification::Message(message) => assert_eq!(message.contents, b"unblocked"),
+        other => panic!("Unexpected notification {:?}", other),
+    }
+    assert_eq!(bob.get_messages().await.inbound.len(), 1);
+}
@@ -0,0 +1,816 @@
+#![allow(dead_code)]
+
+use std::{
+    env,
+    process::{Child, Command, Output, Stdio},
+    time::Duration,
+};
+
+use cryptolib::{RsaPrivateKey, RsaPublicKey};
+use protocol::{
+    ctos::{
+        ChangePassword, ClientFrame, CreateGroup, DeleteAccount, DistributeGroupKey, GroupKey,
+        KeyLogin, KeyProof, Login, PresenceSettings, Register, RequestChallenge, RewrapKeys,
+        RotateKey, SendGroupMessage, SendMessage, SubscribePresence, UpdateProfile,
+    },
+    stoc::{
+        BlockedUser, Challenge, ContactRequest, Group, GroupMessage, Notification, Presence,
+        ResponseGetMessage, Revision,
+    },
+};
+use rand::rngs::OsRng;
+use reqwest::{Client, Response};
+use reqwest_websocket::{Message as WSMessage, RequestBuilderExt, WebSocket};
+use rocket::{
+    futures::{SinkExt, TryStreamExt},
+    tokio,
+};
+use sqlx::{postgres::PgConnectOptions, Connection, Executor, PgConnection};
+
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+const NOTIFICATION_TIMEOUT: Duration = Duration::from_secs(10);
+pub const PASSWORD: &str = "password";
+
+pub struct ServerInstance {
+    process: Child,
+    pub address: String,
+}
+
+impl ServerInstance {
+    pub async fn spawn(database: &str, instance_id: &str, port: u16) -> Self {
+        Self::spawn_with_env(database, instance_id, port, &[]).await
+    }
+
+    pub async fn spawn_with_env(
+        database: &str,
+        instance_id: &str,
+        port: u16,
+        vars: &[(&str, &str)],
+    ) -> Self {
+        let database_user = env::var("PGUSER").unwrap_or("postgres".to_string());
+        let database = format!(
+            "{{name=\"{}\",user=\"{}\",tls=false}}",
+            database, database_user
+        );
+        let mut env = vec![
+            ("ROCKET_NOTIFY_BACKEND", "postgres"),
+            ("ROCKET_INSTANCE_ID", instance_id),
+            ("ROCKET_DATABASE", database.as_str()),
+        ];
+        env.extend_from_slice(vars);
+        Self::launch(port, &env).await
+    }
+
+    // a server on a store that needs no PostgreSQL instance, given as the database config
+    pub async fn spawn_with_store(database: &str, port: u16, vars: &[(&str, &str)]) -> Self {
+        let mut env = vec![("ROCKET_DATABASE", database)];
+        env.extend_from_slice(vars);
+        Self::launch(port, &env).await
+    }
+
+    async fn launch(port: u16, vars: &[(&str, &str)]) -> Self {
+        let process = Command::new(env!("CARGO_BIN_EXE_server"))
+            .env(
+                "ROCKET_CONFIG",
+                concat!(env!("CARGO_MANIFEST_DIR"), "/Rocket.toml"),
+            )
+            .env("ROCKET_PORT", port.to_string())
+            .env("ROCKET_ADDRESS", "127.0.0.1")
+            .envs(vars.iter().copied())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("Failed to spawn server!");
+        let instance = Self {
+            process,
+            address: format!("https://localhost:{}/api", port),
+        };
+        instance.wait_ready().await;
+        instance
+    }
+
+    async fn wait_ready(&self) {
+        let client = create_client();
+        let start = tokio::time::Instant::now();
+        while start.elapsed() < STARTUP_TIMEOUT {
+            if let Ok(response) = client.head(format!("{}/hello", self.address)).send().await {
+                if response.status().is_success() {
+                    return;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        panic!("Server at {} did not start in time!", self.address);
+    }
+}
+
+impl Drop for ServerInstance {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+pub struct TestUser {
+    pub id: String,
+    pub client: Client,
+    pub address: String,
+    pub private_key: RsaPrivateKey,
+}
+
+impl TestUser {
+    pub async fn register(server: &ServerInstance, id: &str) -> Self {
+        let user = Self::unregistered(server, id);
+        let public_key = RsaPublicKey::from(&user.private_key);
+        let public_key = cryptolib::utils::public_key_to_bytes(public_key).unwrap();
+        let proof = user.prove_registration().await;
+        user.register_with(&public_key, proof)
+            .await
+            .error_for_status()
+            .unwrap();
+        user
+    }
+
+    pub async fn register_with(&self, public_key: &[u8], proof: KeyProof) -> Response {
+        self.client
+            .post(format!("{}/users", self.address))
+            .json(&Register::new(
+                &self.id, &self.id, PASSWORD, public_key, proof,
+            ))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn register_challenge(&self) -> Challenge {
+        self.client
+            .post(format!("{}/users/challenge", self.address))
+            .json(&RequestChallenge::new(&self.id))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn prove_registration(&self) -> KeyProof {
+        let challenge = self.register_challenge().await;
+        let signature =
+            cryptolib::sign(&challenge.signed_payload(&self.id), &self.private_key).unwrap();
+        KeyProof::new(&challenge.nonce, &signature)
+    }
+
+    pub fn unregistered(server: &ServerInstance, id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            client: create_client(),
+            address: server.address.clone(),
+            private_key: RsaPrivateKey::new(&mut OsRng, 2048).unwrap(),
+        }
+    }
+
+    // the same user signing in from another device
+    pub fn new_device(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            client: create_client(),
+            address: self.address.clone(),
+            private_key: self.private_key.clone(),
+        }
+    }
+
+    pub async fn login(&self) -> Response {
+        self.login_with_password(PASSWORD).await
+    }
+
+    pub async fn login_with_password(&self, password: &str) -> Response {
+        self.client
+            .post(format!("{}/login", self.address))
+            .json(&Login::new(&self.id, password, "integration test"))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub fn new_session(&self) -> Self {
+        Self {
+            id: self.id.clone(),
+            client: create_client(),
+            address: self.address.clone(),
+            private_key: self.private_key.clone(),
+        }
+    }
+
+    pub async fn login_with_proof(&self, proof: KeyProof) -> Response {
+        self.client
+            .post(format!("{}/login", self.address))
+            .json(&Login::new(&self.id, PASSWORD, "integration test").proof(proof))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn key_login(&self, proof: KeyProof) -> Response {
+        self.client
+            .post(format!("{}/login/key", self.address))
+            .json(&KeyLogin::new(&self.id, proof, "integration test"))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn login_challenge(&self) -> Challenge {
+        self.client
+            .post(format!("{}/login/challenge", self.address))
+            .json(&RequestChallenge::new(&self.id))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn prove_key(&self) -> KeyProof {
+        let challenge = self.login_challenge().await;
+        let signature =
+            cryptolib::sign(&challenge.signed_payload(&self.id), &self.private_key).unwrap();
+        KeyProof::new(&challenge.nonce, &signature)
+    }
+
+    pub async fn connect_notifications(&self) -> WebSocket {
+        let response = self
+            .client
+            .post(format!("{}/notifications", self.address))
+            .upgrade()
+            .send()
+            .await
+            .unwrap();
+        response.into_websocket().await.unwrap()
+    }
+
+    pub async fn send_message(&self, recipient: &str, contents: &[u8]) {
+        self.try_send_message(recipient, contents)
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
+    pub async fn try_send_message(&self, recipient: &str, contents: &[u8]) -> Response {
+        self.client
+            .post(format!("{}/messages", self.address))
+            .json(&SendMessage::new(recipient, contents, b"mine", b"theirs"))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn send_attachment(
+        &self,
+        recipient: &str,
+        contents: &[u8],
+        attachments: &[String],
+    ) -> Response {
+        let request =
+            SendMessage::new(recipient, contents, b"mine", b"theirs").attachments(attachments);
+        self.client
+            .post(format!("{}/messages", self.address))
+            .json(&request)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn send_expiring_message(
+        &self,
+        recipient: &str,
+        contents: &[u8],
+        expires_at: i64,
+    ) -> Response {
+        let request =
+            SendMessage::new(recipient, contents, b"mine", b"theirs").expires_at(Some(expires_at));
+        self.client
+            .post(format!("{}/messages", self.address))
+            .json(&request)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn send_revision(
+        &self,
+        recipient: &str,
+        contents: &[u8],
+        revision: Revision,
+    ) -> Response {
+        let request =
+            SendMessage::new(recipient, contents, b"mine", b"theirs").revision(Some(revision));
+        self.client
+            .post(format!("{}/messages", self.address))
+            .json(&request)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn send_tagged_message(
+        &self,
+        recipient: &str,
+        contents: &[u8],
+        conversation: &str,
+    ) -> Response {
+        let request = SendMessage::new(recipient, contents, b"mine", b"theirs")
+            .conversation(Some(conversation.to_string()));
+        self.client
+            .post(format!("{}/messages", self.address))
+            .json(&request)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_conversation(&self, conversation: &str, query: &str) -> Response {
+        self.client
+            .get(format!(
+                "{}/conversations/{}/messages?{}",
+                self.address, conversation, query
+            ))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn upload_blob(&self, data: &[u8]) -> Response {
+        self.client
+            .post(format!("{}/blobs", self.address))
+            .body(data.to_vec())
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn download_blob(&self, id: &str) -> Response {
+        self.client
+            .get(format!("{}/blobs/{}", self.address, id))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_user(&self, username: &str) -> Response {
+        self.client
+            .get(format!("{}/users/{}", self.address, username))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_messages(&self) -> ResponseGetMessage {
+        self.get_messages_after(-1, -1).await
+    }
+
+    pub async fn get_messages_after(&self, after: i64, out_after: i64) -> ResponseGetMessage {
+        self.client
+            .get(format!(
+                "{}/messages?after={}&out_after={}",
+                self.address, after, out_after
+            ))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn subscribe_presence(&self, users: &[String]) -> Vec<Presence> {
+        self.client
+            .post(format!("{}/presence/subscriptions", self.address))
+            .json(&SubscribePresence::new(users))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_sessions(&self) -> Response {
+        self.client
+            .get(format!("{}/sessions", self.address))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn revoke_session(&self, id: i64) -> Response {
+        self.client
+            .delete(format!("{}/sessions/{}", self.address, id))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn revoke_other_sessions(&self) -> Response {
+        self.client
+            .delete(format!("{}/sessions", self.address))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn set_presence_hidden(&self, hidden: bool) {
+        self.client
+            .put(format!("{}/users/me/presence", self.address))
+            .json(&PresenceSettings::new(hidden))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+
+    pub async fn update_profile(&self, name: &str) -> Response {
+        self.client
+            .patch(format!("{}/users/me", self.address))
+            .json(&UpdateProfile::new(name))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn change_password(&self, current_password: &str, new_password: &str) -> Response {
+        self.client
+            .post(format!("{}/users/me/password", self.address))
+            .json(&ChangePassword::new(current_password, new_password))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn delete_account(&self, password: &str) -> Response {
+        self.client
+            .delete(format!("{}/users/me", self.address))
+            .json(&DeleteAccount::new(password))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn block(&self, username: &str) -> Response {
+        self.client
+            .put(format!("{}/blocks/{}", self.address, username))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn unblock(&self, username: &str) -> Response {
+        self.client
+            .delete(format!("{}/blocks/{}", self.address, username))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_blocks(&self) -> Vec<BlockedUser> {
+        self.client
+            .get(format!("{}/blocks", self.address))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_requests(&self) -> Vec<ContactRequest> {
+        self.client
+            .get(format!("{}/requests", self.address))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn accept_request(&self, username: &str) -> Response {
+        self.client
+            .post(format!("{}/requests/{}/accept", self.address, username))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    // a first message that the other user accepts makes the two contacts
+    pub async fn add_contact(&self, other: &TestUser) {
+        self.send_message(&other.id, b"hi").await;
+        other
+            .accept_request(&self.id)
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+
+    pub async fn decline_request(&self, username: &str) -> Response {
+        self.client
+            .post(format!("{}/requests/{}/decline", self.address, username))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn create_group(&self, name: &str, members: &[String]) -> Group {
+        self.client
+            .post(format!("{}/groups", self.address))
+            .json(&CreateGroup::new(name, members))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_groups(&self) -> Vec<Group> {
+        self.client
+            .get(format!("{}/groups", self.address))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_group(&self, group_id: i64) -> Response {
+        self.client
+            .get(format!("{}/groups/{}", self.address, group_id))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn invite(&self, group_id: i64, username: &str) -> Response {
+        self.client
+            .post(format!(
+                "{}/groups/{}/members/{}",
+                self.address, group_id, username
+            ))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn remove_member(&self, group_id: i64, username: &str) -> Response {
+        self.client
+            .delete(format!(
+                "{}/groups/{}/members/{}",
+                self.address, group_id, username
+            ))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn promote(&self, group_id: i64, username: &str) -> Response {
+        self.client
+            .put(format!(
+                "{}/groups/{}/admins/{}",
+                self.address, group_id, username
+            ))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn demote(&self, group_id: i64, username: &str) -> Response {
+        self.client
+            .delete(format!(
+                "{}/groups/{}/admins/{}",
+                self.address, group_id, username
+            ))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn distribute_group_key(
+        &self,
+        group_id: i64,
+        epoch: i64,
+        members: &[&str],
+    ) -> Response {
+        let keys = members
+            .iter()
+            .map(|m| GroupKey::new(m, format!("{}-{}", m, epoch).as_bytes()))
+            .collect();
+        self.client
+            .put(format!("{}/groups/{}/keys", self.address, group_id))
+            .json(&DistributeGroupKey::new(epoch, keys))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn send_group_message(&self, group_id: i64, epoch: i64, contents: &[u8]) -> Response {
+        self.client
+            .post(format!("{}/groups/{}/messages", self.address, group_id))
+            .json(&SendGroupMessage::new(epoch, contents))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn send_group_attachment(
+        &self,
+        group_id: i64,
+        epoch: i64,
+        contents: &[u8],
+        attachments: &[String],
+    ) -> Response {
+        self.client
+            .post(format!("{}/groups/{}/messages", self.address, group_id))
+            .json(&SendGroupMessage::new(epoch, contents).attachments(attachments))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn send_expiring_group_message(
+        &self,
+        group_id: i64,
+        epoch: i64,
+        contents: &[u8],
+        expires_at: i64,
+    ) -> Response {
+        self.client
+            .post(format!("{}/groups/{}/messages", self.address, group_id))
+            .json(&SendGroupMessage::new(epoch, contents).expires_at(Some(expires_at)))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn send_group_revision(
+        &self,
+        group_id: i64,
+        epoch: i64,
+        contents: &[u8],
+        revision: Revision,
+    ) -> Response {
+        self.client
+            .post(format!("{}/groups/{}/messages", self.address, group_id))
+            .json(&SendGroupMessage::new(epoch, contents).revision(Some(revision)))
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_group_messages(&self) -> Vec<GroupMessage> {
+        self.client
+            .get(format!("{}/groups/messages?after=-1", self.address))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn rotate_key_challenge(&self) -> Challenge {
+        self.client
+            .post(format!("{}/users/me/key/challenge", self.address))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+
+    pub async fn rotate_key(&self, request: &RotateKey) -> Response {
+        self.client
+            .put(format!("{}/users/me/key", self.address))
+            .json(request)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn rewrap_keys(&self, request: &RewrapKeys) -> Response {
+        self.client
+            .put(format!("{}/users/me/key/wrapped", self.address))
+            .json(request)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_presence(&self, username: &str) -> Presence {
+        self.client
+            .get(format!("{}/users/{}/presence", self.address, username))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json()
+            .await
+            .unwrap()
+    }
+}
+
+pub fn create_client() -> Client {
+    Client::builder()
+        .danger_accept_invalid_certs(true)
+        .cookie_store(true)
+        .build()
+        .unwrap()
+}
+
+pub async fn setup_database(database: &str) {
+    let mut conn = PgConnection::connect_with(&PgConnectOptions::new().database("postgres"))
+        .await
+        .expect("Failed to connect to local PostgreSQL!");
+    conn.execute(format!("DROP DATABASE IF EXISTS {} WITH (FORCE)", database).as_str())
+        .await
+        .unwrap();
+    conn.execute(format!("CREATE DATABASE {}", database).as_str())
+        .await
+        .unwrap();
+}
+
+// the schema itself is created by the server, which migrates on startup
+pub async fn connect_database(database: &str) -> PgConnection {
+    PgConnection::connect_with(&PgConnectOptions::new().database(database))
+        .await
+        .unwrap()
+}
+
+pub fn run_migrate(database: &str) -> Output {
+    let database_user = env::var("PGUSER").unwrap_or("postgres".to_string());
+    Command::new(env!("CARGO_BIN_EXE_server"))
+        .arg("migrate")
+        .env(
+            "ROCKET_CONFIG",
+            concat!(env!("CARGO_MANIFEST_DIR"), "/Rocket.toml"),
+        )
+        .env(
+            "ROCKET_DATABASE",
+            format!(
+                "{{name=\"{}\",user=\"{}\",tls=false}}",
+                database, database_user
+            ),
+        )
+        .output()
+        .expect("Failed to run server migrate!")
+}
+
+pub async fn next_notification(websocket: &mut WebSocket) -> Notification {
+    loop {
+        let frame = tokio::time::timeout(NOTIFICATION_TIMEOUT, websocket.try_next())
+            .await
+            .expect("Timed out waiting for notification!")
+            .unwrap()
+            .expect("Websocket closed!");
+        if let WSMessage::Text(text) = frame {
+            return serde_json::from_str(&text).unwrap();
+        }
+    }
+}
+
+pub async fn next_presence(websocket: &mut WebSocket) -> Presence {
+    loop {
+        if let Notification::Presence(presence) = next_notification(websocket).await {
+            return presence;
+        }
+    }
+}
+
+pub async fn ack(websocket: &mut WebSocket, id: i64) {
+    let frame = serde_json::to_string(&ClientFrame::Ack { id }).unwrap();
+    websocket.send(WSMessage::Text(frame)).await.unwrap();
+}
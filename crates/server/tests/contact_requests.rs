@@ -0,0 +1,61 @@
+use common::{ServerInstance, TestUser};
+use reqwest::StatusCode;
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+
+#[rocket::async_test]
+async fn contact_requests() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18453, &[NO_RATE_LIMIT]).await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    let carol = TestUser::register(&server, "ist1000003").await;
+    carol.login().await.error_for_status().unwrap();
+
+    for _ in 0..3 {
+        alice.send_message(&bob.id, b"hello").await;
+    }
+    let response = alice.try_send_message(&bob.id, b"too many").await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(bob.get_messages().await.inbound.len(), 3);
+    let requests = bob.get_requests().await;
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].sender, alice.id);
+    assert_eq!(requests[0].message_count, 3);
+    assert!(alice.get_requests().await.is_empty());
+
+    assert_eq!(
+        bob.accept_request(&carol.id).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    bob.accept_request(&alice.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    assert!(bob.get_requests().await.is_empty());
+    alice.send_message(&bob.id, b"accepted").await;
+    assert_eq!(bob.get_messages().await.inbound.len(), 4);
+
+    carol.send_message(&bob.id, b"spam").await;
+    bob.decline_request(&carol.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = carol.try_send_message(&bob.id, b"ignored").await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(carol.get_messages().await.outbound.len(), 2);
+    assert_eq!(bob.get_messages().await.inbound.len(), 5);
+
+    alice.send_message(&carol.id, b"hi").await;
+    carol.send_message(&alice.id, b"reply").await;
+    assert!(carol.get_requests().await.is_empty());
+    assert!(alice.get_requests().await.is_empty());
+    for _ in 0..3 {
+        alice.send_message(&carol.id, b"chatting").await;
+    }
+}
@@ -0,0 +1,117 @@
+use common::{ServerInstance, TestUser};
+use protocol::stoc::ResponseGetMessage;
+use reqwest::StatusCode;
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+
+async fn page(user: &TestUser, conversation: &str, query: &str) -> ResponseGetMessage {
+    user.get_conversation(conversation, query)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+fn contents(page: &[protocol::stoc::Message]) -> Vec<&[u8]> {
+    page.iter().map(|m| m.contents.as_slice()).collect()
+}
+
+#[rocket::async_test]
+async fn page_through_conversation() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18459, &[NO_RATE_LIMIT]).await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    let carol = TestUser::register(&server, "ist1000003").await;
+    carol.login().await.error_for_status().unwrap();
+
+    let with_alice = "a".repeat(64);
+    let with_carol = "c".repeat(64);
+    for contents in [b"one", b"two", b"thr"] {
+        alice
+            .send_tagged_message(&bob.id, contents, &with_alice)
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+    for contents in [b"ack", b"bye"] {
+        bob.send_tagged_message(&alice.id, contents, &with_alice)
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+    bob.send_message(&alice.id, b"untagged").await;
+    carol
+        .send_tagged_message(&bob.id, b"hi", &with_carol)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // the newest page comes first, oldest to newest within it
+    let newest = page(&bob, &with_alice, "limit=2").await;
+    assert_eq!(contents(&newest.inbound), [b"two", b"thr"]);
+    assert_eq!(contents(&newest.outbound), [b"ack", b"bye"]);
+
+    let query = format!(
+        "before={}&out_before={}&limit=2",
+        newest.inbound[0].id, newest.outbound[0].id
+    );
+    let older = page(&bob, &with_alice, &query).await;
+    assert_eq!(contents(&older.inbound), [b"one"]);
+    assert!(older.outbound.is_empty());
+
+    // both participants share the tag, each sees its own copies
+    let sent = page(&alice, &with_alice, "").await;
+    assert_eq!(contents(&sent.outbound), [b"one", b"two", b"thr"]);
+    assert_eq!(contents(&sent.inbound), [b"ack", b"bye"]);
+
+    let other = page(&bob, &with_carol, "").await;
+    assert_eq!(contents(&other.inbound), [b"hi"]);
+    assert!(page(&carol, &with_alice, "").await.inbound.is_empty());
+
+    let response = bob.get_conversation("not-a-tag", "").await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    bob.send_message(&alice.id, b"x").await;
+
+    // a fresh device only fetches the newest messages
+    let recent: ResponseGetMessage = bob
+        .client
+        .get(format!(
+            "{}/messages?after=-1&out_after=-1&limit=1",
+            bob.address
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(contents(&recent.inbound), [b"hi"]);
+    assert_eq!(contents(&recent.outbound), [b"x"]);
+
+    // and pages back through every conversation when it knows no tag
+    let history: ResponseGetMessage = bob
+        .client
+        .get(format!(
+            "{}/messages/history?before={}&out_before={}&limit=2",
+            bob.address, recent.inbound[0].id, recent.outbound[0].id
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(contents(&history.inbound), [b"two", b"thr"]);
+    assert_eq!(
+        contents(&history.outbound),
+        [b"bye".as_slice(), b"untagged"]
+    );
+}
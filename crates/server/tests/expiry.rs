@@ -0,0 +1,74 @@
+use std::time::Duration;
+
+use common::{ServerInstance, TestUser};
+use rocket::{time::OffsetDateTime, tokio};
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+
+#[rocket::async_test]
+async fn expired_messages_are_deleted() {
+    let server = ServerInstance::spawn_with_store(
+        MEMORY_STORE,
+        18457,
+        &[NO_RATE_LIMIT, ("ROCKET_EXPIRY_INTERVAL", "1")],
+    )
+    .await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    alice.send_message(&bob.id, b"kept").await;
+    alice
+        .send_expiring_message(&bob.id, b"short", now + 2)
+        .await
+        .error_for_status()
+        .unwrap();
+    alice
+        .send_expiring_message(&bob.id, b"long", now + 3600)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    bob.accept_request(&alice.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    let group = alice
+        .create_group("Ephemeral", std::slice::from_ref(&bob.id))
+        .await;
+    alice
+        .distribute_group_key(group.id, 0, &[&alice.id, &bob.id])
+        .await
+        .error_for_status()
+        .unwrap();
+    alice
+        .send_group_message(group.id, 0, b"kept")
+        .await
+        .error_for_status()
+        .unwrap();
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    alice
+        .send_expiring_group_message(group.id, 0, b"short", now + 2)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(bob.get_messages().await.inbound.len(), 3);
+    assert_eq!(bob.get_group_messages().await.len(), 2);
+
+    tokio::time::sleep(Duration::from_secs(5)).await;
+
+    let inbound = bob.get_messages().await.inbound;
+    let contents: Vec<&[u8]> = inbound.iter().map(|m| m.contents.as_slice()).collect();
+    assert_eq!(contents, vec![b"kept".as_slice(), b"long".as_slice()]);
+    assert_eq!(alice.get_messages().await.outbound.len(), 2);
+    let group_messages = bob.get_group_messages().await;
+    assert_eq!(group_messages.len(), 1);
+    assert_eq!(group_messages[0].contents, b"kept");
+}
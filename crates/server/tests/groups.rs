@@ -0,0 +1,160 @@
+use common::{next_notification, ServerInstance, TestUser};
+use protocol::stoc::{ApiError, GroupMessage, GroupRole, Notification};
+use reqwest::StatusCode;
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+
+#[rocket::async_test]
+async fn group_chats() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18454, &[NO_RATE_LIMIT]).await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    let carol = TestUser::register(&server, "ist1000003").await;
+    carol.login().await.error_for_status().unwrap();
+    bob.add_contact(&alice).await;
+
+    let group = alice
+        .create_group("Team", std::slice::from_ref(&bob.id))
+        .await;
+    assert_eq!(group.members.len(), 2);
+    assert_eq!(group.members[0].id, alice.id);
+    assert_eq!(group.members[0].role, GroupRole::Admin);
+    assert_eq!(group.members[1].role, GroupRole::Member);
+    assert!(group.secret_key.is_none());
+    assert_eq!(bob.get_groups().await.len(), 1);
+    assert_eq!(
+        carol.get_group(group.id).await.status(),
+        StatusCode::NOT_FOUND
+    );
+
+    // nobody holds a key for the first epoch yet
+    let response = alice.send_group_message(group.id, 0, b"early").await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let response = alice.distribute_group_key(group.id, 0, &[&alice.id]).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    alice
+        .distribute_group_key(group.id, 0, &[&alice.id, &bob.id])
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = bob
+        .distribute_group_key(group.id, 0, &[&alice.id, &bob.id])
+        .await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let mut bob_ws = bob.connect_notifications().await;
+    let sent: GroupMessage = alice
+        .send_group_message(group.id, 0, b"hello team")
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(sent.secret_key, format!("{}-0", alice.id).into_bytes());
+    match next_notification(&mut bob_ws).await {
+        Notification::GroupMessage(message) => {
+            assert_eq!(message.contents, b"hello team");
+            assert_eq!(message.secret_key, format!("{}-0", bob.id).into_bytes());
+        }
+        other => panic!("Unexpected notification {:?}", other),
+    }
+    assert_eq!(bob.get_group_messages().await.len(), 1);
+
+    // membership changes rotate the key
+    assert_eq!(
+        bob.invite(group.id, &carol.id).await.status(),
+        StatusCode::FORBIDDEN
+    );
+    // only contacts can be added
+    assert_eq!(
+        alice.invite(group.id, &carol.id).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    carol.add_contact(&alice).await;
+    alice
+        .invite(group.id, &carol.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        alice.invite(group.id, &carol.id).await.status(),
+        StatusCode::CONFLICT
+    );
+    let response = alice.send_group_message(group.id, 0, b"stale").await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    match response.json::<ApiError>().await.unwrap() {
+        ApiError::StaleGroupKey { epoch } => assert_eq!(epoch, 1),
+        other => panic!("Unexpected error {:?}", other),
+    }
+    assert!(carol.get_group_messages().await.is_empty());
+    carol
+        .distribute_group_key(group.id, 1, &[&alice.id, &bob.id, &carol.id])
+        .await
+        .error_for_status()
+        .unwrap();
+    alice
+        .send_group_message(group.id, 1, b"welcome carol")
+        .await
+        .error_for_status()
+        .unwrap();
+    let messages = carol.get_group_messages().await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(
+        messages[0].secret_key,
+        format!("{}-1", carol.id).into_bytes()
+    );
+
+    alice
+        .remove_member(group.id, &bob.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        bob.get_group(group.id).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    let group = carol.get_groups().await.remove(0);
+    assert_eq!(group.epoch, 2);
+    assert!(group.secret_key.is_none());
+
+    // a group always keeps an admin
+    assert_eq!(
+        alice.demote(group.id, &alice.id).await.status(),
+        StatusCode::CONFLICT
+    );
+    alice
+        .promote(group.id, &carol.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    alice
+        .demote(group.id, &alice.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        alice.invite(group.id, &bob.id).await.status(),
+        StatusCode::FORBIDDEN
+    );
+    carol
+        .remove_member(group.id, &carol.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    let group = alice.get_groups().await.remove(0);
+    assert_eq!(group.members.len(), 1);
+    assert_eq!(group.members[0].role, GroupRole::Admin);
+    alice
+        .remove_member(group.id, &alice.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    assert!(alice.get_groups().await.is_empty());
+}
@@ -0,0 +1,110 @@
+use common::{next_notification, ServerInstance, TestUser};
+use cryptolib::{RsaPrivateKey, RsaPublicKey};
+use protocol::{
+    ctos::{KeyProof, RewrapKeys, RotateKey, WrappedKey},
+    stoc::{ApiError, Challenge, KeyRotation, Notification},
+    validation::MAX_WRAPPED_KEYS,
+};
+use rand::rngs::OsRng;
+use reqwest::StatusCode;
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+
+#[rocket::async_test]
+async fn key_rotation() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18449, &[]).await;
+
+    let mut alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    let mut bob_ws = bob.connect_notifications().await;
+
+    alice.send_message(&bob.id, b"before rotation").await;
+    let _ = next_notification(&mut bob_ws).await;
+    bob.accept_request(&alice.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    bob.subscribe_presence(&[alice.id.clone()]).await;
+    let sent = alice.get_messages().await.outbound;
+    assert_eq!(sent.len(), 1);
+
+    let new_key = RsaPrivateKey::new(&mut OsRng, 2048).unwrap();
+    let new_public_key =
+        cryptolib::utils::public_key_to_bytes(RsaPublicKey::from(&new_key)).unwrap();
+    let rewrapped = WrappedKey::new(sent[0].id, b"rewrapped");
+
+    let challenge = alice.rotate_key_challenge().await;
+    let payload = Challenge::rotation_payload(&alice.id, &challenge.nonce, &new_public_key);
+    let signature = cryptolib::sign(&payload, &new_key).unwrap();
+    let forged = RotateKey::new(&new_public_key, KeyProof::new(&challenge.nonce, &signature));
+    let response = alice.rotate_key(&forged).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json::<ApiError>().await.unwrap(),
+        ApiError::InvalidProof
+    );
+
+    let challenge = alice.rotate_key_challenge().await;
+    let payload = Challenge::rotation_payload(&alice.id, &challenge.nonce, &new_public_key);
+    let signature = cryptolib::sign(&payload, &alice.private_key).unwrap();
+    let request = RotateKey::new(&new_public_key, KeyProof::new(&challenge.nonce, &signature));
+    let rotation: KeyRotation = alice
+        .rotate_key(&request)
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(rotation.public_key, new_public_key);
+
+    match next_notification(&mut bob_ws).await {
+        Notification::KeyChanged(change) => {
+            assert_eq!(change.id, alice.id);
+            assert_eq!(change.public_key, new_public_key);
+            let previous_key =
+                cryptolib::utils::public_key_from_bytes(&change.previous_key).unwrap();
+            assert!(cryptolib::verify_signature(
+                &change.signed_payload(),
+                &change.signature,
+                &previous_key
+            ));
+        }
+        other => panic!("Unexpected notification {:?}", other),
+    }
+
+    let user: protocol::stoc::ResponseGetUser = bob.get_user(&alice.id).await.json().await.unwrap();
+    assert_eq!(user.public_key, new_public_key);
+    // the keys follow in pages tied to this rotation
+    let stale = RewrapKeys::new(b"stale", vec![], vec![rewrapped.clone()]);
+    assert_eq!(
+        alice.rewrap_keys(&stale).await.status(),
+        StatusCode::CONFLICT
+    );
+    let oversized = RewrapKeys::new(
+        &rotation.nonce,
+        vec![],
+        vec![rewrapped.clone(); MAX_WRAPPED_KEYS + 1],
+    );
+    assert_eq!(
+        alice.rewrap_keys(&oversized).await.status(),
+        StatusCode::UNPROCESSABLE_ENTITY
+    );
+    let page = RewrapKeys::new(&rotation.nonce, vec![], vec![rewrapped]);
+    alice.rewrap_keys(&page).await.error_for_status().unwrap();
+    let sent = alice.get_messages().await.outbound;
+    assert_eq!(sent[0].secret_key, b"rewrapped");
+
+    let stale_proof = alice.prove_key().await;
+    assert_eq!(
+        alice.key_login(stale_proof).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    alice.private_key = new_key;
+    let proof = alice.prove_key().await;
+    alice.key_login(proof).await.error_for_status().unwrap();
+}
@@ -0,0 +1,164 @@
+use common::{connect_database, run_migrate, setup_database, ServerInstance, TestUser, PASSWORD};
+use protocol::stoc::{Message, Revision};
+
+mod common;
+
+// the init.sql databases were created from before the schema was versioned
+const BASELINE_SQL: &str = include_str!("fixtures/baseline_init.sql");
+const UNIFY_SQL: &str = include_str!("../../../database/migrate-unified-messages.sql");
+
+async fn versions(database: &str) -> Vec<i64> {
+    let mut conn = connect_database(database).await;
+    sqlx::query_scalar("SELECT version FROM schema_version ORDER BY version")
+        .fetch_all(&mut conn)
+        .await
+        .unwrap()
+}
+
+#[rocket::async_test]
+#[ignore = "requires a local PostgreSQL instance"]
+async fn migrate_command_is_idempotent() {
+    let database = "messagist_migrate_test";
+    setup_database(database).await;
+
+    let output = run_migrate(database);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Applied migration 4"));
+    assert_eq!(versions(database).await, vec![1, 2, 3, 4, 5, 6, 7]);
+
+    let output = run_migrate(database);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("up to date"));
+    assert_eq!(versions(database).await, vec![1, 2, 3, 4, 5, 6, 7]);
+
+    let mut conn = connect_database(database).await;
+    let index: Option<String> = sqlx::query_scalar(
+        "SELECT indexname::TEXT FROM pg_indexes WHERE indexname = 'message_keys_copy'",
+    )
+    .fetch_optional(&mut conn)
+    .await
+    .unwrap();
+    assert!(index.is_some());
+}
+
+#[rocket::async_test]
+#[ignore = "requires a local PostgreSQL instance"]
+async fn startup_refuses_database_with_split_messages() {
+    let database = "messagist_upgrade_test";
+    setup_database(database).await;
+
+    let mut conn = connect_database(database).await;
+    sqlx::raw_sql(BASELINE_SQL)
+        .execute(&mut conn)
+        .await
+        .unwrap();
+    sqlx::query(
+        "INSERT INTO Users (id, name, password_hash, public_key) VALUES ('old', 'old', 'x', '')",
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+
+    // the messages have to be moved over by hand first, nothing is recorded until then
+    let output = run_migrate(database);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("migrate-unified-messages.sql"));
+    let versioned: bool = sqlx::query_scalar("SELECT to_regclass('schema_version') IS NOT NULL")
+        .fetch_one(&mut conn)
+        .await
+        .unwrap();
+    assert!(!versioned);
+}
+
+fn ids(messages: &[Message]) -> Vec<i64> {
+    messages.iter().map(|m| m.id).collect()
+}
+
+#[rocket::async_test]
+#[ignore = "requires a local PostgreSQL instance"]
+async fn unified_messages_keep_client_ids() {
+    let database = "messagist_unify_test";
+    setup_database(database).await;
+    let mut conn = connect_database(database).await;
+    sqlx::raw_sql(BASELINE_SQL)
+        .execute(&mut conn)
+        .await
+        .unwrap();
+
+    for id in ["ist1000001", "ist1000002"] {
+        sqlx::query(
+            "INSERT INTO Users (id, name, password_hash, public_key) VALUES ($1, $1, $2, '')",
+        )
+        .bind(id)
+        .bind(cryptolib::hash_password(PASSWORD).unwrap())
+        .execute(&mut conn)
+        .await
+        .unwrap();
+    }
+    // both copies were written with the same ciphertext, under unrelated ids
+    sqlx::raw_sql(
+        "INSERT INTO InMessages (id, user_id, content, secret_key) VALUES
+            (10, 'ist1000002', 'hello', 'k'), (11, 'ist1000001', 'reply', 'k'), (12, 'ist1000002', 'again', 'k');
+        INSERT INTO OutMessages (id, user_id, content, secret_key) VALUES
+            (3, 'ist1000001', 'hello', 'k'), (4, 'ist1000002', 'reply', 'k'), (5, 'ist1000001', 'again', 'k'), (6, 'ist1000001', 'lost', 'k');",
+    )
+    .execute(&mut conn)
+    .await
+    .unwrap();
+    sqlx::raw_sql(UNIFY_SQL).execute(&mut conn).await.unwrap();
+
+    let server = ServerInstance::spawn(database, "unify", 18464).await;
+    assert_eq!(versions(database).await, vec![1, 2, 3, 4, 5, 6, 7]);
+    let alice = TestUser::unregistered(&server, "ist1000001");
+    let bob = TestUser::unregistered(&server, "ist1000002");
+    alice.login().await.error_for_status().unwrap();
+    bob.login().await.error_for_status().unwrap();
+
+    let messages = alice.get_messages().await;
+    assert_eq!(ids(&messages.inbound), [11]);
+    assert_eq!(ids(&messages.outbound), [3, 5, 6]);
+    let messages = bob.get_messages().await;
+    assert_eq!(ids(&messages.inbound), [10, 12]);
+    assert_eq!(ids(&messages.outbound), [4]);
+    assert_eq!(ids(&alice.get_messages_after(-1, 5).await.outbound), [6]);
+
+    // a sent copy is revised under its old id, the receiver sees its own id for it
+    let edit: Message = alice
+        .send_revision(&bob.id, b"hello!", Revision::Edit(3))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(edit.id > 12);
+    assert_eq!(edit.revision, Some(Revision::Edit(3)));
+    let inbound = bob.get_messages_after(12, -1).await.inbound;
+    assert_eq!(inbound.len(), 1);
+    assert_eq!(inbound[0].revision, Some(Revision::Edit(10)));
+    assert_eq!(
+        ids(&alice.get_messages_after(-1, 6).await.outbound),
+        [edit.id]
+    );
+
+    // the tombstone still names the deleted copy by the id each side knows it by
+    let delete: Message = alice
+        .send_revision(&bob.id, b"tombstone", Revision::Delete(5))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(delete.revision, Some(Revision::Delete(5)));
+    let outbound = alice.get_messages_after(-1, edit.id).await.outbound;
+    assert_eq!(ids(&outbound), [delete.id]);
+    assert_eq!(outbound[0].revision, Some(Revision::Delete(5)));
+    let inbound = bob.get_messages_after(edit.id, -1).await.inbound;
+    assert_eq!(inbound.len(), 1);
+    assert_eq!(inbound[0].revision, Some(Revision::Delete(12)));
+    assert_eq!(
+        ids(&bob.get_messages().await.inbound),
+        [10, edit.id, delete.id]
+    );
+}
@@ -0,0 +1,54 @@
+use std::time::Duration;
+
+use common::{next_notification, setup_database, ServerInstance, TestUser};
+use protocol::stoc::Notification;
+use rocket::tokio;
+
+mod common;
+
+const DATABASE_NAME: &str = "messagist_multi_instance_test";
+
+#[rocket::async_test]
+#[ignore = "requires a local PostgreSQL instance"]
+async fn multi_instance_fan_out() {
+    setup_database(DATABASE_NAME).await;
+    let server_a = ServerInstance::spawn(DATABASE_NAME, "instance-a", 18443).await;
+    let server_b = ServerInstance::spawn(DATABASE_NAME, "instance-b", 18444).await;
+
+    let alice = TestUser::register(&server_a, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server_b, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    // presence is only shown to contacts
+    alice.send_message(&bob.id, b"hello").await;
+    bob.accept_request(&alice.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    let mut bob_ws = bob.connect_notifications().await;
+    let _ = next_notification(&mut bob_ws).await;
+
+    let presence = alice.get_presence(&bob.id).await;
+    assert!(presence.online);
+
+    alice.send_message(&bob.id, b"hello from instance a").await;
+    match next_notification(&mut bob_ws).await {
+        Notification::Message(msg) => {
+            assert_eq!(msg.contents, b"hello from instance a");
+            assert_eq!(msg.secret_key, b"theirs");
+        }
+        other => panic!("Unexpected notification {:?}", other),
+    }
+
+    let mut alice_ws = alice.connect_notifications().await;
+    bob.send_message(&alice.id, b"hello from instance b").await;
+    match next_notification(&mut alice_ws).await {
+        Notification::Message(msg) => assert_eq!(msg.contents, b"hello from instance b"),
+        other => panic!("Unexpected notification {:?}", other),
+    }
+
+    drop(bob_ws);
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    let presence = alice.get_presence(&bob.id).await;
+    assert!(!presence.online);
+}
@@ -0,0 +1,162 @@
+use std::{slice, time::Duration};
+
+use common::{ack, next_notification, next_presence, ServerInstance, TestUser};
+use protocol::stoc::{Message, Notification};
+use reqwest_websocket::WebSocket;
+use rocket::{futures::TryStreamExt, tokio};
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+// long enough for the server to have handled what was sent before
+const SETTLE: Duration = Duration::from_millis(500);
+
+async fn next_message(websocket: &mut WebSocket) -> Message {
+    loop {
+        if let Notification::Message(message) = next_notification(websocket).await {
+            return message;
+        }
+    }
+}
+
+async fn assert_no_message(websocket: &mut WebSocket) {
+    loop {
+        let frame = tokio::time::timeout(Duration::from_secs(1), websocket.try_next()).await;
+        let Ok(frame) = frame else {
+            return;
+        };
+        let Some(reqwest_websocket::Message::Text(text)) = frame.unwrap() else {
+            continue;
+        };
+        let notification: Notification = serde_json::from_str(&text).unwrap();
+        assert!(
+            !matches!(notification, Notification::Message(_)),
+            "Unexpected message {:?}",
+            notification
+        );
+    }
+}
+
+#[rocket::async_test]
+async fn offline_delivery_until_acked() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18466, &[NO_RATE_LIMIT]).await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    alice.add_contact(&bob).await;
+    alice.send_message(&bob.id, b"one").await;
+
+    // what arrived while offline is replayed on connect, in order
+    let mut bob_ws = bob.connect_notifications().await;
+    let hi = next_message(&mut bob_ws).await;
+    assert_eq!(hi.contents, b"hi");
+    assert_eq!(next_message(&mut bob_ws).await.contents, b"one");
+    ack(&mut bob_ws, hi.id).await;
+
+    // live deliveries stay pending until acked too
+    alice.send_message(&bob.id, b"two").await;
+    assert_eq!(next_message(&mut bob_ws).await.contents, b"two");
+    tokio::time::sleep(SETTLE).await;
+    drop(bob_ws);
+
+    let mut bob_ws = bob.connect_notifications().await;
+    let one = next_message(&mut bob_ws).await;
+    assert_eq!(one.contents, b"one");
+    let two = next_message(&mut bob_ws).await;
+    assert_eq!(two.contents, b"two");
+    ack(&mut bob_ws, one.id).await;
+    ack(&mut bob_ws, two.id).await;
+    tokio::time::sleep(SETTLE).await;
+    drop(bob_ws);
+
+    let mut bob_ws = bob.connect_notifications().await;
+    assert_no_message(&mut bob_ws).await;
+    // acked messages are still there to sync
+    assert_eq!(bob.get_messages().await.inbound.len(), 3);
+}
+
+#[rocket::async_test]
+async fn heartbeat_timeout() {
+    let server = ServerInstance::spawn_with_store(
+        MEMORY_STORE,
+        18467,
+        &[NO_RATE_LIMIT, ("ROCKET_HEARTBEAT_INTERVAL", "1")],
+    )
+    .await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    alice.add_contact(&bob).await;
+    alice.subscribe_presence(slice::from_ref(&bob.id)).await;
+
+    let mut alice_ws = alice.connect_notifications().await;
+    // pongs only go out while a websocket is read, bob's never is
+    let _bob_ws = bob.connect_notifications().await;
+    assert!(next_presence(&mut alice_ws).await.online);
+    let presence = next_presence(&mut alice_ws).await;
+    assert_eq!(presence.id, bob.id);
+    assert!(!presence.online);
+    // alice kept answering while waiting for that
+    assert!(bob.get_presence(&alice.id).await.online);
+}
+
+#[rocket::async_test]
+async fn several_connections() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18468, &[NO_RATE_LIMIT]).await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    alice.add_contact(&bob).await;
+    alice.subscribe_presence(slice::from_ref(&bob.id)).await;
+
+    let mut alice_ws = alice.connect_notifications().await;
+    let mut phone = bob.connect_notifications().await;
+    assert!(next_presence(&mut alice_ws).await.online);
+    let mut laptop = bob.connect_notifications().await;
+    assert!(next_presence(&mut alice_ws).await.online);
+    assert_eq!(next_message(&mut phone).await.contents, b"hi");
+    assert_eq!(next_message(&mut laptop).await.contents, b"hi");
+
+    // every connection gets what arrives live
+    alice.send_message(&bob.id, b"both").await;
+    assert_eq!(next_message(&mut phone).await.contents, b"both");
+    assert_eq!(next_message(&mut laptop).await.contents, b"both");
+
+    // bob stays online until the last one is gone
+    drop(phone);
+    tokio::time::sleep(SETTLE).await;
+    assert!(alice.get_presence(&bob.id).await.online);
+    drop(laptop);
+    let presence = next_presence(&mut alice_ws).await;
+    assert!(!presence.online);
+}
+
+#[rocket::async_test]
+async fn zero_heartbeat_interval() {
+    let server = ServerInstance::spawn_with_store(
+        MEMORY_STORE,
+        18471,
+        &[NO_RATE_LIMIT, ("ROCKET_HEARTBEAT_INTERVAL", "0")],
+    )
+    .await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    alice.add_contact(&bob).await;
+
+    // the interval is clamped to a second instead of failing every websocket
+    let mut bob_ws = bob.connect_notifications().await;
+    assert_eq!(next_message(&mut bob_ws).await.contents, b"hi");
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    alice.send_message(&bob.id, b"still here").await;
+    assert_eq!(next_message(&mut bob_ws).await.contents, b"still here");
+}
@@ -0,0 +1,63 @@
+use std::{slice, time::Duration};
+
+use common::{next_presence, ServerInstance, TestUser};
+use rocket::{futures::TryStreamExt, tokio};
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+
+#[rocket::async_test]
+async fn presence_visibility() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18465, &[NO_RATE_LIMIT]).await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    let carol = TestUser::register(&server, "ist1000003").await;
+    carol.login().await.error_for_status().unwrap();
+    alice.add_contact(&bob).await;
+    // a request that was never accepted does not make carol a contact
+    carol.send_message(&bob.id, b"hello").await;
+
+    // strangers are skipped like unknown users
+    let followed = alice.subscribe_presence(slice::from_ref(&bob.id)).await;
+    assert_eq!(followed.len(), 1);
+    assert!(!followed[0].online);
+    assert!(carol
+        .subscribe_presence(slice::from_ref(&bob.id))
+        .await
+        .is_empty());
+
+    let mut alice_ws = alice.connect_notifications().await;
+    let mut carol_ws = carol.connect_notifications().await;
+    let bob_ws = bob.connect_notifications().await;
+    let presence = next_presence(&mut alice_ws).await;
+    assert_eq!(presence.id, bob.id);
+    assert!(presence.online);
+    assert!(alice.get_presence(&bob.id).await.online);
+
+    // strangers see the same as when presence is hidden
+    let presence = carol.get_presence(&bob.id).await;
+    assert!(!presence.online);
+    assert_eq!(presence.last_seen, None);
+
+    drop(bob_ws);
+    let presence = next_presence(&mut alice_ws).await;
+    assert!(!presence.online);
+    assert!(presence.last_seen.is_some());
+    assert_eq!(carol.get_presence(&bob.id).await.last_seen, None);
+    let frame = tokio::time::timeout(Duration::from_secs(1), carol_ws.try_next()).await;
+    assert!(frame.is_err(), "A stranger was told about presence!");
+
+    // hidden presence stays hidden from contacts too, even while online
+    bob.set_presence_hidden(true).await;
+    let presence = next_presence(&mut alice_ws).await;
+    assert_eq!(presence.last_seen, None);
+    let _bob_ws = bob.connect_notifications().await;
+    let presence = next_presence(&mut alice_ws).await;
+    assert!(!presence.online);
+    assert!(!alice.get_presence(&bob.id).await.online);
+}
@@ -0,0 +1,77 @@
+use common::{next_notification, ServerInstance, TestUser};
+use protocol::stoc::{ApiError, Notification, ResponseGetUser};
+use reqwest::StatusCode;
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+
+#[rocket::async_test]
+async fn profile_management() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18450, &[]).await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let alice_laptop = alice.new_session();
+    alice_laptop.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    bob.send_message(&alice.id, b"hello").await;
+    alice
+        .accept_request(&bob.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    bob.subscribe_presence(std::slice::from_ref(&alice.id))
+        .await;
+    let mut bob_ws = bob.connect_notifications().await;
+
+    let response = alice.update_profile(" padded ").await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let user: ResponseGetUser = alice
+        .update_profile("Alice")
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(user.name, "Alice");
+    match next_notification(&mut bob_ws).await {
+        Notification::ProfileUpdated(profile) => {
+            assert_eq!(profile.id, alice.id);
+            assert_eq!(profile.name, "Alice");
+        }
+        other => panic!("Unexpected notification {:?}", other),
+    }
+    let user: ResponseGetUser = bob.get_user(&alice.id).await.json().await.unwrap();
+    assert_eq!(user.name, "Alice");
+
+    let response = alice
+        .change_password("wrong password", "new password")
+        .await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.json::<ApiError>().await.unwrap(),
+        ApiError::InvalidCredentials
+    );
+    let response = alice.change_password("password", "short").await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    alice
+        .change_password("password", "new password")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    assert_eq!(
+        alice_laptop.get_user(&bob.id).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+    alice.get_user(&bob.id).await.error_for_status().unwrap();
+    assert_eq!(alice.login().await.status(), StatusCode::UNAUTHORIZED);
+    alice
+        .login_with_password("new password")
+        .await
+        .error_for_status()
+        .unwrap();
+}
@@ -0,0 +1,170 @@
+use std::time::Duration;
+
+use common::{ack, next_notification, ServerInstance, TestUser};
+use protocol::stoc::{GroupMessage, Message, Notification, Revision};
+use reqwest::StatusCode;
+use reqwest_websocket::WebSocket;
+use rocket::tokio;
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+
+async fn next_message(websocket: &mut WebSocket) -> Message {
+    loop {
+        if let Notification::Message(message) = next_notification(websocket).await {
+            return message;
+        }
+    }
+}
+
+#[rocket::async_test]
+async fn edit_and_delete_messages() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18458, &[NO_RATE_LIMIT]).await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+
+    let sent: Message = alice
+        .try_send_message(&bob.id, b"hello")
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let received = bob.get_messages().await.inbound[0].id;
+
+    let edit: Message = alice
+        .send_revision(&bob.id, b"hello!", Revision::Edit(sent.id))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(edit.revision, Some(Revision::Edit(sent.id)));
+    let inbound = bob.get_messages().await.inbound;
+    assert_eq!(inbound.len(), 2);
+    assert_eq!(inbound[1].revision, Some(Revision::Edit(received)));
+
+    // only the sender can revise a message, and revisions cannot be revised
+    let response = bob
+        .send_revision(&alice.id, b"hijacked", Revision::Edit(sent.id))
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = alice
+        .send_revision(&bob.id, b"again", Revision::Edit(edit.id))
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    alice
+        .send_revision(&bob.id, b"tombstone", Revision::Delete(sent.id))
+        .await
+        .error_for_status()
+        .unwrap();
+    let inbound = bob.get_messages().await.inbound;
+    assert_eq!(inbound.len(), 1);
+    assert_eq!(inbound[0].revision, Some(Revision::Delete(received)));
+    let outbound = alice.get_messages().await.outbound;
+    assert_eq!(outbound.len(), 1);
+    assert_eq!(outbound[0].revision, Some(Revision::Delete(sent.id)));
+    let response = alice
+        .send_revision(&bob.id, b"tombstone", Revision::Delete(sent.id))
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    bob.accept_request(&alice.id)
+        .await
+        .error_for_status()
+        .unwrap();
+    let group = alice
+        .create_group("Revisions", std::slice::from_ref(&bob.id))
+        .await;
+    alice
+        .distribute_group_key(group.id, 0, &[&alice.id, &bob.id])
+        .await
+        .error_for_status()
+        .unwrap();
+    let sent: GroupMessage = alice
+        .send_group_message(group.id, 0, b"hello")
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let response = bob
+        .send_group_revision(group.id, 0, b"hijacked", Revision::Edit(sent.id))
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    alice
+        .send_group_revision(group.id, 0, b"hello!", Revision::Edit(sent.id))
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(bob.get_group_messages().await.len(), 2);
+    alice
+        .send_group_revision(group.id, 0, b"tombstone", Revision::Delete(sent.id))
+        .await
+        .error_for_status()
+        .unwrap();
+    let messages = bob.get_group_messages().await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].revision, Some(Revision::Delete(sent.id)));
+}
+
+#[rocket::async_test]
+async fn revise_purged_messages() {
+    let server = ServerInstance::spawn_with_store(
+        MEMORY_STORE,
+        18473,
+        &[NO_RATE_LIMIT, ("ROCKET_PURGE_ACKED_MESSAGES", "true")],
+    )
+    .await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    alice.add_contact(&bob).await;
+
+    let mut bob_ws = bob.connect_notifications().await;
+    let hi = next_message(&mut bob_ws).await;
+    ack(&mut bob_ws, hi.id).await;
+    let sent: Message = alice
+        .try_send_message(&bob.id, b"hello")
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let received = next_message(&mut bob_ws).await;
+    ack(&mut bob_ws, received.id).await;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(bob.get_messages().await.inbound.is_empty());
+
+    // the receiver's copy is gone, the message is still the sender's to revise
+    let response = bob
+        .send_revision(&alice.id, b"hijacked", Revision::Edit(sent.id))
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    alice
+        .send_revision(&bob.id, b"hello!", Revision::Edit(sent.id))
+        .await
+        .error_for_status()
+        .unwrap();
+    let edit = next_message(&mut bob_ws).await;
+    assert_eq!(edit.revision, Some(Revision::Edit(received.id)));
+    alice
+        .send_revision(&bob.id, b"tombstone", Revision::Delete(sent.id))
+        .await
+        .error_for_status()
+        .unwrap();
+    let delete = next_message(&mut bob_ws).await;
+    assert_eq!(delete.revision, Some(Revision::Delete(received.id)));
+}
@@ -0,0 +1,113 @@
+use std::time::Duration;
+
+use common::{ServerInstance, TestUser};
+use protocol::stoc::Session;
+use reqwest::StatusCode;
+use rocket::{futures::TryStreamExt, tokio};
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+
+async fn sessions(user: &TestUser) -> Vec<Session> {
+    user.get_sessions()
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn current_session(user: &TestUser) -> i64 {
+    let sessions = sessions(user).await;
+    sessions.iter().find(|s| s.current).unwrap().id
+}
+
+#[rocket::async_test]
+async fn session_expiry() {
+    let server = ServerInstance::spawn_with_store(
+        MEMORY_STORE,
+        18469,
+        &[NO_RATE_LIMIT, ("ROCKET_SESSION_LIFETIME", "2")],
+    )
+    .await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let sessions = sessions(&alice).await;
+    assert_eq!(sessions.len(), 1);
+    assert!(sessions[0].current);
+    assert_eq!(sessions[0].expires_at - sessions[0].created_at, 2);
+
+    // using a session does not extend it
+    tokio::time::sleep(Duration::from_secs(3)).await;
+    let response = alice.get_sessions().await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    alice.login().await.error_for_status().unwrap();
+    alice.get_sessions().await.error_for_status().unwrap();
+}
+
+#[rocket::async_test]
+async fn session_revocation() {
+    let server = ServerInstance::spawn_with_store(
+        MEMORY_STORE,
+        18470,
+        &[NO_RATE_LIMIT, ("ROCKET_HEARTBEAT_INTERVAL", "1")],
+    )
+    .await;
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let laptop = alice.new_device();
+    laptop.login().await.error_for_status().unwrap();
+    let tablet = alice.new_device();
+    tablet.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(&server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+
+    let listed = sessions(&alice).await;
+    assert_eq!(listed.len(), 3);
+    assert_eq!(listed.iter().filter(|s| s.current).count(), 1);
+    let current = current_session(&alice).await;
+    let laptop_session = current_session(&laptop).await;
+
+    // logout is how the current session ends, and others' sessions look unknown
+    let response = alice.revoke_session(current).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = bob.revoke_session(laptop_session).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let mut laptop_ws = laptop.connect_notifications().await;
+    alice
+        .revoke_session(laptop_session)
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = laptop.get_sessions().await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let response = alice.revoke_session(laptop_session).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    // its websocket is closed at the next heartbeat
+    let closed = tokio::time::timeout(Duration::from_secs(10), async {
+        while let Ok(Some(_)) = laptop_ws.try_next().await {}
+    })
+    .await;
+    assert!(
+        closed.is_ok(),
+        "The websocket of a revoked session stayed open!"
+    );
+
+    alice
+        .revoke_other_sessions()
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = tablet.get_sessions().await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let listed = sessions(&alice).await;
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, current);
+    assert_eq!(sessions(&bob).await.len(), 1);
+}
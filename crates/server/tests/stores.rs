@@ -0,0 +1,186 @@
+use std::fs;
+
+use common::{next_notification, setup_database, ServerInstance, TestUser};
+use protocol::stoc::{Blob, Message, Notification, ResponseGetMessage, Revision};
+use reqwest::StatusCode;
+
+mod common;
+
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+
+fn contents(messages: &[Message]) -> Vec<&[u8]> {
+    messages.iter().map(|m| m.contents.as_slice()).collect()
+}
+
+// the same conversation against every store, they have to behave alike
+async fn exercise_store(server: &ServerInstance) {
+    let alice = TestUser::register(server, "ist1000001").await;
+    alice.login().await.error_for_status().unwrap();
+    let bob = TestUser::register(server, "ist1000002").await;
+    bob.login().await.error_for_status().unwrap();
+    let carol = TestUser::register(server, "ist1000003").await;
+    carol.login().await.error_for_status().unwrap();
+
+    let mut bob_ws = bob.connect_notifications().await;
+    let sent: Message = alice
+        .try_send_message(&bob.id, b"hello")
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    match next_notification(&mut bob_ws).await {
+        Notification::Message(message) => assert_eq!(message.contents, b"hello"),
+        other => panic!("Unexpected notification {:?}", other),
+    }
+    bob.accept_request(&alice.id)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    alice
+        .send_revision(&bob.id, b"hello!", Revision::Edit(sent.id))
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(bob.get_messages().await.inbound.len(), 2);
+    alice
+        .send_revision(&bob.id, b"tombstone", Revision::Delete(sent.id))
+        .await
+        .error_for_status()
+        .unwrap();
+    let inbound = bob.get_messages().await.inbound;
+    assert_eq!(contents(&inbound), [b"tombstone"]);
+    let response = bob
+        .send_revision(&alice.id, b"hijacked", Revision::Edit(sent.id))
+        .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let conversation = "a".repeat(64);
+    for contents in [b"one", b"two", b"thr"] {
+        alice
+            .send_tagged_message(&bob.id, contents, &conversation)
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+    let page: ResponseGetMessage = bob
+        .get_conversation(&conversation, "limit=2")
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(contents(&page.inbound), [b"two", b"thr"]);
+
+    assert_eq!(
+        carol.block("ist1000666").await.status(),
+        StatusCode::NOT_FOUND
+    );
+    carol.block(&alice.id).await.error_for_status().unwrap();
+    assert_eq!(carol.get_blocks().await.len(), 1);
+    alice.send_message(&carol.id, b"blocked").await;
+    assert!(carol.get_messages().await.inbound.is_empty());
+
+    let group = alice
+        .create_group("Team", std::slice::from_ref(&bob.id))
+        .await;
+    assert_eq!(group.members.len(), 2);
+    assert_eq!(
+        alice.invite(group.id, "ist1000666").await.status(),
+        StatusCode::NOT_FOUND
+    );
+    alice
+        .distribute_group_key(group.id, 0, &[&alice.id, &bob.id])
+        .await
+        .error_for_status()
+        .unwrap();
+    alice
+        .send_group_message(group.id, 0, b"hello team")
+        .await
+        .error_for_status()
+        .unwrap();
+    let messages = bob.get_group_messages().await;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].contents, b"hello team");
+    bob.send_group_message(group.id, 0, b"bye team")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let blob: Blob = alice
+        .upload_blob(&[7; 16])
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    alice
+        .send_attachment(&bob.id, b"file", std::slice::from_ref(&blob.id))
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = bob.download_blob(&blob.id).await;
+    assert_eq!(response.bytes().await.unwrap().as_ref(), [7; 16]);
+    assert_eq!(
+        carol.download_blob(&blob.id).await.status(),
+        StatusCode::NOT_FOUND
+    );
+
+    // deleting an account takes its messages and memberships along, but what it said in a
+    // group stays with the other members
+    bob.delete_account("password")
+        .await
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        alice.get_user(&bob.id).await.status(),
+        StatusCode::NOT_FOUND
+    );
+    let groups = alice.get_groups().await;
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].members.len(), 1);
+    assert_eq!(groups[0].epoch, 1);
+    let messages = alice.get_group_messages().await;
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[1].contents, b"bye team");
+    assert_eq!(messages[1].sender, "");
+    assert_eq!(alice.get_messages().await.outbound.len(), 6);
+}
+
+#[rocket::async_test]
+async fn memory_store() {
+    let server =
+        ServerInstance::spawn_with_store("{backend=\"memory\"}", 18461, &[NO_RATE_LIMIT]).await;
+    exercise_store(&server).await;
+}
+
+#[rocket::async_test]
+async fn sqlite_store() {
+    let path = concat!(env!("CARGO_TARGET_TMPDIR"), "/messagist_stores_test.db");
+    for file in [path, &format!("{}-wal", path), &format!("{}-shm", path)] {
+        let _ = fs::remove_file(file);
+    }
+    let database = format!("{{backend=\"sqlite\",path=\"{}\"}}", path);
+    let server = ServerInstance::spawn_with_store(&database, 18462, &[NO_RATE_LIMIT]).await;
+    exercise_store(&server).await;
+    drop(server);
+
+    // everything is still there after a restart
+    let server = ServerInstance::spawn_with_store(&database, 18462, &[NO_RATE_LIMIT]).await;
+    let alice = TestUser::unregistered(&server, "ist1000001");
+    alice.login().await.error_for_status().unwrap();
+    assert_eq!(alice.get_messages().await.outbound.len(), 6);
+}
+
+#[rocket::async_test]
+#[ignore = "requires a local PostgreSQL instance"]
+async fn postgres_store() {
+    let database = "messagist_stores_test";
+    setup_database(database).await;
+    let server = ServerInstance::spawn_with_env(database, "stores", 18463, &[NO_RATE_LIMIT]).await;
+    exercise_store(&server).await;
+}
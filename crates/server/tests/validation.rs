@@ -0,0 +1,88 @@
+use common::{ServerInstance, TestUser};
+use cryptolib::RsaPublicKey;
+use protocol::{
+    stoc::ApiError,
+    validation::{ValidationError, MAX_MESSAGE_TIMER},
+};
+use reqwest::StatusCode;
+use rocket::time::OffsetDateTime;
+
+mod common;
+
+const MEMORY_STORE: &str = "{backend=\"memory\"}";
+const NO_RATE_LIMIT: (&str, &str) = ("ROCKET_RATE_LIMIT", "{enabled=false}");
+
+#[rocket::async_test]
+async fn request_validation() {
+    let server = ServerInstance::spawn_with_store(MEMORY_STORE, 18448, &[NO_RATE_LIMIT]).await;
+
+    let invalid = TestUser::unregistered(&server, "alice");
+    let public_key = RsaPublicKey::from(&invalid.private_key);
+    let public_key = cryptolib::utils::public_key_to_bytes(public_key).unwrap();
+    let proof = invalid.prove_registration().await;
+    let response = invalid.register_with(&public_key, proof).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.json::<ApiError>().await.unwrap(),
+        ApiError::InvalidField {
+            field: "id".to_string(),
+            reason: ValidationError::InvalidFormat,
+        }
+    );
+
+    let response = server_post(&server, "/login", "{\"username\": 42}").await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.json::<ApiError>().await.unwrap(),
+        ApiError::MalformedBody
+    );
+
+    let alice = TestUser::register(&server, "ist1000001").await;
+    let bob = TestUser::register(&server, "ist1000002").await;
+    alice.login().await.error_for_status().unwrap();
+
+    let response = alice.try_send_message(&bob.id, &vec![0; 512 * 1024]).await;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(matches!(
+        response.json::<ApiError>().await.unwrap(),
+        ApiError::PayloadTooLarge { limit: Some(_) }
+    ));
+
+    let response = alice.try_send_message(&bob.id, &vec![0; 80 * 1024]).await;
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.json::<ApiError>().await.unwrap(),
+        ApiError::InvalidField {
+            field: "contents".to_string(),
+            reason: ValidationError::TooLong { max: 64 * 1024 },
+        }
+    );
+
+    // expiry times have to lie in the future and within the longest timer
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    for expires_at in [now - 60, now + MAX_MESSAGE_TIMER + 3600] {
+        let response = alice
+            .send_expiring_message(&bob.id, b"expiring", expires_at)
+            .await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            response.json::<ApiError>().await.unwrap(),
+            ApiError::InvalidField {
+                field: "expires_at".to_string(),
+                reason: ValidationError::OutOfRange,
+            }
+        );
+    }
+
+    alice.send_message(&bob.id, b"hello").await;
+}
+
+async fn server_post(server: &ServerInstance, path: &str, body: &str) -> reqwest::Response {
+    common::create_client()
+        .post(format!("{}{}", server.address, path))
+        .header("Content-Type", "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap()
+}
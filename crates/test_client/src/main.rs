@@ -1,7 +1,7 @@
 use base64::{prelude::BASE64_STANDARD, Engine};
 use client::{client_handler::MessageISTClient, message_data::MessageData};
 use cryptolib::RsaPublicKey;
-use protocol::stoc::ResponseGetUser;
+use protocol::{ctos::SendMessage, stoc::ResponseGetUser};
 use std::{
     env,
     io::{stdin, stdout, Write},
@@ -24,15 +24,15 @@ async fn main() -> anyhow::Result<()> {
     let priv_key_path = Path::new(&priv_key_path);
     let registered = fs::try_exists(&priv_key_path).await?;
     let (prk, puk) = utils::gen_key_pair(IST_ID).await?;
-    let prk_bytes = cryptolib::utils::private_key_to_bytes(prk)?;
+    let puk_bytes = cryptolib::utils::public_key_to_bytes(puk.clone())?;
     let mut http_client = MessageISTClient::new();
     http_client.connect(address).await?;
     if !registered {
         http_client
-            .register(IST_ID, &name, &password, &prk_bytes)
+            .register(IST_ID, &name, &password, &puk_bytes, &prk)
             .await?;
     }
-    http_client.login(IST_ID, &password).await?;
+    http_client.login_with_key(IST_ID, &prk).await?;
     println!("Logged in as {} - {}", IST_ID, name);
     print!("IST ID of target user: ");
     stdout().flush()?;
@@ -86,7 +86,12 @@ async fn send_message(
         );
     }
     http_client
-        .send_message(&user.id, &encrypted, &my_secret_key, &other_secret_key)
+        .send_message(&SendMessage::new(
+            &user.id,
+            &encrypted,
+            &my_secret_key,
+            &other_secret_key,
+        ))
         .await
         .expect("Failed to send message");
     println!("Message sent!");
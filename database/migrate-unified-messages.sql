@@ -0,0 +1,105 @@
+-- Moves a database created from the original init.sql, with its separate InMessages and
+-- OutMessages tables, to the shared Messages table with a MessageKeys row per participant.
+-- Run it once on the setup machine before starting the upgraded server:
+--   psql -d messagist -f migrate-unified-messages.sql
+-- It also hands the database and its tables to messagist_server, which applies the schema
+-- migrations itself on startup.
+--
+-- Both copies of a message were written in one transaction with the same ciphertext, which is
+-- how they are paired up again. A delivered message keeps the id of the receiver's copy, so
+-- receive cursors stay valid. The sender's copy had an id of its own that clients keep as their
+-- sent cursor, LegacySentIds maps it to the new message and the server keeps showing it to the
+-- sender. New messages are numbered after every old id so the two never overlap.
+BEGIN;
+
+CREATE TABLE Messages (
+    id BIGSERIAL PRIMARY KEY,
+    sender_id TEXT,
+    content bytea NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT,
+    edits BIGINT,
+    deletes BIGINT,
+    conversation TEXT,
+    FOREIGN KEY (sender_id) REFERENCES Users (id) ON DELETE SET NULL
+);
+
+CREATE TABLE MessageKeys (
+    message_id BIGINT NOT NULL,
+    user_id TEXT NOT NULL,
+    wrapped_key bytea NOT NULL,
+    direction TEXT NOT NULL,
+    PRIMARY KEY (message_id, user_id, direction),
+    FOREIGN KEY (message_id) REFERENCES Messages (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE LegacySentIds (
+    out_id BIGINT PRIMARY KEY,
+    message_id BIGINT NOT NULL UNIQUE
+);
+
+-- identical ciphertexts only happen by accident, they are paired in id order
+CREATE TEMPORARY TABLE Copies ON COMMIT DROP AS
+SELECT o.id AS out_id, i.id AS in_id
+FROM (SELECT id, content, row_number() OVER (PARTITION BY content ORDER BY id) AS n FROM OutMessages) o
+JOIN (SELECT id, content, row_number() OVER (PARTITION BY content ORDER BY id) AS n FROM InMessages) i
+    ON i.content = o.content AND i.n = o.n;
+
+SELECT setval(
+    pg_get_serial_sequence('Messages', 'id'),
+    GREATEST(
+        COALESCE((SELECT MAX(id) FROM InMessages), 0),
+        COALESCE((SELECT MAX(id) FROM OutMessages), 0),
+        1
+    )
+);
+
+ALTER TABLE OutMessages ADD COLUMN message_id BIGINT;
+UPDATE OutMessages o SET message_id = c.in_id FROM Copies c WHERE c.out_id = o.id;
+-- sent copies without a receiver's copy get a fresh id
+UPDATE OutMessages
+SET message_id = nextval(pg_get_serial_sequence('Messages', 'id'))
+WHERE message_id IS NULL;
+
+-- receivers' copies without a sent copy have an unknown sender
+INSERT INTO Messages (id, sender_id, content, created_at)
+SELECT i.id, o.user_id, i.content, EXTRACT(EPOCH FROM now())::BIGINT
+FROM InMessages i
+LEFT JOIN OutMessages o ON o.message_id = i.id;
+
+INSERT INTO Messages (id, sender_id, content, created_at)
+SELECT o.message_id, o.user_id, o.content, EXTRACT(EPOCH FROM now())::BIGINT
+FROM OutMessages o
+WHERE NOT EXISTS (SELECT 1 FROM InMessages i WHERE i.id = o.message_id);
+
+INSERT INTO MessageKeys (message_id, user_id, wrapped_key, direction)
+SELECT i.id, i.user_id, i.secret_key, 'in'
+FROM InMessages i;
+
+INSERT INTO MessageKeys (message_id, user_id, wrapped_key, direction)
+SELECT o.message_id, o.user_id, o.secret_key, 'out'
+FROM OutMessages o;
+
+INSERT INTO LegacySentIds (out_id, message_id)
+SELECT o.id, o.message_id
+FROM OutMessages o;
+
+DROP TABLE OutMessages;
+DROP TABLE InMessages;
+
+DO $$
+DECLARE
+    t record;
+BEGIN
+    IF NOT EXISTS (SELECT 1 FROM pg_roles WHERE rolname = 'messagist_server') THEN
+        RETURN;
+    END IF;
+    EXECUTE format('ALTER DATABASE %I OWNER TO messagist_server', current_database());
+    FOR t IN SELECT tablename FROM pg_tables WHERE schemaname = 'public' LOOP
+        EXECUTE format('ALTER TABLE %I OWNER TO messagist_server', t.tablename);
+    END LOOP;
+END
+$$;
+
+COMMIT;
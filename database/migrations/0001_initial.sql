@@ -0,0 +1,174 @@
+CREATE TABLE IF NOT EXISTS Users (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    password_hash TEXT NOT NULL,
+    public_key bytea NOT NULL,
+    last_seen BIGINT,
+    presence_hidden BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+-- databases created from the original init.sql already have a Users table without these
+ALTER TABLE Users
+    ADD COLUMN IF NOT EXISTS last_seen BIGINT,
+    ADD COLUMN IF NOT EXISTS presence_hidden BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE IF NOT EXISTS Messages (
+    id BIGSERIAL PRIMARY KEY,
+    sender_id TEXT,
+    content bytea NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT,
+    edits BIGINT,
+    deletes BIGINT,
+    conversation TEXT,
+    FOREIGN KEY (sender_id) REFERENCES Users (id) ON DELETE SET NULL
+);
+
+CREATE TABLE IF NOT EXISTS MessageKeys (
+    message_id BIGINT NOT NULL,
+    user_id TEXT NOT NULL,
+    wrapped_key bytea NOT NULL,
+    direction TEXT NOT NULL,
+    PRIMARY KEY (message_id, user_id, direction),
+    FOREIGN KEY (message_id) REFERENCES Messages (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS PendingDeliveries (
+    message_id BIGINT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    FOREIGN KEY (message_id) REFERENCES Messages (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS PresenceSubscriptions (
+    subscriber_id TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    PRIMARY KEY (subscriber_id, target_id),
+    FOREIGN KEY (subscriber_id) REFERENCES Users (id),
+    FOREIGN KEY (target_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS NotifyConnections (
+    instance_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    connection_id BIGINT NOT NULL,
+    PRIMARY KEY (instance_id, connection_id),
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS Sessions (
+    id BIGSERIAL PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    user_id TEXT NOT NULL,
+    device TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    last_used BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS AuthChallenges (
+    nonce bytea PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    purpose TEXT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS KeyRotations (
+    id BIGSERIAL PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    previous_key bytea NOT NULL,
+    public_key bytea NOT NULL,
+    nonce bytea NOT NULL,
+    signature bytea NOT NULL,
+    rotated_at BIGINT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS Blocks (
+    blocker_id TEXT NOT NULL,
+    blocked_id TEXT NOT NULL,
+    blocked_at BIGINT NOT NULL,
+    PRIMARY KEY (blocker_id, blocked_id),
+    FOREIGN KEY (blocker_id) REFERENCES Users (id),
+    FOREIGN KEY (blocked_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS ContactRequests (
+    sender_id TEXT NOT NULL,
+    recipient_id TEXT NOT NULL,
+    status TEXT NOT NULL,
+    message_count INTEGER NOT NULL DEFAULT 0,
+    created_at BIGINT NOT NULL,
+    PRIMARY KEY (sender_id, recipient_id),
+    FOREIGN KEY (sender_id) REFERENCES Users (id),
+    FOREIGN KEY (recipient_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS Groups (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,
+    key_epoch BIGINT NOT NULL DEFAULT 0,
+    created_at BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS GroupMembers (
+    group_id BIGINT NOT NULL,
+    user_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    joined_at BIGINT NOT NULL,
+    PRIMARY KEY (group_id, user_id),
+    FOREIGN KEY (group_id) REFERENCES Groups (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS GroupKeys (
+    group_id BIGINT NOT NULL,
+    epoch BIGINT NOT NULL,
+    user_id TEXT NOT NULL,
+    secret_key bytea NOT NULL,
+    PRIMARY KEY (group_id, epoch, user_id),
+    FOREIGN KEY (group_id) REFERENCES Groups (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS GroupMessages (
+    id BIGSERIAL PRIMARY KEY,
+    group_id BIGINT NOT NULL,
+    sender_id TEXT NOT NULL,
+    epoch BIGINT NOT NULL,
+    content bytea NOT NULL,
+    sent_at BIGINT NOT NULL,
+    expires_at BIGINT,
+    edits BIGINT,
+    deletes BIGINT,
+    FOREIGN KEY (group_id) REFERENCES Groups (id) ON DELETE CASCADE,
+    FOREIGN KEY (sender_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS GroupInbox (
+    user_id TEXT NOT NULL,
+    message_id BIGINT NOT NULL,
+    PRIMARY KEY (user_id, message_id),
+    FOREIGN KEY (user_id) REFERENCES Users (id),
+    FOREIGN KEY (message_id) REFERENCES GroupMessages (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS Blobs (
+    id TEXT PRIMARY KEY,
+    owner_id TEXT,
+    size BIGINT NOT NULL,
+    data bytea NOT NULL,
+    created_at BIGINT NOT NULL,
+    FOREIGN KEY (owner_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS BlobReferences (
+    blob_id TEXT NOT NULL,
+    message_id BIGINT,
+    group_message_id BIGINT,
+    FOREIGN KEY (blob_id) REFERENCES Blobs (id) ON DELETE CASCADE,
+    FOREIGN KEY (message_id) REFERENCES Messages (id) ON DELETE CASCADE,
+    FOREIGN KEY (group_message_id) REFERENCES GroupMessages (id) ON DELETE CASCADE
+);
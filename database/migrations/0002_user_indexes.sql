@@ -0,0 +1,6 @@
+-- the message queries look up the copies of a single user or conversation
+CREATE INDEX IF NOT EXISTS message_keys_user ON MessageKeys (user_id, direction, message_id);
+CREATE INDEX IF NOT EXISTS messages_conversation ON Messages (conversation, id);
+CREATE INDEX IF NOT EXISTS pending_deliveries_user ON PendingDeliveries (user_id, message_id);
+CREATE INDEX IF NOT EXISTS sessions_user ON Sessions (user_id);
+CREATE INDEX IF NOT EXISTS group_members_user ON GroupMembers (user_id);
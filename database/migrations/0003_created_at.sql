@@ -0,0 +1,5 @@
+-- rows are stamped by the database itself, existing ones get the time of the migration
+ALTER TABLE Users
+    ADD COLUMN IF NOT EXISTS created_at BIGINT NOT NULL DEFAULT EXTRACT(EPOCH FROM now())::BIGINT;
+ALTER TABLE Messages
+    ALTER COLUMN created_at SET DEFAULT EXTRACT(EPOCH FROM now())::BIGINT;
@@ -0,0 +1,6 @@
+-- sent copies moved over from OutMessages keep the id their sender knows them by,
+-- filled by migrate-unified-messages.sql and empty everywhere else
+CREATE TABLE IF NOT EXISTS LegacySentIds (
+    out_id BIGINT PRIMARY KEY,
+    message_id BIGINT NOT NULL UNIQUE
+);
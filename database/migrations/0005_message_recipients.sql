@@ -0,0 +1,7 @@
+-- edits and deletes are checked against the recipient, which outlives the receiver's copy
+-- once it is purged on acknowledgement; messages already purged stay without one
+ALTER TABLE Messages
+    ADD COLUMN IF NOT EXISTS recipient_id TEXT REFERENCES Users (id) ON DELETE SET NULL;
+UPDATE Messages m SET recipient_id = k.user_id
+FROM MessageKeys k
+WHERE k.message_id = m.id AND k.direction = 'in' AND m.recipient_id IS NULL;
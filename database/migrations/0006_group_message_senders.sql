@@ -0,0 +1,6 @@
+-- group history stays with the other members when its sender deletes their account
+ALTER TABLE GroupMessages
+    ALTER COLUMN sender_id DROP NOT NULL,
+    DROP CONSTRAINT IF EXISTS groupmessages_sender_id_fkey,
+    ADD CONSTRAINT groupmessages_sender_id_fkey
+        FOREIGN KEY (sender_id) REFERENCES Users (id) ON DELETE SET NULL;
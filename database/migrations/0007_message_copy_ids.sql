@@ -0,0 +1,22 @@
+-- every copy carries the ids its owner knows it and the message it revises by, so listing a
+-- user's copies filters and orders on an indexed column; they only differ from the message's
+-- own for sent copies moved over from OutMessages, which are resolved here once
+ALTER TABLE MessageKeys
+    ADD COLUMN IF NOT EXISTS copy_id BIGINT,
+    ADD COLUMN IF NOT EXISTS edits BIGINT,
+    ADD COLUMN IF NOT EXISTS deletes BIGINT;
+UPDATE MessageKeys k SET copy_id = m.id, edits = m.edits, deletes = m.deletes
+FROM Messages m
+WHERE m.id = k.message_id;
+UPDATE MessageKeys k SET copy_id = l.out_id
+FROM LegacySentIds l
+WHERE k.direction = 'out' AND l.message_id = k.message_id;
+UPDATE MessageKeys k SET edits = l.out_id
+FROM LegacySentIds l
+WHERE k.direction = 'out' AND l.message_id = k.edits;
+UPDATE MessageKeys k SET deletes = l.out_id
+FROM LegacySentIds l
+WHERE k.direction = 'out' AND l.message_id = k.deletes;
+ALTER TABLE MessageKeys ALTER COLUMN copy_id SET NOT NULL;
+DROP INDEX IF EXISTS message_keys_user;
+CREATE INDEX IF NOT EXISTS message_keys_copy ON MessageKeys (user_id, direction, copy_id);
@@ -0,0 +1,169 @@
+-- ids use AUTOINCREMENT so they are never reused, clients page through messages by id
+CREATE TABLE IF NOT EXISTS Users (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    password_hash TEXT NOT NULL,
+    public_key BLOB NOT NULL,
+    last_seen INTEGER,
+    presence_hidden BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER))
+);
+
+CREATE TABLE IF NOT EXISTS Messages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    sender_id TEXT,
+    content BLOB NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+    expires_at INTEGER,
+    edits INTEGER,
+    deletes INTEGER,
+    conversation TEXT,
+    FOREIGN KEY (sender_id) REFERENCES Users (id) ON DELETE SET NULL
+);
+
+CREATE TABLE IF NOT EXISTS MessageKeys (
+    message_id INTEGER NOT NULL,
+    user_id TEXT NOT NULL,
+    wrapped_key BLOB NOT NULL,
+    direction TEXT NOT NULL,
+    PRIMARY KEY (message_id, user_id, direction),
+    FOREIGN KEY (message_id) REFERENCES Messages (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS PendingDeliveries (
+    message_id INTEGER PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    FOREIGN KEY (message_id) REFERENCES Messages (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS PresenceSubscriptions (
+    subscriber_id TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    PRIMARY KEY (subscriber_id, target_id),
+    FOREIGN KEY (subscriber_id) REFERENCES Users (id),
+    FOREIGN KEY (target_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS Sessions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    token_hash TEXT NOT NULL UNIQUE,
+    user_id TEXT NOT NULL,
+    device TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    last_used INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS AuthChallenges (
+    nonce BLOB PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    purpose TEXT NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS KeyRotations (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id TEXT NOT NULL,
+    previous_key BLOB NOT NULL,
+    public_key BLOB NOT NULL,
+    nonce BLOB NOT NULL,
+    signature BLOB NOT NULL,
+    rotated_at INTEGER NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS Blocks (
+    blocker_id TEXT NOT NULL,
+    blocked_id TEXT NOT NULL,
+    blocked_at INTEGER NOT NULL,
+    PRIMARY KEY (blocker_id, blocked_id),
+    FOREIGN KEY (blocker_id) REFERENCES Users (id),
+    FOREIGN KEY (blocked_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS ContactRequests (
+    sender_id TEXT NOT NULL,
+    recipient_id TEXT NOT NULL,
+    status TEXT NOT NULL,
+    message_count INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL,
+    PRIMARY KEY (sender_id, recipient_id),
+    FOREIGN KEY (sender_id) REFERENCES Users (id),
+    FOREIGN KEY (recipient_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS Groups (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    key_epoch INTEGER NOT NULL DEFAULT 0,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS GroupMembers (
+    group_id INTEGER NOT NULL,
+    user_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    joined_at INTEGER NOT NULL,
+    PRIMARY KEY (group_id, user_id),
+    FOREIGN KEY (group_id) REFERENCES Groups (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS GroupKeys (
+    group_id INTEGER NOT NULL,
+    epoch INTEGER NOT NULL,
+    user_id TEXT NOT NULL,
+    secret_key BLOB NOT NULL,
+    PRIMARY KEY (group_id, epoch, user_id),
+    FOREIGN KEY (group_id) REFERENCES Groups (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS GroupMessages (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    group_id INTEGER NOT NULL,
+    sender_id TEXT NOT NULL,
+    epoch INTEGER NOT NULL,
+    content BLOB NOT NULL,
+    sent_at INTEGER NOT NULL,
+    expires_at INTEGER,
+    edits INTEGER,
+    deletes INTEGER,
+    FOREIGN KEY (group_id) REFERENCES Groups (id) ON DELETE CASCADE,
+    FOREIGN KEY (sender_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS GroupInbox (
+    user_id TEXT NOT NULL,
+    message_id INTEGER NOT NULL,
+    PRIMARY KEY (user_id, message_id),
+    FOREIGN KEY (user_id) REFERENCES Users (id),
+    FOREIGN KEY (message_id) REFERENCES GroupMessages (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS Blobs (
+    id TEXT PRIMARY KEY,
+    owner_id TEXT,
+    size INTEGER NOT NULL,
+    data BLOB NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY (owner_id) REFERENCES Users (id)
+);
+
+CREATE TABLE IF NOT EXISTS BlobReferences (
+    blob_id TEXT NOT NULL,
+    message_id INTEGER,
+    group_message_id INTEGER,
+    FOREIGN KEY (blob_id) REFERENCES Blobs (id) ON DELETE CASCADE,
+    FOREIGN KEY (message_id) REFERENCES Messages (id) ON DELETE CASCADE,
+    FOREIGN KEY (group_message_id) REFERENCES GroupMessages (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS message_keys_user ON MessageKeys (user_id, direction, message_id);
+CREATE INDEX IF NOT EXISTS messages_conversation ON Messages (conversation, id);
+CREATE INDEX IF NOT EXISTS pending_deliveries_user ON PendingDeliveries (user_id, message_id);
+CREATE INDEX IF NOT EXISTS sessions_user ON Sessions (user_id);
+CREATE INDEX IF NOT EXISTS group_members_user ON GroupMembers (user_id);
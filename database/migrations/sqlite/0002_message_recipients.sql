@@ -0,0 +1,5 @@
+-- see 0005_message_recipients.sql
+ALTER TABLE Messages ADD COLUMN recipient_id TEXT REFERENCES Users (id) ON DELETE SET NULL;
+UPDATE Messages SET recipient_id = (
+    SELECT k.user_id FROM MessageKeys k WHERE k.message_id = Messages.id AND k.direction = 'in'
+);
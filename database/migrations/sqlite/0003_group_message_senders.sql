@@ -0,0 +1,21 @@
+-- see 0006_group_message_senders.sql, sqlite can only change the column by rebuilding the table,
+-- which the migrations do with foreign keys off so GroupInbox and BlobReferences keep their rows
+CREATE TABLE GroupMessagesNew (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    group_id INTEGER NOT NULL,
+    sender_id TEXT,
+    epoch INTEGER NOT NULL,
+    content BLOB NOT NULL,
+    sent_at INTEGER NOT NULL,
+    expires_at INTEGER,
+    edits INTEGER,
+    deletes INTEGER,
+    FOREIGN KEY (group_id) REFERENCES Groups (id) ON DELETE CASCADE,
+    FOREIGN KEY (sender_id) REFERENCES Users (id) ON DELETE SET NULL
+);
+INSERT INTO GroupMessagesNew SELECT * FROM GroupMessages;
+-- ids of deleted messages must not be handed out again
+DELETE FROM sqlite_sequence WHERE name = 'GroupMessagesNew';
+UPDATE sqlite_sequence SET name = 'GroupMessagesNew' WHERE name = 'GroupMessages';
+DROP TABLE GroupMessages;
+ALTER TABLE GroupMessagesNew RENAME TO GroupMessages;
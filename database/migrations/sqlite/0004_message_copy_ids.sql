@@ -0,0 +1,10 @@
+-- see 0007_message_copy_ids.sql, without sent copies from OutMessages they are the message's own
+ALTER TABLE MessageKeys ADD COLUMN copy_id INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE MessageKeys ADD COLUMN edits INTEGER;
+ALTER TABLE MessageKeys ADD COLUMN deletes INTEGER;
+UPDATE MessageKeys SET
+    copy_id = message_id,
+    edits = (SELECT m.edits FROM Messages m WHERE m.id = MessageKeys.message_id),
+    deletes = (SELECT m.deletes FROM Messages m WHERE m.id = MessageKeys.message_id);
+DROP INDEX IF EXISTS message_keys_user;
+CREATE INDEX IF NOT EXISTS message_keys_copy ON MessageKeys (user_id, direction, copy_id);
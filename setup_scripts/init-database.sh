@@ -13,18 +13,10 @@ echo ">> Start database services"
 sudo systemctl enable --now postgresql.service
 sudo systemctl start postgresql.service
 
-echo ">> Setup initial tables"
-chmod ugo+r "../database/init.sql"
+echo ">> Create database"
 su postgres <<EOF
-psql -c "create database messagist;"
 psql -c "create user messagist_server with encrypted password '2Rk4M4LQGbrZB2j';"
-psql -c "grant all privileges on database messagist to messagist_server;"
-psql -d messagist -c "\ir ../database/init.sql;"
-psql -d messagist -c "grant all privileges on table users to messagist_server;"
-psql -d messagist -c "grant all privileges on table inmessages to messagist_server;"
-psql -d messagist -c "grant all privileges on table outmessages to messagist_server;"
-psql -d messagist -c "grant all privileges on table inmessages_id_seq to messagist_server;"
-psql -d messagist -c "grant all privileges on table outmessages_id_seq to messagist_server;"
+psql -c "create database messagist owner messagist_server;"
 EOF
 
 echo ">> Configuring network interfaces"